@@ -0,0 +1,58 @@
+//! Parsing straight from the lexer compared to normalizing all tokens into a
+//! `Vec` before parsing, like `Parser::new` used to. The whole document takes
+//! about as long either way, but the first form is available right away
+//! instead of after lexing everything. `benches/memory.rs` compares how much
+//! memory both need.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use sweet_expr::lexer::tokenise;
+use sweet_expr::parser::{handle_whitespace, Dialect, Parser};
+
+fn document(forms: usize) -> String {
+    let mut source = String::new();
+    for i in 0..forms {
+        source.push_str(&format!(
+            "define (f{i} x y)\n  let ((z {{x + y}}))\n    print(\"f{i}\" z)\n    g{i}[x] ; done\n\n"
+        ));
+    }
+    source
+}
+
+fn frontend(c: &mut Criterion) {
+    for forms in [100, 10_000] {
+        let source = document(forms);
+        let mut group = c.benchmark_group(format!("frontend/{forms}"));
+        group.throughput(Throughput::Bytes(source.len() as u64));
+
+        group.bench_function("fused", |b| {
+            b.iter(|| Parser::new(tokenise(black_box(&source))).parse_toplevel())
+        });
+        group.bench_function("collected", |b| {
+            b.iter(|| {
+                let tokens = handle_whitespace(tokenise(black_box(&source)));
+                Parser::from_indented(tokens, Dialect::Sweet).parse_toplevel()
+            })
+        });
+        group.bench_function("fused_first_form", |b| {
+            b.iter(|| {
+                Parser::new(tokenise(black_box(&source)))
+                    .iter_toplevel()
+                    .next()
+            })
+        });
+        group.bench_function("collected_first_form", |b| {
+            b.iter(|| {
+                let tokens = handle_whitespace(tokenise(black_box(&source)));
+                Parser::from_indented(tokens, Dialect::Sweet)
+                    .iter_toplevel()
+                    .next()
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, frontend);
+criterion_main!(benches);
@@ -0,0 +1,97 @@
+//! Peak heap usage of parsing straight from the lexer compared to
+//! normalizing all tokens into a `Vec` first. The parser drops the tokens of
+//! every finished form, so a consumer that handles one form at a time only
+//! ever holds a few of them, and a full parse holds the tree but not the
+//! token stream next to it.
+//!
+//! ```text
+//! cargo bench --bench memory
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use sweet_expr::lexer::tokenise;
+use sweet_expr::parser::{handle_whitespace, Dialect, Parser};
+
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(current, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn document(forms: usize) -> String {
+    let mut source = String::new();
+    for i in 0..forms {
+        source.push_str(&format!(
+            "define (f{i} x y)\n  let ((z {{x + y}}))\n    print(\"f{i}\" z)\n    g{i}[x] ; done\n\n"
+        ));
+    }
+    source
+}
+
+// bytes allocated by `f` at its peak, on top of what was allocated before
+fn peak<T>(f: impl FnOnce() -> T) -> usize {
+    let before = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    drop(black_box(f()));
+    PEAK.load(Ordering::Relaxed) - before
+}
+
+fn main() {
+    for forms in [100, 10_000] {
+        let source = document(forms);
+        let runs: [(&str, usize); 4] = [
+            (
+                "fused",
+                peak(|| Parser::new(tokenise(&source)).parse_toplevel()),
+            ),
+            (
+                "collected",
+                peak(|| {
+                    let tokens = handle_whitespace(tokenise(&source));
+                    Parser::from_indented(tokens, Dialect::Sweet).parse_toplevel()
+                }),
+            ),
+            (
+                "fused_one_at_a_time",
+                peak(|| {
+                    Parser::new(tokenise(&source))
+                        .iter_toplevel()
+                        .filter(Result::is_ok)
+                        .count()
+                }),
+            ),
+            (
+                "collected_one_at_a_time",
+                peak(|| {
+                    let tokens = handle_whitespace(tokenise(&source));
+                    Parser::from_indented(tokens, Dialect::Sweet)
+                        .iter_toplevel()
+                        .filter(Result::is_ok)
+                        .count()
+                }),
+            ),
+        ];
+        for (name, bytes) in runs {
+            let name = format!("memory/{forms}/{name}");
+            println!("{name:<36} peak {bytes:>10} bytes");
+        }
+    }
+}
@@ -0,0 +1,61 @@
+/* C interface of the sweet-expr parser, built with the `ffi` feature.
+ *
+ * Documents own everything in them, nodes are valid until their document is
+ * freed. Text is returned as a pointer and a length and is not NUL
+ * terminated. All functions accept null pointers. */
+
+#ifndef SWEET_EXPR_H
+#define SWEET_EXPR_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SweetExprDocument SweetExprDocument;
+typedef struct SweetExprNode SweetExprNode;
+
+/* node kinds */
+#define SWEET_EXPR_IDENTIFIER 0
+#define SWEET_EXPR_STRING 1
+#define SWEET_EXPR_GROUP 2
+#define SWEET_EXPR_NEOTERIC 3
+#define SWEET_EXPR_RAW 4
+#define SWEET_EXPR_ERROR 5
+
+/* group types */
+#define SWEET_EXPR_PARENTHESIS 0
+#define SWEET_EXPR_INDENTATION 1
+#define SWEET_EXPR_CURLY 2
+#define SWEET_EXPR_BRACKET 3
+
+/* Never returns null, check sweet_expr_document_error */
+SweetExprDocument *sweet_expr_parse(const char *source, size_t len);
+void sweet_expr_document_free(SweetExprDocument *document);
+/* Null if the source parsed, the message and its byte range otherwise */
+const char *sweet_expr_document_error(const SweetExprDocument *document, size_t *len,
+                                      uint32_t *start, uint32_t *end);
+size_t sweet_expr_document_len(const SweetExprDocument *document);
+const SweetExprNode *sweet_expr_document_get(const SweetExprDocument *document, size_t index);
+
+/* One of the node kinds, -1 for null */
+int32_t sweet_expr_node_kind(const SweetExprNode *node);
+/* One of the group types for groups, neoteric arguments and #raw bodies */
+int32_t sweet_expr_node_group_type(const SweetExprNode *node);
+size_t sweet_expr_node_len(const SweetExprNode *node);
+const SweetExprNode *sweet_expr_node_child(const SweetExprNode *node, size_t index);
+/* `f` in `f(x)` */
+const SweetExprNode *sweet_expr_node_head(const SweetExprNode *node);
+/* Identifiers, string literals with their quotes, #raw markers */
+const char *sweet_expr_node_text(const SweetExprNode *node, size_t *len);
+/* Copies the string contents if they fit, returns their length */
+size_t sweet_expr_node_unescaped(const SweetExprNode *node, char *buf, size_t capacity);
+void sweet_expr_node_span(const SweetExprNode *node, uint32_t *start, uint32_t *end);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
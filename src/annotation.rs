@@ -0,0 +1,210 @@
+//! Attaching information to atoms without changing the tree, e.g. inferred
+//! types or evaluation results.
+//!
+//! [`NodeIds`] numbers the atoms of a document in source order, the same
+//! document always gets the same numbering. An [`AnnotationMap`] then holds
+//! a value per [`NodeId`]. Atoms are found by address while the document is
+//! borrowed, so [`NodeIds::id`] only knows atoms of the document it was
+//! built from, not clones of them. `#raw` bodies aren't numbered.
+//!
+//! ```
+//! use sweet_expr::annotation::{AnnotationMap, NodeIds};
+//!
+//! let document = sweet_expr::parse("define x 1\nx\n").unwrap();
+//! let ids = NodeIds::new(&document);
+//! let mut types = AnnotationMap::new();
+//! for (id, atom) in ids.iter() {
+//!     if atom.is_number() {
+//!         types.insert(id, "int");
+//!     }
+//! }
+//! let one = &document[0].as_group().unwrap().children[2];
+//! assert_eq!(types.get(ids.id(one).unwrap()), Some(&"int"));
+//! ```
+
+use std::collections::HashMap;
+
+use crate::value::*;
+
+/// Index of an atom in source order, starting at 0 for the first top-level
+/// form. A form comes before its elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(pub u32);
+
+pub struct NodeIds<'a, 'src> {
+    atoms: Vec<&'a Atom<'src>>,
+    parents: Vec<Option<NodeId>>,
+    ids: HashMap<*const Atom<'src>, NodeId>,
+}
+
+impl<'a, 'src> NodeIds<'a, 'src> {
+    pub fn new(document: &'a [Atom<'src>]) -> Self {
+        let mut ids = NodeIds {
+            atoms: vec![],
+            parents: vec![],
+            ids: HashMap::new(),
+        };
+        for atom in document {
+            ids.number(atom, None);
+        }
+        ids
+    }
+
+    fn number(&mut self, atom: &'a Atom<'src>, parent: Option<NodeId>) {
+        let id = NodeId(self.atoms.len() as u32);
+        self.atoms.push(atom);
+        self.parents.push(parent);
+        self.ids.insert(atom, id);
+        match atom {
+            Atom::Group(group) => {
+                for child in &group.children {
+                    self.number(child, Some(id));
+                }
+            }
+            Atom::Neoteric { lhs, rhs } => {
+                self.number(lhs, Some(id));
+                for child in &rhs.children {
+                    self.number(child, Some(id));
+                }
+            }
+            Atom::Identifier(_) | Atom::String(_) | Atom::Raw { .. } | Atom::Error(_) => {}
+        }
+    }
+
+    /// The id of an atom of the document, `None` for any other atom
+    pub fn id(&self, atom: &Atom<'src>) -> Option<NodeId> {
+        self.ids.get(&(atom as *const _)).copied()
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&'a Atom<'src>> {
+        self.atoms.get(id.0 as usize).copied()
+    }
+
+    /// The form the atom is an element of, `None` for top-level forms
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.parents.get(id.0 as usize).copied().flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.atoms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.atoms.is_empty()
+    }
+
+    /// All atoms with their ids, in source order
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &'a Atom<'src>)> + '_ {
+        self.atoms
+            .iter()
+            .enumerate()
+            .map(|(i, &atom)| (NodeId(i as u32), atom))
+    }
+}
+
+/// A value for some of the atoms of a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnotationMap<T> {
+    values: HashMap<NodeId, T>,
+}
+
+impl<T> Default for AnnotationMap<T> {
+    fn default() -> Self {
+        Self {
+            values: HashMap::new(),
+        }
+    }
+}
+
+impl<T> AnnotationMap<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the value the atom had before
+    pub fn insert(&mut self, id: NodeId, value: T) -> Option<T> {
+        self.values.insert(id, value)
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.values.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+        self.values.get_mut(&id)
+    }
+
+    pub fn remove(&mut self, id: NodeId) -> Option<T> {
+        self.values.remove(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// In no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &T)> {
+        self.values.iter().map(|(&id, value)| (id, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_in_source_order() {
+        let document = crate::parse("f(x) [a #raw(b)]\nc\n").unwrap();
+        let ids = NodeIds::new(&document);
+        let texts: Vec<_> = ids.iter().map(|(_, atom)| atom.to_string()).collect();
+        assert_eq!(
+            texts,
+            [
+                "((f x) [a #raw(b)])",
+                "(f x)",
+                "f",
+                "x",
+                "[a #raw(b)]",
+                "a",
+                "#raw(b)",
+                "c"
+            ]
+        );
+        assert_eq!(ids.parent(NodeId(3)), Some(NodeId(1)));
+        assert_eq!(ids.parent(NodeId(7)), None);
+        assert_eq!(ids.id(&document[1]), Some(NodeId(7)));
+        assert_eq!(ids.id(&document[1].clone()), None);
+        assert!(ids.get(NodeId(8)).is_none());
+
+        let again = NodeIds::new(&document);
+        assert_eq!(
+            again.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            ids.iter().map(|(id, _)| id).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn annotations() {
+        let document = crate::parse("a b").unwrap();
+        let ids = NodeIds::new(&document);
+        let mut depth = AnnotationMap::new();
+        for (id, _) in ids.iter() {
+            let mut parent = ids.parent(id);
+            let mut d = 0;
+            while let Some(p) = parent {
+                d += 1;
+                parent = ids.parent(p);
+            }
+            depth.insert(id, d);
+        }
+        assert_eq!(depth.len(), 3);
+        *depth.get_mut(NodeId(0)).unwrap() += 10;
+        assert_eq!(depth.get(NodeId(0)), Some(&10));
+        assert_eq!(depth.remove(NodeId(2)), Some(1));
+        assert!(depth.get(NodeId(2)).is_none());
+    }
+}
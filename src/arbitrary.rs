@@ -0,0 +1,121 @@
+//! [`Arbitrary`] implementations for fuzzing and property tests, with the
+//! `arbitrary` feature.
+//!
+//! The generated trees are well-formed: they only contain what the parser
+//! could have produced, so printing one and parsing the result gives the same
+//! tree back. Identifiers are short and can't be mistaken for other syntax,
+//! strings only contain letters, spaces and escaped quotes, and nesting stops
+//! at [`MAX_DEPTH`]. All spans are empty.
+//!
+//! ```
+//! use arbitrary::{Arbitrary, Unstructured};
+//! use sweet_expr::value::OwnedAtom;
+//!
+//! let mut u = Unstructured::new(&[7, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+//! let atom = OwnedAtom::arbitrary(&mut u).unwrap();
+//! assert!(sweet_expr::parse(&atom.to_string()).is_ok());
+//! ```
+
+use std::borrow::Cow;
+
+use ::arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::source_map::Span;
+use crate::value::*;
+
+/// How deep generated groups nest at most
+pub const MAX_DEPTH: usize = 6;
+
+const IDENTIFIER_START: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const IDENTIFIER_REST: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789-?!*";
+const STRING_CHARS: &[&str] = &["a", "b", "x", "y", " ", "\\\""];
+
+impl<'a> Arbitrary<'a> for Atom<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        atom(u, MAX_DEPTH)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Group<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        group(u, MAX_DEPTH)
+    }
+}
+
+fn atom(u: &mut Unstructured, depth: usize) -> Result<OwnedAtom> {
+    // only leaves once the depth is used up
+    let kinds = match depth {
+        0 => 2,
+        _ => 4,
+    };
+    Ok(match u.choose_index(kinds)? {
+        0 => Atom::Identifier(spanned(identifier(u)?)),
+        1 => Atom::String(spanned(string(u)?)),
+        2 => Atom::Group(group(u, depth - 1)?),
+        _ => Atom::Neoteric {
+            lhs: Box::new(atom(u, depth - 1)?),
+            rhs: group(u, depth - 1)?,
+        },
+    })
+}
+
+fn group(u: &mut Unstructured, depth: usize) -> Result<Group<'static>> {
+    let group_type = *u.choose(&[GroupType::Parenthesis, GroupType::Bracket, GroupType::Curly])?;
+    let len = u.int_in_range(0..=4)?;
+    let children = (0..len).map(|_| atom(u, depth)).collect::<Result<_>>()?;
+    Ok(Group {
+        group_type,
+        start_delim: Spanned((), Span::default(), None),
+        children,
+        end_delim: Spanned((), Span::default(), None),
+    })
+}
+
+fn identifier(u: &mut Unstructured) -> Result<String> {
+    let mut ident = String::from(*u.choose(IDENTIFIER_START)? as char);
+    for _ in 0..u.int_in_range(0..=5)? {
+        ident.push(*u.choose(IDENTIFIER_REST)? as char);
+    }
+    Ok(ident)
+}
+
+// a literal, with its quotes
+fn string(u: &mut Unstructured) -> Result<String> {
+    let mut literal = String::from('"');
+    for _ in 0..u.int_in_range(0..=6)? {
+        literal.push_str(u.choose(STRING_CHARS)?);
+    }
+    literal.push('"');
+    Ok(literal)
+}
+
+fn spanned(text: String) -> Spanned<Cow<'static, str>> {
+    Spanned(Cow::Owned(text), Span::default(), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assert_roundtrip;
+
+    // xorshift, so the test sees the same inputs every time
+    fn bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn printed_trees_parse_back() {
+        for seed in 0..500 {
+            let data = bytes(seed, 256);
+            assert_roundtrip(&OwnedAtom::arbitrary(&mut Unstructured::new(&data)).unwrap());
+        }
+    }
+}
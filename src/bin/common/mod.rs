@@ -0,0 +1,30 @@
+//! What the command line tools share
+
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::path::Path;
+
+use sweet_expr::diagnostic::RenderOptions;
+
+/// The contents of the file, or stdin for `-`
+pub fn read(input: &Path) -> io::Result<String> {
+    if is_stdin(input) {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)?;
+        Ok(source)
+    } else {
+        fs::read_to_string(input)
+    }
+}
+
+pub fn is_stdin(input: &Path) -> bool {
+    input.as_os_str() == "-"
+}
+
+/// Colored if stderr is a terminal
+pub fn render_options() -> RenderOptions {
+    RenderOptions {
+        color: io::stderr().is_terminal(),
+        max_width: None,
+    }
+}
@@ -0,0 +1,155 @@
+//! Formats sweet-expression files in place, or checks that they are.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use sweet_expr::canonical::{canonicalize_source, Style};
+use sweet_expr::format::format;
+use sweet_expr::printer::{Printer, ShortForms};
+
+mod common;
+
+const USAGE: &str = "\
+usage: sweet-fmt [options] [file...]
+
+Formats the files in place. Without files, or for `-`, formats stdin and
+writes the result to stdout. Files with errors are left alone.
+
+options:
+  --check         don't write anything, list the files that aren't formatted
+                  and exit with status 1 if there are any
+  --width N       break lines longer than N chars, 80 by default
+  --indent N      indent by N spaces, 4 by default
+  --indentation-groups
+                  write forms with forms inside on several lines even if
+                  they'd fit on one
+  --align         keep the first argument on the line of the head and line
+                  up the others under it
+  --blank-lines N keep at most N blank lines between forms, 1 by default
+  --join-spaced-groups
+                  rewrite `f (x)` to `f(x)`, the space is taken for a typo
+  --style STYLE   rewrite every form into one style: `indentation`,
+                  `neoteric` or `parens`
+  -h, --help      show this message
+";
+
+#[derive(Default)]
+struct Args {
+    check: bool,
+    width: Option<usize>,
+    indent: Option<usize>,
+    indentation_groups: bool,
+    align: bool,
+    blank_lines: Option<usize>,
+    join_spaced_groups: bool,
+    style: Option<Style>,
+    inputs: Vec<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, lexopt::Error> {
+    use lexopt::prelude::*;
+
+    let mut args = Args::default();
+    let mut parser = lexopt::Parser::from_env();
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("check") => args.check = true,
+            Long("width") => args.width = Some(parser.value()?.parse()?),
+            Long("indent") => args.indent = Some(parser.value()?.parse()?),
+            Long("indentation-groups") => args.indentation_groups = true,
+            Long("align") => args.align = true,
+            Long("join-spaced-groups") => args.join_spaced_groups = true,
+            Long("blank-lines") => args.blank_lines = Some(parser.value()?.parse()?),
+            Long("style") => {
+                let value = parser.value()?;
+                args.style = Some(match value.to_str() {
+                    Some("indentation") => Style::Indentation,
+                    Some("neoteric") => Style::Neoteric,
+                    Some("parens") => Style::Parens,
+                    _ => {
+                        return Err(lexopt::Error::UnexpectedValue {
+                            option: "style".into(),
+                            value,
+                        })
+                    }
+                });
+            }
+            Short('h') | Long("help") => {
+                print!("{USAGE}");
+                std::process::exit(0);
+            }
+            Value(input) => args.inputs.push(input.into()),
+            _ => return Err(arg.unexpected()),
+        }
+    }
+    if args.inputs.is_empty() {
+        args.inputs.push("-".into());
+    }
+    Ok(args)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            eprint!("sweet-fmt: {err}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let mut printer = Printer::new()
+        .align_arguments(args.align)
+        .join_spaced_groups(args.join_spaced_groups);
+    if args.indentation_groups {
+        printer = printer.short_forms(ShortForms::Indentation);
+    }
+    if let Some(width) = args.width {
+        printer = printer.width(width);
+    }
+    if let Some(indent) = args.indent {
+        printer = printer.indent(indent);
+    }
+    if let Some(max) = args.blank_lines {
+        printer = printer.max_blank_lines(max);
+    }
+    let options = common::render_options();
+
+    let mut failed = false;
+    for input in &args.inputs {
+        let result = common::read(input).and_then(|source| {
+            let formatted = match args.style {
+                Some(style) => canonicalize_source(&source, style, &printer),
+                None => format(&source, &printer),
+            };
+            let formatted = match formatted {
+                Ok(formatted) => formatted,
+                Err(err) => {
+                    eprintln!("in {}:", input.display());
+                    eprintln!("{}", err.render_with(&source, &options));
+                    failed = true;
+                    return Ok(());
+                }
+            };
+            match (args.check, common::is_stdin(input)) {
+                (true, _) if formatted != source => {
+                    println!("{}", input.display());
+                    failed = true;
+                    Ok(())
+                }
+                (true, _) => Ok(()),
+                (false, true) => io::stdout().write_all(formatted.as_bytes()),
+                (false, false) if formatted != source => fs::write(input, formatted),
+                (false, false) => Ok(()),
+            }
+        });
+        if let Err(err) = result {
+            eprintln!("sweet-fmt: {}: {err}", input.display());
+            failed = true;
+        }
+    }
+    match failed {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS,
+    }
+}
@@ -0,0 +1,123 @@
+//! The `sweet` tool, for now only `sweet check`, which is meant to be run
+//! from pre-commit hooks and CI.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use sweet_expr::diagnostic::Severity;
+use sweet_expr::lint::Linter;
+
+mod common;
+
+const USAGE: &str = "\
+usage: sweet <command> [options] [file...]
+
+commands:
+  check    parse the files and report errors and warnings, exits with
+           status 1 if there are errors
+
+Without files, or for `-`, stdin is read.
+
+options:
+  --lint             also run the style checks
+  --deny-warnings    exit with status 1 on warnings too
+  -q, --quiet        only report errors
+  -h, --help         show this message
+";
+
+#[derive(Default)]
+struct Args {
+    lint: bool,
+    deny_warnings: bool,
+    quiet: bool,
+    inputs: Vec<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, lexopt::Error> {
+    use lexopt::prelude::*;
+
+    let mut parser = lexopt::Parser::from_env();
+    let command = match parser.next()? {
+        Some(Short('h') | Long("help")) => {
+            print!("{USAGE}");
+            std::process::exit(0);
+        }
+        Some(Value(command)) => command.string()?,
+        Some(arg) => return Err(arg.unexpected()),
+        None => return Err("missing command".into()),
+    };
+    if command != "check" {
+        return Err(format!("unknown command `{command}`").into());
+    }
+
+    let mut args = Args::default();
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("lint") => args.lint = true,
+            Long("deny-warnings") => args.deny_warnings = true,
+            Short('q') | Long("quiet") => args.quiet = true,
+            Short('h') | Long("help") => {
+                print!("{USAGE}");
+                std::process::exit(0);
+            }
+            Value(input) => args.inputs.push(input.into()),
+            _ => return Err(arg.unexpected()),
+        }
+    }
+    if args.inputs.is_empty() {
+        args.inputs.push("-".into());
+    }
+    Ok(args)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            eprint!("sweet: {err}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let linter = match args.lint {
+        true => Linter::new(),
+        false => Linter::empty(),
+    };
+    let fails_at = match args.deny_warnings {
+        true => Severity::Warning,
+        false => Severity::Error,
+    };
+    let shown_from = match args.quiet {
+        true => Severity::Error,
+        false => Severity::Warning,
+    };
+    let options = common::render_options();
+
+    let mut failed = false;
+    for input in &args.inputs {
+        let source = match common::read(input) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("sweet: {}: {err}", input.display());
+                failed = true;
+                continue;
+            }
+        };
+        let diagnostics: Vec<_> = linter
+            .lint(&source)
+            .into_iter()
+            .filter(|diagnostic| diagnostic.severity >= shown_from)
+            .collect();
+        if diagnostics.is_empty() {
+            continue;
+        }
+        eprintln!("in {}:", input.display());
+        for diagnostic in &diagnostics {
+            eprintln!("{}", diagnostic.render_with(&source, &options));
+            failed |= diagnostic.severity >= fails_at;
+        }
+    }
+    match failed {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS,
+    }
+}
@@ -0,0 +1,110 @@
+//! Converts sweet-expressions into plain s-expressions, so they can be fed to
+//! any Lisp that doesn't read them itself.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use sweet_expr::printer::{write_sexpr, write_sexpr_minified};
+
+mod common;
+
+const USAGE: &str = "\
+usage: sweet2sexp [options] [file...]
+
+Reads sweet-expressions from the files, or from stdin if there are none or a
+file is `-`, and writes them as s-expressions, one form per line. Nothing is
+written if any of the files has errors.
+
+options:
+  -m, --minify       leave out all spaces that aren't needed
+  -o, --output FILE  write to FILE instead of stdout
+  -h, --help         show this message
+";
+
+#[derive(Default)]
+struct Args {
+    minify: bool,
+    output: Option<PathBuf>,
+    inputs: Vec<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, lexopt::Error> {
+    use lexopt::prelude::*;
+
+    let mut args = Args::default();
+    let mut parser = lexopt::Parser::from_env();
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Short('m') | Long("minify") => args.minify = true,
+            Short('o') | Long("output") => args.output = Some(parser.value()?.into()),
+            Short('h') | Long("help") => {
+                print!("{USAGE}");
+                std::process::exit(0);
+            }
+            Value(input) => args.inputs.push(input.into()),
+            _ => return Err(arg.unexpected()),
+        }
+    }
+    if args.inputs.is_empty() {
+        args.inputs.push("-".into());
+    }
+    Ok(args)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            eprint!("sweet2sexp: {err}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let options = common::render_options();
+
+    let mut out = String::new();
+    let mut failed = false;
+    for input in &args.inputs {
+        let source = match common::read(input) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("sweet2sexp: {}: {err}", input.display());
+                failed = true;
+                continue;
+            }
+        };
+        match sweet_expr::parse(&source) {
+            Ok(atoms) => {
+                for atom in &atoms {
+                    match args.minify {
+                        true => write_sexpr_minified(atom, &mut out),
+                        false => write_sexpr(atom, &mut out),
+                    }
+                    .expect("writing to a string can't fail");
+                    out.push('\n');
+                }
+            }
+            Err(err) => {
+                eprintln!("in {}:", input.display());
+                eprintln!("{}", err.render_with(&source, &options));
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        return ExitCode::FAILURE;
+    }
+
+    let written = match &args.output {
+        Some(path) => fs::write(path, &out),
+        None => io::stdout().write_all(out.as_bytes()),
+    };
+    match written {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("sweet2sexp: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::sharded::Sharded;
+
+/// Caches whatever a consumer derives from parsing a source text (since the
+/// parsed atoms borrow from the source, usually the consumer's own
+/// representation), keyed by the source text itself.
+///
+/// The cache is `Sync` and sharded, so it can be shared between threads
+/// parsing many documents concurrently.
+#[derive(Debug)]
+pub struct ParseCache<V> {
+    shards: Sharded<HashMap<Arc<str>, Arc<V>>>,
+}
+
+impl<V> Default for ParseCache<V> {
+    fn default() -> Self {
+        Self {
+            shards: Sharded::default(),
+        }
+    }
+}
+
+impl<V> ParseCache<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, source: &str) -> Option<Arc<V>> {
+        let index = self.shards.index_for(source);
+        self.shards.read(index).get(source).cloned()
+    }
+
+    /// Returns the cached value for `source` or computes it with `parse`.
+    ///
+    /// No lock is held while `parse` runs, so two threads might both parse
+    /// the same source; only the first result is kept.
+    pub fn get_or_parse(&self, source: &str, parse: impl FnOnce(&str) -> V) -> Arc<V> {
+        if let Some(value) = self.get(source) {
+            return value;
+        }
+
+        let value = Arc::new(parse(source));
+        let index = self.shards.index_for(source);
+        self.shards
+            .write(index)
+            .entry(source.into())
+            .or_insert(value)
+            .clone()
+    }
+
+    pub fn remove(&self, source: &str) -> Option<Arc<V>> {
+        let index = self.shards.index_for(source);
+        self.shards.write(index).remove(source)
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,168 @@
+//! Rewriting code into a single style, so a shared codebase reads the same
+//! no matter who wrote which part.
+//!
+//! Sweet-expressions can say the same thing in several ways: `f(x)`, `(f x)`
+//! and `f x` on a line of its own are all the same list. [`canonicalize`]
+//! picks one of them for every list according to a [`Style`], and
+//! [`canonicalize_source`] does it for a whole file, keeping comments the
+//! way [`format`](crate::format::format) does.
+//!
+//! ```
+//! use sweet_expr::canonical::{canonicalize_source, Style};
+//! use sweet_expr::printer::Printer;
+//!
+//! let source = "(define (f x) (g x))\n";
+//! let canonical = canonicalize_source(source, Style::Neoteric, &Printer::new()).unwrap();
+//! assert_eq!(canonical, "define f(x) g(x)\n");
+//! ```
+
+use crate::format::format_with;
+use crate::normalize::{GROUP_SPLIT_MARKER, SUBLIST_MARKER};
+use crate::number::Number;
+use crate::parser::{ParseError, RAW_MARKER};
+use crate::printer::{Printer, ShortForms};
+use crate::value::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// indentation wherever possible: every list inside of another one gets
+    /// a line of its own, `f(x)` is written as a list too
+    Indentation,
+    /// lists inside of lines as neoteric calls, `define f(x) g(x)`
+    Neoteric,
+    /// plain s-expressions, one top-level form per line
+    Parens,
+}
+
+/// `atom` with every list in the form `style` asks for. Curly and bracket
+/// groups are different things than lists and are kept, as are `#raw`
+/// groups.
+pub fn canonicalize(atom: Atom<'_>, style: Style) -> Atom<'_> {
+    match (style, lists(atom)) {
+        // the form itself stays a line
+        (Style::Neoteric, Atom::Group(mut group)) if is_list(&group) => {
+            group.children = group.children.into_iter().map(calls).collect();
+            Atom::Group(group)
+        }
+        (Style::Neoteric, atom) => calls(atom),
+        (Style::Indentation | Style::Parens, atom) => atom,
+    }
+}
+
+/// The whole file in `style`, laid out by `printer`. Forms with comments
+/// inside are left as they are, see [`format`](crate::format::format).
+pub fn canonicalize_source<'src>(
+    source: &'src str,
+    style: Style,
+    printer: &Printer,
+) -> Result<String, ParseError<'src>> {
+    let printer = match style {
+        Style::Indentation => printer.clone().short_forms(ShortForms::Indentation),
+        Style::Neoteric | Style::Parens => printer.clone(),
+    };
+    format_with(source, printer.options(), |atom| {
+        let atom = canonicalize(atom.clone(), style);
+        match style {
+            // the printer writes lists as lines, without their parentheses
+            Style::Parens => atom.to_string(),
+            Style::Indentation | Style::Neoteric => printer.print(&atom),
+        }
+    })
+}
+
+fn is_list(group: &Group) -> bool {
+    matches!(
+        group.group_type,
+        GroupType::Parenthesis | GroupType::Indentation
+    )
+}
+
+// `f(x)` → `(f x)`, everywhere
+fn lists(atom: Atom<'_>) -> Atom<'_> {
+    match atom {
+        Atom::Neoteric { lhs, rhs } if rhs.group_type == GroupType::Parenthesis => {
+            let mut children = vec![lists(*lhs)];
+            children.extend(rhs.children.into_iter().map(lists));
+            Atom::Group(Group { children, ..rhs })
+        }
+        Atom::Neoteric { lhs, mut rhs } => {
+            rhs.children = rhs.children.into_iter().map(lists).collect();
+            Atom::Neoteric {
+                lhs: Box::new(lists(*lhs)),
+                rhs,
+            }
+        }
+        Atom::Group(mut group) => {
+            group.children = group.children.into_iter().map(lists).collect();
+            Atom::Group(group)
+        }
+        atom => atom,
+    }
+}
+
+// `(f x)` → `f(x)`, for lists that start with a name
+fn calls(atom: Atom<'_>) -> Atom<'_> {
+    let Atom::Group(mut group) = atom else {
+        return atom;
+    };
+    let named = group
+        .children
+        .first()
+        .and_then(Atom::as_identifier)
+        .is_some_and(|head| {
+            Number::parse(head).is_none()
+                && ![SUBLIST_MARKER, GROUP_SPLIT_MARKER, "."].contains(&head)
+                && !head.starts_with(['\'', '`', ','])
+                // `#raw(...)` is a raw group and `#date(...)` could be a
+                // reader macro, neither is a call
+                && head != RAW_MARKER
+                && !head.starts_with('#')
+        });
+    group.children = group.children.into_iter().map(calls).collect();
+    if !is_list(&group) || !named {
+        return Atom::Group(group);
+    }
+    let lhs = group.children.remove(0);
+    Atom::Neoteric {
+        lhs: Box::new(lhs),
+        rhs: Group {
+            group_type: GroupType::Parenthesis,
+            ..group
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn styles() {
+        let source = "; f\ndefine f(x)\n  g (h x) {x + 1} [1 2]\n(1 2)\n";
+        let canonical = |style| canonicalize_source(source, style, &Printer::new()).unwrap();
+        assert_eq!(
+            canonical(Style::Indentation),
+            "; f\ndefine\n    f x\n    g\n        h x\n        {x + 1}\n        [1 2]\n1 2\n"
+        );
+        assert_eq!(
+            canonical(Style::Neoteric),
+            "; f\ndefine f(x) g(h(x) {x + 1} [1 2])\n1 2\n"
+        );
+        assert_eq!(
+            canonical(Style::Parens),
+            "; f\n(define (f x) (g (h x) {x + 1} [1 2]))\n(1 2)\n"
+        );
+        let source = "(a (#raw b c) (#date d))\n";
+        assert_eq!(
+            canonicalize_source(source, Style::Neoteric, &Printer::new()).unwrap(),
+            "a (#raw b c) (#date d)\n"
+        );
+        for style in [Style::Indentation, Style::Neoteric, Style::Parens] {
+            let once = canonical(style);
+            assert_eq!(
+                canonicalize_source(&once, style, &Printer::new()).unwrap(),
+                once
+            );
+        }
+    }
+}
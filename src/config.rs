@@ -0,0 +1,301 @@
+//! Reading values out of configuration files by path, for applications that
+//! don't want to walk the tree themselves or pull in serde.
+//!
+//! A configuration is a list of `key value...` entries. The elements after a
+//! key are its value, or its own entries, so `server.host` is the value of
+//! `host` among the elements after `server`. When a key is there more than
+//! once the last one counts.
+//!
+//! ```
+//! use sweet_expr::config::Config;
+//!
+//! let config = Config::parse("server\n  host \"localhost\"\n  port 8080\nfeatures gzip tls\n").unwrap();
+//! assert_eq!(config.get_str("server.host").unwrap(), "localhost");
+//! assert_eq!(config.get_int("server.port").unwrap(), 8080);
+//! assert_eq!(config.get_list("features").unwrap().len(), 2);
+//!
+//! let err = config.get_int("server.host").unwrap_err();
+//! assert_eq!(err.to_string(), "`server.host` should be an integer, found a string");
+//! ```
+
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::diagnostic::{Diagnostic, Label, Severity};
+use crate::number::Number;
+use crate::source_map::Span;
+use crate::value::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    Syntax(Diagnostic),
+    /// there is no entry at `path`, the span is that of the section it
+    /// should have been in, `None` at the top level
+    Missing {
+        path: String,
+        span: Option<Span>,
+    },
+    WrongType {
+        path: String,
+        expected: &'static str,
+        found: String,
+        span: Span,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Syntax(err) => err.fmt(f),
+            ConfigError::Missing { path, .. } => write!(f, "`{path}` is missing"),
+            ConfigError::WrongType {
+                path,
+                expected,
+                found,
+                ..
+            } => write!(f, "`{path}` should be {expected}, found {found}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<&ConfigError> for Diagnostic {
+    fn from(err: &ConfigError) -> Self {
+        let diagnostic = Diagnostic::new(Severity::Error, "sweet_expr::config", err.to_string());
+        match err {
+            ConfigError::Syntax(syntax) => syntax.clone(),
+            ConfigError::Missing { span: None, .. } => diagnostic,
+            ConfigError::Missing {
+                span: Some(span), ..
+            } => diagnostic.with_label(Label::primary(*span, "in this section")),
+            ConfigError::WrongType { expected, span, .. } => {
+                diagnostic.with_label(Label::primary(*span, format!("expected {expected}")))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config<'src> {
+    entries: Vec<Atom<'src>>,
+}
+
+impl<'src> Config<'src> {
+    pub fn parse(source: &'src str) -> Result<Self, ConfigError> {
+        crate::parse(source)
+            .map(Config::new)
+            .map_err(|err| ConfigError::Syntax(Diagnostic::from(&err)))
+    }
+
+    pub fn new(entries: Vec<Atom<'src>>) -> Self {
+        Self { entries }
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.value(path).is_ok()
+    }
+
+    /// The elements after the key at `path`
+    pub fn get_all(&self, path: &str) -> Result<&[Atom<'src>], ConfigError> {
+        self.value(path).map(|(elements, _)| elements)
+    }
+
+    /// The single element after the key at `path`
+    pub fn get(&self, path: &str) -> Result<&Atom<'src>, ConfigError> {
+        match self.value(path)? {
+            ([atom], _) => Ok(atom),
+            (elements, span) => Err(ConfigError::WrongType {
+                path: path.to_string(),
+                expected: "a single value",
+                found: format!("{} values", elements.len()),
+                span,
+            }),
+        }
+    }
+
+    /// A string, or an identifier as its name
+    pub fn get_str(&self, path: &str) -> Result<Cow<'_, str>, ConfigError> {
+        let atom = self.get(path)?;
+        match atom {
+            Atom::Identifier(ident) => Ok(Cow::Borrowed(&ident.0)),
+            Atom::String(_) => Ok(atom.unescaped().unwrap()),
+            _ => Err(wrong_type(path, "a string", atom)),
+        }
+    }
+
+    pub fn get_int(&self, path: &str) -> Result<i64, ConfigError> {
+        let atom = self.get(path)?;
+        match atom.as_number() {
+            Some(Number::Integer(int)) => Ok(int),
+            _ => Err(wrong_type(path, "an integer", atom)),
+        }
+    }
+
+    /// Integers are converted
+    pub fn get_float(&self, path: &str) -> Result<f64, ConfigError> {
+        let atom = self.get(path)?;
+        match atom.as_number() {
+            Some(number) => Ok(number.as_f64()),
+            None => Err(wrong_type(path, "a number", atom)),
+        }
+    }
+
+    /// `true`, `false`, `#t` or `#f`, or the key on its own for `true`
+    pub fn get_bool(&self, path: &str) -> Result<bool, ConfigError> {
+        if self.value(path)?.0.is_empty() {
+            return Ok(true);
+        }
+        let atom = self.get(path)?;
+        match atom.as_identifier() {
+            Some("true" | "#t") => Ok(true),
+            Some("false" | "#f") => Ok(false),
+            _ => Err(wrong_type(path, "a boolean", atom)),
+        }
+    }
+
+    /// The elements after the key, or the elements of `[...]` if that's the
+    /// only one
+    pub fn get_list(&self, path: &str) -> Result<&[Atom<'src>], ConfigError> {
+        match self.get_all(path)? {
+            [Atom::Group(group)] if group.group_type == GroupType::Bracket => Ok(&group.children),
+            elements => Ok(elements),
+        }
+    }
+
+    /// The entries at `path` as a configuration of their own
+    pub fn section(&self, path: &str) -> Result<Config<'src>, ConfigError> {
+        self.get_all(path)
+            .map(|entries| Config::new(entries.to_vec()))
+    }
+
+    // the elements after the key and their span, or where they would be
+    fn value(&self, path: &str) -> Result<(&[Atom<'src>], Span), ConfigError> {
+        let mut entries = &self.entries[..];
+        // the entry the last key was found in
+        let mut found: Option<(&[Atom<'src>], Span)> = None;
+        for key in path.split('.') {
+            let section = found.map(|(elements, span)| {
+                entries = elements;
+                span
+            });
+            let entry = entries
+                .iter()
+                .rev()
+                .find_map(|entry| entry_value(entry, key));
+            found = Some(entry.ok_or_else(|| ConfigError::Missing {
+                path: path.to_string(),
+                span: section,
+            })?);
+        }
+        let (elements, span) = found.expect("paths have at least one key");
+        let span = elements
+            .iter()
+            .map(Atom::span)
+            .reduce(Span::to)
+            .unwrap_or(span);
+        Ok((elements, span))
+    }
+}
+
+// the elements after `key` if `entry` is an entry for it
+fn entry_value<'a, 'src>(entry: &'a Atom<'src>, key: &str) -> Option<(&'a [Atom<'src>], Span)> {
+    let elements = match entry {
+        Atom::Group(group) => match group.children.split_first() {
+            Some((head, rest)) if is_key(head, key) => rest,
+            _ => return None,
+        },
+        Atom::Neoteric { lhs, rhs } if is_key(lhs, key) => &rhs.children,
+        _ if is_key(entry, key) => &[],
+        _ => return None,
+    };
+    Some((elements, entry.span()))
+}
+
+fn is_key(atom: &Atom, key: &str) -> bool {
+    match atom {
+        Atom::Identifier(ident) => ident.0 == key,
+        Atom::String(_) => atom.unescaped().unwrap() == key,
+        _ => false,
+    }
+}
+
+fn wrong_type(path: &str, expected: &'static str, atom: &Atom) -> ConfigError {
+    ConfigError::WrongType {
+        path: path.to_string(),
+        expected,
+        found: atom.describe().to_string(),
+        span: atom.span(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+name \"app\"
+debug
+log false
+server
+  host localhost
+  port 8080
+  port 8081
+  ratio 1
+  \"tls cert\" \"a.pem\"
+mirrors [a b]
+limits(10 20)
+";
+
+    #[test]
+    fn typed_getters() {
+        let config = Config::parse(SOURCE).unwrap();
+        assert_eq!(config.get_str("name").unwrap(), "app");
+        assert_eq!(config.get_str("server.host").unwrap(), "localhost");
+        assert_eq!(config.get_int("server.port").unwrap(), 8081);
+        assert_eq!(config.get_float("server.ratio").unwrap(), 1.0);
+        assert_eq!(config.get_str("server.tls cert").unwrap(), "a.pem");
+        assert!(config.get_bool("debug").unwrap());
+        assert!(!config.get_bool("log").unwrap());
+        assert_eq!(config.get_list("mirrors").unwrap().len(), 2);
+        assert_eq!(config.get_list("limits").unwrap().len(), 2);
+        assert!(config.contains("server") && !config.contains("server.user"));
+        assert_eq!(
+            config.section("server").unwrap().get_int("port").unwrap(),
+            8081
+        );
+    }
+
+    #[test]
+    fn errors_have_spans() {
+        let config = Config::parse(SOURCE).unwrap();
+        let err = config.get_int("server.host").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`server.host` should be an integer, found an identifier"
+        );
+        let span = Diagnostic::from(&err).span().unwrap();
+        assert_eq!(&SOURCE[span], "localhost");
+
+        let err = config.get("limits").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`limits` should be a single value, found 2 values"
+        );
+        let span = Diagnostic::from(&err).span().unwrap();
+        assert_eq!(&SOURCE[span], "10 20");
+
+        let err = config.get_str("server.user").unwrap_err();
+        assert_eq!(err.to_string(), "`server.user` is missing");
+        assert!(SOURCE[Diagnostic::from(&err).span().unwrap()].starts_with("server\n"));
+        assert!(matches!(
+            config.get("nope"),
+            Err(ConfigError::Missing { span: None, .. })
+        ));
+
+        assert!(matches!(
+            Config::parse("a (\n"),
+            Err(ConfigError::Syntax(_))
+        ));
+    }
+}
@@ -0,0 +1,154 @@
+//! A stable mapping from the forms and groups of a document to their source
+//! location, so interpreters can report coverage in terms of the original file.
+//!
+//! Regions are listed in pre-order and identified by their index, which stays
+//! the same as long as the source doesn't change. The [`Display`](fmt::Display)
+//! impl writes the map as tab separated values with a header line.
+
+use std::{fmt, ops::Range};
+
+use crate::source_map::LineIndex;
+use crate::value::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    /// a top-level form
+    Form,
+    /// any group, neoteric expression or `#raw` group below the top level
+    Group,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub kind: RegionKind,
+    /// index of the top-level form followed by the child indices leading to
+    /// this node
+    pub path: Vec<usize>,
+    pub bytes: Range<usize>,
+    /// 1-based, inclusive
+    pub start_line: usize,
+    /// 1-based, inclusive
+    pub end_line: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CoverageMap {
+    pub regions: Vec<Region>,
+}
+
+impl CoverageMap {
+    pub fn new(source: &str, document: &[Atom]) -> Self {
+        let index = LineIndex::new(source);
+        let mut map = Self::default();
+        for (i, atom) in document.iter().enumerate() {
+            map.collect(&index, atom, &mut vec![i], RegionKind::Form);
+        }
+        map
+    }
+
+    /// The region id of the node whose extent is exactly `span`, e.g. the
+    /// node an interpreter is about to evaluate
+    pub fn region_for_span(&self, span: &Range<usize>) -> Option<usize> {
+        self.regions.iter().position(|region| region.bytes == *span)
+    }
+
+    /// The id of the innermost region containing `offset`
+    pub fn region_at(&self, offset: usize) -> Option<usize> {
+        // regions are in pre-order, so the last match is the innermost one
+        self.regions
+            .iter()
+            .rposition(|region| region.bytes.contains(&offset))
+    }
+
+    fn collect(&mut self, index: &LineIndex, atom: &Atom, path: &mut Vec<usize>, kind: RegionKind) {
+        let children: &[Atom] = match atom {
+            Atom::Group(group) => &group.children,
+            Atom::Neoteric { rhs, .. } => &rhs.children,
+            Atom::Raw { body, .. } => &body.children,
+            Atom::Identifier(_) | Atom::String(_) | Atom::Error(_) => {
+                if kind == RegionKind::Form {
+                    self.push(index, atom, path, kind);
+                }
+                return;
+            }
+        };
+
+        self.push(index, atom, path, kind);
+        for (i, child) in children.iter().enumerate() {
+            path.push(i);
+            self.collect(index, child, path, RegionKind::Group);
+            path.pop();
+        }
+    }
+
+    fn push(&mut self, index: &LineIndex, atom: &Atom, path: &[usize], kind: RegionKind) {
+        let bytes = atom.span().range();
+        let lines = index.lines(&bytes);
+        self.regions.push(Region {
+            kind,
+            path: path.to_vec(),
+            bytes,
+            start_line: lines.start + 1,
+            end_line: lines.end,
+        });
+    }
+}
+
+impl fmt::Display for CoverageMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "id\tkind\tpath\tstart_line\tend_line\tstart_byte\tend_byte"
+        )?;
+        for (id, region) in self.regions.iter().enumerate() {
+            let kind = match region.kind {
+                RegionKind::Form => "form",
+                RegionKind::Group => "group",
+            };
+            let path: Vec<_> = region.path.iter().map(|i| i.to_string()).collect();
+            writeln!(
+                f,
+                "{id}\t{kind}\t{}\t{}\t{}\t{}\t{}",
+                path.join("."),
+                region.start_line,
+                region.end_line,
+                region.bytes.start,
+                region.bytes.end
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenise;
+    use crate::parser::Parser;
+
+    #[test]
+    fn regions_map_to_lines() {
+        let source = "define f(x)\n    (print x)\n\nf(1)\n";
+        let document = Parser::new(tokenise(source)).parse_toplevel().unwrap();
+        let map = CoverageMap::new(source, &document);
+
+        let summary: Vec<_> = map
+            .regions
+            .iter()
+            .map(|r| (r.kind, r.path.clone(), r.start_line, r.end_line))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (RegionKind::Form, vec![0], 1, 2),
+                (RegionKind::Group, vec![0, 1], 1, 1),
+                (RegionKind::Group, vec![0, 2], 2, 2),
+                (RegionKind::Form, vec![1], 4, 4),
+            ]
+        );
+        assert_eq!(&source[map.regions[2].bytes.clone()], "(print x)");
+        assert_eq!(map.region_at(20), Some(2));
+        assert_eq!(map.region_for_span(&(27..31)), Some(3));
+        assert!(map.to_string().starts_with("id\tkind\tpath"));
+    }
+}
@@ -0,0 +1,231 @@
+//! A lossless concrete syntax tree: every byte of the source ends up in
+//! exactly one token, including whitespace, newlines, comments and the exact
+//! indentation, so printing the tree gives back the source unchanged.
+//!
+//! The structure follows the [`Atom`] tree, identifiers and strings are plain
+//! tokens and everything else is a [`Node`]. Tokens that aren't part of an
+//! atom, like the whitespace between two children, belong to the innermost
+//! node around them.
+//!
+//! ```
+//! let source = "define (f x) ; doc\n    g x\n";
+//! let cst = sweet_expr::cst::parse(source);
+//! assert_eq!(cst.root.to_string(), source);
+//! ```
+
+use std::fmt;
+
+use crate::lexer::{tokenise, SpannedToken, Token};
+use crate::parser::{ParseError, Parser, ParserOptions};
+use crate::source_map::Span;
+use crate::value::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Document,
+    Group(GroupType),
+    Neoteric,
+    Raw,
+    /// tokens the recovering parser couldn't make sense of
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node<'src> {
+    pub kind: NodeKind,
+    pub span: Span,
+    pub children: Vec<Element<'src>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Element<'src> {
+    Node(Node<'src>),
+    Token(CstToken<'src>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CstToken<'src> {
+    pub token: Token<'src>,
+    pub text: &'src str,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct Cst<'src> {
+    pub root: Node<'src>,
+    /// the tree is built even for broken input, parts the parser gave up on
+    /// are [`NodeKind::Error`] nodes
+    pub errors: Vec<ParseError<'src>>,
+}
+
+pub fn parse(source: &str) -> Cst<'_> {
+    parse_with_options(source, ParserOptions::default())
+}
+
+pub fn parse_with_options(source: &str, options: ParserOptions) -> Cst<'_> {
+    let (atoms, errors) =
+        Parser::with_options(tokenise(source), options.recover(true)).parse_toplevel_recovering();
+
+    let mut tokens = Tokens {
+        source,
+        inner: tokenise(source).peekable(),
+        pos: 0,
+    };
+    let span = Span::new(0, source.len());
+    let root = node(NodeKind::Document, span, atoms.iter(), &mut tokens);
+    Cst { root, errors }
+}
+
+impl<'src> Node<'src> {
+    /// All tokens below this node, in source order
+    pub fn tokens(&self) -> impl Iterator<Item = &CstToken<'src>> {
+        let mut stack = vec![self.children.iter()];
+        std::iter::from_fn(move || loop {
+            let children = stack.last_mut()?;
+            match children.next() {
+                Some(Element::Token(token)) => return Some(token),
+                Some(Element::Node(node)) => stack.push(node.children.iter()),
+                None => _ = stack.pop(),
+            }
+        })
+    }
+}
+
+/// The source text of the node, byte for byte
+impl fmt::Display for Node<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.tokens().try_for_each(|token| f.write_str(token.text))
+    }
+}
+
+// the raw tokens, with any bytes the lexer skipped turned into error tokens
+struct Tokens<'src, I: Iterator<Item = SpannedToken<'src>>> {
+    source: &'src str,
+    inner: std::iter::Peekable<I>,
+    pos: usize,
+}
+
+impl<'src, I: Iterator<Item = SpannedToken<'src>>> Tokens<'src, I> {
+    fn peek_start(&mut self) -> Option<usize> {
+        match self.inner.peek() {
+            Some((_, span)) => Some((span.start as usize).min(self.pos)),
+            None => (self.pos < self.source.len()).then_some(self.pos),
+        }
+    }
+
+    fn gap_end(&mut self) -> Option<usize> {
+        let next = self
+            .inner
+            .peek()
+            .map_or(self.source.len(), |(_, span)| span.start as usize);
+        (next > self.pos).then_some(next)
+    }
+
+    fn next(&mut self) -> Option<CstToken<'src>> {
+        let (token, span) = match self.gap_end() {
+            Some(end) => (Token::Error("invalid token"), Span::new(self.pos, end)),
+            None => self.inner.next()?,
+        };
+        self.pos = span.end as usize;
+        Some(CstToken {
+            token,
+            text: &self.source[span],
+            span,
+        })
+    }
+}
+
+fn node<'a, 'src: 'a, I>(
+    kind: NodeKind,
+    span: Span,
+    atoms: impl Iterator<Item = &'a Atom<'src>>,
+    tokens: &mut Tokens<'src, I>,
+) -> Node<'src>
+where
+    I: Iterator<Item = SpannedToken<'src>>,
+{
+    let mut atoms = atoms.peekable();
+    let mut children = vec![];
+    while let Some(start) = tokens.peek_start() {
+        if start >= span.end as usize {
+            break;
+        }
+        match atoms.next_if(|atom| start >= atom.span().start as usize) {
+            Some(atom) => children.extend(element(atom, tokens)),
+            None => children.extend(tokens.next().map(Element::Token)),
+        }
+    }
+    // atoms without any text, e.g. errors at the end of the input
+    for atom in atoms {
+        children.extend(element(atom, tokens));
+    }
+    Node {
+        kind,
+        span,
+        children,
+    }
+}
+
+fn element<'src, I>(atom: &Atom<'src>, tokens: &mut Tokens<'src, I>) -> Option<Element<'src>>
+where
+    I: Iterator<Item = SpannedToken<'src>>,
+{
+    let span = atom.span();
+    let node = match atom {
+        Atom::Identifier(_) | Atom::String(_) => return tokens.next().map(Element::Token),
+        Atom::Group(group) => node(
+            NodeKind::Group(group.group_type),
+            span,
+            group.children.iter(),
+            tokens,
+        ),
+        Atom::Neoteric { lhs, rhs } => {
+            let rhs = Atom::Group(rhs.clone());
+            node(NodeKind::Neoteric, span, [&**lhs, &rhs].into_iter(), tokens)
+        }
+        Atom::Raw { body, .. } => {
+            let body = Atom::Group(body.clone());
+            node(NodeKind::Raw, span, std::iter::once(&body), tokens)
+        }
+        Atom::Error(_) => node(NodeKind::Error, span, std::iter::empty(), tokens),
+    };
+    Some(Element::Node(node))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_exactly() {
+        let sources = [
+            "define (f x) ; doc\n\t  g x\r\n\n  {a + b}   \n",
+            "; only a comment",
+            "f(x)[1]{2} #raw(a(b)) \"s\\\"q\"\n",
+            "a b\n  c\n d\n) e \u{b} (\n",
+            "",
+        ];
+        for source in sources {
+            assert_eq!(parse(source).root.to_string(), source);
+        }
+    }
+
+    #[test]
+    fn structure_follows_atoms() {
+        let cst = parse("f(x) ; c\n");
+        let Element::Node(form) = &cst.root.children[0] else {
+            panic!("expected a node, got {:?}", cst.root.children[0]);
+        };
+        assert_eq!(form.kind, NodeKind::Neoteric);
+        assert_eq!(form.to_string(), "f(x)");
+
+        let rest: Vec<_> = cst.root.children[1..]
+            .iter()
+            .map(|element| match element {
+                Element::Token(token) => token.token,
+                Element::Node(node) => panic!("unexpected node {node:?}"),
+            })
+            .collect();
+        assert_eq!(rest, [Token::Spaces(" "), Token::Comment, Token::Newline]);
+    }
+}
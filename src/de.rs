@@ -0,0 +1,651 @@
+//! Deserializing Rust data from sweet-expressions with serde, for using
+//! sweet-expressions as a configuration language.
+//!
+//! - a document, or the elements after the key of a pair, are a sequence,
+//!   except that a single element stands for itself
+//! - structs and maps are sequences of `(key value...)` pairs, `key(value)`
+//!   works too and a key on its own has no value, which is `true` for a
+//!   `bool`
+//! - identifiers that are numbers (see [`crate::number`]) are numbers,
+//!   `true`, `false`, `#t` and `#f` are booleans and any other identifier or
+//!   string can be a string
+//! - groups are sequences, a single `[...]` after a key is the sequence
+//!   instead of its only element
+//! - an identifier is a unit variant, `(variant value...)` or `variant value...`
+//!   after a key is any other variant
+//! - `()` is the unit, a missing value is `None`
+//!
+//! ```
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, Debug, PartialEq)]
+//! struct Config {
+//!     name: String,
+//!     port: u16,
+//!     debug: bool,
+//!     tags: Vec<String>,
+//!     server: Server,
+//! }
+//!
+//! #[derive(Deserialize, Debug, PartialEq)]
+//! struct Server {
+//!     host: String,
+//!     mode: Mode,
+//! }
+//!
+//! #[derive(Deserialize, Debug, PartialEq)]
+//! #[serde(rename_all = "kebab-case")]
+//! enum Mode {
+//!     ReadOnly,
+//!     Limit(u32),
+//! }
+//!
+//! let source = r#"
+//! name "app"
+//! port 8080
+//! debug
+//! tags web api
+//! server
+//!   host "localhost"
+//!   mode limit 10
+//! "#;
+//! let config: Config = sweet_expr::from_str(source).unwrap();
+//! assert_eq!(config.port, 8080);
+//! assert!(config.debug);
+//! assert_eq!(config.tags, ["web", "api"]);
+//! assert_eq!(config.server.mode, Mode::Limit(10));
+//! ```
+
+use std::fmt;
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+
+use crate::diagnostic::{Diagnostic, Label, Severity};
+use crate::number::Number;
+use crate::source_map::Span;
+use crate::value::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub message: String,
+    /// the atom that couldn't be deserialized, if known
+    pub span: Option<Span>,
+}
+
+impl Error {
+    fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span: Some(span),
+        }
+    }
+
+    // errors from serde's visitors don't know where they happened, the
+    // innermost atom around them does
+    fn at(mut self, span: Span) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self {
+            message: msg.to_string(),
+            span: None,
+        }
+    }
+}
+
+impl From<&Error> for Diagnostic {
+    fn from(err: &Error) -> Self {
+        let diagnostic = Diagnostic::new(Severity::Error, "sweet_expr::deserialize", &err.message);
+        match err.span {
+            Some(span) => diagnostic.with_label(Label::primary(span, "while deserializing this")),
+            None => diagnostic,
+        }
+    }
+}
+
+/// Parses `source` and deserializes the document, see the [module docs](self)
+pub fn from_str<T: DeserializeOwned>(source: &str) -> Result<T, Error> {
+    let atoms = crate::parse(source).map_err(|err| Error {
+        message: err.to_string(),
+        span: Diagnostic::from(&err).span().map(Span::from),
+    })?;
+    from_atoms(&atoms)
+}
+
+/// Deserializes an already parsed document
+pub fn from_atoms<T: DeserializeOwned>(atoms: &[Atom]) -> Result<T, Error> {
+    let span = match (atoms.first(), atoms.last()) {
+        (Some(first), Some(last)) => first.span().to(last.span()),
+        _ => Span::default(),
+    };
+    T::deserialize(Deserializer(Value::Rest(atoms, span)))
+}
+
+#[derive(Clone, Copy)]
+enum Value<'a, 'src> {
+    Atom(&'a Atom<'src>),
+    // elements that are a value together, the span is where they are or
+    // would be
+    Rest(&'a [Atom<'src>], Span),
+}
+
+impl Value<'_, '_> {
+    fn span(self) -> Span {
+        match self {
+            Value::Atom(atom) => atom.span(),
+            Value::Rest(_, span) => span,
+        }
+    }
+}
+
+// a neoteric expression counts as the list of its left-hand side and
+// arguments, but that can't be a slice
+fn elements<'a, 'src>(atom: &'a Atom<'src>) -> Option<Vec<&'a Atom<'src>>> {
+    match atom {
+        Atom::Group(group) => Some(group.children.iter().collect()),
+        Atom::Neoteric { lhs, rhs } => Some(std::iter::once(&**lhs).chain(&rhs.children).collect()),
+        _ => None,
+    }
+}
+
+// the key of a pair and the elements after it
+fn pair<'a, 'src>(atom: &'a Atom<'src>) -> Option<(&'a Atom<'src>, Value<'a, 'src>)> {
+    let key_of = |atom: &'a Atom<'src>| (atom.is_identifier() || atom.is_string()).then_some(atom);
+    match atom {
+        Atom::Group(group) => {
+            let (key, rest) = group.children.split_first()?;
+            let span = rest
+                .iter()
+                .map(Atom::span)
+                .reduce(Span::to)
+                .unwrap_or(Span::at(key.span().end as usize));
+            Some((key_of(key)?, Value::Rest(rest, span)))
+        }
+        Atom::Neoteric { lhs, rhs } => Some((key_of(lhs)?, Value::Rest(&rhs.children, rhs.span()))),
+        Atom::Identifier(_) | Atom::String(_) => {
+            Some((atom, Value::Rest(&[], Span::at(atom.span().end as usize))))
+        }
+        _ => None,
+    }
+}
+
+struct Deserializer<'a, 'src>(Value<'a, 'src>);
+
+impl<'a, 'src> Deserializer<'a, 'src> {
+    // the single element of a rest, for anything that isn't a sequence
+    fn single(&self, expected: &str) -> Result<&'a Atom<'src>, Error> {
+        match self.0 {
+            Value::Atom(atom) => Ok(atom),
+            Value::Rest([atom], _) => Ok(atom),
+            Value::Rest(atoms, span) => Err(Error::new(
+                format!("expected {expected}, found {} elements", atoms.len()),
+                span,
+            )),
+        }
+    }
+
+    fn seq<'de, V: Visitor<'de>>(&self, visitor: V) -> Result<V::Value, Error> {
+        let span = self.0.span();
+        let elements = match self.0 {
+            Value::Rest([Atom::Group(group)], _) if group.group_type == GroupType::Bracket => {
+                group.children.iter().collect()
+            }
+            Value::Rest(atoms, _) => atoms.iter().collect(),
+            Value::Atom(atom) => elements(atom).ok_or_else(|| {
+                Error::new(
+                    format!("expected a sequence, found {}", atom.describe()),
+                    span,
+                )
+            })?,
+        };
+        let mut seq = Seq(elements.into_iter());
+        let value = visitor.visit_seq(&mut seq).map_err(|e| e.at(span))?;
+        match seq.0.next() {
+            Some(extra) => Err(Error::new("too many elements", extra.span())),
+            None => Ok(value),
+        }
+    }
+
+    fn map<'de, V: Visitor<'de>>(&self, visitor: V) -> Result<V::Value, Error> {
+        let span = self.0.span();
+        let elements = match self.0 {
+            Value::Rest(atoms, _) => atoms.iter().collect(),
+            Value::Atom(atom) => elements(atom).ok_or_else(|| {
+                Error::new(
+                    format!("expected `(key value)` pairs, found {}", atom.describe()),
+                    span,
+                )
+            })?,
+        };
+        let pairs = Pairs {
+            elements: elements.into_iter(),
+            value: None,
+        };
+        visitor.visit_map(pairs).map_err(|e| e.at(span))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'_, '_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let atom = match self.0 {
+            Value::Rest([atom], _) | Value::Atom(atom) => atom,
+            Value::Rest(..) => return self.seq(visitor),
+        };
+        let span = atom.span();
+        let value = match atom {
+            Atom::Identifier(ident) => match (atom.as_number(), &*ident.0) {
+                (Some(Number::Integer(int)), _) => visitor.visit_i64(int),
+                (Some(Number::Float(float)), _) => visitor.visit_f64(float),
+                (None, "true" | "#t") => visitor.visit_bool(true),
+                (None, "false" | "#f") => visitor.visit_bool(false),
+                (None, ident) => visitor.visit_str(ident),
+            },
+            Atom::String(_) => visitor.visit_str(&atom.unescaped().unwrap()),
+            Atom::Group(_) | Atom::Neoteric { .. } => Deserializer(Value::Atom(atom)).seq(visitor),
+            Atom::Raw { .. } | Atom::Error(_) => {
+                return Err(Error::new(format!("unexpected {}", atom.describe()), span))
+            }
+        };
+        value.map_err(|e| e.at(span))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        // a key on its own is a flag
+        if let Value::Rest([], _) = self.0 {
+            return visitor.visit_bool(true);
+        }
+        let atom = self.single("a boolean")?;
+        match atom.as_identifier() {
+            Some("true" | "#t") => visitor.visit_bool(true),
+            Some("false" | "#f") => visitor.visit_bool(false),
+            _ => Err(Error::new(
+                format!("expected a boolean, found {}", atom.describe()),
+                atom.span(),
+            )),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let atom = self.single("a string")?;
+        let value = match atom {
+            Atom::Identifier(ident) => visitor.visit_str(&ident.0),
+            Atom::String(_) => visitor.visit_str(&atom.unescaped().unwrap()),
+            _ => {
+                return Err(Error::new(
+                    format!("expected a string, found {}", atom.describe()),
+                    atom.span(),
+                ))
+            }
+        };
+        value.map_err(|e: Error| e.at(atom.span()))
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Rest([], _) => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Rest([], _) => visitor.visit_unit(),
+            _ => {
+                let atom = self.single("`()`")?;
+                match atom {
+                    Atom::Group(group) if group.children.is_empty() => visitor.visit_unit(),
+                    _ => Err(Error::new(
+                        format!("expected `()`, found {}", atom.describe()),
+                        atom.span(),
+                    )),
+                }
+            }
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.seq(visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.map(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let (variant, content) = match self.0 {
+            Value::Atom(atom) | Value::Rest([atom], _) => match pair(atom) {
+                Some((variant, content)) if !atom.is_string() => (variant, content),
+                _ => {
+                    return Err(Error::new(
+                        format!("expected a variant, found {}", atom.describe()),
+                        atom.span(),
+                    ))
+                }
+            },
+            // `variant value...` after a key
+            Value::Rest(atoms, span) => match pair_of_rest(atoms) {
+                Some(pair) => pair,
+                None => return Err(Error::new("expected a variant", span)),
+            },
+        };
+        let span = variant.span().to(content.span());
+        visitor
+            .visit_enum(Enum { variant, content })
+            .map_err(|e| e.at(span))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64
+    }
+}
+
+fn pair_of_rest<'a, 'src>(atoms: &'a [Atom<'src>]) -> Option<(&'a Atom<'src>, Value<'a, 'src>)> {
+    let (variant, rest) = atoms.split_first()?;
+    let span = rest.iter().map(Atom::span).reduce(Span::to)?;
+    variant
+        .is_identifier()
+        .then_some((variant, Value::Rest(rest, span)))
+}
+
+struct Seq<'a, 'src>(std::vec::IntoIter<&'a Atom<'src>>);
+
+impl<'de> SeqAccess<'de> for Seq<'_, '_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        let Some(atom) = self.0.next() else {
+            return Ok(None);
+        };
+        seed.deserialize(Deserializer(Value::Atom(atom)))
+            .map(Some)
+            .map_err(|e| e.at(atom.span()))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+struct Pairs<'a, 'src> {
+    elements: std::vec::IntoIter<&'a Atom<'src>>,
+    value: Option<Value<'a, 'src>>,
+}
+
+impl<'de> MapAccess<'de> for Pairs<'_, '_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        let Some(atom) = self.elements.next() else {
+            return Ok(None);
+        };
+        let Some((key, value)) = pair(atom) else {
+            return Err(Error::new(
+                format!("expected a `(key value)` pair, found {}", atom.describe()),
+                atom.span(),
+            ));
+        };
+        self.value = Some(value);
+        seed.deserialize(Deserializer(Value::Atom(key)))
+            .map(Some)
+            .map_err(|e| e.at(key.span()))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let value = self.value.take().expect("next_key_seed is called first");
+        seed.deserialize(Deserializer(value))
+            .map_err(|e| e.at(value.span()))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+struct Enum<'a, 'src> {
+    variant: &'a Atom<'src>,
+    content: Value<'a, 'src>,
+}
+
+impl<'de, 'a, 'src> EnumAccess<'de> for Enum<'a, 'src> {
+    type Error = Error;
+    type Variant = Deserializer<'a, 'src>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Error> {
+        let name = self.variant.as_identifier().unwrap_or_default();
+        let variant = seed
+            .deserialize(name.into_deserializer())
+            .map_err(|e: Error| e.at(self.variant.span()))?;
+        Ok((variant, Deserializer(self.content)))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Deserializer<'_, '_> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.0 {
+            Value::Rest([], _) => Ok(()),
+            value => Err(Error::new(
+                "unexpected value for a unit variant",
+                value.span(),
+            )),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.seq(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.map(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[test]
+    fn scalars() {
+        assert_eq!(from_str::<i32>("42"), Ok(42));
+        assert_eq!(from_str::<f64>("-1.5"), Ok(-1.5));
+        assert_eq!(from_str::<bool>("#f"), Ok(false));
+        assert_eq!(
+            from_str::<String>("\"a \\\"b\\\"\""),
+            Ok("a \"b\"".to_string())
+        );
+        assert_eq!(from_str::<String>("symbol"), Ok("symbol".to_string()));
+        assert_eq!(from_str::<char>("x"), Ok('x'));
+        assert_eq!(from_str::<()>("()"), Ok(()));
+        assert_eq!(from_str::<Option<u8>>(""), Ok(None));
+        assert_eq!(from_str::<Option<u8>>("3"), Ok(Some(3)));
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(from_str::<Vec<i32>>("1\n2\n3\n"), Ok(vec![1, 2, 3]));
+        assert_eq!(from_str::<Vec<i32>>("[1 2 3]"), Ok(vec![1, 2, 3]));
+        assert_eq!(
+            from_str::<Vec<(String, u8)>>("(a 1)\nb(2)\n"),
+            Ok(vec![("a".into(), 1), ("b".into(), 2)])
+        );
+        assert_eq!(
+            from_str::<BTreeMap<String, Vec<u8>>>("a [1 2]\nb 3 4\nc\n"),
+            Ok(BTreeMap::from([
+                ("a".into(), vec![1, 2]),
+                ("b".into(), vec![3, 4]),
+                ("c".into(), vec![])
+            ]))
+        );
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Shape {
+        Point,
+        Circle(f64),
+        Rect(f64, f64),
+        Poly { sides: u8 },
+    }
+
+    #[test]
+    fn enums() {
+        assert_eq!(
+            from_str::<Vec<Shape>>("point\n(circle 1)\nrect(2 3)\n(poly (sides 5))\n"),
+            Ok(vec![
+                Shape::Point,
+                Shape::Circle(1.0),
+                Shape::Rect(2.0, 3.0),
+                Shape::Poly { sides: 5 }
+            ])
+        );
+        assert_eq!(
+            from_str::<BTreeMap<String, Shape>>("a circle 2\nb point\n"),
+            Ok(BTreeMap::from([
+                ("a".into(), Shape::Circle(2.0)),
+                ("b".into(), Shape::Point)
+            ]))
+        );
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[allow(dead_code)]
+    struct Server {
+        host: String,
+        port: u16,
+        #[serde(default)]
+        verbose: bool,
+    }
+
+    #[test]
+    fn errors_point_at_the_atom() {
+        let source = "host \"x\"\nport 70000\n";
+        let err = from_str::<Server>(source).unwrap_err();
+        assert_eq!(&source[err.span.unwrap().range()], "70000");
+        assert!(err.message.contains("70000"), "{}", err.message);
+
+        let source = "host \"x\"\nport 1 2\n";
+        let err = from_str::<Server>(source).unwrap_err();
+        assert_eq!(&source[err.span.unwrap().range()], "1 2");
+
+        let err = from_str::<Server>("host \"x\"\n").unwrap_err();
+        assert_eq!(err.message, "missing field `port`");
+
+        let source = "host x\nport 1\n\"oops\" [\n";
+        let err = from_str::<Server>(source).unwrap_err();
+        assert_eq!(err.span.map(|span| span.start), Some(21));
+
+        let source = "host \"x\"\n#raw(a b)\nport 1\n";
+        let err = from_str::<Server>(source).unwrap_err();
+        assert_eq!(&source[err.span.unwrap().range()], "#raw(a b)");
+    }
+}
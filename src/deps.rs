@@ -0,0 +1,186 @@
+//! Extraction of definitions and the names they refer to, e.g. to compute the
+//! order in which a set of files has to be loaded.
+
+use crate::source_map::Span;
+use crate::value::*;
+
+/// Names of the forms that define something and of the forms that refer to
+/// something defined elsewhere
+#[derive(Debug, Clone)]
+pub struct DepsConfig<'a> {
+    /// `(define name ...)`, `(define (name args...) ...)` and `define name(args...)`
+    /// all define `name`
+    pub definition_forms: &'a [&'a str],
+    /// every identifier or string argument of these forms is a reference,
+    /// e.g. `(import foo bar)` or `(load "file.sweet")`
+    pub reference_forms: &'a [&'a str],
+}
+
+impl Default for DepsConfig<'_> {
+    fn default() -> Self {
+        Self {
+            definition_forms: &["define"],
+            reference_forms: &["import", "load"],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Dependency<'src> {
+    /// index into [`DepGraph::definitions`] of the innermost definition the
+    /// reference appears in, `None` for references outside of any definition
+    pub from: Option<usize>,
+    pub to: Spanned<&'src str>,
+}
+
+#[derive(Debug, Default)]
+pub struct DepGraph<'src> {
+    pub definitions: Vec<Spanned<&'src str>>,
+    pub dependencies: Vec<Dependency<'src>>,
+}
+
+impl<'src> DepGraph<'src> {
+    /// All names referenced from within the definition of `name`
+    pub fn dependencies_of<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a Spanned<&'src str>> + 'a {
+        self.dependencies.iter().filter_map(move |dep| {
+            let from = dep.from?;
+            (self.definitions[from].0 == name).then_some(&dep.to)
+        })
+    }
+
+    /// References that appear outside of any definition, like a top-level
+    /// `(import ...)`
+    pub fn toplevel_dependencies(&self) -> impl Iterator<Item = &Spanned<&'src str>> {
+        self.dependencies
+            .iter()
+            .filter(|dep| dep.from.is_none())
+            .map(|dep| &dep.to)
+    }
+}
+
+/// The names in the graph borrow from `document`
+pub fn extract<'a>(document: &'a [Atom], config: &DepsConfig) -> DepGraph<'a> {
+    let mut graph = DepGraph::default();
+    for atom in document {
+        visit(atom, config, None, &mut graph);
+    }
+    graph
+}
+
+fn visit<'a>(
+    atom: &'a Atom,
+    config: &DepsConfig,
+    mut current: Option<usize>,
+    graph: &mut DepGraph<'a>,
+) {
+    if let Some((head, args)) = form_parts(atom) {
+        if config.definition_forms.contains(&head) {
+            if let Some(name) = args.first().and_then(defined_name) {
+                graph.definitions.push(name);
+                current = Some(graph.definitions.len() - 1);
+            }
+        } else if config.reference_forms.contains(&head) {
+            for arg in args {
+                if let Some(to) = referenced_name(arg) {
+                    graph.dependencies.push(Dependency { from: current, to });
+                }
+            }
+        }
+    }
+
+    match atom {
+        Atom::Identifier(_) | Atom::String(_) | Atom::Raw { .. } | Atom::Error(_) => {}
+        Atom::Group(group) => {
+            for child in &group.children {
+                visit(child, config, current, graph);
+            }
+        }
+        Atom::Neoteric { lhs, rhs } => {
+            visit(lhs, config, current, graph);
+            for child in &rhs.children {
+                visit(child, config, current, graph);
+            }
+        }
+    }
+}
+
+/// Splits a form into its head identifier and its arguments
+fn form_parts<'a, 'src>(atom: &'a Atom<'src>) -> Option<(&'a str, &'a [Atom<'src>])> {
+    match atom {
+        Atom::Group(group) => {
+            let (head, args) = group.children.split_first()?;
+            Some((head.as_identifier()?, args))
+        }
+        Atom::Neoteric { lhs, rhs } => Some((lhs.as_identifier()?, &rhs.children)),
+        _ => None,
+    }
+}
+
+fn defined_name<'a>(atom: &'a Atom) -> Option<Spanned<&'a str>> {
+    match atom {
+        Atom::Identifier(Spanned(name, span, file)) => Some(Spanned(name, *span, *file)),
+        Atom::Group(group) => group.children.first().and_then(defined_name),
+        Atom::Neoteric { lhs, .. } => defined_name(lhs),
+        Atom::String(_) | Atom::Raw { .. } | Atom::Error(_) => None,
+    }
+}
+
+fn referenced_name<'a>(atom: &'a Atom) -> Option<Spanned<&'a str>> {
+    match atom {
+        Atom::Identifier(Spanned(name, span, file)) => Some(Spanned(name, *span, *file)),
+        Atom::String(Spanned(s, span, file)) => {
+            // strip the quotes, the name is what's in between
+            let inner = s.strip_prefix('"')?.strip_suffix('"')?;
+            Some(Spanned(
+                inner,
+                Span {
+                    start: span.start + 1,
+                    end: span.end - 1,
+                },
+                *file,
+            ))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenise;
+    use crate::parser::Parser;
+
+    #[test]
+    fn definitions_and_references() {
+        let source = r#"
+import base
+load "util.sweet"
+
+define (main args)
+    import io
+    run(args)
+
+define helper(x)
+    (import math strings)
+"#;
+        let document = Parser::new(tokenise(source)).parse_toplevel().unwrap();
+        let graph = extract(&document, &DepsConfig::default());
+
+        let defs: Vec<_> = graph.definitions.iter().map(|def| def.0).collect();
+        assert_eq!(defs, ["main", "helper"]);
+
+        let toplevel: Vec<_> = graph.toplevel_dependencies().map(|dep| dep.0).collect();
+        assert_eq!(toplevel, ["base", "util.sweet"]);
+
+        let util = graph.toplevel_dependencies().nth(1).unwrap();
+        assert_eq!(&source[util.1], "util.sweet");
+
+        let main: Vec<_> = graph.dependencies_of("main").map(|dep| dep.0).collect();
+        assert_eq!(main, ["io"]);
+        let helper: Vec<_> = graph.dependencies_of("helper").map(|dep| dep.0).collect();
+        assert_eq!(helper, ["math", "strings"]);
+    }
+}
@@ -0,0 +1,775 @@
+//! Diagnostics and their rendering.
+//!
+//! # Stability of rendered messages
+//!
+//! Output rendered with [`RenderOptions::stable`] is treated as part of the
+//! public API, so test suites can assert on it:
+//!
+//! - it never contains color codes and doesn't depend on the terminal
+//! - error codes (like `sweet_expr::mismatched_token`) are never reused for
+//!   something else or removed in a patch release
+//! - the wording or layout only changes in minor releases, and every
+//!   change shows up as a diff of the snapshot corpus in
+//!   `tests/snapshots/errors`, which is shipped with the crate
+//!
+//! Everything else, including the default render mode, can change at any time.
+
+use std::{fmt::Write, ops::Range};
+
+use crate::lexer::Token;
+use crate::parser::{Expected, ParseError};
+use crate::refactor::{apply_edits, TextEdit};
+use crate::source_map::LineIndex;
+
+/// A span of the source with a short explanation attached to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Range<usize>,
+    pub message: String,
+    /// the primary label points at the cause of the error, secondary ones
+    /// only add context
+    pub primary: bool,
+}
+
+impl Label {
+    pub fn primary(span: impl Into<Range<usize>>, message: impl Into<String>) -> Self {
+        Self {
+            span: span.into(),
+            message: message.into(),
+            primary: true,
+        }
+    }
+
+    pub fn secondary(span: impl Into<Range<usize>>, message: impl Into<String>) -> Self {
+        Self {
+            span: span.into(),
+            message: message.into(),
+            primary: false,
+        }
+    }
+}
+
+/// Edits to the source that take care of what a diagnostic is about, for
+/// editors and [`apply_fixes`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    /// what the fix does, like "replace `]` with `)`"
+    pub message: String,
+    pub edits: Vec<TextEdit>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// purely informational, nothing is necessarily wrong
+    Note,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// A message about the source, e.g. a warning for something that parses but
+/// looks suspicious
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// A stable, machine-readable identifier, like `sweet_expr::lone_atom`
+    pub code: &'static str,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub help: Option<String>,
+    /// only for problems with an obvious solution, the first fix is the
+    /// most likely one
+    pub fixes: Vec<Fix>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code,
+            message: message.into(),
+            labels: vec![],
+            help: None,
+            fixes: vec![],
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    pub fn note(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Note, code, message)
+    }
+
+    pub fn with_label(mut self, label: Label) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn with_fix(
+        mut self,
+        message: impl Into<String>,
+        edits: impl IntoIterator<Item = TextEdit>,
+    ) -> Self {
+        self.fixes.push(Fix {
+            message: message.into(),
+            edits: edits.into_iter().collect(),
+        });
+        self
+    }
+
+    /// The span of the first primary label
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.labels
+            .iter()
+            .find(|label| label.primary)
+            .map(|label| label.span.clone())
+    }
+
+    /// Same output format as [`ParseError::render`]
+    pub fn render(&self, source: &str) -> String {
+        self.render_with(source, &RenderOptions::default())
+    }
+
+    pub fn render_with(&self, source: &str, options: &RenderOptions) -> String {
+        let snippet = Snippet {
+            severity: self.severity,
+            code: self.code,
+            message: &self.message,
+            labels: &self.labels,
+            help: self.help.as_deref(),
+        };
+        render_snippet(source, &snippet, options)
+    }
+}
+
+/// Just the message, see [`Diagnostic::render`] for the full report
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+impl From<&ParseError<'_>> for Diagnostic {
+    fn from(err: &ParseError<'_>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: err.code(),
+            message: err.to_string(),
+            labels: err.labels(),
+            help: err.help(),
+            fixes: err.fix().into_iter().collect(),
+        }
+    }
+}
+
+/// `source` with the first fix of every diagnostic applied. Fixes that
+/// overlap one applied before them are left out, running it again takes
+/// care of those.
+pub fn apply_fixes(source: &str, diagnostics: &[Diagnostic]) -> String {
+    let mut edits: Vec<TextEdit> = vec![];
+    for fix in diagnostics.iter().filter_map(|d| d.fixes.first()) {
+        let overlaps = fix.edits.iter().any(|edit| {
+            edits.iter().any(|other| {
+                // two insertions at the same place would be applied in
+                // either order
+                edit.span.start < other.span.end && other.span.start < edit.span.end
+                    || edit.span.start == other.span.start
+            })
+        });
+        if !overlaps {
+            edits.extend(fix.edits.iter().cloned());
+        }
+    }
+    apply_edits(source, &edits)
+}
+
+impl ParseError<'_> {
+    /// A stable, machine-readable identifier for this kind of error
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::MismatchedToken { .. } => "sweet_expr::mismatched_token",
+            ParseError::ExpectedTokFoundEof { .. } => "sweet_expr::unexpected_eof",
+            ParseError::ExpectedEofFoundToken { .. } => "sweet_expr::trailing_token",
+            ParseError::MismatchedDelimiter { .. } => "sweet_expr::mismatched_delimiter",
+            ParseError::UnclosedGroup { .. } => "sweet_expr::unclosed_group",
+            ParseError::TooDeep { .. } => "sweet_expr::too_deep",
+            ParseError::ReaderMacro { .. } => "sweet_expr::reader_macro",
+            ParseError::TooLong { .. } => "sweet_expr::too_long",
+        }
+    }
+
+    pub fn help(&self) -> Option<String> {
+        match self {
+            ParseError::MismatchedToken {
+                found: Token::Error(msg),
+                ..
+            }
+            | ParseError::ExpectedEofFoundToken {
+                found: Token::Error(msg),
+                ..
+            } => Some(error_token_help(msg).to_string()),
+            ParseError::MismatchedToken {
+                expected: Expected::Token(tok),
+                ..
+            } => Some(format!("try inserting {tok} before this")),
+            ParseError::ExpectedTokFoundEof {
+                expected: Expected::Token(tok),
+                ..
+            } => Some(format!(
+                "the input ends before the group is closed with {tok}"
+            )),
+            ParseError::ExpectedEofFoundToken {
+                found: Token::ParenClose | Token::BracketClose | Token::CurlyClose,
+                ..
+            } => Some("this closing delimiter has no matching opener".to_string()),
+            ParseError::MismatchedDelimiter {
+                expected, found, ..
+            } => Some(format!("replace {found} with {expected}")),
+            ParseError::UnclosedGroup { group_type, .. } => Some(format!(
+                "the input ends before the group is closed with `{}`",
+                group_type.delimiters().1
+            )),
+            ParseError::TooDeep { .. } => {
+                Some("the limit can be raised with `Parser::set_max_depth`".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// The edit that most likely repairs the error, for the errors where
+    /// there is an obvious one
+    pub fn fix(&self) -> Option<Fix> {
+        let (message, span, replacement) = match self {
+            ParseError::MismatchedDelimiter {
+                expected,
+                found,
+                span,
+                ..
+            } => (
+                format!("replace {found} with {expected}"),
+                span.range(),
+                closing_delimiter(*expected)?,
+            ),
+            ParseError::ExpectedTokFoundEof {
+                expected: Expected::Token(tok),
+                pos: Some(pos),
+            } => (
+                format!("insert {tok}"),
+                *pos..*pos,
+                closing_delimiter(*tok)?,
+            ),
+            ParseError::UnclosedGroup {
+                group_type, end, ..
+            } => {
+                let close = group_type.delimiters().1;
+                (format!("close the group with `{close}`"), *end..*end, close)
+            }
+            _ => return None,
+        };
+        Some(Fix {
+            message,
+            edits: vec![TextEdit {
+                span,
+                replacement: replacement.to_string(),
+            }],
+        })
+    }
+
+    /// All source locations relevant to this error, primary label first
+    pub fn labels(&self) -> Vec<Label> {
+        match self {
+            ParseError::MismatchedToken { expected, span, .. } => {
+                vec![Label::primary(*span, format!("expected {expected}"))]
+            }
+            ParseError::ExpectedTokFoundEof { expected, pos } => pos
+                .iter()
+                .map(|&pos| Label::primary(pos..pos, format!("expected {expected}")))
+                .collect(),
+            ParseError::ExpectedEofFoundToken { span, .. } => {
+                vec![Label::primary(*span, "unexpected token")]
+            }
+            ParseError::MismatchedDelimiter {
+                expected,
+                open_span,
+                span,
+                ..
+            } => vec![
+                Label::primary(*span, format!("expected {expected}")),
+                Label::secondary(*open_span, "group opened here"),
+            ],
+            ParseError::UnclosedGroup { open_span, .. } => {
+                vec![Label::primary(*open_span, "this group is never closed")]
+            }
+            ParseError::TooDeep { span, .. } => {
+                vec![Label::primary(*span, "nested too deeply")]
+            }
+            ParseError::ReaderMacro { span, .. } => {
+                vec![Label::primary(*span, "in this reader macro")]
+            }
+            ParseError::TooLong { .. } => vec![],
+        }
+    }
+}
+
+fn closing_delimiter(token: Token) -> Option<&'static str> {
+    match token {
+        Token::ParenClose => Some(")"),
+        Token::BracketClose => Some("]"),
+        Token::CurlyClose => Some("}"),
+        _ => None,
+    }
+}
+
+impl ParseError<'_> {
+    /// Renders the error together with an excerpt of `source` in which all
+    /// labels are underlined, similar to rustc's error output
+    pub fn render(&self, source: &str) -> String {
+        self.render_with(source, &RenderOptions::default())
+    }
+
+    pub fn render_with(&self, source: &str, options: &RenderOptions) -> String {
+        Diagnostic::from(self).render_with(source, options)
+    }
+}
+
+/// Controls how [`ParseError::render_with`] and [`Diagnostic::render_with`]
+/// lay out their output
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RenderOptions {
+    /// highlight the output with ANSI escape codes
+    pub color: bool,
+    /// cut excerpt lines that are longer than this many chars
+    pub max_width: Option<usize>,
+}
+
+impl RenderOptions {
+    /// Plain text, with excerpts cut at 100 chars. Output in this mode is
+    /// covered by the [stability policy](self#stability-of-rendered-messages).
+    pub fn stable() -> Self {
+        Self {
+            color: false,
+            max_width: Some(100),
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+
+fn severity_color(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Note => "\x1b[1;32m",
+        Severity::Warning => "\x1b[1;33m",
+        Severity::Error => "\x1b[1;31m",
+    }
+}
+
+pub(crate) struct Snippet<'a> {
+    pub severity: Severity,
+    pub code: &'a str,
+    pub message: &'a str,
+    pub labels: &'a [Label],
+    pub help: Option<&'a str>,
+}
+
+pub(crate) fn render_snippet(source: &str, snippet: &Snippet, options: &RenderOptions) -> String {
+    let paint = |s: &str, color: &str| {
+        if options.color {
+            format!("{color}{s}{RESET}")
+        } else {
+            s.to_string()
+        }
+    };
+    let index = LineIndex::new(source);
+
+    let mut labels: Vec<_> = snippet
+        .labels
+        .iter()
+        .map(|label| (index.line(label.span.start), label))
+        .collect();
+    labels.sort_by_key(|(line, label)| (*line, label.span.start));
+
+    let gutter = labels
+        .last()
+        .map(|(line, _)| (line + 1).to_string().len())
+        .unwrap_or(1);
+    let bar = paint("|", BLUE);
+    let severity_color = severity_color(snippet.severity);
+
+    let mut out = String::new();
+    _ = writeln!(
+        out,
+        "{}{}",
+        paint(
+            &format!("{}[{}]", snippet.severity.as_str(), snippet.code),
+            severity_color
+        ),
+        paint(&format!(": {}", snippet.message), BOLD)
+    );
+
+    if let Some((_, primary)) = labels.iter().find(|(_, label)| label.primary) {
+        let (line, col) = index.line_col(source, primary.span.start);
+        _ = writeln!(
+            out,
+            "{:gutter$}{} {}:{}",
+            "",
+            paint("-->", BLUE),
+            line + 1,
+            col + 1
+        );
+    }
+
+    if !labels.is_empty() {
+        _ = writeln!(out, "{:gutter$} {bar}", "");
+    }
+
+    let max_width = options.max_width.unwrap_or(usize::MAX);
+    let mut prev_line = None;
+    for (line, label) in &labels {
+        let line_span = index.line_span(*line);
+        let text = source[line_span.clone()].trim_end_matches(['\n', '\r']);
+
+        if prev_line != Some(*line) {
+            if prev_line.is_some_and(|prev| prev + 1 < *line) {
+                _ = writeln!(out, "{:gutter$} {}", "", paint("...", BLUE));
+            }
+            let shown = match text.char_indices().nth(max_width) {
+                Some((cut, _)) => format!("{}...", &text[..cut]),
+                None => text.to_string(),
+            };
+            let number = paint(&format!("{:>gutter$}", line + 1), BLUE);
+            _ = writeln!(out, "{number} {bar} {shown}");
+            prev_line = Some(*line);
+        }
+
+        // the underline stops at the end of the first line of the label
+        let start = label.span.start.min(line_span.start + text.len());
+        let end = label.span.end.clamp(start, line_span.start + text.len());
+        let offset = source[line_span.start..start]
+            .chars()
+            .count()
+            .min(max_width);
+        let width = source[start..end]
+            .chars()
+            .count()
+            .min(max_width - offset)
+            .max(1);
+        let (marker, color) = if label.primary {
+            ("^", severity_color)
+        } else {
+            ("-", BLUE)
+        };
+
+        let underline = if label.message.is_empty() {
+            marker.repeat(width)
+        } else {
+            format!("{} {}", marker.repeat(width), label.message)
+        };
+        _ = writeln!(
+            out,
+            "{:gutter$} {bar} {:offset$}{}",
+            "",
+            "",
+            paint(&underline, color)
+        );
+    }
+
+    if let Some(help) = snippet.help {
+        _ = writeln!(out, "{:gutter$} {} {help}", "", paint("= help:", BOLD));
+    }
+
+    out
+}
+
+fn error_token_help(msg: &str) -> &'static str {
+    match msg {
+        "invalid indentation" => "dedent to the indentation of an enclosing line",
+        _ => "this is not valid sweet-expression syntax",
+    }
+}
+
+#[cfg(feature = "codespan-reporting")]
+pub mod codespan {
+    //! Conversions to [`codespan_reporting`] diagnostics
+
+    use super::*;
+    use crate::lexer::SpannedToken;
+    use codespan_reporting::diagnostic::{Diagnostic, Label as CodespanLabel};
+
+    fn convert_label<FileId>(file_id: FileId, label: Label) -> CodespanLabel<FileId> {
+        let codespan_label = if label.primary {
+            CodespanLabel::primary(file_id, label.span)
+        } else {
+            CodespanLabel::secondary(file_id, label.span)
+        };
+        codespan_label.with_message(label.message)
+    }
+
+    impl ParseError<'_> {
+        pub fn to_codespan<FileId: Copy>(&self, file_id: FileId) -> Diagnostic<FileId> {
+            Diagnostic::error()
+                .with_message(self)
+                .with_code(self.code())
+                .with_labels_iter(
+                    self.labels()
+                        .into_iter()
+                        .map(|label| convert_label(file_id, label)),
+                )
+                .with_notes_iter(self.help())
+        }
+    }
+
+    /// Creates a diagnostic for every error token in the token stream, which
+    /// covers both invalid characters and invalid indentation
+    pub fn lex_errors<'src, FileId: Copy>(
+        tokens: impl IntoIterator<Item = SpannedToken<'src>>,
+        file_id: FileId,
+    ) -> Vec<Diagnostic<FileId>> {
+        tokens
+            .into_iter()
+            .filter_map(|(tok, span)| match tok {
+                Token::Error(msg) => Some(
+                    Diagnostic::error()
+                        .with_message(msg)
+                        .with_code("sweet_expr::invalid_token")
+                        .with_label(CodespanLabel::primary(file_id, span.range()))
+                        .with_note(error_token_help(msg)),
+                ),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "miette")]
+mod miette_impl {
+    use super::*;
+    use miette::{Diagnostic, LabeledSpan, NamedSource, Report};
+    use std::fmt::Display;
+
+    fn labeled_spans(labels: &[Label]) -> impl Iterator<Item = LabeledSpan> + '_ {
+        labels.iter().map(|label| {
+            let span = (label.span.start, label.span.len());
+            let message = Some(label.message.clone());
+            if label.primary {
+                LabeledSpan::new_primary_with_span(message, span)
+            } else {
+                LabeledSpan::new_with_span(message, span)
+            }
+        })
+    }
+
+    impl super::Diagnostic {
+        /// A [`Report`] that shows the diagnostic in `source`, which is
+        /// called `name` in the output
+        ///
+        /// ```
+        /// use sweet_expr::parser::{Parser, ParserOptions};
+        ///
+        /// let source = "define f(x) \n  g x\n";
+        /// let options = ParserOptions::new().warn_trailing_whitespace(true);
+        /// let mut parser = Parser::with_options(sweet_expr::lexer::tokenise(source), options);
+        /// parser.parse_toplevel().unwrap();
+        /// let report = parser.warnings()[0].clone().into_report("f.sweet", source);
+        /// eprintln!("{report:?}");
+        /// ```
+        pub fn into_report(self, name: impl AsRef<str>, source: impl Into<String>) -> Report {
+            Report::new(self).with_source_code(NamedSource::new(name, source.into()))
+        }
+    }
+
+    impl ParseError<'_> {
+        /// See [`Diagnostic::into_report`](super::Diagnostic::into_report)
+        pub fn to_report(&self, name: impl AsRef<str>, source: impl Into<String>) -> Report {
+            super::Diagnostic::from(self).into_report(name, source)
+        }
+    }
+
+    impl Diagnostic for super::Diagnostic {
+        fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+            Some(Box::new(self.code))
+        }
+
+        fn severity(&self) -> Option<miette::Severity> {
+            Some(match self.severity {
+                Severity::Note => miette::Severity::Advice,
+                Severity::Warning => miette::Severity::Warning,
+                Severity::Error => miette::Severity::Error,
+            })
+        }
+
+        fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+            self.help
+                .as_ref()
+                .map(|help| Box::new(help) as Box<dyn Display>)
+        }
+
+        fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+            Some(Box::new(labeled_spans(&self.labels)))
+        }
+    }
+
+    impl Diagnostic for ParseError<'_> {
+        fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+            Some(Box::new(ParseError::code(self)))
+        }
+
+        fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+            ParseError::help(self).map(|help| Box::new(help) as Box<dyn Display>)
+        }
+
+        fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+            let labels: Vec<_> = labeled_spans(&ParseError::labels(self)).collect();
+            Some(Box::new(labels.into_iter()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenise;
+    use crate::parser::{Parser, ParserOptions};
+
+    #[test]
+    fn unclosed_group_points_at_opener() {
+        let err = Parser::new(tokenise("(a b")).parse_toplevel().unwrap_err();
+
+        assert_eq!(err.code(), "sweet_expr::unclosed_group");
+        assert_eq!(err.labels()[0].span, 0..1);
+        assert!(err.labels()[0].primary);
+        assert!(err.help().is_some());
+    }
+
+    #[test]
+    fn fixes() {
+        let fixed = |source: &str| {
+            let mut parser =
+                Parser::with_options(tokenise(source), ParserOptions::default().recover(true));
+            let (_, errors) = parser.parse_toplevel_recovering();
+            let mut diagnostics: Vec<Diagnostic> = errors.iter().map(Diagnostic::from).collect();
+            diagnostics.extend_from_slice(parser.warnings());
+            apply_fixes(source, &diagnostics)
+        };
+        assert_eq!(fixed("a (b [c)\n"), "a (b [c])\n");
+        // one group at a time, the inner one first
+        assert_eq!(fixed("a (b {c\n"), "a (b {c}\n");
+        assert_eq!(fixed(&fixed("a (b {c\n")), "a (b {c})\n");
+        assert_eq!(fixed("a\n \t b c\n"), "a\n   b c\n");
+
+        let err = Parser::new(tokenise("(a b]")).parse_toplevel().unwrap_err();
+        let fix = err.fix().unwrap();
+        assert_eq!(fix.message, "replace `]` with `)`");
+        assert_eq!(fix.edits[0].span, 4..5);
+    }
+
+    #[test]
+    fn render_underlines_labels() {
+        let source = "define foo\n    (bar baz]\n";
+        let err = Parser::new(tokenise(source)).parse_toplevel().unwrap_err();
+
+        assert_eq!(
+            err.render(source),
+            "\
+error[sweet_expr::mismatched_delimiter]: mismatched closing delimiter: expected `)`, found `]`
+ --> 2:13
+  |
+2 |     (bar baz]
+  |     - group opened here
+  |             ^ expected `)`
+  = help: replace `]` with `)`
+"
+        );
+    }
+
+    #[cfg(feature = "miette")]
+    #[test]
+    fn miette_labels() {
+        use miette::Diagnostic;
+
+        let err = Parser::new(tokenise("a b)")).parse_toplevel().unwrap_err();
+
+        let labels: Vec<_> = Diagnostic::labels(&err).unwrap().collect();
+        assert_eq!(labels.len(), 1);
+        assert_eq!(labels[0].offset(), 3);
+        assert_eq!(labels[0].len(), 1);
+        assert_eq!(
+            Diagnostic::code(&err).unwrap().to_string(),
+            "sweet_expr::trailing_token"
+        );
+    }
+
+    #[cfg(feature = "miette")]
+    #[test]
+    fn miette_reports() {
+        use miette::{NarratableReportHandler, ReportHandler};
+
+        struct Rendered<'a>(&'a miette::Report);
+        impl std::fmt::Display for Rendered<'_> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                NarratableReportHandler::new().debug(self.0.as_ref(), f)
+            }
+        }
+
+        let source = "a\n \t b\n";
+        let mut parser = Parser::new(tokenise(source));
+        parser.parse_toplevel().unwrap();
+        let report = parser.warnings()[0].clone().into_report("a.sweet", source);
+        let rendered = Rendered(&report).to_string();
+        assert!(rendered.starts_with("indentation mixes tabs and spaces"));
+        assert!(rendered.contains("Diagnostic severity: warning"));
+        assert!(rendered.contains("snippet for a.sweet"));
+        assert!(rendered.contains("label at line 2, columns 1 to 3: tabs and spaces"));
+        assert!(rendered.contains("sweet_expr::mixed_indentation"));
+
+        let err = Parser::new(tokenise("a b)")).parse_toplevel().unwrap_err();
+        let rendered = Rendered(&err.to_report("b.sweet", "a b)")).to_string();
+        assert!(rendered.contains("b.sweet"));
+        assert!(rendered.contains("a b)"));
+    }
+
+    #[cfg(feature = "codespan-reporting")]
+    #[test]
+    fn codespan_diagnostics() {
+        use codespan_reporting::diagnostic::LabelStyle;
+
+        let err = Parser::new(tokenise("(a b]")).parse_toplevel().unwrap_err();
+        let diagnostic = err.to_codespan(());
+        assert_eq!(
+            diagnostic.code.as_deref(),
+            Some("sweet_expr::mismatched_delimiter")
+        );
+        assert_eq!(diagnostic.labels[0].style, LabelStyle::Primary);
+        assert_eq!(diagnostic.labels[0].range, 4..5);
+        assert_eq!(diagnostic.labels[1].style, LabelStyle::Secondary);
+        assert_eq!(diagnostic.labels[1].range, 0..1);
+
+        let lex_errors = super::codespan::lex_errors(tokenise("a \"b"), ());
+        assert_eq!(lex_errors.len(), 1);
+        assert_eq!(lex_errors[0].message, "invalid token");
+
+        let indented = crate::parser::handle_whitespace(tokenise("a\n  b\n c"));
+        let lex_errors = super::codespan::lex_errors(indented, ());
+        assert_eq!(lex_errors.len(), 1);
+        assert_eq!(lex_errors[0].message, "invalid indentation");
+    }
+}
@@ -0,0 +1,171 @@
+//! Writes trees as [EDN](https://github.com/edn-format/edn), for handing data
+//! written as sweet-expressions to Clojure.
+//!
+//! Neoteric expressions and indentation are lowered like in
+//! [`Atom::normalize`]. Parentheses are lists, brackets are vectors and curly
+//! groups with an even number of elements are maps, the others are
+//! curly-infix. `:name` is a keyword, `true`, `false` and `nil` are
+//! themselves and `#t` and `#f` are booleans. Identifiers that aren't valid
+//! EDN symbols and `#raw` groups are written as strings, invalid syntax as
+//! `nil`.
+//!
+//! ```
+//! let atoms = sweet_expr::parse("server {:host \"a\" :ports [80 443]}\nlimit {max + 1}\n").unwrap();
+//! let mut out = String::new();
+//! sweet_expr::edn::write_edn_document(&atoms, &mut out).unwrap();
+//! assert_eq!(out, "(server {:host \"a\" :ports [80 443]})\n(limit (+ max 1))\n");
+//! ```
+
+use std::fmt::{self, Write};
+
+use crate::normalize::{DialectConfig, GroupRule};
+use crate::number::Number;
+use crate::value::*;
+
+pub fn write_edn(atom: &Atom, out: &mut impl Write) -> fmt::Result {
+    value(&lowering().normalize(atom.clone()), out)
+}
+
+/// Every form on a line of its own
+pub fn write_edn_document(atoms: &[Atom], out: &mut impl Write) -> fmt::Result {
+    for atom in lowering().normalize_all(atoms.to_vec()) {
+        value(&atom, out)?;
+        out.write_char('\n')?;
+    }
+    Ok(())
+}
+
+// curly groups are kept until it's known if they're maps
+fn lowering() -> DialectConfig {
+    DialectConfig {
+        brackets: GroupRule::Keep,
+        curlies: GroupRule::Keep,
+        ..DialectConfig::default()
+    }
+}
+
+fn value(atom: &Atom, out: &mut impl Write) -> fmt::Result {
+    match atom {
+        Atom::Identifier(ident) => match atom.as_number() {
+            Some(Number::Float(float)) if float.is_nan() => out.write_str("##NaN"),
+            Some(Number::Float(float)) if float.is_infinite() => {
+                out.write_str(if float > 0.0 { "##Inf" } else { "##-Inf" })
+            }
+            Some(number) => write!(out, "{number}"),
+            None => match &*ident.0 {
+                "#t" => out.write_str("true"),
+                "#f" => out.write_str("false"),
+                symbol if is_symbol(symbol.strip_prefix(':').unwrap_or(symbol)) => {
+                    out.write_str(symbol)
+                }
+                text => string(text, out),
+            },
+        },
+        Atom::String(_) => string(&atom.unescaped().unwrap(), out),
+        Atom::Group(group) => match group.group_type {
+            GroupType::Bracket => elements("[", &group.children, "]", out),
+            GroupType::Curly if group.children.len().is_multiple_of(2) => {
+                elements("{", &group.children, "}", out)
+            }
+            GroupType::Curly => value(&DialectConfig::curly_infix().apply_rule(group.clone()), out),
+            GroupType::Parenthesis | GroupType::Indentation => {
+                elements("(", &group.children, ")", out)
+            }
+        },
+        // lowered, only in case they come back
+        Atom::Neoteric { lhs, rhs } => {
+            out.write_char('(')?;
+            value(lhs, out)?;
+            for child in &rhs.children {
+                out.write_char(' ')?;
+                value(child, out)?;
+            }
+            out.write_char(')')
+        }
+        Atom::Raw { .. } => string(&atom.to_string(), out),
+        Atom::Error(_) => out.write_str("nil"),
+    }
+}
+
+fn elements(open: &str, children: &[Atom], close: &str, out: &mut impl Write) -> fmt::Result {
+    out.write_str(open)?;
+    for (i, child) in children.iter().enumerate() {
+        if i > 0 {
+            out.write_char(' ')?;
+        }
+        value(child, out)?;
+    }
+    out.write_str(close)
+}
+
+fn string(text: &str, out: &mut impl Write) -> fmt::Result {
+    out.write_char('"')?;
+    for c in text.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+// EDN symbols start with something that isn't a digit and are made of
+// alphanumerics and a few punctuation chars
+fn is_symbol(text: &str) -> bool {
+    let mut chars = text.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+    let second_is_digit = chars.next().is_some_and(|c| c.is_ascii_digit());
+    let bad_start = match first {
+        '0'..='9' | ':' | '#' => true,
+        '-' | '+' | '.' => second_is_digit,
+        _ => false,
+    };
+    !bad_start
+        && text
+            .chars()
+            .all(|c| c.is_alphanumeric() || ".*+!-_?$%&=<>/:#".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edn(source: &str) -> String {
+        let mut out = String::new();
+        write_edn_document(&crate::parse(source).unwrap(), &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn data() {
+        assert_eq!(
+            edn("[1 -2.5 +inf.0 +nan.0 #t false nil]\n{}\n"),
+            "[1 -2.5 ##Inf ##NaN true false nil]\n{}\n"
+        );
+        assert_eq!(
+            edn("f(x) \"a\\\"b\" 'q x::y 1st\n"),
+            "((f x) \"a\\\"b\" \"'q\" x::y \"1st\")\n"
+        );
+        assert_eq!(
+            edn("{a + b - c} {x} #raw(y z)\n"),
+            "(($nfx$ a + b - c) x \"#raw(y z)\")\n"
+        );
+    }
+
+    #[test]
+    fn single_atom() {
+        let mut out = String::new();
+        write_edn(
+            &crate::parse_one("{:a [1] :b {:c f(2)}}").unwrap(),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(out, "{:a [1] :b {:c (f 2)}}");
+    }
+}
@@ -0,0 +1,392 @@
+//! A C interface, for embedding the parser in programs that aren't written
+//! in Rust. The declarations are in `include/sweet_expr.h`.
+//!
+//! Build the crate as a static or dynamic library with the `ffi` feature,
+//! e.g. `cargo rustc --release --features ffi --crate-type staticlib`.
+//!
+//! A parsed document is a `SweetExprDocument` that owns everything in it.
+//! Nodes are borrowed from their document and stay valid until it is freed
+//! with [`sweet_expr_document_free`]. Text is handed out as a pointer and a
+//! length, it isn't NUL terminated. All functions accept null pointers and
+//! return null or 0 for them.
+
+use std::ffi::c_char;
+use std::ptr;
+
+use crate::diagnostic::Diagnostic;
+use crate::value::*;
+
+/// The owner of a parsed source, opaque to C
+pub struct SweetExprDocument {
+    atoms: Vec<OwnedAtom>,
+    error: Option<(String, u32, u32)>,
+}
+
+/// A node in a [`SweetExprDocument`], opaque to C
+#[repr(transparent)]
+pub struct SweetExprNode(OwnedAtom);
+
+pub const SWEET_EXPR_IDENTIFIER: i32 = 0;
+pub const SWEET_EXPR_STRING: i32 = 1;
+pub const SWEET_EXPR_GROUP: i32 = 2;
+pub const SWEET_EXPR_NEOTERIC: i32 = 3;
+pub const SWEET_EXPR_RAW: i32 = 4;
+pub const SWEET_EXPR_ERROR: i32 = 5;
+
+pub const SWEET_EXPR_PARENTHESIS: i32 = 0;
+pub const SWEET_EXPR_INDENTATION: i32 = 1;
+pub const SWEET_EXPR_CURLY: i32 = 2;
+pub const SWEET_EXPR_BRACKET: i32 = 3;
+
+impl SweetExprNode {
+    fn new(atom: &OwnedAtom) -> *const SweetExprNode {
+        // fine because of `repr(transparent)`
+        (atom as *const OwnedAtom).cast()
+    }
+
+    fn group(&self) -> Option<&Group<'static>> {
+        match &self.0 {
+            Atom::Group(group) => Some(group),
+            Atom::Neoteric { rhs, .. } => Some(rhs),
+            Atom::Raw { body, .. } => Some(body),
+            _ => None,
+        }
+    }
+}
+
+/// Parses `len` bytes of UTF-8 at `source`. The result is never null, even
+/// if the source doesn't parse, see [`sweet_expr_document_error`].
+///
+/// # Safety
+///
+/// `source` must point to `len` readable bytes, or be null if `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn sweet_expr_parse(
+    source: *const c_char,
+    len: usize,
+) -> *mut SweetExprDocument {
+    let bytes: &[u8] = match source.is_null() {
+        true => &[],
+        false => std::slice::from_raw_parts(source.cast(), len),
+    };
+    let document = match std::str::from_utf8(bytes) {
+        Ok(source) => match crate::parse(source) {
+            Ok(atoms) => SweetExprDocument {
+                atoms: atoms.into_iter().map(Atom::into_owned).collect(),
+                error: None,
+            },
+            Err(err) => {
+                let span = Diagnostic::from(&err).span().unwrap_or(0..0);
+                SweetExprDocument {
+                    atoms: vec![],
+                    error: Some((err.to_string(), span.start as u32, span.end as u32)),
+                }
+            }
+        },
+        Err(err) => {
+            let at = err.valid_up_to() as u32;
+            SweetExprDocument {
+                atoms: vec![],
+                error: Some(("the source isn't valid UTF-8".to_string(), at, at + 1)),
+            }
+        }
+    };
+    Box::into_raw(Box::new(document))
+}
+
+/// # Safety
+///
+/// `document` must come from [`sweet_expr_parse`] and not have been freed
+/// before. Its nodes can't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn sweet_expr_document_free(document: *mut SweetExprDocument) {
+    if !document.is_null() {
+        drop(Box::from_raw(document));
+    }
+}
+
+/// The error message if the source didn't parse, null otherwise. The byte
+/// range of the error is written to `start` and `end` unless they are null.
+///
+/// # Safety
+///
+/// `document` must be a live document, `len`, `start` and `end` writable or
+/// null.
+#[no_mangle]
+pub unsafe extern "C" fn sweet_expr_document_error(
+    document: *const SweetExprDocument,
+    len: *mut usize,
+    start: *mut u32,
+    end: *mut u32,
+) -> *const c_char {
+    let Some((message, from, to)) = document.as_ref().and_then(|doc| doc.error.as_ref()) else {
+        return ptr::null();
+    };
+    write(start, *from);
+    write(end, *to);
+    text(message, len)
+}
+
+/// The number of top-level nodes
+///
+/// # Safety
+///
+/// `document` must be a live document or null.
+#[no_mangle]
+pub unsafe extern "C" fn sweet_expr_document_len(document: *const SweetExprDocument) -> usize {
+    document.as_ref().map_or(0, |doc| doc.atoms.len())
+}
+
+/// The top-level node at `index`, null if there are fewer
+///
+/// # Safety
+///
+/// `document` must be a live document or null.
+#[no_mangle]
+pub unsafe extern "C" fn sweet_expr_document_get(
+    document: *const SweetExprDocument,
+    index: usize,
+) -> *const SweetExprNode {
+    match document.as_ref().and_then(|doc| doc.atoms.get(index)) {
+        Some(atom) => SweetExprNode::new(atom),
+        None => ptr::null(),
+    }
+}
+
+/// One of the `SWEET_EXPR_IDENTIFIER`... constants, -1 for null
+///
+/// # Safety
+///
+/// `node` must belong to a live document or be null.
+#[no_mangle]
+pub unsafe extern "C" fn sweet_expr_node_kind(node: *const SweetExprNode) -> i32 {
+    match node.as_ref().map(|node| &node.0) {
+        Some(Atom::Identifier(_)) => SWEET_EXPR_IDENTIFIER,
+        Some(Atom::String(_)) => SWEET_EXPR_STRING,
+        Some(Atom::Group(_)) => SWEET_EXPR_GROUP,
+        Some(Atom::Neoteric { .. }) => SWEET_EXPR_NEOTERIC,
+        Some(Atom::Raw { .. }) => SWEET_EXPR_RAW,
+        Some(Atom::Error(_)) => SWEET_EXPR_ERROR,
+        None => -1,
+    }
+}
+
+/// One of the `SWEET_EXPR_PARENTHESIS`... constants for groups, the
+/// arguments of neoteric expressions and `#raw` bodies, -1 for other nodes
+///
+/// # Safety
+///
+/// `node` must belong to a live document or be null.
+#[no_mangle]
+pub unsafe extern "C" fn sweet_expr_node_group_type(node: *const SweetExprNode) -> i32 {
+    match node.as_ref().and_then(SweetExprNode::group) {
+        Some(group) => match group.group_type {
+            GroupType::Parenthesis => SWEET_EXPR_PARENTHESIS,
+            GroupType::Indentation => SWEET_EXPR_INDENTATION,
+            GroupType::Curly => SWEET_EXPR_CURLY,
+            GroupType::Bracket => SWEET_EXPR_BRACKET,
+        },
+        None => -1,
+    }
+}
+
+/// The number of children of a group, the arguments of a neoteric
+/// expression or the elements of a `#raw` body, 0 for other nodes
+///
+/// # Safety
+///
+/// `node` must belong to a live document or be null.
+#[no_mangle]
+pub unsafe extern "C" fn sweet_expr_node_len(node: *const SweetExprNode) -> usize {
+    node.as_ref()
+        .and_then(SweetExprNode::group)
+        .map_or(0, |group| group.children.len())
+}
+
+/// The child at `index`, see [`sweet_expr_node_len`]
+///
+/// # Safety
+///
+/// `node` must belong to a live document or be null.
+#[no_mangle]
+pub unsafe extern "C" fn sweet_expr_node_child(
+    node: *const SweetExprNode,
+    index: usize,
+) -> *const SweetExprNode {
+    match node
+        .as_ref()
+        .and_then(SweetExprNode::group)
+        .and_then(|group| group.children.get(index))
+    {
+        Some(child) => SweetExprNode::new(child),
+        None => ptr::null(),
+    }
+}
+
+/// The function of a neoteric expression, `f` in `f(x)`, null for other
+/// nodes
+///
+/// # Safety
+///
+/// `node` must belong to a live document or be null.
+#[no_mangle]
+pub unsafe extern "C" fn sweet_expr_node_head(node: *const SweetExprNode) -> *const SweetExprNode {
+    match node.as_ref().map(|node| &node.0) {
+        Some(Atom::Neoteric { lhs, .. }) => SweetExprNode::new(lhs),
+        _ => ptr::null(),
+    }
+}
+
+/// The text of an identifier, a string literal with its quotes or the
+/// marker of a `#raw` group, null for other nodes
+///
+/// # Safety
+///
+/// `node` must belong to a live document or be null, `len` writable or null.
+#[no_mangle]
+pub unsafe extern "C" fn sweet_expr_node_text(
+    node: *const SweetExprNode,
+    len: *mut usize,
+) -> *const c_char {
+    match node.as_ref().map(|node| &node.0) {
+        Some(Atom::Identifier(Spanned(name, ..))) => text(name, len),
+        Some(Atom::String(Spanned(literal, ..))) => text(literal, len),
+        Some(Atom::Raw { marker, .. }) => text(&marker.0, len),
+        _ => ptr::null(),
+    }
+}
+
+/// Copies the contents of a string literal without its quotes and escapes
+/// into `buf` and returns their length. Nothing is written if that's more
+/// than `capacity`, so the caller can retry with a bigger buffer. Returns 0
+/// for nodes that aren't strings.
+///
+/// # Safety
+///
+/// `node` must belong to a live document or be null, `buf` must have room
+/// for `capacity` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn sweet_expr_node_unescaped(
+    node: *const SweetExprNode,
+    buf: *mut c_char,
+    capacity: usize,
+) -> usize {
+    let Some(contents) = node.as_ref().and_then(|node| node.0.unescaped()) else {
+        return 0;
+    };
+    if contents.len() <= capacity && !buf.is_null() {
+        ptr::copy_nonoverlapping(contents.as_ptr(), buf.cast(), contents.len());
+    }
+    contents.len()
+}
+
+/// Writes the byte range of the node in the source to `start` and `end`
+///
+/// # Safety
+///
+/// `node` must belong to a live document or be null, `start` and `end`
+/// writable or null.
+#[no_mangle]
+pub unsafe extern "C" fn sweet_expr_node_span(
+    node: *const SweetExprNode,
+    start: *mut u32,
+    end: *mut u32,
+) {
+    if let Some(node) = node.as_ref() {
+        let span = node.0.span();
+        write(start, span.start);
+        write(end, span.end);
+    }
+}
+
+unsafe fn write<T>(out: *mut T, value: T) {
+    if !out.is_null() {
+        out.write(value);
+    }
+}
+
+unsafe fn text(text: &str, len: *mut usize) -> *const c_char {
+    write(len, text.len());
+    text.as_ptr().cast()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn node_text<'a>(node: *const SweetExprNode) -> &'a str {
+        let mut len = 0;
+        let text = sweet_expr_node_text(node, &mut len);
+        std::str::from_utf8(std::slice::from_raw_parts(text.cast(), len)).unwrap()
+    }
+
+    #[test]
+    fn walk_a_document() {
+        let source = "define f(x) \"a \\\"b\\\"\"\n[1]";
+        unsafe {
+            let doc = sweet_expr_parse(source.as_ptr().cast(), source.len());
+            assert!(sweet_expr_document_error(
+                doc,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut()
+            )
+            .is_null());
+            assert_eq!(sweet_expr_document_len(doc), 2);
+
+            let first = sweet_expr_document_get(doc, 0);
+            assert_eq!(sweet_expr_node_kind(first), SWEET_EXPR_GROUP);
+            assert_eq!(sweet_expr_node_len(first), 3);
+            let call = sweet_expr_node_child(first, 1);
+            assert_eq!(sweet_expr_node_kind(call), SWEET_EXPR_NEOTERIC);
+            assert_eq!(node_text(sweet_expr_node_head(call)), "f");
+            assert_eq!(node_text(sweet_expr_node_child(call, 0)), "x");
+            let (mut start, mut end) = (0, 0);
+            sweet_expr_node_span(call, &mut start, &mut end);
+            assert_eq!(&source[start as usize..end as usize], "f(x)");
+
+            let string = sweet_expr_node_child(first, 2);
+            assert_eq!(node_text(string), "\"a \\\"b\\\"\"");
+            let mut buf = [0u8; 5];
+            assert_eq!(
+                sweet_expr_node_unescaped(string, buf.as_mut_ptr().cast(), 5),
+                5
+            );
+            assert_eq!(&buf, b"a \"b\"");
+            assert_eq!(
+                sweet_expr_node_unescaped(string, buf.as_mut_ptr().cast(), 2),
+                5
+            );
+
+            let list = sweet_expr_document_get(doc, 1);
+            assert_eq!(sweet_expr_node_group_type(list), SWEET_EXPR_BRACKET);
+            assert!(sweet_expr_document_get(doc, 2).is_null());
+            assert!(sweet_expr_node_child(list, 1).is_null());
+            assert_eq!(sweet_expr_node_kind(ptr::null()), -1);
+            sweet_expr_document_free(doc);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        unsafe {
+            let source = "a (";
+            let doc = sweet_expr_parse(source.as_ptr().cast(), source.len());
+            let (mut len, mut start, mut end) = (0, 0, 0);
+            let message = sweet_expr_document_error(doc, &mut len, &mut start, &mut end);
+            assert!(!message.is_null() && len > 0);
+            assert_eq!(sweet_expr_document_len(doc), 0);
+            sweet_expr_document_free(doc);
+
+            let bytes = b"a \xff";
+            let doc = sweet_expr_parse(bytes.as_ptr().cast(), bytes.len());
+            sweet_expr_document_error(doc, &mut len, &mut start, &mut end);
+            assert_eq!((start, end), (2, 3));
+            sweet_expr_document_free(doc);
+
+            let doc = sweet_expr_parse(ptr::null(), 0);
+            assert_eq!(sweet_expr_document_len(doc), 0);
+            sweet_expr_document_free(doc);
+        }
+    }
+}
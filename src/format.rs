@@ -0,0 +1,255 @@
+//! Formatting whole files: every top-level form is laid out by a [`Printer`],
+//! comments and the blank lines between forms are kept.
+//!
+//! The printer doesn't know about comments, so forms with comments inside
+//! are left exactly as they are, as is any form the printer would change the
+//! meaning of. Runs of blank lines between forms are kept up to
+//! [`FormatOptions::max_blank_lines`](crate::printer::FormatOptions::max_blank_lines),
+//! comment lines between forms keep their indentation and comments after a
+//! form stay on its last line.
+//!
+//! ```
+//! use sweet_expr::{format::format, printer::Printer};
+//!
+//! let source = "; helpers\n(define (f x)\n  (g x))\n\n\n\nf 1 ; call it\n";
+//! assert_eq!(
+//!     format(source, &Printer::new()).unwrap(),
+//!     "; helpers\ndefine (f x) (g x)\n\nf 1 ; call it\n"
+//! );
+//! ```
+
+use std::collections::HashMap;
+
+use crate::cst::{self, Element};
+use crate::lexer::Token;
+use crate::normalize::DialectConfig;
+use crate::parser::ParseError;
+use crate::printer::{FormatOptions, Printer};
+use crate::trivia::TopLevelTrivia;
+use crate::value::{Atom, Group, GroupType};
+
+/// The formatted source, ending with a newline unless it's empty
+pub fn format<'src>(source: &'src str, printer: &Printer) -> Result<String, ParseError<'src>> {
+    format_with(source, printer.options(), |atom| printer.print(atom))
+}
+
+// `format` with every form written by `print`
+pub(crate) fn format_with<'src>(
+    source: &'src str,
+    options: &FormatOptions,
+    print: impl Fn(&Atom) -> String,
+) -> Result<String, ParseError<'src>> {
+    let mut atoms = crate::parse(source)?;
+    if options.join_spaced_groups {
+        atoms = atoms
+            .into_iter()
+            .map(|atom| join_spaced_groups(atom, source))
+            .collect();
+    }
+    let max_blank_lines = options.max_blank_lines;
+    let cst = cst::parse(source);
+    // the runs of blank lines by where the line after them starts
+    let blank_lines: HashMap<usize, usize> = TopLevelTrivia::new(source, &atoms)
+        .blank_lines
+        .iter()
+        .map(|run| (run.span.end, run.count.min(max_blank_lines)))
+        .collect();
+
+    let mut out = String::new();
+    let mut forms = atoms.iter();
+    // newlines since the last thing written, `None` before the first
+    let mut newlines: Option<usize> = None;
+    let mut indent = "";
+    let mut line_start = 0;
+    for element in &cst.root.children {
+        let text = match element {
+            Element::Token(token) => match token.token {
+                Token::Newline => {
+                    line_start = token.span.end as usize;
+                    newlines = newlines.map(|n| n + 1);
+                    indent = "";
+                    continue;
+                }
+                Token::Spaces(spaces) => {
+                    indent = spaces;
+                    continue;
+                }
+                Token::Comment => token.text.to_string(),
+                Token::Identifier(_) | Token::String(_) => form(forms.next(), token.text, &print),
+                _ => token.text.to_string(),
+            },
+            Element::Node(node) => {
+                let verbatim = node.to_string();
+                match node.tokens().any(|token| token.token == Token::Comment) {
+                    true => {
+                        forms.next();
+                        verbatim
+                    }
+                    false => form(forms.next(), &verbatim, &print),
+                }
+            }
+        };
+
+        match newlines {
+            Some(0) => out.push(' '),
+            Some(_) => {
+                let blank = blank_lines.get(&line_start).copied().unwrap_or(0);
+                out.extend(std::iter::repeat_n('\n', blank + 1));
+            }
+            None => {}
+        }
+        // comment lines keep their indentation
+        if text.starts_with(';') && newlines != Some(0) {
+            out.push_str(indent);
+        }
+        out.push_str(&text);
+        newlines = Some(0);
+    }
+    if newlines.is_some() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+// `f (x)` turned into `f(x)`, where an identifier is followed by an explicit
+// group after a single space
+fn join_spaced_groups<'src>(atom: Atom<'src>, source: &str) -> Atom<'src> {
+    let join_all = |children: Vec<Atom<'src>>| {
+        let mut out: Vec<Atom<'src>> = Vec::with_capacity(children.len());
+        for child in children {
+            let child = join_spaced_groups(child, source);
+            let spaced = |lhs: &Atom, rhs: &Group| {
+                let end = lhs.span().end as usize;
+                lhs.as_identifier().is_some()
+                    && rhs.group_type != GroupType::Indentation
+                    && rhs.start_delim.1.start as usize == end + 1
+                    && source.get(end..end + 1) == Some(" ")
+            };
+            match (out.last(), child) {
+                (Some(lhs), Atom::Group(rhs)) if spaced(lhs, &rhs) => {
+                    let lhs = out.pop().expect("there's a last element");
+                    out.push(Atom::Neoteric {
+                        lhs: Box::new(lhs),
+                        rhs,
+                    });
+                }
+                (_, child) => out.push(child),
+            }
+        }
+        out
+    };
+    match atom {
+        Atom::Group(mut group) => {
+            group.children = join_all(group.children);
+            // a line of a single element is that element
+            if group.group_type == GroupType::Indentation && group.children.len() == 1 {
+                return group.children.remove(0);
+            }
+            Atom::Group(group)
+        }
+        Atom::Neoteric { lhs, mut rhs } => {
+            rhs.children = join_all(rhs.children);
+            Atom::Neoteric {
+                lhs: Box::new(join_spaced_groups(*lhs, source)),
+                rhs,
+            }
+        }
+        atom => atom,
+    }
+}
+
+// the printed form, or the original text if printing it would change it
+fn form(atom: Option<&Atom>, verbatim: &str, print: &impl Fn(&Atom) -> String) -> String {
+    let Some(atom) = atom else {
+        return verbatim.to_string();
+    };
+    let printed = print(atom);
+    // indentation and parentheses are the same thing
+    let lowered = |atom: &Atom| {
+        DialectConfig::default()
+            .normalize(atom.clone())
+            .into_owned()
+    };
+    match crate::parse(&printed) {
+        Ok(reparsed) if matches!(&reparsed[..], [again] if lowered(again).eq_ignoring_spans(&lowered(atom))) => {
+            printed
+        }
+        _ => verbatim.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_comments_and_blank_lines() {
+        let source = "\
+;; header
+
+
+define f(x) ; short
+  g x
+
+  ; about h
+    ; indented
+h [a
+   b]
+  ; after h
+define g(y)
+  ; inside
+  y
+";
+        assert_eq!(
+            format(source, &Printer::new()).unwrap(),
+            "\
+;; header
+
+define f(x) ; short
+  g x
+
+  ; about h
+    ; indented
+h [a b]
+  ; after h
+define g(y)
+  ; inside
+  y
+"
+        );
+    }
+
+    #[test]
+    fn idempotent() {
+        let source = "a   b\n(c\n d)\n\n\n\"e\"\n";
+        let once = format(source, &Printer::new()).unwrap();
+        assert_eq!(once, "a b\nc d\n\n\"e\"\n");
+        assert_eq!(format(&once, &Printer::new()).unwrap(), once);
+        assert_eq!(format("", &Printer::new()).unwrap(), "");
+        assert!(format("a (", &Printer::new()).is_err());
+    }
+
+    #[test]
+    fn blank_lines() {
+        let source = "a\n\n\n\nb\n\n; c\n\n\n\nd\ne\n  \n\n";
+        let format = |max| format(source, &Printer::new().max_blank_lines(max)).unwrap();
+        assert_eq!(format(0), "a\nb\n; c\nd\ne\n");
+        assert_eq!(format(1), "a\n\nb\n\n; c\n\nd\ne\n");
+        assert_eq!(format(2), "a\n\n\nb\n\n; c\n\n\nd\ne\n");
+        assert_eq!(format(usize::MAX), "a\n\n\n\nb\n\n; c\n\n\n\nd\ne\n");
+    }
+
+    #[test]
+    fn joins_spaced_groups() {
+        let source = "f (x) g  (y) [z]\nh (i (j))\nk {1 + 2}\n";
+        let printer = Printer::new().join_spaced_groups(true);
+        assert_eq!(
+            format(source, &printer).unwrap(),
+            "f(x) g (y) [z]\nh(i(j))\nk{1 + 2}\n"
+        );
+        assert_eq!(
+            format(source, &Printer::new()).unwrap(),
+            "f (x) g (y) [z]\nh (i (j))\nk {1 + 2}\n"
+        );
+    }
+}
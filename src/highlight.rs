@@ -0,0 +1,412 @@
+//! Syntax highlighting, for terminal pagers and documentation generators.
+//!
+//! [`classify`] splits a source into classified pieces, [`to_ansi`] and
+//! [`to_html`] render them. Highlighting only looks at the tokens, so it
+//! works for sources that don't parse as well.
+//!
+//! [`roles`] goes further and looks at the parsed forms, to tell the head of
+//! a form from its arguments and names being defined from names being used.
+//!
+//! ```
+//! use sweet_expr::highlight::to_html;
+//!
+//! assert_eq!(
+//!     to_html("f(1) ; <3"),
+//!     "<span class=\"sx-function\">f</span><span class=\"sx-bracket\">(</span>\
+//!      <span class=\"sx-number\">1</span><span class=\"sx-bracket\">)</span> \
+//!      <span class=\"sx-comment\">; &lt;3</span>"
+//! );
+//! ```
+
+use crate::lexer::{tokenise, Token};
+use crate::number::Number;
+use crate::parser::{Parser, ParserOptions};
+use crate::source_map::Span;
+use crate::value::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    Identifier,
+    /// the identifier right before the `(` of a neoteric call, `f` in `f(x)`
+    Function,
+    Number,
+    String,
+    /// identifiers starting with `#`, like `#t`, `#raw` or `#include`
+    Directive,
+    /// `'`, `` ` ``, `,` and `,@` in front of an identifier
+    Quote,
+    Comment,
+    Bracket,
+    Whitespace,
+    /// something the lexer didn't understand
+    Error,
+}
+
+impl TokenClass {
+    /// The CSS class [`to_html`] uses, like `sx-number`
+    pub fn css_class(self) -> &'static str {
+        match self {
+            TokenClass::Identifier => "sx-identifier",
+            TokenClass::Function => "sx-function",
+            TokenClass::Number => "sx-number",
+            TokenClass::String => "sx-string",
+            TokenClass::Directive => "sx-directive",
+            TokenClass::Quote => "sx-quote",
+            TokenClass::Comment => "sx-comment",
+            TokenClass::Bracket => "sx-bracket",
+            TokenClass::Whitespace => "sx-whitespace",
+            TokenClass::Error => "sx-error",
+        }
+    }
+
+    /// The escape sequence [`to_ansi`] starts the piece with, `None` for
+    /// plain text
+    pub fn ansi_color(self) -> Option<&'static str> {
+        match self {
+            TokenClass::Function => Some("\x1b[34m"),
+            TokenClass::Number => Some("\x1b[36m"),
+            TokenClass::String => Some("\x1b[32m"),
+            TokenClass::Directive | TokenClass::Quote => Some("\x1b[35m"),
+            TokenClass::Comment => Some("\x1b[90m"),
+            TokenClass::Error => Some("\x1b[4;31m"),
+            TokenClass::Identifier | TokenClass::Bracket | TokenClass::Whitespace => None,
+        }
+    }
+}
+
+/// The pieces of `source` in order, together they cover all of it
+pub fn classify(source: &str) -> Vec<(TokenClass, Span)> {
+    let mut pieces = vec![];
+    let mut tokens = tokenise(source).peekable();
+    while let Some((token, span)) = tokens.next() {
+        let class = match token {
+            Token::Identifier(ident) => {
+                let calls = matches!(
+                    tokens.peek(),
+                    Some((Token::ParenOpen | Token::BracketOpen | Token::CurlyOpen, next))
+                        if next.start == span.end
+                );
+                let unquoted = ident.trim_start_matches(['\'', '`', ',', '@']);
+                let quote = ident.len() - unquoted.len();
+                if quote > 0 && unquoted.is_empty() {
+                    TokenClass::Quote
+                } else if quote > 0 {
+                    let start = span.range().start;
+                    pieces.push((TokenClass::Quote, Span::new(start, start + quote)));
+                    let span = Span::new(start + quote, span.range().end);
+                    pieces.push((identifier_class(unquoted, calls), span));
+                    continue;
+                } else {
+                    identifier_class(ident, calls)
+                }
+            }
+            Token::String(_) => TokenClass::String,
+            Token::Comment => TokenClass::Comment,
+            Token::ParenOpen
+            | Token::ParenClose
+            | Token::CurlyOpen
+            | Token::CurlyClose
+            | Token::BracketOpen
+            | Token::BracketClose => TokenClass::Bracket,
+            Token::Newline | Token::Spaces(_) | Token::Indent | Token::Dedent => {
+                TokenClass::Whitespace
+            }
+            Token::Error(_) => TokenClass::Error,
+        };
+        pieces.push((class, span));
+    }
+    pieces
+}
+
+fn identifier_class(ident: &str, calls: bool) -> TokenClass {
+    if Number::parse(ident).is_some() {
+        TokenClass::Number
+    } else if ident.starts_with('#') {
+        TokenClass::Directive
+    } else if calls {
+        TokenClass::Function
+    } else {
+        TokenClass::Identifier
+    }
+}
+
+/// `source` with ANSI color escapes, for terminals
+pub fn to_ansi(source: &str) -> String {
+    let mut out = String::with_capacity(source.len() * 2);
+    for (class, span) in classify(source) {
+        let text = &source[span.range()];
+        match class.ansi_color() {
+            Some(color) => {
+                out.push_str(color);
+                out.push_str(text);
+                out.push_str("\x1b[0m");
+            }
+            None => out.push_str(text),
+        }
+    }
+    out
+}
+
+/// `source` as HTML, every piece but whitespace in a `<span>` with the
+/// [`TokenClass::css_class`] of the piece. It's meant to go into a `<pre>`.
+pub fn to_html(source: &str) -> String {
+    let mut out = String::with_capacity(source.len() * 4);
+    for (class, span) in classify(source) {
+        let text = &source[span.range()];
+        if class == TokenClass::Whitespace {
+            escape_html(text, &mut out);
+            continue;
+        }
+        out.push_str("<span class=\"");
+        out.push_str(class.css_class());
+        out.push_str("\">");
+        escape_html(text, &mut out);
+        out.push_str("</span>");
+    }
+    out
+}
+
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// What an atom does in the form around it, see [`roles`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    /// the identifier a form starts with, `f` in `(f x)` and `f(x)`, and the
+    /// operators of `{a + b}`
+    Head,
+    Argument,
+    /// the name a definition form defines, `f` in `define f(x)`
+    Definition,
+    String,
+    Number,
+    Comment,
+}
+
+/// The forms [`roles`] treats as definitions
+#[derive(Debug, Clone)]
+pub struct RoleConfig<'a> {
+    /// the first argument of these is a [`Role::Definition`], or its head if
+    /// it is a form itself, like in `define (f x)` and `define f(x)`
+    pub definition_forms: &'a [&'a str],
+}
+
+impl Default for RoleConfig<'_> {
+    fn default() -> Self {
+        Self {
+            definition_forms: &["define"],
+        }
+    }
+}
+
+/// The role of every identifier, string and comment in `source`, in source
+/// order. Items in `[...]` are all arguments, brackets usually hold data.
+pub fn roles(source: &str, config: &RoleConfig) -> Vec<(Span, Role)> {
+    let (atoms, _) = Parser::with_options(tokenise(source), ParserOptions::default().recover(true))
+        .parse_toplevel_recovering();
+    let mut out = vec![];
+    for atom in &atoms {
+        role_of(atom, Role::Argument, config, &mut out);
+    }
+    out.extend(
+        tokenise(source)
+            .filter(|(token, _)| *token == Token::Comment)
+            .map(|(_, span)| (span, Role::Comment)),
+    );
+    out.sort_by_key(|(span, _)| span.start);
+    out
+}
+
+fn role_of(atom: &Atom, role: Role, config: &RoleConfig, out: &mut Vec<(Span, Role)>) {
+    match atom {
+        Atom::Identifier(Spanned(ident, span, _)) => match Number::parse(ident) {
+            Some(_) => out.push((*span, Role::Number)),
+            None => out.push((*span, role)),
+        },
+        Atom::String(string) => out.push((string.1, Role::String)),
+        Atom::Group(group) => group_roles(group, config, out),
+        Atom::Neoteric { lhs, rhs } if rhs.group_type == GroupType::Parenthesis => {
+            form_roles(lhs, &rhs.children, config, out)
+        }
+        // `f{x}` and `f[x]` pass the group as it is
+        Atom::Neoteric { lhs, rhs } => {
+            role_of(lhs, Role::Head, config, out);
+            group_roles(rhs, config, out);
+        }
+        Atom::Raw { marker, body } => {
+            out.push((marker.1, Role::Head));
+            for child in &body.children {
+                role_of(child, Role::Argument, config, out);
+            }
+        }
+        Atom::Error(_) => {}
+    }
+}
+
+fn group_roles(group: &Group, config: &RoleConfig, out: &mut Vec<(Span, Role)>) {
+    match group.group_type {
+        GroupType::Parenthesis | GroupType::Indentation => {
+            if let Some((head, args)) = group.children.split_first() {
+                form_roles(head, args, config, out);
+            }
+        }
+        GroupType::Curly if group.children.len() >= 3 => {
+            for (i, child) in group.children.iter().enumerate() {
+                let role = if i % 2 == 1 {
+                    Role::Head
+                } else {
+                    Role::Argument
+                };
+                role_of(child, role, config, out);
+            }
+        }
+        GroupType::Curly | GroupType::Bracket => {
+            for child in &group.children {
+                role_of(child, Role::Argument, config, out);
+            }
+        }
+    }
+}
+
+fn form_roles(head: &Atom, args: &[Atom], config: &RoleConfig, out: &mut Vec<(Span, Role)>) {
+    role_of(head, Role::Head, config, out);
+    let defines = head
+        .as_identifier()
+        .is_some_and(|head| config.definition_forms.contains(&head));
+    for (i, arg) in args.iter().enumerate() {
+        if defines && i == 0 {
+            definition_roles(arg, config, out);
+        } else {
+            role_of(arg, Role::Argument, config, out);
+        }
+    }
+}
+
+// the defined name, the rest of a `(f x)` or `f(x)` are arguments
+fn definition_roles(atom: &Atom, config: &RoleConfig, out: &mut Vec<(Span, Role)>) {
+    let (name, args) = match atom {
+        Atom::Identifier(_) => (atom, &[][..]),
+        Atom::Group(group) if group.group_type == GroupType::Parenthesis => {
+            match group.children.split_first() {
+                Some((name, args)) => (name, args),
+                None => return,
+            }
+        }
+        Atom::Neoteric { lhs, rhs } => (&**lhs, &rhs.children[..]),
+        _ => return role_of(atom, Role::Argument, config, out),
+    };
+    match name {
+        Atom::Identifier(_) => out.push((name.span(), Role::Definition)),
+        _ => definition_roles(name, config, out),
+    }
+    for arg in args {
+        role_of(arg, Role::Argument, config, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes() {
+        let source = "define f(x) ; doc\n  '(a #t \"s\" -1.5 ,@b) f (x) ]";
+        let classes: Vec<_> = classify(source)
+            .into_iter()
+            .filter(|(class, _)| *class != TokenClass::Whitespace)
+            .map(|(class, span)| (class, &source[span.range()]))
+            .collect();
+        use TokenClass::*;
+        assert_eq!(
+            classes,
+            [
+                (Identifier, "define"),
+                (Function, "f"),
+                (Bracket, "("),
+                (Identifier, "x"),
+                (Bracket, ")"),
+                (Comment, "; doc"),
+                (Quote, "'"),
+                (Bracket, "("),
+                (Identifier, "a"),
+                (Directive, "#t"),
+                (String, "\"s\""),
+                (Number, "-1.5"),
+                (Quote, ",@"),
+                (Identifier, "b"),
+                (Bracket, ")"),
+                (Identifier, "f"),
+                (Bracket, "("),
+                (Identifier, "x"),
+                (Bracket, ")"),
+                (Bracket, "]"),
+            ]
+        );
+        let covered: usize = classify(source).iter().map(|(_, span)| span.len()).sum();
+        assert_eq!(covered, source.len());
+    }
+
+    #[test]
+    fn ansi() {
+        assert_eq!(
+            to_ansi("f(2) \"s\""),
+            "\x1b[34mf\x1b[0m(\x1b[36m2\x1b[0m) \x1b[32m\"s\"\x1b[0m"
+        );
+        assert_eq!(to_ansi("a b\n"), "a b\n");
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        assert_eq!(
+            to_html("\"a&b\""),
+            "<span class=\"sx-string\">&quot;a&amp;b&quot;</span>"
+        );
+    }
+
+    #[test]
+    fn roles_of_atoms() {
+        let source = "define f(x) ; doc\n  g {x + 1} \"s\"\ndefine (h y) [a b]\ndef z 2\n";
+        let found: Vec<_> = roles(source, &RoleConfig::default())
+            .into_iter()
+            .map(|(span, role)| (&source[span.range()], role))
+            .collect();
+        use Role::*;
+        assert_eq!(
+            found,
+            [
+                ("define", Head),
+                ("f", Definition),
+                ("x", Argument),
+                ("; doc", Comment),
+                ("g", Head),
+                ("x", Argument),
+                ("+", Head),
+                ("1", Number),
+                ("\"s\"", String),
+                ("define", Head),
+                ("h", Definition),
+                ("y", Argument),
+                ("a", Argument),
+                ("b", Argument),
+                ("def", Head),
+                ("z", Argument),
+                ("2", Number),
+            ]
+        );
+
+        let config = RoleConfig {
+            definition_forms: &["def"],
+        };
+        let z = source.rfind('z').unwrap();
+        assert!(roles(source, &config).contains(&(Span::new(z, z + 1), Definition)));
+    }
+}
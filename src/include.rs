@@ -0,0 +1,414 @@
+//! Splicing included files into a document, for configurations and programs
+//! split across several files.
+//!
+//! [`Resolver::resolve`] parses a file and replaces every include form in it,
+//! like `include "common.sweet"`, with the forms of the files it names,
+//! recursively. Where the files come from is up to a [`Loader`]. Every file
+//! is added to a [`SourceMap`] and parsed with its own [`FileId`], so the
+//! spans of the spliced atoms point into the file they were written in.
+//!
+//! ```
+//! use std::collections::HashMap;
+//! use sweet_expr::include::Resolver;
+//! use sweet_expr::source_map::SourceMap;
+//!
+//! let loader = HashMap::from([
+//!     ("main.sweet".to_string(), "include \"ports.sweet\"\nhost \"localhost\"\n".to_string()),
+//!     ("ports.sweet".to_string(), "port 8080\n".to_string()),
+//! ]);
+//! let files = SourceMap::new();
+//! let atoms = Resolver::new(loader, &files).resolve("main.sweet").unwrap();
+//! assert_eq!(atoms[0].to_string(), "(port 8080)");
+//! let file = files.get(atoms[0].file().unwrap()).unwrap();
+//! assert_eq!(file.name, "ports.sweet");
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::diagnostic::Diagnostic;
+use crate::lexer::tokenise;
+use crate::parser::{Parser, ParserOptions};
+use crate::source_map::{FileId, SourceMap};
+use crate::value::*;
+
+/// Where included files come from
+pub trait Loader {
+    /// The name of the file `path` refers to when `from` includes it, e.g. a
+    /// path relative to the including file. Files with the same name are the
+    /// same file. `from` is empty for the file resolving starts with.
+    fn resolve(&self, path: &str, from: &str) -> String {
+        let _ = from;
+        path.to_owned()
+    }
+
+    fn load(&mut self, name: &str) -> io::Result<String>;
+}
+
+/// Files in memory, by name
+impl Loader for HashMap<String, String> {
+    fn load(&mut self, name: &str) -> io::Result<String> {
+        self.get(name)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file"))
+    }
+}
+
+/// Reads files from disk, relative paths are relative to the directory of
+/// the including file. Files are named by their canonical path, or with `.`
+/// and `..` taken out if they don't exist, so every file has one name.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsLoader;
+
+impl Loader for FsLoader {
+    fn resolve(&self, path: &str, from: &str) -> String {
+        let path = match Path::new(from).parent() {
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        };
+        let path = std::fs::canonicalize(&path).unwrap_or_else(|_| normalized(&path));
+        path.to_string_lossy().into_owned()
+    }
+
+    fn load(&mut self, name: &str) -> io::Result<String> {
+        std::fs::read_to_string(name)
+    }
+}
+
+// `path` without `.`, and without `..` where there's a directory to go up from
+fn normalized(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(out.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                out.pop();
+            }
+            // `..` of the root is the root
+            Component::ParentDir if out.has_root() => {}
+            component => out.push(component),
+        }
+    }
+    if out.as_os_str().is_empty() {
+        out.push(".");
+    }
+    out
+}
+
+/// Names of the forms that include files. Their arguments are the paths,
+/// as strings or identifiers.
+#[derive(Debug, Clone)]
+pub struct IncludeConfig<'a> {
+    pub include_forms: &'a [&'a str],
+}
+
+impl Default for IncludeConfig<'_> {
+    fn default() -> Self {
+        Self {
+            include_forms: &["include"],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum IncludeError {
+    /// the loader couldn't load `name`, `include` is the form that asked for
+    /// it, `None` for the file resolving started with
+    Load {
+        name: String,
+        include: Option<Spanned<()>>,
+        error: io::Error,
+    },
+    Syntax {
+        file: FileId,
+        diagnostic: Diagnostic,
+    },
+    /// an argument of an include form that isn't a string or identifier
+    BadArgument(Spanned<()>),
+    /// a file includes itself, `chain` goes from that file through the ones
+    /// it includes back to it
+    Cycle {
+        chain: Vec<String>,
+        include: Spanned<()>,
+    },
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IncludeError::Load { name, error, .. } => write!(f, "can't load `{name}`: {error}"),
+            IncludeError::Syntax { diagnostic, .. } => diagnostic.fmt(f),
+            IncludeError::BadArgument(_) => {
+                f.write_str("files to include are named by strings or identifiers")
+            }
+            IncludeError::Cycle { chain, .. } => {
+                write!(f, "`{}` includes itself: {}", chain[0], chain.join(" -> "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for IncludeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IncludeError::Load { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+pub struct Resolver<'a, L> {
+    loader: L,
+    files: &'a SourceMap,
+    config: IncludeConfig<'a>,
+    options: ParserOptions,
+    // files that were already loaded, a file included twice is read once
+    loaded: HashMap<String, FileId>,
+}
+
+impl<'a, L: Loader> Resolver<'a, L> {
+    pub fn new(loader: L, files: &'a SourceMap) -> Self {
+        Self {
+            loader,
+            files,
+            config: IncludeConfig::default(),
+            options: ParserOptions::default(),
+            loaded: HashMap::new(),
+        }
+    }
+
+    pub fn config(mut self, config: IncludeConfig<'a>) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Options every file is parsed with, the file is set for each one
+    pub fn options(mut self, options: ParserOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn loader(&self) -> &L {
+        &self.loader
+    }
+
+    /// The forms of the file `name`, with the includes replaced by the forms
+    /// of the included files. The same file can be included more than once,
+    /// just not from itself.
+    pub fn resolve(&mut self, name: &str) -> Result<Vec<OwnedAtom>, IncludeError> {
+        let name = self.loader.resolve(name, "");
+        self.file(name, None, &mut vec![])
+    }
+
+    fn file(
+        &mut self,
+        name: String,
+        include: Option<Spanned<()>>,
+        stack: &mut Vec<String>,
+    ) -> Result<Vec<OwnedAtom>, IncludeError> {
+        let id = match self.loaded.get(&name) {
+            Some(&id) => id,
+            None => match self.loader.load(&name) {
+                Ok(source) => {
+                    let id = self.files.add(name.clone(), source);
+                    self.loaded.insert(name.clone(), id);
+                    id
+                }
+                Err(error) => {
+                    return Err(IncludeError::Load {
+                        name,
+                        include,
+                        error,
+                    })
+                }
+            },
+        };
+        let file = self.files.get(id).expect("the file was just added");
+        let options = self.options.clone().file(id);
+        let atoms = Parser::with_options(tokenise(&file.source), options)
+            .parse_toplevel()
+            .map_err(|err| IncludeError::Syntax {
+                file: id,
+                diagnostic: Diagnostic::from(&err),
+            })?;
+        let atoms = atoms.into_iter().map(Atom::into_owned).collect();
+
+        stack.push(name);
+        let atoms = self.splice(atoms, stack);
+        stack.pop();
+        atoms
+    }
+
+    // replaces the include forms among `atoms` and everything inside of them
+    fn splice(
+        &mut self,
+        atoms: Vec<OwnedAtom>,
+        stack: &mut Vec<String>,
+    ) -> Result<Vec<OwnedAtom>, IncludeError> {
+        let mut out = Vec::with_capacity(atoms.len());
+        for atom in atoms {
+            if let Some(paths) = self.include_paths(&atom) {
+                for path in paths {
+                    let Spanned(path, span, file) = path?;
+                    let from = stack.last().expect("includes are inside of a file");
+                    let name = self.loader.resolve(&path, from);
+                    let include = Spanned((), span, file);
+                    if let Some(pos) = stack.iter().position(|other| *other == name) {
+                        let mut chain = stack[pos..].to_vec();
+                        chain.push(name);
+                        return Err(IncludeError::Cycle { chain, include });
+                    }
+                    out.extend(self.file(name, Some(include), stack)?);
+                }
+                continue;
+            }
+            out.push(match atom {
+                Atom::Group(mut group) => {
+                    group.children = self.splice(group.children, stack)?;
+                    Atom::Group(group)
+                }
+                Atom::Neoteric { lhs, mut rhs } => {
+                    rhs.children = self.splice(rhs.children, stack)?;
+                    Atom::Neoteric { lhs, rhs }
+                }
+                atom => atom,
+            });
+        }
+        Ok(out)
+    }
+
+    // the paths an include form names, `None` for other atoms
+    fn include_paths(
+        &self,
+        atom: &OwnedAtom,
+    ) -> Option<Vec<Result<Spanned<String>, IncludeError>>> {
+        let (head, args) = match atom {
+            Atom::Group(group) => group.children.split_first()?,
+            Atom::Neoteric { lhs, rhs } => (&**lhs, &rhs.children[..]),
+            _ => return None,
+        };
+        let head = head.as_identifier()?;
+        if !self.config.include_forms.contains(&head) {
+            return None;
+        }
+        let path = |arg: &OwnedAtom| {
+            let span = Spanned((), arg.span(), arg.file());
+            match arg {
+                Atom::Identifier(ident) => Ok(span.map(|()| ident.0.to_string())),
+                Atom::String(_) => Ok(span.map(|()| arg.unescaped().unwrap().into_owned())),
+                _ => Err(IncludeError::BadArgument(span)),
+            }
+        };
+        Some(args.iter().map(path).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_map::Span;
+
+    fn loader(files: &[(&str, &str)]) -> HashMap<String, String> {
+        files
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn splices_included_files() {
+        let loader = loader(&[
+            ("main", "include a b\nserver\n  include \"b\"\n"),
+            ("a", "x 1\ninclude(b)\n"),
+            ("b", "y 2\n"),
+        ]);
+        let files = SourceMap::new();
+        let mut resolver = Resolver::new(loader, &files);
+        let atoms = resolver.resolve("main").unwrap();
+        let text: Vec<_> = atoms.iter().map(ToString::to_string).collect();
+        assert_eq!(text, ["(x 1)", "(y 2)", "(y 2)", "(server (y 2))"]);
+        assert_eq!(files.len(), 3);
+
+        let name = |atom: &OwnedAtom| files.get(atom.file().unwrap()).unwrap().name.clone();
+        assert_eq!(name(&atoms[0]), "a");
+        assert_eq!(name(&atoms[1]), "b");
+        assert_eq!(name(&atoms[3]), "main");
+        let Atom::Group(server) = &atoms[3] else {
+            panic!("expected a group, got {:?}", atoms[3]);
+        };
+        let y = &server.children[1];
+        assert_eq!(name(y), "b");
+        assert_eq!(&*files.get(y.file().unwrap()).unwrap().source, "y 2\n");
+        assert_eq!(y.span(), Span::new(0, 3));
+    }
+
+    #[test]
+    fn errors() {
+        let resolve = |files: &[(&str, &str)]| {
+            Resolver::new(loader(files), &SourceMap::new())
+                .config(IncludeConfig {
+                    include_forms: &["include", "import"],
+                })
+                .resolve("main")
+                .unwrap_err()
+        };
+
+        let err = resolve(&[
+            ("main", "import a\n"),
+            ("a", "include b\n"),
+            ("b", "include a\n"),
+        ]);
+        assert_eq!(err.to_string(), "`a` includes itself: a -> b -> a");
+        let IncludeError::Cycle { include, .. } = err else {
+            panic!("expected a cycle, got {err:?}");
+        };
+        assert_eq!(include.1, Span::new(8, 9));
+
+        let err = resolve(&[("main", "x\ninclude missing\n")]);
+        assert_eq!(err.to_string(), "can't load `missing`: no such file");
+        assert!(
+            matches!(err, IncludeError::Load { include: Some(Spanned((), span, _)), .. } if span == Span::new(10, 17))
+        );
+
+        let err = resolve(&[("main", "include a\n"), ("a", "(x\n")]);
+        assert!(matches!(
+            err,
+            IncludeError::Syntax {
+                file: FileId(1),
+                ..
+            }
+        ));
+        let err = resolve(&[("main", "include (a)\n")]);
+        assert!(matches!(err, IncludeError::BadArgument(_)));
+        let err = resolve(&[]);
+        assert!(matches!(err, IncludeError::Load { include: None, .. }));
+    }
+
+    #[test]
+    fn fs_paths_are_normalized() {
+        let dir = std::env::temp_dir().join(format!("sweet-expr-{}-include", std::process::id()));
+        std::fs::create_dir_all(dir.join("inc")).unwrap();
+        let x = dir.join("inc").join("x.sweet");
+        std::fs::write(&x, "include \"../inc/./x.sweet\"\n").unwrap();
+        let files = SourceMap::new();
+        let err = Resolver::new(FsLoader, &files)
+            .resolve(&x.to_string_lossy())
+            .unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let IncludeError::Cycle { chain, .. } = err else {
+            panic!("expected a cycle, got {err:?}");
+        };
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain[0], chain[1]);
+        assert_eq!(files.len(), 1);
+
+        assert_eq!(normalized(Path::new("a/./b/../../c/d/..")), Path::new("c"));
+        assert_eq!(normalized(Path::new("../a/..")), Path::new(".."));
+        assert_eq!(normalized(Path::new("/../a")), Path::new("/a"));
+        assert_eq!(normalized(Path::new("a/..")), Path::new("."));
+    }
+}
@@ -0,0 +1,435 @@
+//! Reparsing only what an edit touched, for editors that reparse on every
+//! keystroke.
+//!
+//! Top-level forms start on unindented lines and don't depend on each other,
+//! so an [`IncrementalParser`] only reparses the forms an edit overlaps and
+//! the one before it, which an indented line could have become part of. The
+//! forms after them are kept and only moved. Every top-level form has a
+//! [`FormId`] that stays the same as long as the form does, [`Changes`] says
+//! which forms an edit replaced.
+//!
+//! When an edit leaves a group or a string open, it can change how all of
+//! the rest of the source parses, so the whole source is reparsed instead.
+//!
+//! ```
+//! use sweet_expr::incremental::IncrementalParser;
+//! use sweet_expr::refactor::TextEdit;
+//!
+//! let mut parser = IncrementalParser::new("define x 1\n\ndefine y 2\n");
+//! let y = parser.forms().nth(1).unwrap().0;
+//! let changes = parser.edit(TextEdit { span: 9..10, replacement: "42".into() });
+//! assert_eq!(changes.removed.len(), 1);
+//! assert_eq!(changes.added.len(), 1);
+//! // `define y 2` wasn't touched
+//! assert_eq!(parser.forms().nth(1).unwrap().0, y);
+//! assert_eq!(parser.source(), "define x 42\n\ndefine y 2\n");
+//! ```
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{tokenise, Token};
+use crate::parser::{ParseError, Parser, ParserOptions};
+use crate::refactor::TextEdit;
+use crate::source_map::Span;
+use crate::value::*;
+use crate::visit::{walk_group_mut, VisitMut};
+
+/// Identifies a top-level form across edits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FormId(pub u64);
+
+/// What an edit changed, forms that aren't in either list are still there
+/// and only moved
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub removed: Vec<FormId>,
+    /// in source order
+    pub added: Vec<FormId>,
+}
+
+#[derive(Debug, Clone)]
+struct Form {
+    id: FormId,
+    // where the line the form starts on starts
+    line_start: usize,
+    atom: OwnedAtom,
+    // the errors and warnings between the start of this form and the next
+    diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IncrementalParser {
+    source: String,
+    forms: Vec<Form>,
+    options: ParserOptions,
+    next_id: u64,
+}
+
+impl IncrementalParser {
+    pub fn new(source: impl Into<String>) -> Self {
+        Self::with_options(source, ParserOptions::default())
+    }
+
+    pub fn with_options(source: impl Into<String>, options: ParserOptions) -> Self {
+        let mut parser = Self {
+            source: source.into(),
+            forms: vec![],
+            options: options.recover(true),
+            next_id: 0,
+        };
+        parser.forms = parser
+            .parse_window(0..parser.source.len())
+            .unwrap_or_default();
+        parser
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The top-level forms in source order, with errors as [`Atom::Error`]s
+    pub fn forms(&self) -> impl Iterator<Item = (FormId, &OwnedAtom)> {
+        self.forms.iter().map(|form| (form.id, &form.atom))
+    }
+
+    pub fn get(&self, id: FormId) -> Option<&OwnedAtom> {
+        self.forms
+            .iter()
+            .find(|form| form.id == id)
+            .map(|form| &form.atom)
+    }
+
+    /// The parse errors and warnings for the whole source
+    pub fn diagnostics(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.forms.iter().flat_map(|form| &form.diagnostics)
+    }
+
+    /// Applies `edit` to the source and reparses what it touched
+    pub fn edit(&mut self, edit: TextEdit) -> Changes {
+        let TextEdit { span, replacement } = edit;
+        // the forms that are reparsed: the ones starting up to the end of the
+        // edit, from the region before the one the edit starts in
+        let region = |line_start| {
+            self.forms
+                .partition_point(|form: &Form| form.line_start < line_start)
+        };
+        let containing = self
+            .forms
+            .partition_point(|form| form.line_start <= span.start)
+            .saturating_sub(1);
+        let containing = region(self.forms.get(containing).map_or(0, |form| form.line_start));
+        let first = match containing.checked_sub(1) {
+            Some(before) => region(self.forms[before].line_start),
+            None => 0,
+        };
+        let end = self
+            .forms
+            .partition_point(|form| form.line_start <= span.end);
+        let window_start = match first {
+            0 => 0,
+            _ => self.forms[first].line_start,
+        };
+        let window_end = self
+            .forms
+            .get(end)
+            .map_or(self.source.len(), |form| form.line_start);
+
+        self.source.replace_range(span.clone(), &replacement);
+        let delta = replacement.len() as isize - span.len() as isize;
+        let new_end = (window_end as isize + delta) as usize;
+
+        let (range, new_forms) = match self.parse_window(window_start..new_end) {
+            Some(new_forms) => (first..end, new_forms),
+            None => {
+                let all = self.parse_window(0..self.source.len()).unwrap_or_default();
+                (0..self.forms.len(), all)
+            }
+        };
+        for form in &mut self.forms[range.end..] {
+            form.line_start = (form.line_start as isize + delta) as usize;
+            shift(&mut form.atom, delta);
+            shift_diagnostics(&mut form.diagnostics, delta);
+        }
+        self.replace(range, new_forms)
+    }
+
+    // swaps the forms in `range` for `new_forms`, keeping the ids of those
+    // at either end that didn't change
+    fn replace(&mut self, range: Range<usize>, mut new_forms: Vec<Form>) -> Changes {
+        let old = &self.forms[range.clone()];
+        let prefix = old
+            .iter()
+            .zip(&new_forms)
+            .take_while(|(old, new)| old.atom.eq_ignoring_spans(&new.atom))
+            .count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new_forms[prefix..].iter().rev())
+            .take_while(|(old, new)| old.atom.eq_ignoring_spans(&new.atom))
+            .count();
+        for (old, new) in old.iter().zip(&mut new_forms).take(prefix) {
+            new.id = old.id;
+        }
+        let new_len = new_forms.len();
+        for (old, new) in old
+            .iter()
+            .rev()
+            .zip(new_forms.iter_mut().rev())
+            .take(suffix)
+        {
+            new.id = old.id;
+        }
+
+        let changes = Changes {
+            removed: old[prefix..old.len() - suffix]
+                .iter()
+                .map(|form| form.id)
+                .collect(),
+            added: new_forms[prefix..new_len - suffix]
+                .iter()
+                .map(|form| form.id)
+                .collect(),
+        };
+        self.forms.splice(range, new_forms);
+        changes
+    }
+
+    // the forms in `window`, `None` if parsing it on its own could give a
+    // different result than parsing everything
+    fn parse_window(&mut self, window: Range<usize>) -> Option<Vec<Form>> {
+        let text = &self.source[window.clone()];
+        // an unclosed string runs until the next quote after the window
+        let whole = window.start == 0 && window.end == self.source.len();
+        if !whole && tokenise(text).any(|(token, _)| matches!(token, Token::Error(_))) {
+            return None;
+        }
+        let mut parser = Parser::with_options(tokenise(text), self.options.clone());
+        let (atoms, errors) = parser.parse_toplevel_recovering();
+        // including groups closed by the dedent at the end
+        let open_at_end = errors.iter().any(|err| {
+            matches!(
+                err,
+                ParseError::UnclosedGroup { .. } | ParseError::ExpectedTokFoundEof { .. }
+            ) || Diagnostic::from(err)
+                .span()
+                .is_none_or(|span| span.end >= text.len())
+        });
+        if open_at_end && !whole {
+            return None;
+        }
+        let mut diagnostics: Vec<Diagnostic> = errors.iter().map(Diagnostic::from).collect();
+        diagnostics.extend_from_slice(parser.warnings());
+
+        let mut forms: Vec<Form> = vec![];
+        for atom in atoms {
+            let mut atom = atom.into_owned();
+            shift(&mut atom, window.start as isize);
+            let start = atom.span().range().start;
+            // only forms starting on an unindented line start a new region,
+            // the rest are parsed together with the form before them
+            // (an indentation error starts with the indentation)
+            let unindented = (start == 0 || self.source.as_bytes()[start - 1] == b'\n')
+                && !self.source[start..].starts_with([' ', '\t']);
+            let line_start = match forms.last() {
+                Some(form) if !unindented => form.line_start,
+                _ if !unindented => window.start,
+                _ => start,
+            };
+            self.next_id += 1;
+            forms.push(Form {
+                id: FormId(self.next_id),
+                line_start,
+                atom,
+                diagnostics: vec![],
+            });
+        }
+        // blocks closed by the end of the window end at the first token
+        // after it, as they would if everything was parsed
+        let end = Span::at(window.end);
+        let next = tokenise(&self.source[window.end..])
+            .find(|(token, _)| !matches!(token, Token::Spaces(_) | Token::Newline | Token::Comment))
+            .map(|(_, span)| {
+                Span::new(
+                    window.end + span.range().start,
+                    window.end + span.range().end,
+                )
+            });
+        if let (Some(last), Some(next)) = (forms.last_mut(), next) {
+            MapSpans(|span: &mut Span| {
+                if *span == end {
+                    *span = next;
+                }
+            })
+            .visit_atom_mut(&mut last.atom);
+        }
+        shift_diagnostics(&mut diagnostics, window.start as isize);
+        for diagnostic in diagnostics {
+            let start = diagnostic.span().map_or(0, |span| span.start);
+            let owner = forms
+                .partition_point(|form| form.line_start <= start)
+                .saturating_sub(1);
+            match forms.get_mut(owner) {
+                Some(form) => form.diagnostics.push(diagnostic),
+                // nothing parsed, the errors are kept with an error atom
+                None => {
+                    self.next_id += 1;
+                    let span = Span::new(window.start, window.end);
+                    forms.push(Form {
+                        id: FormId(self.next_id),
+                        line_start: window.start,
+                        atom: Atom::Error(Spanned((), span, self.options.file)),
+                        diagnostics: vec![diagnostic],
+                    });
+                }
+            }
+        }
+        Some(forms)
+    }
+}
+
+// calls the closure on every span in a tree
+struct MapSpans<F>(F);
+
+impl<F: FnMut(&mut Span)> VisitMut<'static> for MapSpans<F> {
+    fn visit_group_mut(&mut self, group: &mut Group<'static>) {
+        (self.0)(&mut group.start_delim.1);
+        (self.0)(&mut group.end_delim.1);
+        walk_group_mut(self, group);
+    }
+
+    fn visit_identifier_mut(&mut self, ident: &mut Spanned<Cow<'static, str>>) {
+        (self.0)(&mut ident.1);
+    }
+
+    fn visit_string_mut(&mut self, string: &mut Spanned<Cow<'static, str>>) {
+        (self.0)(&mut string.1);
+    }
+
+    fn visit_raw_mut(
+        &mut self,
+        marker: &mut Spanned<Cow<'static, str>>,
+        body: &mut Group<'static>,
+    ) {
+        (self.0)(&mut marker.1);
+        self.visit_group_mut(body);
+    }
+
+    fn visit_error_mut(&mut self, error: &mut Spanned<()>) {
+        (self.0)(&mut error.1);
+    }
+}
+
+fn shift(atom: &mut OwnedAtom, by: isize) {
+    MapSpans(|span: &mut Span| {
+        let range = span.range();
+        *span = Span::new(
+            (range.start as isize + by) as usize,
+            (range.end as isize + by) as usize,
+        );
+    })
+    .visit_atom_mut(atom);
+}
+
+fn shift_diagnostics(diagnostics: &mut [Diagnostic], by: isize) {
+    let shift = |pos: usize| (pos as isize + by) as usize;
+    for label in diagnostics.iter_mut().flat_map(|d| &mut d.labels) {
+        label.span = shift(label.span.start)..shift(label.span.end);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(parser: &mut IncrementalParser, span: Range<usize>, replacement: &str) -> Changes {
+        let changes = parser.edit(TextEdit {
+            span,
+            replacement: replacement.to_string(),
+        });
+        // the same as parsing everything again, spans included
+        let (atoms, _) = Parser::with_options(tokenise(parser.source()), ParserOptions::default())
+            .parse_toplevel_recovering();
+        let forms: Vec<_> = parser.forms().map(|(_, atom)| atom.clone()).collect();
+        assert_eq!(forms, atoms, "after editing to {:?}", parser.source());
+        changes
+    }
+
+    #[test]
+    fn only_touched_forms_change() {
+        let mut parser = IncrementalParser::new("a 1\nb\n  2\n\n; c\nc 3\nd 4\n");
+        let ids: Vec<_> = parser.forms().map(|(id, _)| id).collect();
+        assert_eq!(ids.len(), 4);
+
+        // `3` becomes `33`, `b` is reparsed too but doesn't change
+        let changes = edit(&mut parser, 17..18, "33");
+        assert_eq!(changes.removed, [ids[2]]);
+        assert_eq!(changes.added.len(), 1);
+        let c = changes.added[0];
+        assert_eq!(parser.get(c).unwrap().to_string(), "(c 33)");
+        assert_eq!(
+            parser.forms().map(|(id, _)| id).collect::<Vec<_>>(),
+            [ids[0], ids[1], c, ids[3]]
+        );
+
+        // indenting `d` makes it part of `c`
+        let changes = edit(&mut parser, 20..20, "  ");
+        assert_eq!(changes.removed, [c, ids[3]]);
+        assert_eq!(parser.forms().count(), 3);
+
+        // a new form at the start
+        let changes = edit(&mut parser, 0..0, "z\n");
+        assert_eq!(changes.removed, []);
+        assert_eq!(changes.added.len(), 1);
+        assert_eq!(parser.forms().nth(1).unwrap().0, ids[0]);
+    }
+
+    #[test]
+    fn open_groups_and_strings_reparse_everything() {
+        let mut parser = IncrementalParser::new("a 1\nb \"x\"\nc 3\n");
+        let changes = edit(&mut parser, 2..3, "(1");
+        assert!(parser.diagnostics().count() > 0);
+        assert_eq!(changes.removed.len(), 3);
+        edit(&mut parser, 2..4, "1");
+        assert_eq!(parser.diagnostics().count(), 0);
+
+        edit(&mut parser, 6..7, "");
+        edit(&mut parser, 6..6, "\"");
+        assert_eq!(parser.forms().count(), 3);
+
+        // the group left open ends where the whole source does
+        let mut parser = IncrementalParser::new(" \".]\n\\( ");
+        edit(&mut parser, 1..3, "f(");
+    }
+
+    #[test]
+    fn random_edits_match_full_parses() {
+        let pieces = [
+            "a", " ", "\n", "  ", "(", ")", "x y", ";", "\n  z", "\"", "\\", "[", "]", "{", "}",
+            "#raw(", "f(", ".", "é", "λ x",
+        ];
+        let mut parser = IncrementalParser::new("f 1\ng\n  2 3\n\nh (4\n   5)\n");
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize
+        };
+        for _ in 0..5000 {
+            let len = parser.source().len();
+            let source = parser.source();
+            let boundary = |mut pos: usize| {
+                while !source.is_char_boundary(pos) {
+                    pos -= 1;
+                }
+                pos
+            };
+            let start = boundary(next() % (len + 1));
+            let end = boundary((start + next() % 3).min(len));
+            edit(&mut parser, start..end, pieces[next() % pieces.len()]);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::sharded::{Sharded, SHARDS, SHARD_BITS};
+
+/// A cheap handle for an interned string, only meaningful for the
+/// [`Interner`] that created it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+#[derive(Debug, Default)]
+struct Shard {
+    ids: HashMap<Arc<str>, Symbol>,
+    strings: Vec<Arc<str>>,
+}
+
+/// Deduplicates identifiers and other strings. It is `Sync`, so one interner
+/// can be shared by all threads of a server; the strings are spread over
+/// several independently locked shards to keep contention low.
+#[derive(Debug, Default)]
+pub struct Interner {
+    shards: Sharded<Shard>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&self, s: &str) -> Symbol {
+        let index = self.shards.index_for(s);
+        if let Some(&sym) = self.shards.read(index).ids.get(s) {
+            return sym;
+        }
+
+        let mut shard = self.shards.write(index);
+        // another thread might have been faster
+        if let Some(&sym) = shard.ids.get(s) {
+            return sym;
+        }
+        // the shard is stored in the low bits, so symbols are unique across
+        // all shards
+        let sym = Symbol(((shard.strings.len() as u32) << SHARD_BITS) | index as u32);
+        let s: Arc<str> = s.into();
+        shard.strings.push(s.clone());
+        shard.ids.insert(s, sym);
+        sym
+    }
+
+    /// The symbol of `s`, if it was interned before
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        let index = self.shards.index_for(s);
+        self.shards.read(index).ids.get(s).copied()
+    }
+
+    pub fn resolve(&self, sym: Symbol) -> Option<Arc<str>> {
+        let index = sym.0 as usize % SHARDS;
+        let local = (sym.0 >> SHARD_BITS) as usize;
+        self.shards.read(index).strings.get(local).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.strings.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_from_many_threads() {
+        let interner = Interner::new();
+        let names = ["define", "lambda", "let", "if", "cond", "begin"];
+
+        let symbols: Vec<Vec<Symbol>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| names.map(|name| interner.intern(name)).to_vec()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert!(symbols.windows(2).all(|w| w[0] == w[1]));
+        assert_eq!(interner.len(), names.len());
+        for (name, sym) in names.iter().zip(&symbols[0]) {
+            assert_eq!(interner.resolve(*sym).as_deref(), Some(*name));
+            assert_eq!(interner.get(name), Some(*sym));
+        }
+    }
+}
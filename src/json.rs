@@ -0,0 +1,212 @@
+//! Lowering trees into JSON, for handing parsed data to `jq`, web UIs and
+//! other JSON tooling.
+//!
+//! Atoms are first lowered with [`JsonOptions::dialect`], so neoteric
+//! expressions and indentation become plain lists. Then:
+//!
+//! - lists are arrays, except for the objects below, and brackets are always
+//!   arrays
+//! - a list of nothing but `(key value)` pairs is an object, keys being
+//!   identifiers or strings
+//! - a list of keywords and values, `(:host "a" :port 1)` or
+//!   `(host: "a" port: 1)`, is an object
+//! - identifiers that are numbers are numbers, `true`, `false`, `#t` and `#f`
+//!   are booleans, other identifiers and strings are strings
+//! - `#raw` groups are written out as strings, errors are `null`
+//!
+//! Later keys replace earlier ones. A document is lowered like a list of its
+//! forms, so a configuration file of `key value` lines is an object.
+//!
+//! ```
+//! use sweet_expr::json::{document_to_json, JsonOptions};
+//!
+//! let atoms = sweet_expr::parse("name \"app\"\nports [80 443]\nserver (:host \"a\" :tls #t)\n").unwrap();
+//! let json = document_to_json(&atoms, &JsonOptions::default());
+//! assert_eq!(
+//!     json.to_string(),
+//!     r#"{"name":"app","ports":[80,443],"server":{"host":"a","tls":true}}"#
+//! );
+//! ```
+
+use serde_json::{Map, Value};
+
+use crate::normalize::{DialectConfig, GroupRule};
+use crate::number::Number;
+use crate::value::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonOptions {
+    /// how atoms are lowered to lists first, brackets are kept and curly
+    /// groups are curly-infix by default
+    pub dialect: DialectConfig,
+    /// lists of `(key value)` pairs are objects
+    pub pairs: bool,
+    /// lists of `:key value` or `key: value` are objects
+    pub keywords: bool,
+    /// numbers and booleans are JSON numbers and booleans instead of strings
+    pub literals: bool,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self {
+            dialect: DialectConfig {
+                brackets: GroupRule::Keep,
+                curlies: GroupRule::CurlyInfix,
+                ..DialectConfig::default()
+            },
+            pairs: true,
+            keywords: true,
+            literals: true,
+        }
+    }
+}
+
+pub fn to_json(atom: &Atom, options: &JsonOptions) -> Value {
+    options.value(&options.dialect.normalize(atom.clone()))
+}
+
+/// Lowers the forms of a document like the elements of a list
+pub fn document_to_json(atoms: &[Atom], options: &JsonOptions) -> Value {
+    options.list(&options.dialect.normalize_all(atoms.to_vec()))
+}
+
+impl Atom<'_> {
+    /// [`to_json`] with the default options
+    pub fn to_json(&self) -> Value {
+        to_json(self, &JsonOptions::default())
+    }
+}
+
+impl JsonOptions {
+    fn value(&self, atom: &Atom) -> Value {
+        match atom {
+            Atom::Identifier(ident) => match (self.literals, atom.as_number(), &*ident.0) {
+                (true, Some(Number::Integer(int)), _) => int.into(),
+                // NaN and infinities are `null`
+                (true, Some(Number::Float(float)), _) => float.into(),
+                (true, None, "true" | "#t") => true.into(),
+                (true, None, "false" | "#f") => false.into(),
+                (_, _, ident) => ident.into(),
+            },
+            Atom::String(_) => atom.unescaped().unwrap().into(),
+            Atom::Group(group) if group.group_type == GroupType::Bracket => {
+                self.array(&group.children)
+            }
+            Atom::Group(group) => self.list(&group.children),
+            // only left if the dialect keeps them
+            Atom::Neoteric { lhs, rhs } => {
+                let mut items = vec![self.value(lhs)];
+                items.extend(rhs.children.iter().map(|child| self.value(child)));
+                Value::Array(items)
+            }
+            Atom::Raw { .. } => atom.to_string().into(),
+            Atom::Error(_) => Value::Null,
+        }
+    }
+
+    fn array(&self, items: &[Atom]) -> Value {
+        items.iter().map(|item| self.value(item)).collect()
+    }
+
+    fn list(&self, items: &[Atom]) -> Value {
+        if items.is_empty() {
+            return Value::Array(vec![]);
+        }
+        if self.pairs {
+            let pairs: Option<Vec<_>> = items.iter().map(pair).collect();
+            if let Some(pairs) = pairs {
+                return self.object(pairs);
+            }
+        }
+        if self.keywords && items.len().is_multiple_of(2) {
+            let pairs: Option<Vec<_>> = items
+                .chunks(2)
+                .map(|chunk| Some((keyword(&chunk[0])?, &chunk[1])))
+                .collect();
+            if let Some(pairs) = pairs {
+                return self.object(pairs);
+            }
+        }
+        self.array(items)
+    }
+
+    fn object<'a>(&self, pairs: impl IntoIterator<Item = (String, &'a Atom<'a>)>) -> Value {
+        let mut object = Map::new();
+        for (key, value) in pairs {
+            object.insert(key, self.value(value));
+        }
+        Value::Object(object)
+    }
+}
+
+fn pair<'a, 'src>(atom: &'a Atom<'src>) -> Option<(String, &'a Atom<'src>)> {
+    match &atom.as_group()?.children[..] {
+        [key @ Atom::Identifier(ident), value] if !key.is_number() => {
+            Some((ident.0.to_string(), value))
+        }
+        [key @ Atom::String(_), value] => Some((key.unescaped()?.into_owned(), value)),
+        _ => None,
+    }
+}
+
+fn keyword(atom: &Atom) -> Option<String> {
+    let ident = atom.as_identifier()?;
+    let name = ident
+        .strip_prefix(':')
+        .or_else(|| ident.strip_suffix(':'))?;
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn lower(source: &str, options: &JsonOptions) -> Value {
+        document_to_json(&crate::parse(source).unwrap(), options)
+    }
+
+    #[test]
+    fn lists_and_objects() {
+        let options = JsonOptions::default();
+        assert_eq!(
+            lower("f(x) {a + 1}\n[]\n()\n#raw(a  b) \"s\\\"\"\n", &options),
+            json!([[["f", "x"], ["+", "a", 1]], [], [], ["#raw(a b)", "s\""]])
+        );
+        assert_eq!(
+            lower(
+                "[(a 1) (b 2)]\n(a 1) (\"b c\" 2.5) (a 3)\n(1 2)\n",
+                &options
+            ),
+            json!([[["a", 1], ["b", 2]], {"a": 3, "b c": 2.5}, [1, 2]])
+        );
+        assert_eq!(
+            lower("a: 1 :b +inf.0", &options),
+            json!([{"a": 1, "b": null}])
+        );
+        assert_eq!(lower(":a 1 :b", &options), json!([[":a", 1, ":b"]]));
+    }
+
+    #[test]
+    fn configurable() {
+        let options = JsonOptions {
+            pairs: false,
+            keywords: false,
+            literals: false,
+            dialect: DialectConfig {
+                brackets: GroupRule::Prefix("bracketaccess".into()),
+                ..DialectConfig::default()
+            },
+        };
+        assert_eq!(
+            lower("x #t\ny [1 :a 2]\n", &options),
+            json!([["x", "#t"], ["y", ["bracketaccess", "1", ":a", "2"]]])
+        );
+        assert_eq!(
+            crate::parse_one("(k v) (j 1)").unwrap().to_json(),
+            json!({"k": "v", "j": 1})
+        );
+    }
+}
@@ -1,8 +1,10 @@
-use std::ops::Range;
+use std::fmt;
 
 use logos::Logos;
 
-#[derive(Logos, Debug, PartialEq, Clone, Copy)]
+use crate::source_map::Span;
+
+#[derive(Logos, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Token<'src> {
     #[regex(r#"[^\s\(\)\{\}\[\]\";]+"#)]
     Identifier(&'src str),
@@ -10,7 +12,7 @@ pub enum Token<'src> {
     #[regex(r#""([^"\\]|\\")*""#)]
     String(&'src str),
 
-    #[regex(r";[^\\n]*")]
+    #[regex(r";[^\n]*")]
     Comment,
 
     #[token("(")]
@@ -26,12 +28,13 @@ pub enum Token<'src> {
     #[token("]")]
     BracketClose,
 
-    #[regex("[ \\t\\f]*\n")]
-    #[regex("[ \\t\\f]*\r\n")]
+    #[regex("\n")]
+    #[regex("\r\n")]
     Newline,
     #[regex(r"[ \t\f]+")]
     Spaces(&'src str),
 
+    /// what went wrong, lowercase so it reads well inside other messages
     Error(&'src str),
 
     // These are only inserted later by the parser when whitespace and comments
@@ -40,10 +43,41 @@ pub enum Token<'src> {
     Dedent,
 }
 
-pub fn tokenise(s: &'_ str) -> impl Iterator<Item = (Token<'_>, Range<usize>)> {
+/// A token together with its byte range in the source, the currency between
+/// all passes up to the parser
+pub type SpannedToken<'src> = (Token<'src>, Span);
+
+/// Spans are 32 bit, so `s` can be at most 4 GiB.
+///
+/// # Panics
+///
+/// If a token ends past 4 GiB. [`crate::parse`] and [`crate::parse_lossy`]
+/// check the length before tokenising.
+pub fn tokenise(s: &'_ str) -> impl Iterator<Item = SpannedToken<'_>> {
     Token::lexer(s)
         .spanned()
-        .map(|(tok, span)| (tok.unwrap_or(Token::Error("Invalid token")), span))
+        .map(|(tok, span)| (tok.unwrap_or(Token::Error("invalid token")), span.into()))
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Identifier(s) => write!(f, "identifier `{s}`"),
+            Token::String(s) => write!(f, "string {s}"),
+            Token::Comment => f.write_str("comment"),
+            Token::ParenOpen => f.write_str("`(`"),
+            Token::ParenClose => f.write_str("`)`"),
+            Token::CurlyOpen => f.write_str("`{`"),
+            Token::CurlyClose => f.write_str("`}`"),
+            Token::BracketOpen => f.write_str("`[`"),
+            Token::BracketClose => f.write_str("`]`"),
+            Token::Newline => f.write_str("newline"),
+            Token::Spaces(_) => f.write_str("whitespace"),
+            Token::Error(msg) => f.write_str(msg),
+            Token::Indent => f.write_str("indentation"),
+            Token::Dedent => f.write_str("dedent"),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -59,4 +93,15 @@ mod tests {
             dbg!(token, span);
         }
     }
+
+    #[test]
+    fn comments_end_at_newlines() {
+        let tokens: Vec<_> = tokenise("; a comment with \\n\nd")
+            .map(|(tok, _)| tok)
+            .collect();
+        assert_eq!(
+            tokens,
+            [Token::Comment, Token::Newline, Token::Identifier("d")]
+        );
+    }
 }
@@ -0,0 +1,122 @@
+//! Converting trees to and from [`lexpr::Value`], for using the crates around
+//! `lexpr` (`serde-lexpr`, its printer, Scheme interop) with sweet syntax.
+//!
+//! Atoms go through [`Atom::to_value`] first, so they are lowered into plain
+//! lists and numbers, booleans, symbols and strings. The way back builds
+//! parenthesized lists with empty spans, the layout is gone. Vectors become
+//! bracket groups and the empty list `()`.
+//!
+//! ```
+//! use sweet_expr::lexpr::{from_lexpr, to_lexpr};
+//!
+//! let atom = sweet_expr::parse_one("server(\"web\" 8080 #t) {a + 1}").unwrap();
+//! let value = to_lexpr(&atom);
+//! assert_eq!(value.to_string(), "((server \"web\" 8080 #t) (+ a 1))");
+//! let atom = from_lexpr(&lexpr::from_str("(a #(1 2.5) \"x\")").unwrap()).unwrap();
+//! assert_eq!(atom.to_string(), "(a [1 2.5] \"x\")");
+//! ```
+
+use std::fmt;
+
+use crate::number::Number;
+use crate::value::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexprError {
+    /// a value sweet-expressions can't write, like a character or a dotted
+    /// list, `what` says which
+    Unsupported { what: &'static str },
+}
+
+impl fmt::Display for LexprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexprError::Unsupported { what } => write!(f, "{what} can't be converted"),
+        }
+    }
+}
+
+impl std::error::Error for LexprError {}
+
+/// The data `atom` stands for, see [`Atom::to_value`]
+pub fn to_lexpr(atom: &Atom) -> lexpr::Value {
+    atom.to_value().into()
+}
+
+impl From<Value> for lexpr::Value {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Symbol(symbol) => lexpr::Value::symbol(symbol),
+            Value::String(string) => lexpr::Value::string(string),
+            Value::Int(int) => lexpr::Value::from(int),
+            Value::Float(float) => lexpr::Value::from(float),
+            Value::Bool(bool) => lexpr::Value::Bool(bool),
+            Value::List(values) => lexpr::Value::list(values.into_iter().map(lexpr::Value::from)),
+        }
+    }
+}
+
+/// A tree writing `value`, with empty spans
+pub fn from_lexpr(value: &lexpr::Value) -> Result<OwnedAtom, LexprError> {
+    let unsupported = |what| Err(LexprError::Unsupported { what });
+    Ok(match value {
+        lexpr::Value::Nil | lexpr::Value::Null => Group::paren([]).into(),
+        lexpr::Value::Bool(true) => Atom::ident("#t"),
+        lexpr::Value::Bool(false) => Atom::ident("#f"),
+        lexpr::Value::Number(number) => match (number.as_i64(), number.as_f64()) {
+            (Some(int), _) => Atom::ident(Number::Integer(int).to_string()),
+            (None, Some(float)) => Atom::ident(Number::Float(float).to_string()),
+            (None, None) => return unsupported("numbers out of range"),
+        },
+        // only quotes can be escaped in string literals
+        lexpr::Value::String(string) => match Atom::try_string(string) {
+            Some(atom) => atom,
+            None => return unsupported("strings with backslashes"),
+        },
+        lexpr::Value::Symbol(symbol) => Atom::ident(symbol.to_string()),
+        lexpr::Value::Cons(cons) => {
+            let (values, tail) = cons.to_ref_vec();
+            if !tail.is_null() {
+                return unsupported("dotted lists");
+            }
+            let children = values.into_iter().map(from_lexpr);
+            Group::paren(children.collect::<Result<Vec<_>, _>>()?).into()
+        }
+        lexpr::Value::Vector(values) => {
+            let children = values.iter().map(from_lexpr);
+            Group::bracket(children.collect::<Result<Vec<_>, _>>()?).into()
+        }
+        lexpr::Value::Char(_) => return unsupported("characters"),
+        lexpr::Value::Keyword(_) => return unsupported("keywords"),
+        lexpr::Value::Bytes(_) => return unsupported("byte vectors"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let atom = crate::parse_one("f(-3 0.5 sym \"q\\\"uote\" #f () [x])").unwrap();
+        let value = to_lexpr(&atom);
+        assert_eq!(value.to_string(), "(f -3 0.5 sym \"q\\\"uote\" #f () (x))");
+        assert_eq!(from_lexpr(&value).unwrap().to_value(), atom.to_value());
+
+        let unsupported = |text| from_lexpr(&lexpr::from_str(text).unwrap()).unwrap_err();
+        assert_eq!(
+            unsupported("(a . b)").to_string(),
+            "dotted lists can't be converted"
+        );
+        assert_eq!(
+            unsupported("#\\a"),
+            LexprError::Unsupported { what: "characters" }
+        );
+        assert_eq!(
+            unsupported("\"a\\\\b\""),
+            LexprError::Unsupported {
+                what: "strings with backslashes"
+            }
+        );
+    }
+}
@@ -0,0 +1,667 @@
+//! Style checks that go beyond what the parser warns about.
+//!
+//! A [`Linter`] parses the source once and hands the tree and the lossless
+//! [`Cst`] to each of its [`Rule`]s. The built-in rules are on by default,
+//! rules of your own are added with [`Linter::rule`].
+//!
+//! ```
+//! use sweet_expr::lint::Linter;
+//!
+//! let source = "define f(x)\n  g x  \n";
+//! let diagnostics = Linter::new().lint(source);
+//! assert_eq!(diagnostics.len(), 1);
+//! assert_eq!(diagnostics[0].code, "sweet_expr::trailing_whitespace");
+//! ```
+
+use crate::cst::{self, Cst};
+use crate::diagnostic::{Diagnostic, Label};
+use crate::lexer::{tokenise, Token};
+use crate::parser::{Parser, ParserOptions};
+use crate::refactor::TextEdit;
+use crate::source_map::Span;
+use crate::value::*;
+
+/// Everything a rule gets to look at
+pub struct LintContext<'a, 'src> {
+    pub source: &'src str,
+    /// the tree, with [`Atom::Error`]s where the source didn't parse
+    pub document: &'a [Atom<'src>],
+    pub cst: &'a Cst<'src>,
+}
+
+impl<'src> LintContext<'_, 'src> {
+    /// The indentation at the start of every line that has something other
+    /// than a comment on it
+    pub fn indented_lines(&self) -> Vec<IndentedLine<'src>> {
+        let mut lines = vec![];
+        let mut depth = 0usize;
+        // the indentation of the current line, until something follows it
+        let mut pending = Some(("", Span::at(0)));
+        for token in self.cst.root.tokens() {
+            match token.token {
+                Token::Newline => {
+                    pending = Some(("", Span::at(token.span.end as usize)));
+                    continue;
+                }
+                Token::Spaces(spaces) if pending.is_some() => {
+                    pending = Some((spaces, token.span));
+                    continue;
+                }
+                Token::Comment => {
+                    pending = None;
+                    continue;
+                }
+                _ => {}
+            }
+            if let Some((indent, span)) = pending.take() {
+                lines.push(IndentedLine {
+                    indent,
+                    span,
+                    in_group: depth > 0,
+                });
+            }
+            match token.token {
+                Token::ParenOpen | Token::CurlyOpen | Token::BracketOpen => depth += 1,
+                Token::ParenClose | Token::CurlyClose | Token::BracketClose => {
+                    depth = depth.saturating_sub(1)
+                }
+                _ => {}
+            }
+        }
+        lines
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndentedLine<'src> {
+    pub indent: &'src str,
+    /// the span of the indentation, empty for lines that aren't indented
+    pub span: Span,
+    /// whether the line starts inside `()`, `[]` or `{}`, where indentation
+    /// has no meaning
+    pub in_group: bool,
+}
+
+pub trait Rule {
+    /// The code of the diagnostics it reports, like `sweet_expr::deep_nesting`
+    fn code(&self) -> &'static str;
+
+    fn check(&self, cx: &LintContext<'_, '_>, out: &mut Vec<Diagnostic>);
+}
+
+/// Runs a set of rules over a source
+pub struct Linter {
+    rules: Vec<Box<dyn Rule>>,
+    options: ParserOptions,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Linter {
+    /// A linter with all the built-in rules
+    pub fn new() -> Self {
+        Self::empty()
+            .rule(IndentWidth)
+            .rule(DeepNesting::default())
+            .rule(TrailingWhitespace)
+            .rule(TabsAndSpaces)
+            .rule(BlockIndentWidth)
+            .rule(UnopenedDedent)
+            .rule(SingleSpaceIndent)
+            .rule(NeotericAdjacency)
+    }
+
+    /// A linter without any rules
+    pub fn empty() -> Self {
+        Self {
+            rules: vec![],
+            options: ParserOptions::default(),
+        }
+    }
+
+    pub fn rule(mut self, rule: impl Rule + 'static) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Drops the rules reporting `code`
+    pub fn without(mut self, code: &str) -> Self {
+        self.rules.retain(|rule| rule.code() != code);
+        self
+    }
+
+    pub fn options(mut self, options: ParserOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// The parser's errors and warnings and what the rules found, in source
+    /// order
+    pub fn lint(&self, source: &str) -> Vec<Diagnostic> {
+        let options = self.options.clone().recover(true);
+        let mut parser = Parser::with_options(tokenise(source), options.clone());
+        let (document, errors) = parser.parse_toplevel_recovering();
+        let cst = cst::parse_with_options(source, options);
+
+        let mut out: Vec<Diagnostic> = errors.iter().map(Diagnostic::from).collect();
+        out.extend_from_slice(parser.warnings());
+        let cx = LintContext {
+            source,
+            document: &document,
+            cst: &cst,
+        };
+        for rule in &self.rules {
+            rule.check(&cx, &mut out);
+        }
+        out.sort_by_key(|diagnostic| diagnostic.span().map(|span| span.start));
+        out
+    }
+}
+
+/// Indentation that goes in by a different amount than the first indented
+/// line did
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IndentWidth;
+
+impl Rule for IndentWidth {
+    fn code(&self) -> &'static str {
+        "sweet_expr::indent_width"
+    }
+
+    fn check(&self, cx: &LintContext<'_, '_>, out: &mut Vec<Diagnostic>) {
+        let mut width = None;
+        let mut levels = vec![0];
+        for line in cx.indented_lines() {
+            if line.in_group {
+                continue;
+            }
+            let column = line.indent.chars().count();
+            while levels.last().is_some_and(|&level| level > column) {
+                levels.pop();
+            }
+            let level = levels.last().copied().unwrap_or(0);
+            if column == level {
+                continue;
+            }
+            levels.push(column);
+            let step = column - level;
+            match width {
+                None => width = Some(step),
+                Some(width) if width != step => out.push(
+                    Diagnostic::warning(
+                        self.code(),
+                        format!("indented by {step} instead of {width}"),
+                    )
+                    .with_label(Label::primary(
+                        line.span,
+                        format!("{step} more than the line above"),
+                    ))
+                    .with_help(format!("the first indented line goes in by {width}")),
+                ),
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+/// Groups nested deeper than `max_depth`, only the outermost one too deep
+/// is reported
+#[derive(Debug, Clone, Copy)]
+pub struct DeepNesting {
+    pub max_depth: usize,
+}
+
+impl Default for DeepNesting {
+    fn default() -> Self {
+        Self { max_depth: 8 }
+    }
+}
+
+impl DeepNesting {
+    fn visit(&self, atom: &Atom, depth: usize, out: &mut Vec<Diagnostic>) {
+        let group = match atom {
+            Atom::Group(group) => group,
+            Atom::Neoteric { lhs, rhs } => {
+                self.visit(lhs, depth, out);
+                rhs
+            }
+            Atom::Raw { body, .. } => body,
+            _ => return,
+        };
+        let depth = depth + 1;
+        if depth > self.max_depth {
+            out.push(
+                Diagnostic::warning(
+                    self.code(),
+                    format!("nested {depth} levels deep, more than {}", self.max_depth),
+                )
+                .with_label(Label::primary(atom.span(), "too deep"))
+                .with_help("consider splitting it up with a helper definition"),
+            );
+            return;
+        }
+        for child in &group.children {
+            self.visit(child, depth, out);
+        }
+    }
+}
+
+impl Rule for DeepNesting {
+    fn code(&self) -> &'static str {
+        "sweet_expr::deep_nesting"
+    }
+
+    fn check(&self, cx: &LintContext<'_, '_>, out: &mut Vec<Diagnostic>) {
+        for atom in cx.document {
+            self.visit(atom, 0, out);
+        }
+    }
+}
+
+/// Spaces or tabs at the end of a line
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrailingWhitespace;
+
+impl Rule for TrailingWhitespace {
+    fn code(&self) -> &'static str {
+        "sweet_expr::trailing_whitespace"
+    }
+
+    fn check(&self, cx: &LintContext<'_, '_>, out: &mut Vec<Diagnostic>) {
+        let mut tokens = cx.cst.root.tokens().peekable();
+        while let Some(token) = tokens.next() {
+            let trailing = match token.token {
+                Token::Spaces(spaces)
+                    if tokens
+                        .peek()
+                        .is_none_or(|next| next.token == Token::Newline) =>
+                {
+                    spaces.len()
+                }
+                _ => 0,
+            };
+            if trailing > 0 {
+                let start = token.span.range().start;
+                let span = Span::new(start, start + trailing);
+                out.push(
+                    Diagnostic::warning(self.code(), "trailing whitespace")
+                        .with_label(Label::primary(span, "remove this"))
+                        .with_fix(
+                            "remove the trailing whitespace",
+                            [TextEdit {
+                                span: span.range(),
+                                replacement: String::new(),
+                            }],
+                        ),
+                );
+            }
+        }
+    }
+}
+
+/// Lines indented with tabs in a file indented with spaces, or the other way
+/// around. The parser already warns about lines that mix both.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TabsAndSpaces;
+
+impl Rule for TabsAndSpaces {
+    fn code(&self) -> &'static str {
+        "sweet_expr::tabs_and_spaces"
+    }
+
+    fn check(&self, cx: &LintContext<'_, '_>, out: &mut Vec<Diagnostic>) {
+        let mut style = None;
+        for line in cx.indented_lines() {
+            let Some(first) = line.indent.chars().next() else {
+                continue;
+            };
+            let (expected, name) = *style.get_or_insert(match first {
+                '\t' => ('\t', "tabs"),
+                _ => (' ', "spaces"),
+            });
+            if !line.indent.contains(expected) {
+                out.push(
+                    Diagnostic::warning(
+                        self.code(),
+                        format!("this file is indented with {name}, but this line isn't"),
+                    )
+                    .with_label(Label::primary(line.span, format!("not {name}")))
+                    // one for one, which keeps the meaning with the default
+                    // tab width
+                    .with_fix(
+                        format!("indent with {name}"),
+                        [TextEdit {
+                            span: line.span.range(),
+                            replacement: expected.to_string().repeat(line.indent.chars().count()),
+                        }],
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// A top-level form whose blocks don't all go in by the same amount, even
+/// if the file as a whole isn't consistent either
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlockIndentWidth;
+
+impl Rule for BlockIndentWidth {
+    fn code(&self) -> &'static str {
+        "sweet_expr::block_indent_width"
+    }
+
+    fn check(&self, cx: &LintContext<'_, '_>, out: &mut Vec<Diagnostic>) {
+        let mut width = None;
+        for line in indentation_steps(cx) {
+            let Step::In { step, .. } = line.step else {
+                if line.column == 0 {
+                    // a new top-level form
+                    width = None;
+                }
+                continue;
+            };
+            match width {
+                None => width = Some(step),
+                Some(width) if width != step => out.push(
+                    Diagnostic::warning(
+                        self.code(),
+                        format!("this block is indented by {step}, the form's first by {width}"),
+                    )
+                    .with_label(Label::primary(
+                        line.line.span,
+                        format!("indented by {step}"),
+                    ))
+                    .with_help(format!("indent every block of the form by {width}")),
+                ),
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+/// A line that goes back to a column none of the lines above it are at.
+/// The parser reports it as invalid indentation, this says what's wrong.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnopenedDedent;
+
+impl Rule for UnopenedDedent {
+    fn code(&self) -> &'static str {
+        "sweet_expr::unopened_dedent"
+    }
+
+    fn check(&self, cx: &LintContext<'_, '_>, out: &mut Vec<Diagnostic>) {
+        for line in indentation_steps(cx) {
+            let Step::Unopened { open } = &line.step else {
+                continue;
+            };
+            let open: Vec<_> = open.iter().map(|level| level.to_string()).collect();
+            out.push(
+                Diagnostic::warning(
+                    self.code(),
+                    format!("dedent to column {}, which was never opened", line.column),
+                )
+                .with_label(Label::primary(
+                    line.line.span,
+                    "no block starts at this column",
+                ))
+                .with_help(format!(
+                    "the blocks open here are at columns {}",
+                    open.join(", ")
+                )),
+            );
+        }
+    }
+}
+
+/// A block indented by a single space, which is easy to miss when reading
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SingleSpaceIndent;
+
+impl Rule for SingleSpaceIndent {
+    fn code(&self) -> &'static str {
+        "sweet_expr::single_space_indent"
+    }
+
+    fn check(&self, cx: &LintContext<'_, '_>, out: &mut Vec<Diagnostic>) {
+        for line in indentation_steps(cx) {
+            let Step::In { step: 1, parent } = line.step else {
+                continue;
+            };
+            // a tab is wide enough
+            if line.line.indent[parent..].starts_with(' ') {
+                let start = line.line.span.range().start + parent;
+                out.push(
+                    Diagnostic::warning(self.code(), "block indented by a single space")
+                        .with_label(Label::primary(Span::new(start, start + 1), "only this"))
+                        .with_help("indent blocks by at least two spaces"),
+                );
+            }
+        }
+    }
+}
+
+/// Neoteric expressions whose head isn't a name, like `(f x)(y)` or
+/// `f(x)(y)`. They read like two elements, and anything that puts a space
+/// between elements turns them into two.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NeotericAdjacency;
+
+impl NeotericAdjacency {
+    fn visit(&self, atom: &Atom, out: &mut Vec<Diagnostic>) {
+        let children = match atom {
+            Atom::Group(group) => &group.children,
+            Atom::Raw { body, .. } => &body.children,
+            Atom::Neoteric { lhs, rhs } => {
+                if !lhs.is_identifier() {
+                    let at = Span::at(lhs.span().range().end);
+                    out.push(
+                        Diagnostic::warning(
+                            self.code(),
+                            "group applied to something that isn't a name",
+                        )
+                        .with_label(Label::primary(at, "no space here makes this a call"))
+                        .with_label(Label::secondary(lhs.span(), "this is called"))
+                        .with_help(format!(
+                            "write the call out, e.g. `({} ...)`, or put a space here if these \
+                             are separate elements",
+                            crate::printer::Printer::minified().print(lhs)
+                        )),
+                    );
+                }
+                self.visit(lhs, out);
+                &rhs.children
+            }
+            _ => return,
+        };
+        for child in children {
+            self.visit(child, out);
+        }
+    }
+}
+
+impl Rule for NeotericAdjacency {
+    fn code(&self) -> &'static str {
+        "sweet_expr::neoteric_adjacency"
+    }
+
+    fn check(&self, cx: &LintContext<'_, '_>, out: &mut Vec<Diagnostic>) {
+        for atom in cx.document {
+            self.visit(atom, out);
+        }
+    }
+}
+
+enum Step {
+    /// the line stays at an open level, or goes back to one
+    Same,
+    /// the line opens a block `step` columns right of `parent`
+    In { step: usize, parent: usize },
+    /// the line goes back to a column that isn't open, `open` are the levels
+    /// that are
+    Unopened { open: Vec<usize> },
+}
+
+struct SteppedLine<'src> {
+    line: IndentedLine<'src>,
+    column: usize,
+    step: Step,
+}
+
+// how the indentation of every line outside of explicit groups relates to
+// the lines above it
+fn indentation_steps<'src>(cx: &LintContext<'_, 'src>) -> Vec<SteppedLine<'src>> {
+    let mut levels = vec![0];
+    let mut steps = vec![];
+    for line in cx.indented_lines() {
+        if line.in_group {
+            continue;
+        }
+        let column = line.indent.chars().count();
+        let open = levels.clone();
+        while levels.last().is_some_and(|&level| level > column) {
+            levels.pop();
+        }
+        let level = levels.last().copied().unwrap_or(0);
+        let step = if column == level {
+            Step::Same
+        } else if open.len() > levels.len() {
+            // dedented past `level`, which the parser reports as an error
+            // and goes on at `level`
+            Step::Unopened { open }
+        } else {
+            levels.push(column);
+            Step::In {
+                step: column - level,
+                parent: level,
+            }
+        };
+        steps.push(SteppedLine { line, column, step });
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::apply_fixes;
+
+    fn codes(linter: &Linter, source: &str) -> Vec<(&'static str, String)> {
+        linter
+            .lint(source)
+            .iter()
+            .map(|d| (d.code, source[d.span().unwrap()].to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn indent_width() {
+        let source = "a\n  b 1\n    c 2\n  d 3\n     e 4\nf\n   g 5\n";
+        assert_eq!(
+            codes(&Linter::new(), source),
+            [
+                ("sweet_expr::indent_width", "     ".to_string()),
+                ("sweet_expr::block_indent_width", "     ".to_string()),
+                ("sweet_expr::indent_width", "   ".to_string()),
+            ]
+        );
+        // continuation lines in parentheses are aligned however
+        assert!(codes(&Linter::new(), "a (b\n     c)\n  d e\n").is_empty());
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let linter = Linter::empty().rule(DeepNesting { max_depth: 2 });
+        assert_eq!(
+            codes(&linter, "a (b (c (d (e))))\nf(g(h(x)))\n"),
+            [
+                ("sweet_expr::deep_nesting", "(c (d (e)))".to_string()),
+                ("sweet_expr::deep_nesting", "h(x)".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn whitespace() {
+        let source = "a \t\n\tb 1\nc\n  d 2  ";
+        assert_eq!(
+            codes(&Linter::new().without("sweet_expr::indent_width"), source),
+            [
+                ("sweet_expr::trailing_whitespace", " \t".to_string()),
+                ("sweet_expr::tabs_and_spaces", "  ".to_string()),
+                ("sweet_expr::trailing_whitespace", "  ".to_string()),
+            ]
+        );
+        let fixed = apply_fixes(source, &Linter::new().lint(source));
+        assert_eq!(fixed, "a\n\tb 1\nc\n\t\td 2");
+    }
+
+    #[test]
+    fn parse_errors_and_warnings_are_included() {
+        let found = codes(&Linter::empty(), "a (\n");
+        assert_eq!(found.len(), 1);
+        assert_ne!(found[0].0, "sweet_expr::trailing_whitespace");
+
+        struct NoFoo;
+        impl Rule for NoFoo {
+            fn code(&self) -> &'static str {
+                "test::no_foo"
+            }
+            fn check(&self, cx: &LintContext<'_, '_>, out: &mut Vec<Diagnostic>) {
+                for atom in cx.document {
+                    if atom.as_identifier() == Some("foo") {
+                        out.push(
+                            Diagnostic::warning(self.code(), "foo")
+                                .with_label(Label::primary(atom.span(), "")),
+                        );
+                    }
+                }
+            }
+        }
+        let found = codes(&Linter::empty().rule(NoFoo), "bar\nfoo\n");
+        assert_eq!(found, [("test::no_foo", "foo".to_string())]);
+    }
+
+    #[test]
+    fn indentation_hygiene() {
+        // `h` is consistent with the file, but not with the rest of its form
+        let source = "a\n   b 1\nf\n  g 2\n     h 3\n";
+        let linter = Linter::empty().rule(BlockIndentWidth);
+        assert_eq!(
+            codes(&linter, source),
+            [("sweet_expr::block_indent_width", "     ".to_string())]
+        );
+
+        let source = "a\n    b 1\n  c 2\nd\n e 3\n";
+        let found = codes(&Linter::new().without("sweet_expr::indent_width"), source);
+        assert!(found.contains(&("sweet_expr::unopened_dedent", "  ".to_string())));
+        assert!(found.contains(&("sweet_expr::single_space_indent", " ".to_string())));
+        let dedent = Linter::empty()
+            .rule(UnopenedDedent)
+            .lint(source)
+            .into_iter()
+            .find(|d| d.code == "sweet_expr::unopened_dedent")
+            .unwrap();
+        assert_eq!(
+            dedent.help.as_deref(),
+            Some("the blocks open here are at columns 0, 4")
+        );
+    }
+
+    #[test]
+    fn neoteric_adjacency() {
+        let linter = Linter::empty().rule(NeotericAdjacency);
+        let source = "f(x) (g x)(y) h(1)(2)\n";
+        let found: Vec<_> = linter
+            .lint(source)
+            .into_iter()
+            .filter(|d| d.code == "sweet_expr::neoteric_adjacency")
+            .map(|d| d.span().unwrap())
+            .collect();
+        assert_eq!(found, [10..10, 18..18]);
+    }
+}
@@ -0,0 +1,328 @@
+//! Parsing of files that are too big to be read into a `String` first.
+//!
+//! [`MappedSource`] maps the file into memory and [`MappedSource::forms`]
+//! parses it a chunk at a time. Chunks end in front of a line starting at
+//! column 0, so every chunk holds complete top-level forms, and UTF-8 is only
+//! validated for the chunk that is about to be parsed. Atoms borrow straight
+//! from the mapping.
+//!
+//! Spans are 32 bit, so files can be at most 4 GiB.
+
+use std::{collections::VecDeque, fmt, fs::File, io, path::Path, str::Utf8Error};
+
+use memmap2::Mmap;
+
+use crate::lexer::{tokenise, Token};
+use crate::parser::{ParseError, Parser, ParserOptions};
+use crate::source_map::Span;
+use crate::value::Atom;
+
+// chunks are cut at the first suitable line after this many bytes
+const CHUNK_SIZE: usize = 1 << 20;
+
+#[derive(Debug)]
+pub struct MappedSource {
+    map: Mmap,
+}
+
+impl MappedSource {
+    /// Maps the file at `path`, files over 4 GiB are an error.
+    ///
+    /// The file must not be changed while it is mapped, otherwise the atoms
+    /// borrowing from it can change or even stop being valid UTF-8 under
+    /// your feet.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: see above, the file must not be modified while mapped
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() > u32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "files over 4 GiB can't be parsed",
+            ));
+        }
+        Ok(Self { map })
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// The top-level forms of the file, parsed lazily
+    pub fn forms(&self, options: &ParserOptions) -> MappedForms<'_> {
+        MappedForms {
+            bytes: &self.map,
+            pos: 0,
+            chunk_size: CHUNK_SIZE,
+            options: options.clone(),
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MappedError<'m> {
+    /// The chunk starting at `chunk_start` isn't valid UTF-8
+    Utf8 {
+        chunk_start: usize,
+        error: Utf8Error,
+    },
+    Parse(ParseError<'m>),
+}
+
+impl fmt::Display for MappedError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MappedError::Utf8 { chunk_start, error } => write!(
+                f,
+                "invalid UTF-8 at byte {}",
+                chunk_start + error.valid_up_to()
+            ),
+            MappedError::Parse(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MappedError<'_> {}
+
+/// Iterator returned by [`MappedSource::forms`], it stops after the first
+/// error
+pub struct MappedForms<'m> {
+    bytes: &'m [u8],
+    pos: usize,
+    chunk_size: usize,
+    options: ParserOptions,
+    pending: VecDeque<Atom<'m>>,
+    done: bool,
+}
+
+impl<'m> MappedForms<'m> {
+    /// Parses the next chunk of complete forms into `pending`
+    fn parse_chunk(&mut self) -> Result<(), MappedError<'m>> {
+        let start = self.pos;
+        let mut end = self.chunk_end(start + self.chunk_size);
+        let mut scan = Scan::new(start);
+        // only the lines added since the last try are validated and scanned,
+        // chunks always grow by whole lines
+        let mut validated = start;
+        loop {
+            if std::str::from_utf8(&self.bytes[validated..end]).is_err() {
+                let error = std::str::from_utf8(&self.bytes[start..end]).unwrap_err();
+                return Err(MappedError::Utf8 {
+                    chunk_start: start,
+                    error,
+                });
+            }
+            validated = end;
+            // a group or string that continues past the end of the chunk
+            // means the cut was inside of a form, take the next line too
+            if end == self.bytes.len() || scan.is_complete(self.bytes, end) {
+                break;
+            }
+            end = self.chunk_end(end + 1);
+        }
+        // SAFETY: `start..end` was validated piece by piece, and every piece
+        // starts after a newline
+        let text = unsafe { std::str::from_utf8_unchecked(&self.bytes[start..end]) };
+        self.pos = end;
+
+        let tokens = tokenise(text).map(move |(tok, span)| {
+            let span = Span::new(start + span.start as usize, start + span.end as usize);
+            (tok, span)
+        });
+        let atoms = Parser::with_options(tokens, self.options.clone())
+            .parse_toplevel()
+            .map_err(MappedError::Parse)?;
+        self.pending.extend(atoms);
+        Ok(())
+    }
+
+    /// The start of the first line at or after `from` that starts at
+    /// column 0 with something other than whitespace
+    fn chunk_end(&self, from: usize) -> usize {
+        let bytes = self.bytes;
+        let mut i = from.min(bytes.len());
+        while i < bytes.len() {
+            if bytes[i - 1] == b'\n' && !bytes[i].is_ascii_whitespace() {
+                return i;
+            }
+            i += 1;
+        }
+        bytes.len()
+    }
+}
+
+/// How far a chunk was scanned for open groups and strings, so that growing
+/// it only scans the new lines
+struct Scan {
+    pos: usize,
+    nesting: usize,
+    // the string `pos` is inside of
+    in_string: bool,
+    // something the lexer doesn't accept, the chunk is never complete then
+    error: bool,
+}
+
+impl Scan {
+    fn new(start: usize) -> Self {
+        Self {
+            pos: start,
+            nesting: 0,
+            in_string: false,
+            error: false,
+        }
+    }
+
+    /// Whether no explicit group or string is still open at `end`. `bytes`
+    /// up to `end` are valid UTF-8 from where the scan started.
+    fn is_complete(&mut self, bytes: &[u8], end: usize) -> bool {
+        while !self.error && self.pos < end {
+            if self.in_string {
+                self.scan_string(bytes, end);
+                if self.in_string {
+                    break;
+                }
+            }
+            // SAFETY: `pos` is right after a token or a string, and `bytes`
+            // are valid UTF-8 from there on
+            let text = unsafe { std::str::from_utf8_unchecked(&bytes[self.pos..end]) };
+            let mut scanned = end;
+            for (tok, span) in tokenise(text) {
+                match tok {
+                    Token::ParenOpen | Token::BracketOpen | Token::CurlyOpen => self.nesting += 1,
+                    Token::ParenClose | Token::BracketClose | Token::CurlyClose => {
+                        self.nesting = self.nesting.saturating_sub(1)
+                    }
+                    // a string that doesn't end before `end`
+                    Token::Error(_) if text[span.range()].starts_with('"') => {
+                        self.in_string = true;
+                        scanned = self.pos + span.start as usize + 1;
+                        break;
+                    }
+                    Token::Error(_) => {
+                        self.error = true;
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            self.pos = scanned;
+        }
+        !self.error && !self.in_string && self.nesting == 0
+    }
+
+    // moves `pos` to the end of the string it's in, or as far as it can get
+    // towards `end`
+    fn scan_string(&mut self, bytes: &[u8], end: usize) {
+        while self.pos < end {
+            match bytes[self.pos] {
+                b'"' => {
+                    self.pos += 1;
+                    self.in_string = false;
+                    return;
+                }
+                b'\\' if self.pos + 1 == end => return,
+                b'\\' if bytes[self.pos + 1] == b'"' => self.pos += 2,
+                // only quotes are escaped
+                b'\\' => {
+                    self.error = true;
+                    return;
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+}
+
+impl<'m> Iterator for MappedForms<'m> {
+    type Item = Result<Atom<'m>, MappedError<'m>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(atom) = self.pending.pop_front() {
+                return Some(Ok(atom));
+            }
+            if self.done || self.pos >= self.bytes.len() {
+                return None;
+            }
+            if let Err(err) = self.parse_chunk() {
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::render_all;
+
+    fn mapped(name: &str, contents: &[u8]) -> MappedSource {
+        let path =
+            std::env::temp_dir().join(format!("sweet-expr-{}-{name}.sweet", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let source = MappedSource::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        source
+    }
+
+    #[test]
+    fn chunks_end_between_forms() {
+        let text = "define f(x)\n    g x\n(a\nb)\n\"multi\nline\" c\nlast {1 + 2}\n";
+        let source = mapped("chunks", text.as_bytes());
+        for chunk_size in [1, 5, 1000] {
+            let mut forms = source.forms(&ParserOptions::new());
+            forms.chunk_size = chunk_size;
+            let atoms: Vec<_> = forms.collect::<Result<_, _>>().unwrap();
+            assert_eq!(render_all(&atoms), render_all(&crate::parse(text).unwrap()));
+        }
+
+        let mut forms = source.forms(&ParserOptions::new());
+        forms.chunk_size = 1;
+        let last = forms.last().unwrap().unwrap();
+        assert_eq!(&text[last.span()], "last {1 + 2}");
+    }
+
+    #[test]
+    fn invalid_utf8_is_found_per_chunk() {
+        let source = mapped("utf8", b"a b\nc \xff\n");
+        let mut forms = source.forms(&ParserOptions::new());
+        forms.chunk_size = 1;
+        assert!(forms.next().unwrap().is_ok());
+        let err = forms.next().unwrap().unwrap_err();
+        assert!(matches!(err, MappedError::Utf8 { chunk_start: 4, .. }));
+        assert_eq!(err.to_string(), "invalid UTF-8 at byte 6");
+        assert!(forms.next().is_none());
+    }
+
+    #[test]
+    fn long_forms_are_scanned_once() {
+        // each line added to the chunk is only scanned once, this used to
+        // take quadratic time
+        let mut text = "(\n".to_string() + &"x\n".repeat(200_000) + ")\n\"a\n";
+        text += &"b\n".repeat(10_000);
+        text += "\\\"\" c\nlast\n";
+        let source = mapped("long", text.as_bytes());
+        let mut forms = source.forms(&ParserOptions::new());
+        forms.chunk_size = 1;
+        let atoms: Vec<_> = forms.collect::<Result<_, _>>().unwrap();
+        assert_eq!(atoms.len(), 3);
+        assert_eq!(&text[atoms[2].span().range()], "last");
+    }
+
+    #[test]
+    fn files_over_4_gib_are_an_error() {
+        let path =
+            std::env::temp_dir().join(format!("sweet-expr-{}-big.sweet", std::process::id()));
+        // sparse, nothing is written
+        File::create(&path)
+            .unwrap()
+            .set_len(u32::MAX as u64 + 1)
+            .unwrap();
+        let err = MappedSource::open(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
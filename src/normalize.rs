@@ -0,0 +1,500 @@
+//! Passes that rewrite syntax-level constructs into plain lists, for
+//! embedders that want to assign their own meaning to `[]` and `{}`.
+//!
+//! The parser keeps every group as written, [`DialectConfig::apply`] then
+//! rewrites bracket and curly groups according to the configured
+//! [`GroupRule`]s. Synthesized symbols get the span of the opening delimiter
+//! of the group they replace.
+//!
+//! [`DialectConfig::normalize`] goes further and lowers everything that only
+//! exists in the syntax, neoteric expressions, indentation and the `$` and
+//! `\\` markers of SRFI 110, leaving nothing but lists for an interpreter to
+//! deal with. [`Atom::normalize`] does that with brackets as lists and curly
+//! groups as curly-infix.
+//!
+//! ```
+//! let atom = sweet_expr::parse_one("let\n  \\\\\n    x f[1]\n  g $ h {x + 1}").unwrap();
+//! assert_eq!(atom.normalize().to_string(), "(let ((x ($bracket-apply$ f 1))) (g (h (+ x 1))))");
+//! ```
+
+use std::borrow::Cow;
+
+use crate::diagnostic::{Diagnostic, Label, Severity};
+use crate::source_map::{FileId, Span};
+use crate::value::*;
+use crate::visit::{walk_group, Visit};
+
+/// Symbol SRFI 105 wraps infix expressions in that aren't simple, i.e. that
+/// mix operators
+pub const NFX_SYMBOL: &str = "$nfx$";
+
+/// SRFI 110 SUBLIST, the rest of an indented line becomes a single element:
+/// `a b $ c d` → `(a b (c d))`
+pub const SUBLIST_MARKER: &str = "$";
+
+/// SRFI 110 GROUP and SPLIT. At the start of a line the rest of it is a list
+/// even if it's a single element, which is mostly useful for a line of just
+/// `\\` and children. Anywhere else it splits the line into siblings,
+/// `a b \\ c d` → `(a b) (c d)`.
+pub const GROUP_SPLIT_MARKER: &str = "\\\\";
+
+/// What to turn a group into
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupRule {
+    /// leave the group as it is
+    Keep,
+    /// a plain parenthesized list, `[a b]` → `(a b)`
+    List,
+    /// a list headed by the symbol, `[a b]` → `(bracketaccess a b)`
+    Prefix(Cow<'static, str>),
+    /// SRFI 105 curly-infix: `{a + b + c}` → `(+ a b c)`, anything that
+    /// doesn't alternate between operands and a single operator is wrapped
+    /// in [`DialectConfig::nfx_symbol`]. `{}` is `()`, `{x}` is `x` and two elements are a
+    /// plain list, so `{- x}` is `(- x)`.
+    CurlyInfix,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DialectConfig {
+    pub brackets: GroupRule,
+    pub curlies: GroupRule,
+    /// what curly-infix wraps expressions that aren't simple in,
+    /// [`NFX_SYMBOL`] by default
+    pub nfx_symbol: Cow<'static, str>,
+}
+
+impl Default for DialectConfig {
+    fn default() -> Self {
+        Self {
+            brackets: GroupRule::Keep,
+            curlies: GroupRule::Keep,
+            nfx_symbol: Cow::Borrowed(NFX_SYMBOL),
+        }
+    }
+}
+
+impl DialectConfig {
+    /// `{}` is curly-infix like in SRFI 105 and 110, `[]` stays as it is
+    pub fn curly_infix() -> Self {
+        Self {
+            curlies: GroupRule::CurlyInfix,
+            ..Self::default()
+        }
+    }
+
+    /// Applies the rules to all groups in `atom`, except inside of `#raw`
+    pub fn apply<'src>(&self, atom: Atom<'src>) -> Atom<'src> {
+        match atom {
+            Atom::Identifier(_) | Atom::String(_) | Atom::Raw { .. } | Atom::Error(_) => atom,
+            Atom::Group(group) => self.apply_group(group),
+            Atom::Neoteric { lhs, mut rhs } => {
+                // the group of a neoteric expression is part of the call
+                // syntax, only what's inside of it is rewritten
+                rhs.children = self.apply_all(rhs.children);
+                Atom::Neoteric {
+                    lhs: Box::new(self.apply(*lhs)),
+                    rhs,
+                }
+            }
+        }
+    }
+
+    /// [`DialectConfig::apply`] for strict SRFI 105: curly groups that mix
+    /// operators, like `{a + b * c}`, are an error instead of being wrapped in
+    /// [`DialectConfig::nfx_symbol`]. See [`mixed_infix`].
+    pub fn apply_strict<'src>(&self, atom: Atom<'src>) -> Result<Atom<'src>, Diagnostic> {
+        if self.curlies == GroupRule::CurlyInfix {
+            if let Some(err) = mixed_infix(std::slice::from_ref(&atom)).into_iter().next() {
+                return Err(err);
+            }
+        }
+        Ok(self.apply(atom))
+    }
+
+    pub fn apply_all<'src>(&self, atoms: Vec<Atom<'src>>) -> Vec<Atom<'src>> {
+        atoms.into_iter().map(|atom| self.apply(atom)).collect()
+    }
+
+    fn apply_group<'src>(&self, mut group: Group<'src>) -> Atom<'src> {
+        group.children = self.apply_all(group.children);
+        self.apply_rule(group)
+    }
+
+    // rewrites the group itself, its children are already done
+    pub(crate) fn apply_rule<'src>(&self, mut group: Group<'src>) -> Atom<'src> {
+        let rule = match group.group_type {
+            GroupType::Indentation | GroupType::Parenthesis => &GroupRule::Keep,
+            GroupType::Bracket => &self.brackets,
+            GroupType::Curly => &self.curlies,
+        };
+        match rule {
+            GroupRule::Keep => return Atom::Group(group),
+            GroupRule::List => {}
+            GroupRule::Prefix(symbol) => prepend_symbol(&mut group, symbol),
+            // `{x}` is just `x`
+            GroupRule::CurlyInfix if group.children.len() == 1 => {
+                if let Some(atom) = group.children.pop() {
+                    return atom;
+                }
+            }
+            GroupRule::CurlyInfix => curly_infix(&mut group, &self.nfx_symbol),
+        }
+        group.group_type = GroupType::Parenthesis;
+        Atom::Group(group)
+    }
+
+    /// Lowers `atom` into nested lists: the group rules are applied,
+    /// neoteric expressions become lists like in SRFI 105, indentation groups
+    /// become parenthesized and the [`SUBLIST_MARKER`] and
+    /// [`GROUP_SPLIT_MARKER`] in them are resolved. `#raw` is left alone.
+    ///
+    /// A line split into several forms with `\\` turns into a list of them,
+    /// [`DialectConfig::normalize_all`] keeps them as separate forms.
+    pub fn normalize<'src>(&self, atom: Atom<'src>) -> Atom<'src> {
+        let span = atom.span();
+        let mut atoms = vec![];
+        self.lower_into(atom, &mut atoms);
+        match <[Atom; 1]>::try_from(atoms) {
+            Ok([atom]) => atom,
+            Err(atoms) => list(atoms, span, None),
+        }
+    }
+
+    pub fn normalize_all<'src>(&self, atoms: Vec<Atom<'src>>) -> Vec<Atom<'src>> {
+        let mut out = vec![];
+        for atom in atoms {
+            self.lower_into(atom, &mut out);
+        }
+        out
+    }
+
+    // pushes the lowered atom, or the forms a line is split into
+    fn lower_into<'src>(&self, atom: Atom<'src>, out: &mut Vec<Atom<'src>>) {
+        match atom {
+            Atom::Group(group) if group.group_type == GroupType::Indentation => {
+                self.lower_line(group, out)
+            }
+            Atom::Group(mut group) => {
+                group.children = self.normalize_all(group.children);
+                out.push(self.apply_rule(group));
+            }
+            Atom::Neoteric { lhs, mut rhs } => {
+                let lhs = self.normalize(*lhs);
+                let file = rhs.start_delim.2;
+                let start_delim = Spanned((), Span::at(lhs.span().start as usize), file);
+                let end_delim = rhs.end_delim;
+                rhs.children = self.normalize_all(rhs.children);
+                let children = match rhs.group_type {
+                    GroupType::Indentation | GroupType::Parenthesis => {
+                        std::iter::once(lhs).chain(rhs.children).collect()
+                    }
+                    GroupType::Bracket => {
+                        let apply = Spanned("$bracket-apply$".into(), rhs.start_delim.1, file);
+                        [Atom::Identifier(apply), lhs]
+                            .into_iter()
+                            .chain(rhs.children)
+                            .collect()
+                    }
+                    GroupType::Curly => vec![lhs, self.apply_rule(rhs)],
+                };
+                out.push(Atom::Group(Group {
+                    group_type: GroupType::Parenthesis,
+                    start_delim,
+                    children,
+                    end_delim,
+                }));
+            }
+            Atom::Identifier(_) | Atom::String(_) | Atom::Raw { .. } | Atom::Error(_) => {
+                out.push(atom)
+            }
+        }
+    }
+
+    // the items of an indented line, with its children lines at the end
+    fn lower_line<'src>(&self, group: Group<'src>, out: &mut Vec<Atom<'src>>) {
+        let span = group.span();
+        let file = group.start_delim.2;
+        let mut items = group.children;
+        if items
+            .first()
+            .is_some_and(|item| is_marker(item, GROUP_SPLIT_MARKER))
+        {
+            items.remove(0);
+            out.push(list(self.sublists(items, file), span, file));
+            return;
+        }
+
+        let mut segments = vec![];
+        let mut segment = vec![];
+        for item in items {
+            if is_marker(&item, GROUP_SPLIT_MARKER) {
+                segments.push(std::mem::take(&mut segment));
+            } else {
+                segment.push(item);
+            }
+        }
+        segments.push(segment);
+        for segment in segments {
+            let items = self.sublists(segment, file);
+            out.extend(single_or_list(items, file));
+        }
+    }
+
+    // lowers the items of a line, everything after a `$` turns into a single
+    // element like a line of its own
+    fn sublists<'src>(&self, mut items: Vec<Atom<'src>>, file: Option<FileId>) -> Vec<Atom<'src>> {
+        let marker = items
+            .iter()
+            .position(|item| is_marker(item, SUBLIST_MARKER));
+        let rest = marker.map(|pos| items.split_off(pos).split_off(1));
+        let mut out = self.normalize_all(items);
+        if let Some(rest) = rest {
+            out.extend(single_or_list(self.sublists(rest, file), file));
+        }
+        out
+    }
+}
+
+impl<'src> Atom<'src> {
+    /// Lowers the tree into plain nested lists, with brackets as lists and
+    /// curly groups as curly-infix. See [`DialectConfig::normalize`].
+    pub fn normalize(self) -> Atom<'src> {
+        DialectConfig {
+            brackets: GroupRule::List,
+            curlies: GroupRule::CurlyInfix,
+            ..DialectConfig::default()
+        }
+        .normalize(self)
+    }
+}
+
+fn is_marker(atom: &Atom, marker: &str) -> bool {
+    atom.as_identifier() == Some(marker)
+}
+
+fn single_or_list<'src>(mut items: Vec<Atom<'src>>, file: Option<FileId>) -> Option<Atom<'src>> {
+    match items.len() {
+        0 => None,
+        1 => items.pop(),
+        _ => Some(list(items, Span::default(), file)),
+    }
+}
+
+// a parenthesized list spanning its items, or `span` if there are none
+fn list<'src>(items: Vec<Atom<'src>>, span: Span, file: Option<FileId>) -> Atom<'src> {
+    let span = items
+        .iter()
+        .map(Atom::span)
+        .reduce(Span::to)
+        .unwrap_or(span);
+    Atom::Group(Group {
+        group_type: GroupType::Parenthesis,
+        start_delim: Spanned((), Span::at(span.start as usize), file),
+        children: items,
+        end_delim: Spanned((), Span::at(span.end as usize), file),
+    })
+}
+
+fn prepend_symbol(group: &mut Group, symbol: &str) {
+    let span = group.start_delim.1;
+    let symbol = Atom::Identifier(Spanned(symbol.to_owned().into(), span, group.start_delim.2));
+    group.children.insert(0, symbol);
+}
+
+// everything but `{x}`, which isn't a list at all
+fn curly_infix(group: &mut Group, nfx_symbol: &str) {
+    let children = &mut group.children;
+    if children.len() < 3 {
+        // `{}` → `()` and `{- x}` → `(- x)`
+    } else if is_simple_infix(children) {
+        // `a op b op c`, keep the first operator and drop the others
+        let mut operands = vec![];
+        let mut op = None;
+        for (i, child) in children.drain(..).enumerate() {
+            if i % 2 == 0 {
+                operands.push(child);
+            } else if op.is_none() {
+                op = Some(child);
+            }
+        }
+        children.extend(op);
+        children.append(&mut operands);
+    } else {
+        prepend_symbol(group, nfx_symbol);
+    }
+}
+
+/// An error for every curly group in `document` that mixes operators, like
+/// `{a + b * c}`. SRFI 105 has no precedence, so these can't be turned into a
+/// call without knowing which operator binds tighter. `#raw` groups aren't
+/// checked.
+pub fn mixed_infix(document: &[Atom]) -> Vec<Diagnostic> {
+    let mut finder = MixedInfix(vec![]);
+    for atom in document {
+        finder.visit_atom(atom);
+    }
+    finder.0
+}
+
+struct MixedInfix(Vec<Diagnostic>);
+
+impl<'src> Visit<'src> for MixedInfix {
+    fn visit_group(&mut self, group: &Group<'src>) {
+        let children = &group.children;
+        let odd = children.len() >= 3 && !children.len().is_multiple_of(2);
+        if group.group_type == GroupType::Curly && odd && !is_simple_infix(children) {
+            let mut err = Diagnostic::new(
+                Severity::Error,
+                "sweet_expr::mixed_infix",
+                "curly-infix expression mixes operators",
+            )
+            .with_label(Label::primary(group.span(), "operators differ"))
+            .with_help("nest the expression in curly braces to make the order explicit, like `{a + {b * c}}`");
+            for op in children.iter().skip(1).step_by(2) {
+                err = err.with_label(Label::secondary(op.span(), "operator"));
+            }
+            self.0.push(err);
+        }
+        walk_group(self, group);
+    }
+}
+
+fn is_simple_infix(children: &[Atom]) -> bool {
+    if children.len() < 3 || children.len().is_multiple_of(2) {
+        return false;
+    }
+    let Atom::Identifier(op) = &children[1] else {
+        return false;
+    };
+    children
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .all(|child| matches!(child, Atom::Identifier(other) if other.0 == op.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::render_all;
+
+    fn normalized(config: &DialectConfig, source: &str) -> String {
+        render_all(&config.apply_all(crate::parse(source).unwrap()))
+    }
+
+    #[test]
+    fn bracket_and_curly_rules() {
+        let config = DialectConfig {
+            brackets: GroupRule::Prefix("bracketaccess".into()),
+            curlies: GroupRule::List,
+            ..DialectConfig::default()
+        };
+        assert_eq!(
+            normalized(&config, "f [a b] {c [d]} g[x] #raw[y]"),
+            "(f (bracketaccess a b) (c (bracketaccess d)) g[x] #raw[y])"
+        );
+        assert_eq!(normalized(&DialectConfig::default(), "[a {b}]"), "[a {b}]");
+    }
+
+    #[test]
+    fn simple_curly_infix() {
+        let config = DialectConfig::curly_infix();
+        assert_eq!(normalized(&config, "{a + b + c}"), "(+ a b c)");
+        assert_eq!(normalized(&config, "f{x * {y - 1}}"), "f{x * (- y 1)}");
+        assert_eq!(normalized(&config, "{a + b * c}"), "($nfx$ a + b * c)");
+        assert_eq!(normalized(&config, "[a b]"), "[a b]");
+    }
+
+    fn lowered(source: &str) -> String {
+        let atoms = crate::parse(source).unwrap();
+        let config = DialectConfig {
+            brackets: GroupRule::List,
+            curlies: GroupRule::CurlyInfix,
+            ..DialectConfig::default()
+        };
+        render_all(&config.normalize_all(atoms))
+    }
+
+    #[test]
+    fn custom_nfx_symbol() {
+        // e.g. read from a config file
+        let symbol = String::from("infix");
+        let config = DialectConfig {
+            nfx_symbol: symbol.into(),
+            ..DialectConfig::curly_infix()
+        };
+        assert_eq!(normalized(&config, "{a + b * c}"), "(infix a + b * c)");
+        assert_eq!(normalized(&config, "{a + b}"), "(+ a b)");
+    }
+
+    #[test]
+    fn degenerate_curly_infix() {
+        let config = DialectConfig::curly_infix();
+        assert_eq!(normalized(&config, "{x}"), "x");
+        assert_eq!(normalized(&config, "{- x}"), "(- x)");
+        assert_eq!(normalized(&config, "{}"), "()");
+        assert_eq!(normalized(&config, "{{a}}"), "a");
+        assert_eq!(normalized(&config, "{a b c d}"), "($nfx$ a b c d)");
+        assert_eq!(normalized(&config, "f {x} {- {y}}"), "(f x (- y))");
+        assert_eq!(lowered("f{x} g{}"), "((f x) (g ()))");
+    }
+
+    #[test]
+    fn mixed_operators() {
+        let atoms = crate::parse("f{a + b * c} {a + b + c} #raw{a + b * c} {x + {y - z}}").unwrap();
+        let errors = mixed_infix(&atoms);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "sweet_expr::mixed_infix");
+        assert_eq!(errors[0].span(), Some(1..12));
+        assert_eq!(errors[0].labels.len(), 3);
+
+        let config = DialectConfig::curly_infix();
+        let atom = crate::parse_one("{a + b * c}").unwrap();
+        assert!(config.apply_strict(atom.clone()).is_err());
+        assert!(DialectConfig::default().apply_strict(atom.clone()).is_ok());
+        let ok = config.apply_strict(crate::parse_one("{a * {b + c}}").unwrap());
+        assert_eq!(render_all(&[ok.unwrap()]), "(* a (+ b c))");
+    }
+
+    #[test]
+    fn neoteric_and_indentation() {
+        assert_eq!(
+            lowered("define fact(n)\n  if {n <= 1} 1 {n * fact{n - 1}}"),
+            "(define (fact n) (if (<= n 1) 1 (* n (fact (- n 1)))))"
+        );
+        assert_eq!(
+            lowered("f(x)(y) v[i] [a] #raw(f(x))"),
+            "(((f x) y) ($bracket-apply$ v i) (a) #raw(f (x)))"
+        );
+    }
+
+    #[test]
+    fn sublist() {
+        assert_eq!(lowered("a b $ c d"), "(a b (c d))");
+        assert_eq!(lowered("a $ b $ c d"), "(a (b (c d)))");
+        assert_eq!(lowered("a $ b"), "(a b)");
+        assert_eq!(lowered("run $ grep x\n  sort"), "(run (grep x sort))");
+        assert_eq!(lowered("(a $ b)"), "(a $ b)");
+    }
+
+    #[test]
+    fn group_and_split() {
+        assert_eq!(
+            lowered("let\n  \\\\\n    x 1\n    y 2\n  body"),
+            "(let ((x 1) (y 2)) body)"
+        );
+        assert_eq!(
+            lowered("let\n  \\\\\n    x 1\n  body"),
+            "(let ((x 1)) body)"
+        );
+        assert_eq!(lowered("a b \\\\ c d\n"), "(a b) (c d)");
+        assert_eq!(lowered("f\n  a \\\\ b c\n"), "(f a (b c))");
+        assert_eq!(
+            crate::parse_one("a \\\\ b")
+                .unwrap()
+                .normalize()
+                .to_string(),
+            "(a b)"
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! Numbers. The lexer has no numeric tokens, `42` is an identifier like any
+//! other, so this is the one place that decides which identifiers are
+//! numbers and what they're worth. Evaluators and config loaders should use
+//! [`Atom::as_number`] instead of parsing identifiers themselves.
+//!
+//! The syntax is the decimal subset of Scheme's: an optional sign, digits
+//! with an optional fraction and exponent, and `+inf.0`, `-inf.0` and
+//! `+nan.0`. Integers that don't fit into an `i64` are floats.
+//!
+//! ```
+//! use sweet_expr::number::Number;
+//!
+//! let atom = sweet_expr::parse_one("f 42 -1.5e3 x").unwrap();
+//! let numbers: Vec<_> = atom.as_group().unwrap().children.iter().map(|a| a.as_number()).collect();
+//! assert_eq!(numbers, [None, Some(Number::Integer(42)), Some(Number::Float(-1500.0)), None]);
+//! ```
+
+use std::fmt;
+
+use crate::value::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Number {
+    Integer(i64),
+    Float(f64),
+}
+
+impl Number {
+    /// `None` for anything that isn't a number, including `+` and `-`
+    pub fn parse(text: &str) -> Option<Number> {
+        match text {
+            "+inf.0" => return Some(Number::Float(f64::INFINITY)),
+            "-inf.0" => return Some(Number::Float(f64::NEG_INFINITY)),
+            "+nan.0" | "-nan.0" => return Some(Number::Float(f64::NAN)),
+            _ => {}
+        }
+        if !is_decimal(text) {
+            return None;
+        }
+        if !text.contains(['.', 'e', 'E']) {
+            if let Ok(int) = text.parse() {
+                return Some(Number::Integer(int));
+            }
+        }
+        text.parse().ok().map(Number::Float)
+    }
+
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Number::Integer(int) => int as f64,
+            Number::Float(float) => float,
+        }
+    }
+}
+
+// `[+-]digits[.digits][e[+-]digits]`, with digits on at least one side of the
+// point. Rust's float parser also takes `inf`, `nan` and `1.`, which aren't
+// numbers here.
+fn is_decimal(text: &str) -> bool {
+    let digits = |s: &str| s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = text.strip_prefix(['+', '-']).unwrap_or(text);
+
+    let int = digits(rest);
+    let mut rest = &rest[int..];
+    let mut frac = 0;
+    if let Some(after) = rest.strip_prefix('.') {
+        frac = digits(after);
+        if frac == 0 {
+            return false;
+        }
+        rest = &after[frac..];
+    }
+    if int + frac == 0 {
+        return false;
+    }
+    if let Some(exp) = rest.strip_prefix(['e', 'E']) {
+        let exp = exp.strip_prefix(['+', '-']).unwrap_or(exp);
+        return digits(exp) > 0 && digits(exp) == exp.len();
+    }
+    rest.is_empty()
+}
+
+/// Written so that [`Number::parse`] reads it back
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Number::Integer(int) => write!(f, "{int}"),
+            Number::Float(float) if float.is_nan() => f.write_str("+nan.0"),
+            Number::Float(float) if float.is_infinite() => {
+                f.write_str(if float > 0.0 { "+inf.0" } else { "-inf.0" })
+            }
+            Number::Float(float) if float.fract() == 0.0 && float.abs() < 1e16 => {
+                write!(f, "{float:.1}")
+            }
+            Number::Float(float) => write!(f, "{float:?}"),
+        }
+    }
+}
+
+impl Atom<'_> {
+    /// The value of an identifier that is a number, see [`Number::parse`]
+    pub fn as_number(&self) -> Option<Number> {
+        self.as_identifier().and_then(Number::parse)
+    }
+
+    pub fn is_number(&self) -> bool {
+        self.as_number().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_decimals() {
+        let cases = [
+            ("0", Some(Number::Integer(0))),
+            ("-17", Some(Number::Integer(-17))),
+            ("+3", Some(Number::Integer(3))),
+            ("1.5", Some(Number::Float(1.5))),
+            (".5", Some(Number::Float(0.5))),
+            ("2e3", Some(Number::Float(2000.0))),
+            ("-2.5E-1", Some(Number::Float(-0.25))),
+            ("99999999999999999999", Some(Number::Float(1e20))),
+            ("-inf.0", Some(Number::Float(f64::NEG_INFINITY))),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(Number::parse(text), expected, "{text}");
+        }
+        for text in [
+            "", "+", "-", ".", "1.", "e3", "1e", "1e+", "inf", "nan", "1x", "1_000", "--1",
+        ] {
+            assert_eq!(Number::parse(text), None, "{text}");
+        }
+        assert!(matches!(Number::parse("+nan.0"), Some(Number::Float(f)) if f.is_nan()));
+    }
+
+    #[test]
+    fn display_reads_back() {
+        for text in ["42", "-1.5", "2.0", "1e20", "0.1", "+inf.0", "-inf.0"] {
+            let number = Number::parse(text).unwrap();
+            assert_eq!(Number::parse(&number.to_string()), Some(number), "{text}");
+        }
+        assert_eq!(Number::Float(2.0).to_string(), "2.0");
+    }
+}
@@ -1,87 +1,729 @@
-use crate::lexer::Token;
+use crate::diagnostic::{Diagnostic, Label};
+use crate::lexer::{SpannedToken, Token};
+use crate::reader_macro::{ReaderMacro, ReaderMacros};
+use crate::refactor::TextEdit;
+use crate::source_map::{FileId, Span};
 use crate::value::*;
-use std::{cmp::Ordering, iter::Peekable, ops::Range};
+use std::{borrow::Cow, cell::Cell, cmp::Ordering, collections::VecDeque, fmt};
+
+/// What the parser was looking for when it found something else
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expected {
+    /// One specific token, like a closing delimiter
+    Token(Token<'static>),
+    /// Anything that can start an atom: an identifier, a string or an opening delimiter
+    Atom,
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Token(tok) => tok.fmt(f),
+            Expected::Atom => f.write_str("identifier, string, `(`, `[` or `{`"),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum ParseError<'src> {
     MismatchedToken {
-        expected: Token<'static>,
+        expected: Expected,
         found: Token<'src>,
-        span: Range<usize>,
+        span: Span,
     },
     ExpectedTokFoundEof {
-        expected: Token<'static>,
+        expected: Expected,
         pos: Option<usize>,
     },
     ExpectedEofFoundToken {
         found: Token<'src>,
-        span: Range<usize>,
+        span: Span,
+    },
+    /// A group was closed with the wrong kind of delimiter, like `(a]`
+    MismatchedDelimiter {
+        expected: Token<'static>,
+        found: Token<'src>,
+        open_span: Span,
+        span: Span,
+    },
+    /// The input ended inside of an explicit group
+    UnclosedGroup {
+        group_type: GroupType,
+        open_span: Span,
+        /// where the input ended, the recovering parser closes the group here
+        end: usize,
+    },
+    /// Groups are nested deeper than [`Parser::set_max_depth`] allows
+    TooDeep {
+        span: Span,
+        limit: usize,
+    },
+    /// A [reader macro](crate::reader_macro) didn't accept its input
+    ReaderMacro {
+        message: String,
+        span: Span,
     },
+    /// The source is over 4 GiB, which spans can't point into
+    TooLong {
+        len: usize,
+    },
+}
+
+impl fmt::Display for ParseError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MismatchedToken {
+                expected, found, ..
+            } => write!(f, "expected {expected}, found {found}"),
+            ParseError::ExpectedTokFoundEof { expected, .. } => {
+                write!(f, "expected {expected}, found end of input")
+            }
+            ParseError::ExpectedEofFoundToken { found, .. } => {
+                write!(f, "expected end of input, found {found}")
+            }
+            ParseError::MismatchedDelimiter {
+                expected, found, ..
+            } => write!(
+                f,
+                "mismatched closing delimiter: expected {expected}, found {found}"
+            ),
+            ParseError::UnclosedGroup { group_type, .. } => {
+                write!(f, "unclosed delimiter `{}`", group_type.delimiters().0)
+            }
+            ParseError::TooDeep { limit, .. } => {
+                write!(f, "groups are nested more than {limit} levels deep")
+            }
+            ParseError::ReaderMacro { message, .. } => f.write_str(message),
+            ParseError::TooLong { len } => {
+                write!(f, "the source is {len} bytes long, the limit is 4 GiB")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError<'_> {}
+
+/// Prefix that makes the following group opt out of all desugaring
+pub const RAW_MARKER: &str = "#raw";
+
+/// Which set of indentation rules the parser applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dialect {
+    /// SRFI 110 sweet-expressions
+    #[default]
+    Sweet,
+    /// SRFI 119 wisp: every line is a list, a leading `.` continues the
+    /// parent list instead and `:` opens a list that lasts until the end of the line
+    Wisp,
+}
+
+/// Default for [`ParserOptions::max_depth`]
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Everything that can be configured about a [`Parser`]
+///
+/// ```
+/// use sweet_expr::parser::{Dialect, Parser, ParserOptions};
+///
+/// let options = ParserOptions::new().dialect(Dialect::Wisp).max_depth(64);
+/// let parser = Parser::with_options(sweet_expr::lexer::tokenise("a b"), options);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParserOptions {
+    pub dialect: Dialect,
+    /// See [`Parser::set_max_depth`]
+    pub max_depth: usize,
+    /// Tabs in indentation advance to the next multiple of this many columns.
+    /// The default of 1 makes a tab count the same as a space.
+    pub tab_width: usize,
+    /// Makes [`Parser::parse_toplevel`] recover from errors like
+    /// [`Parser::parse_toplevel_recovering`] does, the errors are available
+    /// from [`Parser::errors`] afterwards
+    pub recover: bool,
+    /// See [`Parser::set_report_eof_closes`]
+    pub report_eof_closes: bool,
+    /// See [`Parser::set_warn_spaced_neoteric`]
+    pub warn_spaced_neoteric: bool,
+    /// See [`Parser::set_warn_lone_atoms`]
+    pub warn_lone_atoms: bool,
+    /// Warns about spaces and tabs at the end of a line. The
+    /// [`WhitespaceNormalizer`] reports them, so this has to be set before
+    /// the tokens go through it.
+    pub warn_trailing_whitespace: bool,
+    /// Attached to every span in the tree, so spans from different files can
+    /// be told apart once they're mixed
+    pub file: Option<FileId>,
+    /// Groups in which indentation stays significant instead of being
+    /// ignored, see [`Delimiters`]
+    pub indentation_in: Delimiters,
+    /// Handlers for identifiers with a registered prefix, see
+    /// [`reader_macro`](crate::reader_macro)
+    pub reader_macros: ReaderMacros,
+}
+
+/// A selection of explicit group kinds.
+///
+/// Used for [`ParserOptions::indentation_in`]: the first line of such a group
+/// is parsed as usual, every following line becomes a child just like the
+/// lines of an indentation block, e.g.
+///
+/// ```text
+/// config [
+///     name "x"
+///     ports 80 443
+/// ]
+/// ```
+///
+/// is `config [(name "x") (ports 80 443)]`. Inside of groups that ignore
+/// indentation, it stays ignored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Delimiters {
+    pub parens: bool,
+    pub brackets: bool,
+    pub curlies: bool,
+}
+
+impl Delimiters {
+    pub const NONE: Delimiters = Delimiters {
+        parens: false,
+        brackets: false,
+        curlies: false,
+    };
+    pub const BRACKETS: Delimiters = Delimiters {
+        brackets: true,
+        ..Delimiters::NONE
+    };
+
+    fn contains(self, opener: &Token) -> bool {
+        match opener {
+            Token::ParenOpen => self.parens,
+            Token::BracketOpen => self.brackets,
+            Token::CurlyOpen => self.curlies,
+            _ => false,
+        }
+    }
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            dialect: Dialect::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            tab_width: 1,
+            recover: false,
+            report_eof_closes: false,
+            warn_spaced_neoteric: false,
+            warn_lone_atoms: false,
+            warn_trailing_whitespace: false,
+            file: None,
+            indentation_in: Delimiters::NONE,
+            reader_macros: ReaderMacros::new(),
+        }
+    }
+}
+
+impl ParserOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width;
+        self
+    }
+
+    pub fn recover(mut self, recover: bool) -> Self {
+        self.recover = recover;
+        self
+    }
+
+    pub fn report_eof_closes(mut self, report: bool) -> Self {
+        self.report_eof_closes = report;
+        self
+    }
+
+    pub fn warn_spaced_neoteric(mut self, warn: bool) -> Self {
+        self.warn_spaced_neoteric = warn;
+        self
+    }
+
+    pub fn warn_lone_atoms(mut self, warn: bool) -> Self {
+        self.warn_lone_atoms = warn;
+        self
+    }
+
+    pub fn warn_trailing_whitespace(mut self, warn: bool) -> Self {
+        self.warn_trailing_whitespace = warn;
+        self
+    }
+
+    pub fn file(mut self, file: FileId) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    pub fn indentation_in(mut self, delimiters: Delimiters) -> Self {
+        self.indentation_in = delimiters;
+        self
+    }
+
+    pub fn reader_macros(mut self, macros: ReaderMacros) -> Self {
+        self.reader_macros = macros;
+        self
+    }
+}
+
+/// A position to rewind a [`Parser`] to, see [`Parser::checkpoint`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    // counted from the start of the input, not of the token buffer
+    cur_tok: usize,
+    depth: usize,
+    raw_depth: usize,
+    errors: usize,
+    warnings: usize,
+    eof_closes: usize,
 }
 
+// the lexer and indentation layer behind `Parser::new`, type-erased so the
+// parser doesn't need another type parameter
+trait TokenSource<'src>: Iterator<Item = SpannedToken<'src>> {
+    fn take_warnings(&mut self) -> Vec<Diagnostic>;
+}
+
+impl<'src, I> TokenSource<'src> for WhitespaceNormalizer<'src, I>
+where
+    I: Iterator<Item = SpannedToken<'src>>,
+{
+    fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        WhitespaceNormalizer::take_warnings(self)
+    }
+}
+
+// tokens are pulled in batches, small enough to still be in cache when the
+// parser gets to them
+const FILL_BATCH: usize = 256;
+
+/// Turns tokens into atoms.
+///
+/// No input makes the parser panic: whatever isn't valid is a [`ParseError`],
+/// and so is nesting deeper than [`ParserOptions::max_depth`], which would
+/// overflow the stack otherwise. [`crate::parse_lossy`] is the entry point
+/// for fuzzing this.
 pub struct Parser<'src> {
-    tokens: Vec<(Token<'src>, Range<usize>)>,
+    // borrowed when the tokens come from the embedder
+    tokens: Cow<'src, [SpannedToken<'src>]>,
     cur_tok: usize,
+    // when parsing straight from the lexer, tokens are pulled into `tokens`
+    // one at a time as the parser reaches them instead of all up front
+    source: Option<Box<dyn TokenSource<'src> + 'src>>,
+    // and the ones a finished form was made of are dropped again, this many
+    // so far
+    dropped: usize,
+    // the end of the last dropped token that wasn't layout, for `eof_pos`
+    dropped_end: Option<usize>,
+    // the oldest position a checkpoint was taken at, tokens from there on
+    // are kept since the parser might be rewound to it
+    pinned: Cell<usize>,
+    // the warnings from `source` are kept in front of the parser's own ones,
+    // so rewinding to a checkpoint doesn't drop them
+    layout_warnings: usize,
+    options: ParserOptions,
+    // number of `#raw` groups the parser is currently inside of
+    raw_depth: usize,
+    // when set, errors are collected here instead of aborting the parse
+    recover: bool,
+    errors: Vec<ParseError<'src>>,
+    warnings: Vec<Diagnostic>,
+    depth: usize,
+    // start spans of the indentation blocks that were still open at the end
+    // of the input
+    eof_closes: Vec<Span>,
 }
 
 impl<'src> Parser<'src> {
-    pub fn new(tokens: impl Iterator<Item = (Token<'src>, Range<usize>)>) -> Self {
+    pub fn new(tokens: impl Iterator<Item = SpannedToken<'src>> + 'src) -> Self {
+        Self::with_options(tokens, ParserOptions::default())
+    }
+
+    pub fn with_dialect(
+        tokens: impl Iterator<Item = SpannedToken<'src>> + 'src,
+        dialect: Dialect,
+    ) -> Self {
+        Self::with_options(tokens, ParserOptions::new().dialect(dialect))
+    }
+
+    /// The tokens are normalized lazily, as the parser reaches them
+    pub fn with_options(
+        tokens: impl Iterator<Item = SpannedToken<'src>> + 'src,
+        options: ParserOptions,
+    ) -> Self {
+        let source = WhitespaceNormalizer::with_options(tokens, &options);
+        let mut parser = Self::from_cow(Cow::Owned(vec![]), options.dialect);
+        parser.options = options;
+        parser.source = Some(Box::new(source));
+        parser.fill(1);
+        parser
+    }
+
+    /// Creates a parser from tokens that already went through
+    /// [`handle_whitespace`]
+    pub fn from_indented(tokens: Vec<SpannedToken<'src>>, dialect: Dialect) -> Self {
+        Self::from_cow(Cow::Owned(tokens), dialect)
+    }
+
+    /// [`Parser::from_indented`] with all the options, the tokens went through
+    /// a [`WhitespaceNormalizer`] with the same ones
+    pub fn from_indented_with_options(
+        tokens: Vec<SpannedToken<'src>>,
+        options: ParserOptions,
+    ) -> Self {
+        let mut parser = Self::from_cow(Cow::Owned(tokens), options.dialect);
+        parser.options = options;
+        parser
+    }
+
+    /// [`Parser::from_indented`] without taking ownership of the tokens, e.g.
+    /// when they are cached or spliced together from several sources
+    ///
+    /// ```
+    /// use sweet_expr::lexer::tokenise;
+    /// use sweet_expr::parser::{handle_whitespace, Dialect, Parser};
+    ///
+    /// let tokens = handle_whitespace(tokenise("a b\nc d\n"));
+    /// for _ in 0..2 {
+    ///     let atoms = Parser::from_tokens(&tokens, Dialect::Sweet).parse_toplevel();
+    ///     assert_eq!(atoms.unwrap().len(), 2);
+    /// }
+    /// ```
+    pub fn from_tokens(tokens: &'src [SpannedToken<'src>], dialect: Dialect) -> Self {
+        Self::from_cow(Cow::Borrowed(tokens), dialect)
+    }
+
+    fn from_cow(tokens: Cow<'src, [SpannedToken<'src>]>, dialect: Dialect) -> Self {
         Self {
-            tokens: handle_whitespace(tokens),
+            tokens,
             cur_tok: 0,
+            source: None,
+            dropped: 0,
+            dropped_end: None,
+            pinned: Cell::new(usize::MAX),
+            layout_warnings: 0,
+            options: ParserOptions::new().dialect(dialect),
+            raw_depth: 0,
+            recover: false,
+            errors: vec![],
+            warnings: vec![],
+            depth: 0,
+            eof_closes: vec![],
         }
     }
 
-    fn advance(&mut self) {
-        self.cur_tok += 1;
+    pub fn options(&self) -> &ParserOptions {
+        &self.options
+    }
+
+    /// Limits how deeply groups (explicit or by indentation) can be nested
+    /// before parsing fails with [`ParseError::TooDeep`]. The parser is
+    /// recursive, so this protects against running out of stack on
+    /// malicious input.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.options.max_depth = max_depth;
+    }
+
+    /// Adds a note to [`Parser::warnings`] listing the indentation blocks
+    /// that were still open when the input ended. That's perfectly valid, but
+    /// it's also what a truncated file looks like.
+    pub fn set_report_eof_closes(&mut self, report: bool) {
+        self.options.report_eof_closes = report;
     }
 
-    fn peek_tok(&self, n: usize) -> Option<(Token<'src>, Range<usize>)> {
-        self.tokens.get(self.cur_tok + n).cloned()
+    /// Warns about `f (x)`, which is two elements but looks a lot like the
+    /// neoteric expression `f(x)`
+    pub fn set_warn_spaced_neoteric(&mut self, warn: bool) {
+        self.options.warn_spaced_neoteric = warn;
     }
 
-    fn last_tok_span(&self) -> Option<Range<usize>> {
-        self.tokens.get(self.cur_tok - 1).map(|x| x.1.clone())
+    /// Warns about an identifier alone on a line of an indentation block,
+    /// which is the identifier itself and not a call of it
+    pub fn set_warn_lone_atoms(&mut self, warn: bool) {
+        self.options.warn_lone_atoms = warn;
     }
 
-    fn expect(
+    /// Runs `f` one nesting level deeper
+    fn nested<T>(
         &mut self,
-        expected: Token<'static>,
-    ) -> Result<(Token<'src>, Range<usize>), ParseError<'src>> {
-        if let Some((tok, span)) = self.peek_tok(0) {
+        f: impl FnOnce(&mut Self) -> Result<T, ParseError<'src>>,
+    ) -> Result<T, ParseError<'src>> {
+        if self.depth >= self.options.max_depth {
+            let span = match self.peek_tok(0) {
+                Some((_, span)) => span,
+                None => Span::at(self.eof_pos().unwrap_or(0)),
+            };
+            return Err(ParseError::TooDeep {
+                span,
+                limit: self.options.max_depth,
+            });
+        }
+
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Remembers the current position, so parsing can continue from here
+    /// again after a speculative attempt with [`Parser::restore`]
+    pub fn checkpoint(&self) -> Checkpoint {
+        let cur_tok = self.dropped + self.cur_tok;
+        self.pinned.set(self.pinned.get().min(cur_tok));
+        Checkpoint {
+            cur_tok,
+            depth: self.depth,
+            raw_depth: self.raw_depth,
+            errors: self.errors.len(),
+            warnings: self.warnings.len() - self.layout_warnings,
+            eof_closes: self.eof_closes.len(),
+        }
+    }
+
+    /// Rewinds to `checkpoint`, dropping the errors and warnings that were
+    /// recorded since. The checkpoint has to come from this parser.
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        self.cur_tok = checkpoint.cur_tok - self.dropped;
+        self.depth = checkpoint.depth;
+        self.raw_depth = checkpoint.raw_depth;
+        self.errors.truncate(checkpoint.errors);
+        self.warnings
+            .truncate(self.layout_warnings + checkpoint.warnings);
+        self.eof_closes.truncate(checkpoint.eof_closes);
+    }
+
+    /// The errors [`Parser::parse_toplevel`] recovered from when
+    /// [`ParserOptions::recover`] is set
+    pub fn errors(&self) -> &[ParseError<'src>] {
+        &self.errors
+    }
+
+    /// Non-fatal diagnostics for things that parse, but look suspicious.
+    /// Those are collected while parsing, so this is only complete after
+    /// the parse is done.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    pub(crate) fn advance(&mut self) {
+        self.cur_tok += 1;
+        if self.cur_tok >= self.tokens.len() {
+            self.fill(self.cur_tok + 1);
+        }
+    }
+
+    /// Pulls tokens from the lexer until there are at least `len` buffered
+    /// (or the input ends)
+    fn fill(&mut self, len: usize) {
+        let Some(source) = &mut self.source else {
+            return;
+        };
+        let tokens = self.tokens.to_mut();
+        while tokens.len() < len {
+            let before = tokens.len();
+            tokens.extend(source.by_ref().take(FILL_BATCH));
+            if tokens.len() - before < FILL_BATCH {
+                break;
+            }
+        }
+        let warnings = source.take_warnings();
+        let at = self.layout_warnings;
+        self.layout_warnings += warnings.len();
+        self.warnings.splice(at..at, warnings);
+        if self.tokens.len() < len {
+            self.source = None;
+        }
+    }
+
+    /// Drops the tokens that were pulled from the lexer and parsed already,
+    /// except for the last one and those a checkpoint could rewind to. Only
+    /// called between top-level forms, so nothing still looks at them.
+    fn drop_taken(&mut self) {
+        let Cow::Owned(tokens) = &mut self.tokens else {
+            return;
+        };
+        let pinned = self.pinned.get().saturating_sub(self.dropped);
+        let n = self.cur_tok.saturating_sub(1).min(pinned);
+        // a batch at a time, so the tokens after them aren't moved for
+        // every form
+        if self.source.is_none() || n < FILL_BATCH {
+            return;
+        }
+        if let Some((_, span)) = tokens[..n]
+            .iter()
+            .rev()
+            .find(|(tok, _)| !matches!(tok, Token::Indent | Token::Dedent | Token::Newline))
+        {
+            self.dropped_end = Some(span.end as usize);
+        }
+        tokens.drain(..n);
+        self.dropped += n;
+        self.cur_tok -= n;
+    }
+
+    #[cfg(test)]
+    pub(crate) fn buffered_tokens(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// The token `n` tokens ahead, borrowed from the token buffer
+    pub(crate) fn peek_tok(&self, n: usize) -> Option<(&Token<'src>, Span)> {
+        self.tokens
+            .get(self.cur_tok + n)
+            .map(|(tok, span)| (tok, *span))
+    }
+
+    /// Whether the next `Dedent` is one of those closing the blocks that are
+    /// still open at the end of the input
+    fn closes_at_eof(&mut self) -> bool {
+        let mut n = self.cur_tok;
+        loop {
+            self.fill(n + 1);
+            match self.tokens.get(n) {
+                Some((Token::Dedent, _)) => n += 1,
+                Some(_) => return false,
+                None => return true,
+            }
+        }
+    }
+
+    pub(crate) fn last_tok_span(&self) -> Option<Span> {
+        let last = self.cur_tok.checked_sub(1)?;
+        self.tokens.get(last).map(|x| x.1)
+    }
+
+    fn expect(&mut self, expected: Token<'static>) -> Result<Span, ParseError<'src>> {
+        if let Some((&tok, span)) = self.peek_tok(0) {
             if tok == expected {
                 self.advance();
-                Ok((tok, span))
+                Ok(span)
             } else {
                 Err(ParseError::MismatchedToken {
-                    expected,
+                    expected: Expected::Token(expected),
                     found: tok,
                     span,
                 })
             }
         } else {
-            let pos = if let Some((_, span)) = self.tokens.get(self.cur_tok - 1).cloned() {
-                Some(span.end)
-            } else {
-                None
-            };
-            Err(ParseError::ExpectedTokFoundEof { expected, pos })
+            Err(ParseError::ExpectedTokFoundEof {
+                expected: Expected::Token(expected),
+                pos: self.eof_pos(),
+            })
         }
     }
 
+    fn only_dedents_left(&mut self) -> bool {
+        let mut n = 0;
+        loop {
+            self.fill(self.cur_tok + n + 1);
+            match self.peek_tok(n) {
+                Some((Token::Dedent, _)) => n += 1,
+                Some(_) => return false,
+                None => return true,
+            }
+        }
+    }
+
+    // the end of the last token that was written, the layout tokens after it
+    // depend on the indentation of the lines before, so an edit further up
+    // would move it
+    fn eof_pos(&self) -> Option<usize> {
+        let taken = &self.tokens[..self.cur_tok.min(self.tokens.len())];
+        taken
+            .iter()
+            .rev()
+            .find(|(tok, _)| !matches!(tok, Token::Indent | Token::Dedent | Token::Newline))
+            .map(|(_, span)| span.end as usize)
+            .or(self.dropped_end)
+            .or_else(|| self.last_tok_span().map(|span| span.end as usize))
+    }
+
     pub fn parse_toplevel(&mut self) -> Result<Vec<Atom<'src>>, ParseError<'src>> {
-        // the whole file might be indented
+        if self.options.recover {
+            let (children, errors) = self.parse_toplevel_recovering();
+            self.errors = errors;
+            return Ok(children);
+        }
+        self.iter_toplevel().collect()
+    }
+
+    /// Parses the top-level forms one at a time, so a consumer can process
+    /// them as they come or stop early. The iterator ends after the first
+    /// error.
+    pub fn iter_toplevel(&mut self) -> TopLevelForms<'_, 'src> {
+        TopLevelForms {
+            parser: self,
+            started: false,
+            is_indented: false,
+            pending: VecDeque::new(),
+            done: false,
+            skip_trailing: false,
+        }
+    }
+
+    /// Like [`Parser::parse_toplevel`], but tokens that can't start a form
+    /// are reported and skipped instead of ending the parse, so everything
+    /// around them is still returned. Errors inside of a form still stop
+    /// parsing, see [`Parser::parse_toplevel_recovering`] for a parser that
+    /// carries on after those as well.
+    pub fn parse_toplevel_partial(&mut self) -> (Vec<Atom<'src>>, Vec<ParseError<'src>>) {
+        let mut forms = self.iter_toplevel();
+        forms.skip_trailing = true;
+
+        let mut children = vec![];
+        let mut errors = vec![];
+        for result in forms {
+            match result {
+                Ok(atom) => children.push(atom),
+                Err(err) => errors.push(err),
+            }
+        }
+        (children, errors)
+    }
+
+    /// Parses exactly one form, anything after it is an error. In the sweet
+    /// dialect that's still a whole line with its indented children, so
+    /// `f x y` is one expression.
+    pub fn parse_expr(&mut self) -> Result<Atom<'src>, ParseError<'src>> {
         let mut is_indented = false;
         if let Some((Token::Indent, _)) = self.peek_tok(0) {
             self.advance();
             is_indented = true;
         }
 
-        // while there's any atom or indent-group, parse those
-        let mut children = vec![];
-
-        while self.atom_start() {
-            children.push(self.parse_maybe_indent_group()?);
+        let mut forms = vec![];
+        match self.peek_tok(0) {
+            _ if self.atom_start() => self.parse_form(&mut forms)?,
+            Some((&found, span)) => {
+                return Err(ParseError::MismatchedToken {
+                    expected: Expected::Atom,
+                    found,
+                    span,
+                })
+            }
+            None => {
+                return Err(ParseError::ExpectedTokFoundEof {
+                    expected: Expected::Atom,
+                    pos: self.eof_pos(),
+                })
+            }
         }
 
         if let Some((Token::Dedent, _)) = self.peek_tok(0) {
@@ -89,24 +731,193 @@ impl<'src> Parser<'src> {
                 self.advance();
             }
         }
-        if let Some((tok, span)) = self.peek_tok(0) {
-            return Err(ParseError::ExpectedEofFoundToken { found: tok, span });
+        if let Some((&found, span)) = self.peek_tok(0) {
+            return Err(ParseError::ExpectedEofFoundToken { found, span });
         }
 
-        Ok(children)
+        // a wisp continuation line (`. a b`) contributes its atoms one by one
+        let mut forms = forms.into_iter();
+        let Some(form) = forms.next() else {
+            return Err(ParseError::ExpectedTokFoundEof {
+                expected: Expected::Atom,
+                pos: self.eof_pos(),
+            });
+        };
+        if let Some(extra) = forms.next() {
+            let start = extra.span().start;
+            let (found, span) = self
+                .tokens
+                .iter()
+                .find(|(_, span)| span.start == start)
+                .cloned()
+                .unwrap_or((Token::Error("unexpected form"), extra.span()));
+            return Err(ParseError::ExpectedEofFoundToken { found, span });
+        }
+        self.flush_eof_closes();
+        Ok(form)
+    }
+
+    /// Like [`Parser::parse_toplevel`], but instead of stopping at the first
+    /// error it records it, inserts an [`Atom::Error`] for the offending
+    /// tokens and carries on, so the valid parts of the input are still
+    /// available.
+    pub fn parse_toplevel_recovering(&mut self) -> (Vec<Atom<'src>>, Vec<ParseError<'src>>) {
+        self.recover = true;
+
+        let mut children = vec![];
+        loop {
+            self.drop_taken();
+            let Some((&tok, span)) = self.peek_tok(0) else {
+                break;
+            };
+            if self.atom_start() {
+                if let Err(err) = self.parse_form(&mut children) {
+                    self.errors.push(err);
+                    self.skip_rest_of_line(&mut children);
+                }
+                continue;
+            }
+
+            match tok {
+                // stray structure, e.g. left over from an earlier error
+                Token::Newline | Token::Indent | Token::Dedent => self.advance(),
+                _ => {
+                    self.errors
+                        .push(ParseError::ExpectedEofFoundToken { found: tok, span });
+                    self.skip_error_token(&mut children);
+                }
+            }
+        }
+
+        self.recover = false;
+        self.flush_eof_closes();
+        (children, std::mem::take(&mut self.errors))
+    }
+
+    fn flush_eof_closes(&mut self) {
+        let closes = std::mem::take(&mut self.eof_closes);
+        if !self.options.report_eof_closes || closes.is_empty() {
+            return;
+        }
+        // blocks are closed from the inside out, so the first one is the
+        // innermost
+        let mut note = Diagnostic::note(
+            "sweet_expr::closed_at_eof",
+            match closes.len() {
+                1 => "the end of the input closes an indented block".to_owned(),
+                n => format!("the end of the input closes {n} indented blocks"),
+            },
+        );
+        for (i, span) in closes.into_iter().enumerate() {
+            note = note.with_label(if i == 0 {
+                Label::primary(span, "innermost block starts here")
+            } else {
+                Label::secondary(span, "enclosing block starts here")
+            });
+        }
+        self.warnings
+            .push(note.with_help("if the file was cut off, the rest of it is missing"));
+    }
+
+    /// Parses one top-level form according to the dialect
+    fn parse_form(&mut self, out: &mut Vec<Atom<'src>>) -> Result<(), ParseError<'src>> {
+        match self.options.dialect {
+            Dialect::Sweet => out.push(self.parse_maybe_indent_group()?),
+            Dialect::Wisp => self.parse_wisp_line(out)?,
+        }
+        Ok(())
+    }
+
+    /// Parses as many atoms as possible, see [`Parser::skip_invalid`] for
+    /// what happens with tokens that can't start an atom
+    fn parse_atoms(
+        &mut self,
+        children: &mut Vec<Atom<'src>>,
+        in_group: bool,
+    ) -> Result<(), ParseError<'src>> {
+        loop {
+            if self.atom_start() {
+                children.push(self.parse_atom()?);
+            } else if !self.skip_invalid(children, in_group) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// In recovery mode, records an error for an invalid token or a stray
+    /// closing delimiter (unless `in_group` is set, then the group handles
+    /// it) and replaces it with an [`Atom::Error`]. Returns whether a token was
+    /// skipped.
+    fn skip_invalid(&mut self, children: &mut Vec<Atom<'src>>, in_group: bool) -> bool {
+        if !self.recover {
+            return false;
+        }
+        match self.peek_tok(0) {
+            Some((&found @ Token::Error(_), span)) => {
+                self.errors.push(ParseError::MismatchedToken {
+                    expected: Expected::Atom,
+                    found,
+                    span,
+                });
+            }
+            Some((
+                &found @ (Token::ParenClose | Token::BracketClose | Token::CurlyClose),
+                span,
+            )) if !in_group => {
+                self.errors
+                    .push(ParseError::ExpectedEofFoundToken { found, span });
+            }
+            _ => return false,
+        }
+        self.skip_error_token(children);
+        true
+    }
+
+    /// Replaces everything up to the next newline with a single
+    /// [`Atom::Error`]
+    fn skip_rest_of_line(&mut self, children: &mut Vec<Atom<'src>>) {
+        let Some((_, start)) = self.peek_tok(0) else {
+            return;
+        };
+        let start = start.start;
+        let mut end = None;
+        while let Some((tok, span)) = self.peek_tok(0) {
+            if *tok == Token::Newline {
+                break;
+            }
+            end = Some(span.end);
+            self.advance();
+        }
+        if let Some(end) = end {
+            children.push(Atom::Error(Spanned(
+                (),
+                Span { start, end },
+                self.options.file,
+            )));
+        }
+    }
+
+    fn skip_error_token(&mut self, children: &mut Vec<Atom<'src>>) {
+        if let Some((_, span)) = self.peek_tok(0) {
+            self.advance();
+            children.push(Atom::Error(Spanned((), span, self.options.file)));
+        }
     }
 
     pub fn parse_maybe_indent_group(&mut self) -> Result<Atom<'src>, ParseError<'src>> {
-        let start_span = if let Some((_, span)) = self.peek_tok(0) {
-            span
-        } else {
-            unreachable!()
+        self.nested(Self::parse_maybe_indent_group_inner)
+    }
+
+    fn parse_maybe_indent_group_inner(&mut self) -> Result<Atom<'src>, ParseError<'src>> {
+        let Some((_, start_span)) = self.peek_tok(0) else {
+            return Err(ParseError::ExpectedTokFoundEof {
+                expected: Expected::Atom,
+                pos: self.eof_pos(),
+            });
         };
         let mut children = vec![];
         // first parse all n-atoms on the current line
-        while self.atom_start() {
-            children.push(self.parse_atom()?);
-        }
+        self.parse_atoms(&mut children, false)?;
 
         // if there's a newline + indent...
         //
@@ -120,7 +931,22 @@ impl<'src> Parser<'src> {
                 // parse more indent groups
 
                 while self.atom_start() {
-                    children.push(self.parse_maybe_indent_group()?);
+                    let child = self.parse_maybe_indent_group()?;
+                    if let Atom::Identifier(Spanned(ident, span, _)) = &child {
+                        if self.options.warn_lone_atoms {
+                            self.warnings.push(
+                                Diagnostic::warning(
+                                    "sweet_expr::lone_atom",
+                                    format!("`{ident}` is alone on its line"),
+                                )
+                                .with_label(Label::primary(*span, "this is not a call"))
+                                .with_help(format!(
+                                    "if `{ident}` should be called, write `({ident})`"
+                                )),
+                            );
+                        }
+                    }
+                    children.push(child);
 
                     if let Some((Token::Dedent, _)) = self.peek_tok(0) {
                         break;
@@ -131,68 +957,190 @@ impl<'src> Parser<'src> {
 
                 match self.peek_tok(0) {
                     Some((Token::Dedent, end_span)) => {
+                        if self.closes_at_eof() {
+                            self.eof_closes.push(start_span);
+                        }
                         self.advance();
                         return Ok(Atom::Group(Group {
                             group_type: GroupType::Indentation,
                             children,
-                            start_delim: Spanned((), start_span),
-                            end_delim: Spanned((), end_span),
+                            start_delim: Spanned((), start_span, self.options.file),
+                            end_delim: Spanned((), end_span, self.options.file),
                         }));
                     }
                     Some((_, end_span)) => {
                         return Ok(Atom::Group(Group {
                             group_type: GroupType::Indentation,
                             children,
-                            start_delim: Spanned((), start_span),
-                            end_delim: Spanned((), end_span),
+                            start_delim: Spanned((), start_span, self.options.file),
+                            end_delim: Spanned((), end_span, self.options.file),
                         }))
                     }
                     None => {
+                        self.eof_closes.push(start_span);
                         // fall through to end-case
                     }
                 };
             }
         }
 
-        if children.len() == 1 {
-            Ok(children.pop().unwrap())
+        match <[Atom; 1]>::try_from(children) {
+            Ok([child]) => Ok(child),
+            Err(children) => {
+                // no new line, so end of file or error? stop here
+                let end_span = self.last_tok_span().unwrap_or(start_span);
+                Ok(Atom::Group(Group {
+                    group_type: GroupType::Indentation,
+                    children,
+                    start_delim: Spanned((), start_span, self.options.file),
+                    end_delim: Spanned((), end_span, self.options.file),
+                }))
+            }
+        }
+    }
+
+    /// Parses one wisp line together with its indented children and pushes
+    /// the result to `out`.
+    ///
+    /// A line normally becomes a single list, but a line starting with `.`
+    /// pushes its elements to `out` directly.
+    fn parse_wisp_line(&mut self, out: &mut Vec<Atom<'src>>) -> Result<(), ParseError<'src>> {
+        self.nested(|parser| parser.parse_wisp_line_inner(out))
+    }
+
+    fn parse_wisp_line_inner(&mut self, out: &mut Vec<Atom<'src>>) -> Result<(), ParseError<'src>> {
+        let Some((_, start_span)) = self.peek_tok(0) else {
+            return Ok(());
+        };
+
+        let continuation = matches!(self.peek_tok(0), Some((Token::Identifier("."), _)));
+        if continuation {
+            self.advance();
+        }
+
+        let mut children = self.parse_wisp_inline()?;
+
+        if let Some((Token::Newline, _)) = self.peek_tok(0) {
+            self.advance();
+            if let Some((Token::Indent, _)) = self.peek_tok(0) {
+                self.advance();
+
+                while self.atom_start() {
+                    self.parse_wisp_line(&mut children)?;
+                }
+
+                match self.peek_tok(0) {
+                    Some((Token::Dedent, _)) => {
+                        if self.closes_at_eof() {
+                            self.eof_closes.push(start_span);
+                        }
+                        self.advance();
+                    }
+                    None => self.eof_closes.push(start_span),
+                    Some(_) => {}
+                }
+            }
+        }
+
+        if continuation {
+            out.append(&mut children);
         } else {
-            // no new line, so end of file or error? stop here
-            let end_span = self.last_tok_span().unwrap();
-            Ok(Atom::Group(Group {
+            let end_span = self.last_tok_span().unwrap_or(start_span);
+            out.push(Atom::Group(Group {
                 group_type: GroupType::Indentation,
+                start_delim: Spanned((), start_span, self.options.file),
                 children,
-                start_delim: Spanned((), start_span),
-                end_delim: Spanned((), end_span),
-            }))
+                end_delim: Spanned((), end_span, self.options.file),
+            }));
+        }
+
+        Ok(())
+    }
+
+    /// Parses the atoms of the current wisp line, where a `:` wraps the rest
+    /// of the line in a new list.
+    fn parse_wisp_inline(&mut self) -> Result<Vec<Atom<'src>>, ParseError<'src>> {
+        self.nested(Self::parse_wisp_inline_inner)
+    }
+
+    fn parse_wisp_inline_inner(&mut self) -> Result<Vec<Atom<'src>>, ParseError<'src>> {
+        let mut children = vec![];
+        loop {
+            if let Some((Token::Identifier(":"), colon_span)) = self.peek_tok(0) {
+                self.advance();
+                let rest = self.parse_wisp_inline()?;
+                let end_span = self.last_tok_span().unwrap_or(colon_span);
+                children.push(Atom::Group(Group {
+                    group_type: GroupType::Indentation,
+                    start_delim: Spanned((), colon_span, self.options.file),
+                    children: rest,
+                    end_delim: Spanned((), end_span, self.options.file),
+                }));
+                break;
+            }
+            if self.atom_start() {
+                children.push(self.parse_atom()?);
+            } else if !self.skip_invalid(&mut children, false) {
+                break;
+            }
         }
+        Ok(children)
     }
 
     pub fn parse_atom(&mut self) -> Result<Atom<'src>, ParseError<'src>> {
-        let Some((tok, span)) = self.peek_tok(0) else {
-            todo!()
+        let Some((&tok, span)) = self.peek_tok(0) else {
+            return Err(ParseError::ExpectedTokFoundEof {
+                expected: Expected::Atom,
+                pos: self.eof_pos(),
+            });
         };
         match tok {
             Token::ParenOpen | Token::BracketOpen | Token::CurlyOpen => {
                 let group = self.parse_explicit_group()?;
-                Ok(Atom::Group(group))
+                self.parse_neoteric_tail(Atom::Group(group))
             }
             Token::Identifier(ident) => {
                 self.advance();
-                let val = Atom::Identifier(Spanned(ident, span.clone()));
+                if let (0, Some((prefix_len, handler))) =
+                    (self.raw_depth, self.options.reader_macros.find(ident))
+                {
+                    // handlers can parse atoms themselves, so they count as
+                    // a level of nesting
+                    return self.nested(|parser| {
+                        handler(&mut ReaderMacro::new(parser, ident, span, prefix_len))
+                    });
+                }
+                let val = Atom::Identifier(Spanned(ident.into(), span, self.options.file));
 
                 if let Some((Token::ParenOpen | Token::BracketOpen | Token::CurlyOpen, next_span)) =
                     self.peek_tok(0)
                 {
-                    if next_span.start == span.end {
-                        // neoteric expression
-                        let group = self.parse_explicit_group()?;
-                        Ok(Atom::Neoteric {
-                            lhs: Box::new(val),
-                            rhs: group,
+                    if next_span.start != span.end || self.raw_depth > 0 {
+                        if self.options.warn_spaced_neoteric
+                            && self.raw_depth == 0
+                            && next_span.start == span.end + 1
+                        {
+                            self.warn_spaced_neoteric(
+                                ident,
+                                Span {
+                                    start: span.end,
+                                    end: next_span.start,
+                                },
+                            );
+                        }
+                        return Ok(val);
+                    }
+
+                    if ident == RAW_MARKER {
+                        self.raw_depth += 1;
+                        let body = self.parse_explicit_group();
+                        self.raw_depth -= 1;
+                        Ok(Atom::Raw {
+                            marker: Spanned(ident.into(), span, self.options.file),
+                            body: body?,
                         })
                     } else {
-                        Ok(val)
+                        self.parse_neoteric_tail(val)
                     }
                 } else {
                     Ok(val)
@@ -200,12 +1148,70 @@ impl<'src> Parser<'src> {
             }
             Token::String(str) => {
                 self.advance();
-                Ok(Atom::String(Spanned(str, span)))
+                let val = Atom::String(Spanned(str.into(), span, self.options.file));
+                self.parse_neoteric_tail(val)
             }
-            _ => todo!(),
+            _ => Err(ParseError::MismatchedToken {
+                expected: Expected::Atom,
+                found: tok,
+                span,
+            }),
         }
     }
 
+    /// Applies `lhs` to every group that directly follows it, left to right:
+    /// `f(x)(y)` is `((f x) y)`
+    fn parse_neoteric_tail(&mut self, mut lhs: Atom<'src>) -> Result<Atom<'src>, ParseError<'src>> {
+        // every link nests the chain one level deeper, `f()()()` is as deep
+        // as `(((f)))`
+        let depth = self.depth;
+        let mut end = lhs.span().end;
+        let result = loop {
+            let next_span = match self.peek_tok(0) {
+                Some((Token::ParenOpen | Token::BracketOpen | Token::CurlyOpen, next_span))
+                    if self.raw_depth == 0 && next_span.start == end =>
+                {
+                    next_span
+                }
+                _ => break Ok(lhs),
+            };
+            if self.depth >= self.options.max_depth {
+                break Err(ParseError::TooDeep {
+                    span: next_span,
+                    limit: self.options.max_depth,
+                });
+            }
+            self.depth += 1;
+            let rhs = match self.parse_explicit_group() {
+                Ok(rhs) => rhs,
+                Err(err) => break Err(err),
+            };
+            end = rhs.span().end;
+            lhs = Atom::Neoteric {
+                lhs: Box::new(lhs),
+                rhs,
+            };
+        };
+        self.depth = depth;
+        result
+    }
+
+    fn warn_spaced_neoteric(&mut self, ident: &str, space: Span) {
+        self.warnings.push(
+            Diagnostic::warning(
+                "sweet_expr::spaced_neoteric",
+                format!("`{ident}` is followed by a group after a single space"),
+            )
+            .with_label(Label::primary(
+                space,
+                "the group is a separate element because of this space",
+            ))
+            .with_help(format!(
+                "to apply `{ident}` to the group, remove the space, e.g. `{ident}(...)`"
+            )),
+        );
+    }
+
     fn atom_start(&self) -> bool {
         let Some((tok, _span)) = self.peek_tok(0) else {
             return false;
@@ -220,248 +1226,689 @@ impl<'src> Parser<'src> {
         )
     }
 
+    /// The lines after the first one in a group with significant
+    /// indentation, each of them is a form of its own
+    fn parse_indented_lines(
+        &mut self,
+        children: &mut Vec<Atom<'src>>,
+    ) -> Result<(), ParseError<'src>> {
+        loop {
+            match self.peek_tok(0) {
+                Some((Token::Newline | Token::Indent | Token::Dedent, _)) => self.advance(),
+                _ if self.atom_start() => self.parse_form(children)?,
+                _ => return Ok(()),
+            }
+        }
+    }
+
     fn parse_explicit_group(&mut self) -> Result<Group<'src>, ParseError<'src>> {
-        let Some((tok, start_span)) = self.peek_tok(0) else {
-            todo!()
+        self.nested(Self::parse_explicit_group_inner)
+    }
+
+    fn parse_explicit_group_inner(&mut self) -> Result<Group<'src>, ParseError<'src>> {
+        let Some((&tok, start_span)) = self.peek_tok(0) else {
+            return Err(ParseError::ExpectedTokFoundEof {
+                expected: Expected::Atom,
+                pos: self.eof_pos(),
+            });
+        };
+        let (to_expect, group_type) = match tok {
+            Token::ParenOpen => (Token::ParenClose, GroupType::Parenthesis),
+            Token::CurlyOpen => (Token::CurlyClose, GroupType::Curly),
+            Token::BracketOpen => (Token::BracketClose, GroupType::Bracket),
+            found => {
+                return Err(ParseError::MismatchedToken {
+                    expected: Expected::Atom,
+                    found,
+                    span: start_span,
+                })
+            }
         };
 
         self.advance();
 
         let mut children = vec![];
-        while self.atom_start() {
-            children.push(self.parse_atom()?);
+        self.parse_atoms(&mut children, true)?;
+        if self.options.indentation_in.contains(&tok) {
+            self.parse_indented_lines(&mut children)?;
         }
 
-        let (to_expect, group_type) = match tok {
-            Token::ParenOpen => (Token::ParenClose, GroupType::Parenthesis),
-            Token::CurlyOpen => (Token::CurlyClose, GroupType::Curly),
-            Token::BracketOpen => (Token::BracketClose, GroupType::Bracket),
-            _ => unreachable!(),
+        let closed = match self.expect(to_expect) {
+            // the dedents closing the blocks at the end of the input depend on
+            // the lines before, the group ends with the input either way
+            Err(ParseError::MismatchedToken {
+                found: Token::Dedent,
+                ..
+            }) if self.only_dedents_left() => Err(ParseError::ExpectedTokFoundEof {
+                expected: Expected::Token(to_expect),
+                pos: self.eof_pos(),
+            }),
+            closed => closed,
+        };
+        let end_span = match closed {
+            Ok(end_span) => end_span,
+            Err(ParseError::MismatchedToken {
+                found: found @ (Token::ParenClose | Token::BracketClose | Token::CurlyClose),
+                span,
+                ..
+            }) => {
+                let err = ParseError::MismatchedDelimiter {
+                    expected: to_expect,
+                    found,
+                    open_span: start_span,
+                    span,
+                };
+                if !self.recover {
+                    return Err(err);
+                }
+                // a wrong closing delimiter still closes the group
+                self.advance();
+                self.errors.push(err);
+                span
+            }
+            Err(ParseError::ExpectedTokFoundEof { pos, .. }) => {
+                let end = pos.unwrap_or(start_span.end as usize);
+                let err = ParseError::UnclosedGroup {
+                    group_type,
+                    open_span: start_span,
+                    end,
+                };
+                if !self.recover {
+                    return Err(err);
+                }
+                // at the end of the input the group is closed implicitly
+                self.errors.push(err);
+                Span::at(end)
+            }
+            Err(ParseError::MismatchedToken {
+                expected,
+                found,
+                span,
+            }) if self.recover => {
+                self.advance();
+                self.errors.push(ParseError::MismatchedToken {
+                    expected,
+                    found,
+                    span,
+                });
+                span
+            }
+            Err(err) => return Err(err),
         };
-
-        let (_tok, end_span) = self.expect(to_expect)?;
 
         Ok(Group {
             group_type,
-            start_delim: Spanned((), start_span),
+            start_delim: Spanned((), start_span, self.options.file),
             children,
-            end_delim: Spanned((), end_span),
+            end_delim: Spanned((), end_span, self.options.file),
         })
     }
 }
 
-pub(crate) fn handle_whitespace<'src>(
-    tokens: impl Iterator<Item = (Token<'src>, Range<usize>)>,
-) -> Vec<(Token<'src>, Range<usize>)> {
-    enum State {
-        Start,
-        StartOfLine,
-        InLine,
-        Ignore(usize),
+// chunks handed to the thread pool have at least this many tokens, so tiny
+// forms don't drown in scheduling overhead
+#[cfg(feature = "rayon")]
+const MIN_CHUNK_TOKENS: usize = 1024;
+
+#[cfg(feature = "rayon")]
+impl<'src> Parser<'src> {
+    /// [`Parser::parse_toplevel`] on the rayon thread pool. Lines starting at
+    /// column 0 outside of any group begin independent forms, runs of those
+    /// are parsed concurrently and merged in order.
+    ///
+    /// The result is the same as when parsing sequentially, including which
+    /// error is reported if there are several. Warnings are not collected.
+    pub fn parse_toplevel_parallel(&mut self) -> Result<Vec<Atom<'src>>, ParseError<'src>> {
+        use rayon::prelude::*;
+
+        self.fill(usize::MAX);
+        let tokens = &self.tokens[self.cur_tok.min(self.tokens.len())..];
+        let options = ParserOptions {
+            recover: false,
+            ..self.options.clone()
+        };
+        let chunks = split_toplevel(tokens, MIN_CHUNK_TOKENS);
+        let results: Vec<_> = chunks
+            .par_iter()
+            .map(|chunk| {
+                let mut parser = Parser::from_indented(chunk.to_vec(), options.dialect);
+                parser.options = options.clone();
+                parser.iter_toplevel().collect::<Result<Vec<_>, _>>()
+            })
+            .collect();
+        self.cur_tok = self.tokens.len();
+
+        let mut atoms = vec![];
+        for result in results {
+            atoms.extend(result?);
+        }
+        Ok(atoms)
     }
+}
 
-    let mut indents = vec![];
+/// Splits normalized tokens in front of lines that start at column 0 outside
+/// of any group, each chunk has at least `min_len` tokens unless it is the
+/// last one
+#[cfg(feature = "rayon")]
+fn split_toplevel<'t, 'src>(
+    tokens: &'t [SpannedToken<'src>],
+    min_len: usize,
+) -> Vec<&'t [SpannedToken<'src>]> {
+    let mut chunks = vec![];
+    let mut start = 0;
+    let mut indent = 0usize;
+    let mut nesting = 0usize;
+    for (i, window) in tokens.windows(2).enumerate() {
+        match window[0].0 {
+            Token::Indent => indent += 1,
+            Token::Dedent => indent = indent.saturating_sub(1),
+            Token::ParenOpen | Token::BracketOpen | Token::CurlyOpen => nesting += 1,
+            Token::ParenClose | Token::BracketClose | Token::CurlyClose => {
+                nesting = nesting.saturating_sub(1)
+            }
+            _ => {}
+        }
+        let line_start = matches!(window[0].0, Token::Newline | Token::Dedent)
+            && !matches!(window[1].0, Token::Newline | Token::Indent | Token::Dedent);
+        let next = i + 1;
+        if line_start && indent == 0 && nesting == 0 && next - start >= min_len {
+            chunks.push(&tokens[start..next]);
+            start = next;
+        }
+    }
+    if start < tokens.len() {
+        chunks.push(&tokens[start..]);
+    }
+    chunks
+}
 
-    let mut toks = vec![];
+/// Iterator returned by [`Parser::iter_toplevel`]
+pub struct TopLevelForms<'p, 'src> {
+    parser: &'p mut Parser<'src>,
+    started: bool,
+    // the whole file might be indented
+    is_indented: bool,
+    // a wisp continuation line can produce several forms at once
+    pending: VecDeque<Atom<'src>>,
+    done: bool,
+    // report tokens after the last form and continue behind them
+    skip_trailing: bool,
+}
 
-    let mut state = State::Start;
+impl<'src> Iterator for TopLevelForms<'_, 'src> {
+    type Item = Result<Atom<'src>, ParseError<'src>>;
 
-    fn pop_stack(stack: &mut Vec<usize>, level: usize) -> Result<usize, ()> {
-        let mut levels_to_pop = 0;
+    fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let last_indent = *stack.last().unwrap_or(&0);
-            match last_indent.cmp(&level) {
-                Ordering::Less => return Err(()),
-                Ordering::Equal => return Ok(levels_to_pop),
-                Ordering::Greater => {
-                    levels_to_pop += 1;
-                    _ = stack.pop();
+            if let Some(atom) = self.pending.pop_front() {
+                return Some(Ok(atom));
+            }
+            if self.done {
+                return None;
+            }
+
+            let parser = &mut *self.parser;
+            parser.drop_taken();
+            if !self.started {
+                self.started = true;
+                if let Some((Token::Indent, _)) = parser.peek_tok(0) {
+                    parser.advance();
+                    self.is_indented = true;
+                }
+            }
+
+            if parser.atom_start() {
+                let mut forms = vec![];
+                if let Err(err) = parser.parse_form(&mut forms) {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+                self.pending.extend(forms);
+                continue;
+            }
+
+            if let Some((Token::Dedent, _)) = parser.peek_tok(0) {
+                if self.is_indented {
+                    parser.advance();
+                    self.is_indented = false;
+                }
+            }
+            if let Some((&found, span)) = parser.peek_tok(0) {
+                if self.skip_trailing {
+                    parser.advance();
+                    // layout around a skipped token isn't worth another error,
+                    // lines indented below it just become forms of their own
+                    if matches!(found, Token::Newline | Token::Indent | Token::Dedent) {
+                        continue;
+                    }
+                } else {
+                    self.done = true;
+                }
+                return Some(Err(ParseError::ExpectedEofFoundToken { found, span }));
+            }
+            self.done = true;
+            parser.flush_eof_closes();
+            return None;
+        }
+    }
+}
+
+/// Replaces spaces, newlines and comments with the `Newline`, `Indent` and
+/// `Dedent` tokens the parser works with. Whitespace inside of explicit groups
+/// is dropped entirely.
+pub fn handle_whitespace<'src>(
+    tokens: impl Iterator<Item = SpannedToken<'src>>,
+) -> Vec<SpannedToken<'src>> {
+    handle_whitespace_with_warnings(tokens, &mut vec![])
+}
+
+/// [`handle_whitespace`], but suspicious indentation is reported to `warnings`
+pub fn handle_whitespace_with_warnings<'src>(
+    tokens: impl Iterator<Item = SpannedToken<'src>>,
+    warnings: &mut Vec<Diagnostic>,
+) -> Vec<SpannedToken<'src>> {
+    handle_whitespace_with_options(tokens, &ParserOptions::default(), warnings)
+}
+
+/// [`handle_whitespace_with_warnings`] taking [`ParserOptions::tab_width`]
+/// and [`ParserOptions::indentation_in`] into account
+pub fn handle_whitespace_with_options<'src>(
+    tokens: impl Iterator<Item = SpannedToken<'src>>,
+    options: &ParserOptions,
+    warnings: &mut Vec<Diagnostic>,
+) -> Vec<SpannedToken<'src>> {
+    let mut normalizer = WhitespaceNormalizer::with_options(tokens, options);
+    let toks = normalizer.by_ref().collect();
+    warnings.append(&mut normalizer.take_warnings());
+    toks
+}
+
+enum State<'src> {
+    Start,
+    StartOfLine,
+    // indentation that only counts once something other than a comment
+    // follows it on the line
+    Indented(&'src str, Span),
+    InLine,
+    Ignore(usize),
+}
+
+// an explicit group that keeps indentation significant
+struct Frame {
+    // the indentation stack when the group was opened
+    depth: usize,
+    // lines inside the group can't be indented less than this
+    floor: usize,
+}
+
+/// [`handle_whitespace_with_options`] as an iterator adapter, tokens are
+/// normalized as they are pulled through it instead of all at once.
+///
+/// ```
+/// use sweet_expr::lexer::{tokenise, Token};
+/// use sweet_expr::parser::WhitespaceNormalizer;
+///
+/// let toks: Vec<_> = WhitespaceNormalizer::new(tokenise("a\n  b"))
+///     .map(|(tok, _)| tok)
+///     .collect();
+/// assert_eq!(
+///     toks,
+///     [
+///         Token::Identifier("a"),
+///         Token::Newline,
+///         Token::Indent,
+///         Token::Identifier("b"),
+///         Token::Dedent,
+///     ]
+/// );
+/// ```
+pub struct WhitespaceNormalizer<'src, I> {
+    tokens: I,
+    state: State<'src>,
+    indents: Vec<usize>,
+    frames: Vec<Frame>,
+    // a single input token can turn into several output tokens
+    out: VecDeque<SpannedToken<'src>>,
+    end: u32,
+    finished: bool,
+    tab_width: usize,
+    significant: Delimiters,
+    warnings: Vec<Diagnostic>,
+    warn_trailing_whitespace: bool,
+    // the spaces right before the current token
+    trailing: Option<Span>,
+}
+
+impl<'src, I> WhitespaceNormalizer<'src, I>
+where
+    I: Iterator<Item = SpannedToken<'src>>,
+{
+    pub fn new(tokens: I) -> Self {
+        Self::with_options(tokens, &ParserOptions::default())
+    }
+
+    pub fn with_options(tokens: I, options: &ParserOptions) -> Self {
+        Self {
+            tokens,
+            state: State::Start,
+            indents: vec![],
+            frames: vec![],
+            out: VecDeque::new(),
+            end: 0,
+            finished: false,
+            tab_width: options.tab_width.max(1),
+            significant: options.indentation_in,
+            warnings: vec![],
+            warn_trailing_whitespace: options.warn_trailing_whitespace,
+            trailing: None,
+        }
+    }
+
+    /// Suspicious indentation and, if enabled, trailing whitespace found in
+    /// the tokens pulled through so far
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn indent_width(&self, s: &str) -> usize {
+        let tab_width = self.tab_width;
+        s.chars().fold(0, |col, c| match c {
+            '\t' => (col / tab_width + 1) * tab_width,
+            _ => col + 1,
+        })
+    }
+
+    fn indent_to(&mut self, indent: usize, span: Span) {
+        if indent > *self.indents.last().unwrap_or(&0) {
+            self.indents.push(indent);
+            self.out.push_back((Token::Indent, span));
+        } else if indent < self.frames.last().map_or(0, |frame| frame.floor) {
+            self.out
+                .push_back((Token::Error("invalid indentation"), span));
+        } else {
+            match pop_stack(&mut self.indents, indent) {
+                Ok(n) => {
+                    for _ in 0..n {
+                        self.out.push_back((Token::Dedent, span));
+                    }
                 }
+                Err(_) => self
+                    .out
+                    .push_back((Token::Error("invalid indentation"), span)),
+            }
+        }
+    }
+
+    fn open(&mut self, tok: Token<'src>, span: Span) -> State<'src> {
+        let significant = self.significant.contains(&tok);
+        self.out.push_back((tok, span));
+        if significant {
+            self.frames.push(Frame {
+                depth: self.indents.len(),
+                floor: *self.indents.last().unwrap_or(&0),
+            });
+            State::InLine
+        } else {
+            State::Ignore(1)
+        }
+    }
+
+    // closing a group with significant indentation also closes the blocks
+    // that were opened inside of it
+    fn close(&mut self, tok: Token<'src>, span: Span) {
+        if let Some(frame) = self.frames.pop() {
+            for _ in frame.depth..self.indents.len() {
+                self.out
+                    .push_back((Token::Dedent, Span::at(span.start as usize)));
             }
+            self.indents.truncate(frame.depth);
         }
+        self.out.push_back((tok, span));
     }
 
-    for (tok, span) in tokens {
-        match state {
+    // by default every tab counts as one level of indentation, same as a
+    // space, so mixing them is almost certainly not what was intended
+    fn check_mixed_indent(&mut self, s: &str, span: &Span) {
+        if s.contains('\t') && s.contains(' ') {
+            let help = match self.tab_width {
+                1 => "a tab counts as a single space of indentation".to_owned(),
+                n => format!("a tab advances to the next multiple of {n} columns"),
+            };
+            self.warnings.push(
+                Diagnostic::warning(
+                    "sweet_expr::mixed_indentation",
+                    "indentation mixes tabs and spaces",
+                )
+                .with_label(Label::primary(*span, "tabs and spaces"))
+                .with_help(help)
+                .with_fix(
+                    "indent with spaces only",
+                    [TextEdit {
+                        span: span.range(),
+                        replacement: " ".repeat(self.indent_width(s)),
+                    }],
+                ),
+            );
+        }
+    }
+
+    // the lexer keeps the whitespace at the end of a line apart from the
+    // line break, so it's the spaces right before a newline
+    fn check_trailing(&mut self, tok: &Token<'src>, span: Span) {
+        let trailing = self.trailing.take();
+        match tok {
+            Token::Spaces(_) => self.trailing = Some(span),
+            Token::Newline if self.warn_trailing_whitespace => {
+                if let Some(trailing) = trailing {
+                    self.warnings.push(
+                        Diagnostic::warning(
+                            "sweet_expr::trailing_whitespace",
+                            "trailing whitespace",
+                        )
+                        .with_label(Label::primary(trailing, "remove this"))
+                        .with_fix(
+                            "remove the trailing whitespace",
+                            [TextEdit {
+                                span: trailing.range(),
+                                replacement: String::new(),
+                            }],
+                        ),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn push(&mut self, tok: Token<'src>, span: Span) {
+        self.end = span.end;
+        match self.state {
             State::Start => match tok {
                 Token::Identifier(_) | Token::String(_) => {
-                    toks.push((tok, span));
-                    state = State::InLine;
+                    self.out.push_back((tok, span));
+                    self.state = State::InLine;
                 }
                 Token::Comment => {
                     // comment can only be followed by a newline or
                     // the end of the file, so we just skip it and don't change
                     // the state.
-                    continue;
                 }
                 Token::Spaces(s) => {
                     // same as on a new line, check for indentation.
-                    let indent = s.len();
-
-                    if indent > *indents.last().unwrap_or(&0) {
-                        indents.push(indent);
-                        toks.push((Token::Indent, span));
-                    } else {
-                        match pop_stack(&mut indents, indent) {
-                            Ok(n) => {
-                                for _ in 0..n {
-                                    toks.push((Token::Dedent, span.clone()));
-                                }
-                            }
-                            Err(_) => toks.push((Token::Error("Invalid indentation"), span)),
-                        }
-                    }
-
-                    state = State::InLine;
+                    self.state = State::Indented(s, span);
                 }
 
                 Token::ParenOpen | Token::CurlyOpen | Token::BracketOpen => {
-                    toks.push((tok, span));
-                    state = State::Ignore(1);
+                    self.state = self.open(tok, span);
                 }
                 Token::ParenClose | Token::CurlyClose | Token::BracketClose => {
                     // weird to start with those, but let's just let the parser
                     // handle it
-                    toks.push((tok, span));
-                    state = State::InLine;
+                    self.close(tok, span);
+                    self.state = State::InLine;
                 }
                 Token::Newline => {
                     // starting with a new-line is like as if the next line is
                     // the start
-                    continue;
                 }
                 Token::Error(_) | Token::Indent | Token::Dedent => {
                     // all else, pass through
-                    toks.push((tok, span));
+                    self.out.push_back((tok, span));
                 }
             },
             State::StartOfLine => {
                 if !matches!(tok, Token::Spaces(_) | Token::Newline | Token::Comment) {
                     // This could be a dedent too
 
-                    if *indents.last().unwrap_or(&0) > 0 {
-                        match pop_stack(&mut indents, 0) {
-                            Ok(n) => {
-                                for _ in 0..n {
-                                    toks.push((Token::Dedent, span.clone()));
-                                }
-                            }
-                            Err(_) => {
-                                toks.push((Token::Error("Invalid indentation"), span.clone()))
-                            }
-                        }
+                    if *self.indents.last().unwrap_or(&0) > 0 {
+                        self.indent_to(0, span);
                     }
                 }
                 match tok {
                     Token::Identifier(_) | Token::String(_) => {
-                        toks.push((tok, span));
-                        state = State::InLine;
+                        self.out.push_back((tok, span));
+                        self.state = State::InLine;
                     }
                     Token::Comment => {
                         // comments are always followed by a new-line, so we just
                         // skip and let the newline handle the rest.
-                        continue;
                     }
                     Token::ParenOpen | Token::CurlyOpen | Token::BracketOpen => {
-                        toks.push((tok, span));
-                        state = State::Ignore(1);
+                        self.state = self.open(tok, span);
                     }
                     Token::ParenClose | Token::CurlyClose | Token::BracketClose => {
-                        // weird to have them, let's just pass them to the parser
-                        toks.push((tok, span));
-                        state = State::InLine;
+                        // either closes a group with significant indentation
+                        // or is weird, either way the parser handles it
+                        self.close(tok, span);
+                        self.state = State::InLine;
                     }
 
                     Token::Newline => {
                         // empty line! let's just skip this one
-                        state = State::StartOfLine;
-                        continue;
                     }
                     Token::Spaces(s) => {
-                        // same as on a new line, check for indentation.
-                        let indent = s.len();
-
-                        if indent > *indents.last().unwrap_or(&0) {
-                            indents.push(indent);
-                            toks.push((Token::Indent, span));
-                        } else {
-                            match pop_stack(&mut indents, indent) {
-                                Ok(n) => {
-                                    for _ in 0..n {
-                                        toks.push((Token::Dedent, span.clone()));
-                                    }
-                                }
-                                Err(_) => toks.push((Token::Error("Invalid indentation"), span)),
-                            }
-                        }
-
-                        state = State::InLine;
+                        self.state = State::Indented(s, span);
                     }
                     Token::Error(_) | Token::Indent | Token::Dedent => {
-                        toks.push((tok, span));
-                        state = State::InLine;
+                        self.out.push_back((tok, span));
+                        self.state = State::InLine;
                     }
                 }
             }
+            State::Indented(indent, indent_span) => match tok {
+                // lines with nothing but a comment don't count
+                Token::Comment => {}
+                Token::Newline => self.state = State::StartOfLine,
+                _ => {
+                    self.check_mixed_indent(indent, &indent_span);
+                    self.indent_to(self.indent_width(indent), indent_span);
+                    self.state = State::InLine;
+                    self.push(tok, span);
+                }
+            },
             State::InLine => match tok {
-                Token::Identifier(_) | Token::String(_) => {
-                    toks.push((tok, span));
-                    state = State::InLine;
+                Token::Identifier(_) | Token::String(_) | Token::Error(_) => {
+                    self.out.push_back((tok, span));
                 }
                 Token::Comment => {
                     // comments are always followed by a new-line, so we just
                     // skip and let the newline handle the rest.
-                    continue;
                 }
                 Token::ParenOpen | Token::CurlyOpen | Token::BracketOpen => {
-                    toks.push((tok, span));
-                    state = State::Ignore(1);
+                    self.state = self.open(tok, span);
                 }
                 Token::ParenClose | Token::CurlyClose | Token::BracketClose => {
-                    toks.push((tok, span));
-                    continue;
+                    self.close(tok, span);
                 }
                 Token::Newline => {
-                    toks.push((tok, span));
-                    state = State::StartOfLine;
+                    self.out.push_back((tok, span));
+                    self.state = State::StartOfLine;
                 }
                 Token::Spaces(_) => {
                     // inside a line, whitespace is not significant
-                    continue;
                 }
-                Token::Error(_) | Token::Indent | Token::Dedent => {
-                    toks.push((tok, span));
+                Token::Indent | Token::Dedent => {
+                    self.out.push_back((tok, span));
                 }
             },
             State::Ignore(n) => match tok {
                 Token::Identifier(_) | Token::String(_) => {
-                    toks.push((tok, span));
-                    continue;
-                }
-                Token::Comment => {
-                    continue;
+                    self.out.push_back((tok, span));
                 }
+                Token::Comment => {}
                 Token::ParenOpen | Token::CurlyOpen | Token::BracketOpen => {
-                    toks.push((tok, span));
-                    state = State::Ignore(n + 1);
+                    self.out.push_back((tok, span));
+                    self.state = State::Ignore(n + 1);
                 }
                 Token::ParenClose | Token::CurlyClose | Token::BracketClose => {
-                    toks.push((tok, span));
+                    self.out.push_back((tok, span));
                     if n == 1 {
-                        state = State::InLine;
+                        self.state = State::InLine;
                     } else {
-                        state = State::Ignore(n - 1);
+                        self.state = State::Ignore(n - 1);
                     }
                 }
-                Token::Newline | Token::Spaces(_) => {
-                    continue;
-                }
+                Token::Newline | Token::Spaces(_) => {}
                 Token::Error(_) | Token::Indent | Token::Dedent => {
-                    toks.push((tok, span));
+                    self.out.push_back((tok, span));
                 }
             },
         }
     }
+}
 
-    toks
+impl<'src, I> Iterator for WhitespaceNormalizer<'src, I>
+where
+    I: Iterator<Item = SpannedToken<'src>>,
+{
+    type Item = SpannedToken<'src>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(tok) = self.out.pop_front() {
+                return Some(tok);
+            }
+            if self.finished {
+                return None;
+            }
+            match self.tokens.next() {
+                Some((tok, span)) => {
+                    self.check_trailing(&tok, span);
+                    self.push(tok, span);
+                }
+                None => {
+                    self.finished = true;
+                    // blocks still open at the end of the input are closed there
+                    for _ in self.indents.drain(..) {
+                        self.out.push_back((
+                            Token::Dedent,
+                            Span {
+                                start: self.end,
+                                end: self.end,
+                            },
+                        ));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn pop_stack(stack: &mut Vec<usize>, level: usize) -> Result<usize, ()> {
+    let mut levels_to_pop = 0;
+    loop {
+        let last_indent = *stack.last().unwrap_or(&0);
+        match last_indent.cmp(&level) {
+            Ordering::Less => return Err(()),
+            Ordering::Equal => return Ok(levels_to_pop),
+            Ordering::Greater => {
+                levels_to_pop += 1;
+                _ = stack.pop();
+            }
+        }
+    }
 }
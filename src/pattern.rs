@@ -0,0 +1,276 @@
+//! Matching forms against patterns written as sweet-expressions, instead of
+//! picking them apart by index.
+//!
+//! In a pattern `?name` matches any single atom and binds it, `?name...`
+//! binds the rest of a group as a slice and `_` and `_...` do the same
+//! without binding anything. A group can have at most one rest, but it
+//! doesn't have to come last. A variable used twice has to match equal atoms
+//! both times, spans aside. Everything else has to be the same as in the
+//! pattern, with parentheses and indentation counting as the same.
+//!
+//! ```
+//! use sweet_expr::pattern::Pattern;
+//!
+//! let pattern: Pattern = "define ?name(?params...) ?body...".parse().unwrap();
+//! let atom = sweet_expr::parse_one("define f(x y)\n  g x\n  h y\n").unwrap();
+//! let bindings = pattern.matches(&atom).unwrap();
+//! assert_eq!(bindings.get("name").unwrap().as_identifier(), Some("f"));
+//! assert_eq!(bindings.rest("params").unwrap().len(), 2);
+//! assert_eq!(bindings.rest("body").unwrap().len(), 2);
+//!
+//! assert!(pattern.matches(&sweet_expr::parse_one("define x 1").unwrap()).is_none());
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::diagnostic::Diagnostic;
+use crate::value::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    atom: OwnedAtom,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatternError {
+    /// the pattern isn't a single valid expression
+    Syntax(Diagnostic),
+    /// a group with more than one `...` variable, which is ambiguous
+    MultipleRests { span: Range<usize> },
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternError::Syntax(err) => err.fmt(f),
+            PatternError::MultipleRests { .. } => {
+                f.write_str("a group in a pattern can only have one rest variable")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl FromStr for Pattern {
+    type Err = PatternError;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let atom: OwnedAtom = pattern.parse().map_err(PatternError::Syntax)?;
+        check_rests(&atom)?;
+        Ok(Pattern { atom })
+    }
+}
+
+fn check_rests(atom: &Atom) -> Result<(), PatternError> {
+    let group = match atom {
+        Atom::Group(group) => group,
+        Atom::Neoteric { lhs, rhs } => {
+            check_rests(lhs)?;
+            rhs
+        }
+        _ => return Ok(()),
+    };
+    if group
+        .children
+        .iter()
+        .filter(|child| rest_name(child).is_some())
+        .count()
+        > 1
+    {
+        return Err(PatternError::MultipleRests {
+            span: group.span().range(),
+        });
+    }
+    group.children.iter().try_for_each(check_rests)
+}
+
+// `Some("")` for `_...`
+fn rest_name<'a>(atom: &'a Atom) -> Option<&'a str> {
+    let ident = atom.as_identifier()?.strip_suffix("...")?;
+    match ident {
+        "_" => Some(""),
+        _ => ident.strip_prefix('?'),
+    }
+}
+
+/// What the variables of a pattern matched
+#[derive(Debug, Clone, Default)]
+pub struct Bindings<'a, 'src> {
+    single: HashMap<String, &'a Atom<'src>>,
+    rests: HashMap<String, &'a [Atom<'src>]>,
+}
+
+impl<'a, 'src> Bindings<'a, 'src> {
+    /// The atom `?name` matched
+    pub fn get(&self, name: &str) -> Option<&'a Atom<'src>> {
+        self.single.get(name).copied()
+    }
+
+    /// The atoms `?name...` matched
+    pub fn rest(&self, name: &str) -> Option<&'a [Atom<'src>]> {
+        self.rests.get(name).copied()
+    }
+}
+
+impl Pattern {
+    /// The bindings if `atom` has the shape of the pattern
+    pub fn matches<'a, 'src>(&self, atom: &'a Atom<'src>) -> Option<Bindings<'a, 'src>> {
+        let mut bindings = Bindings::default();
+        match_atom(&self.atom, atom, &mut bindings).then_some(bindings)
+    }
+}
+
+fn match_atom<'a, 'src>(
+    pattern: &Atom,
+    atom: &'a Atom<'src>,
+    bindings: &mut Bindings<'a, 'src>,
+) -> bool {
+    match pattern {
+        Atom::Identifier(ident) if ident.0 == "_" => true,
+        Atom::Identifier(ident) if ident.starts_with('?') => {
+            let name = &ident.0[1..];
+            match bindings.single.get(name) {
+                Some(bound) => bound.eq_ignoring_spans(atom),
+                None => {
+                    bindings.single.insert(name.to_string(), atom);
+                    true
+                }
+            }
+        }
+        Atom::Identifier(ident) => atom.as_identifier() == Some(&ident.0),
+        Atom::String(string) => atom.as_string() == Some(&string.0),
+        Atom::Group(pattern) => match atom {
+            Atom::Group(group) => match_group(pattern, group, bindings),
+            _ => false,
+        },
+        Atom::Neoteric {
+            lhs: p_lhs,
+            rhs: p_rhs,
+        } => match atom {
+            Atom::Neoteric { lhs, rhs } => {
+                match_atom(p_lhs, lhs, bindings) && match_group(p_rhs, rhs, bindings)
+            }
+            _ => false,
+        },
+        Atom::Raw { .. } | Atom::Error(_) => pattern.eq_ignoring_spans(atom),
+    }
+}
+
+fn match_group<'a, 'src>(
+    pattern: &Group,
+    group: &'a Group<'src>,
+    bindings: &mut Bindings<'a, 'src>,
+) -> bool {
+    let explicit = |group_type| match group_type {
+        GroupType::Indentation => GroupType::Parenthesis,
+        other => other,
+    };
+    if explicit(pattern.group_type) != explicit(group.group_type) {
+        return false;
+    }
+
+    let (patterns, atoms) = (&pattern.children, &group.children);
+    let Some(rest) = patterns.iter().position(|p| rest_name(p).is_some()) else {
+        return patterns.len() == atoms.len()
+            && patterns
+                .iter()
+                .zip(atoms)
+                .all(|(p, atom)| match_atom(p, atom, bindings));
+    };
+    let after = patterns.len() - rest - 1;
+    if atoms.len() < patterns.len() - 1 {
+        return false;
+    }
+    let middle = rest..atoms.len() - after;
+    let ends_match = patterns[..rest]
+        .iter()
+        .zip(&atoms[..rest])
+        .chain(patterns[rest + 1..].iter().zip(&atoms[middle.end..]))
+        .all(|(p, atom)| match_atom(p, atom, bindings));
+    if !ends_match {
+        return false;
+    }
+
+    let name = rest_name(&patterns[rest]).unwrap();
+    let matched = &atoms[middle];
+    match bindings.rests.get(name) {
+        _ if name.is_empty() => true,
+        Some(bound) => {
+            bound.len() == matched.len()
+                && bound
+                    .iter()
+                    .zip(matched)
+                    .all(|(a, b)| a.eq_ignoring_spans(b))
+        }
+        None => {
+            bindings.rests.insert(name.to_string(), matched);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(text: &str) -> Pattern {
+        text.parse().unwrap()
+    }
+
+    fn atom(source: &str) -> OwnedAtom {
+        source.parse().unwrap()
+    }
+
+    #[test]
+    fn binds_variables() {
+        let import = pattern("import ?module _...");
+        let form = atom("import (std io) a b");
+        let bindings = import.matches(&form).unwrap();
+        assert_eq!(bindings.get("module").unwrap().to_string(), "(std io)");
+        assert!(bindings.rest("").is_none());
+
+        let pair = pattern("(?a ?a)");
+        assert!(pair.matches(&atom("(f(x) f(x))")).is_some());
+        assert!(pair.matches(&atom("(f(x) f(y))")).is_none());
+    }
+
+    #[test]
+    fn rests_anywhere() {
+        let last = pattern("[?init... ?last]");
+        let form = atom("[a b c]");
+        let bindings = last.matches(&form).unwrap();
+        assert_eq!(bindings.rest("init").unwrap().len(), 2);
+        assert_eq!(bindings.get("last").unwrap().as_identifier(), Some("c"));
+        assert!(last.matches(&atom("[]")).is_none());
+        assert!(last.matches(&atom("(a)")).is_none());
+
+        assert!(pattern("let (?bindings...) _...")
+            .matches(&atom("let ()"))
+            .is_some());
+    }
+
+    #[test]
+    fn literals_must_be_equal() {
+        let p = pattern("f(\"x\" {?a + 1})");
+        assert!(p.matches(&atom("f(\"x\" {y + 1})")).is_some());
+        assert!(p.matches(&atom("f(\"y\" {y + 1})")).is_none());
+        assert!(p.matches(&atom("f(\"x\" (y + 1))")).is_none());
+        assert!(p.matches(&atom("g(\"x\" {y + 1})")).is_none());
+    }
+
+    #[test]
+    fn at_most_one_rest_per_group() {
+        assert_eq!(
+            "f a... (?b... ?c...)".parse::<Pattern>(),
+            Err(PatternError::MultipleRests { span: 7..20 })
+        );
+        assert!(matches!(
+            "f (".parse::<Pattern>(),
+            Err(PatternError::Syntax(_))
+        ));
+    }
+}
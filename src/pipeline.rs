@@ -0,0 +1,75 @@
+//! The passes behind [`Parser::new`] and [`crate::parse_lossy`], exported
+//! individually so custom passes (token filters, synthetic tokens, ...) can be
+//! slotted in between them.
+//!
+//! 1. [`lex`] turns the source into raw tokens, including whitespace and
+//!    comments
+//! 2. [`indent`] drops insignificant whitespace and comments and turns the
+//!    significant parts into `Newline`, `Indent` and `Dedent` tokens
+//! 3. [`group`] builds the tree of atoms according to the dialect. Neoteric
+//!    expressions and `#raw` groups are resolved here as well
+//! 4. [`desugar`] rewrites bracket and curly groups by the rules of a
+//!    [`DialectConfig`], see [`DialectConfig::apply`]
+//! 5. [`normalize`] lowers what's left of the syntax into plain lists, see
+//!    [`DialectConfig::normalize`]
+//!
+//! All passes up to [`group`] exchange [`SpannedToken`]s, the ones after it
+//! atoms, so every stage can be replaced or wrapped on its own. The token
+//! passes are iterators, so filters in between see each token as it is
+//! produced. [`indent`] and [`group`] take the same [`ParserOptions`], the
+//! ones [`Parser::with_options`] would get.
+//!
+//! ```
+//! use sweet_expr::lexer::Token;
+//! use sweet_expr::normalize::DialectConfig;
+//! use sweet_expr::parser::ParserOptions;
+//! use sweet_expr::pipeline;
+//!
+//! let options = ParserOptions::new();
+//! let tokens = pipeline::lex("fn(x) {x + 1}\n").map(|(tok, span)| match tok {
+//!     Token::Identifier("fn") => (Token::Identifier("lambda"), span),
+//!     tok => (tok, span),
+//! });
+//! let atoms = pipeline::group(pipeline::indent(tokens, &options), &options).unwrap();
+//! let config = DialectConfig::curly_infix();
+//! let atoms = pipeline::normalize(pipeline::desugar(atoms, &config), &config);
+//! assert_eq!(atoms[0].to_string(), "((lambda x) (+ x 1))");
+//! ```
+
+use crate::lexer::tokenise;
+use crate::normalize::DialectConfig;
+use crate::parser::{ParseError, Parser, ParserOptions, WhitespaceNormalizer};
+use crate::value::Atom;
+
+pub use crate::lexer::SpannedToken;
+
+pub fn lex(source: &str) -> impl Iterator<Item = SpannedToken<'_>> {
+    tokenise(source)
+}
+
+/// Lazy, nothing is normalized before the tokens are pulled through
+pub fn indent<'src, I>(tokens: I, options: &ParserOptions) -> WhitespaceNormalizer<'src, I>
+where
+    I: Iterator<Item = SpannedToken<'src>>,
+{
+    WhitespaceNormalizer::with_options(tokens, options)
+}
+
+pub fn group<'src>(
+    tokens: impl IntoIterator<Item = SpannedToken<'src>>,
+    options: &ParserOptions,
+) -> Result<Vec<Atom<'src>>, ParseError<'src>> {
+    Parser::from_indented_with_options(tokens.into_iter().collect(), options.clone())
+        .parse_toplevel()
+}
+
+/// Applies the group rules of `config` to every form
+pub fn desugar<'src>(atoms: Vec<Atom<'src>>, config: &DialectConfig) -> Vec<Atom<'src>> {
+    config.apply_all(atoms)
+}
+
+/// Lowers every form into plain lists, a line split with `\\` becomes
+/// several forms
+pub fn normalize<'src>(atoms: Vec<Atom<'src>>, config: &DialectConfig) -> Vec<Atom<'src>> {
+    config.normalize_all(atoms)
+}
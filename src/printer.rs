@@ -0,0 +1,575 @@
+//! Renders trees back into sweet-expression text.
+//!
+//! Lists are written as indented lines: a list that fits into the configured
+//! width stays on one line without its outer parentheses, anything longer
+//! puts its head on the line and every other element on an indented line of
+//! its own. Neoteric expressions, curly and bracket groups and `#raw` groups
+//! are always written inline, the way they were parsed. [`FormatOptions`]
+//! changes the widths and the layout.
+//!
+//! Printed text always parses back to the same tree, apart from spans and
+//! indentation groups turning into parenthesized ones and the other way
+//! around. Trees built by hand have to stick to what can be written, see
+//! [`test_support::assert_roundtrip`](crate::test_support::assert_roundtrip).
+//!
+//! [`write_sexpr`] writes plain s-expressions instead, for readers that don't
+//! know about sweet-expressions. [`write_sexpr_document`] also maps the
+//! output back to the source.
+//!
+//! ```
+//! use sweet_expr::printer::Printer;
+//!
+//! let atoms = sweet_expr::parse("(define (f x) (g x) {x + 1})").unwrap();
+//! let text = Printer::new().width(16).print_document(&atoms);
+//! assert_eq!(text, "define\n    f x\n    g x\n    {x + 1}\n");
+//! ```
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::source_map::Span;
+use crate::value::*;
+
+/// How a [`Printer`] lays out forms, to encode a house style
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// lines longer than this are broken up where possible, 80 by default
+    pub width: usize,
+    /// spaces per level of indentation, 4 by default
+    pub indent: usize,
+    pub short_forms: ShortForms,
+    /// keep the first argument on the line of the head and line the others
+    /// up under it, instead of putting them all on lines of their own
+    pub align_arguments: bool,
+    /// blank lines kept between top-level forms when formatting a file,
+    /// longer runs are shortened to this, 1 by default. `usize::MAX` keeps
+    /// them all.
+    pub max_blank_lines: usize,
+    /// rewrite `f (x)` to `f(x)` when formatting a file, for an identifier
+    /// followed by a group after a single space. That changes what the
+    /// code means, it's for sources where the space is a typo.
+    pub join_spaced_groups: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            indent: 4,
+            short_forms: ShortForms::Parens,
+            align_arguments: false,
+            max_blank_lines: 1,
+            join_spaced_groups: false,
+        }
+    }
+}
+
+/// How forms with forms inside that fit on a line are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShortForms {
+    /// on a single line, `define (f x) (g x)`
+    #[default]
+    Parens,
+    /// with an indentation group for every form inside, like forms that are
+    /// too long
+    Indentation,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Printer {
+    options: FormatOptions,
+}
+
+impl From<FormatOptions> for Printer {
+    fn from(options: FormatOptions) -> Self {
+        Self::with_options(options)
+    }
+}
+
+impl Printer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: FormatOptions) -> Self {
+        Self::new()
+            .width(options.width)
+            .indent(options.indent)
+            .short_forms(options.short_forms)
+            .align_arguments(options.align_arguments)
+            .max_blank_lines(options.max_blank_lines)
+            .join_spaced_groups(options.join_spaced_groups)
+    }
+
+    pub fn options(&self) -> &FormatOptions {
+        &self.options
+    }
+
+    /// Every form on a single line, no matter how long. The spaces between
+    /// elements are all needed in sweet-expressions, `a (b)` and `a(b)` are
+    /// different things.
+    pub fn minified() -> Self {
+        Self::new().width(usize::MAX)
+    }
+
+    /// Lines longer than this are broken up where possible, 80 by default
+    pub fn width(mut self, width: usize) -> Self {
+        self.options.width = width;
+        self
+    }
+
+    /// Spaces per level of indentation, 4 by default
+    pub fn indent(mut self, indent: usize) -> Self {
+        self.options.indent = indent.max(1);
+        self
+    }
+
+    pub fn short_forms(mut self, short_forms: ShortForms) -> Self {
+        self.options.short_forms = short_forms;
+        self
+    }
+
+    pub fn align_arguments(mut self, align: bool) -> Self {
+        self.options.align_arguments = align;
+        self
+    }
+
+    /// Blank lines kept between top-level forms by
+    /// [`format`](crate::format::format), 1 by default
+    pub fn max_blank_lines(mut self, max: usize) -> Self {
+        self.options.max_blank_lines = max;
+        self
+    }
+
+    /// Rewrites `f (x)` to `f(x)` in [`format`](crate::format::format), see
+    /// [`FormatOptions::join_spaced_groups`]
+    pub fn join_spaced_groups(mut self, join: bool) -> Self {
+        self.options.join_spaced_groups = join;
+        self
+    }
+
+    /// Every form starts on a new line, the text ends with a newline
+    pub fn print_document(&self, atoms: &[Atom]) -> String {
+        let mut out = String::new();
+        for atom in atoms {
+            self.line(&mut out, atom, 0);
+            out.push('\n');
+        }
+        out
+    }
+
+    /// A single form, without a trailing newline
+    pub fn print(&self, atom: &Atom) -> String {
+        let mut out = String::new();
+        self.line(&mut out, atom, 0);
+        out
+    }
+
+    // writes `atom` as if it started a line at column `indent`
+    fn line(&self, out: &mut String, atom: &Atom, indent: usize) {
+        let Some(children) = as_list(atom) else {
+            inline(out, atom);
+            return;
+        };
+        let flat = {
+            let mut flat = String::new();
+            inline_all(&mut flat, children);
+            flat
+        };
+        let nested = children[1..].iter().any(|child| as_list(child).is_some());
+        let short = self.options.short_forms == ShortForms::Parens || !nested;
+        if indent + flat.len() <= self.options.width && short {
+            out.push_str(&flat);
+            return;
+        }
+
+        let (head, mut rest) = children
+            .split_first()
+            .expect("lists have two or more elements");
+        let line_start = out.len();
+        inline(out, head);
+        let column = indent;
+        let mut indent = indent + self.options.indent;
+        if let (true, Some((first, others))) = (self.options.align_arguments, rest.split_first()) {
+            out.push(' ');
+            // the others start where the first one does
+            indent = column + out[line_start..].chars().count();
+            inline(out, first);
+            rest = others;
+        }
+        for child in rest {
+            out.push('\n');
+            out.extend(std::iter::repeat_n(' ', indent));
+            self.line(out, child, indent);
+        }
+    }
+}
+
+// lists that can be written as a line of their elements. A line with a single
+// element is that element, not a list of it, so those keep their parentheses.
+fn as_list<'a, 'src>(atom: &'a Atom<'src>) -> Option<&'a [Atom<'src>]> {
+    match atom {
+        Atom::Group(group)
+            if matches!(
+                group.group_type,
+                GroupType::Indentation | GroupType::Parenthesis
+            ) && group.children.len() >= 2 =>
+        {
+            Some(&group.children)
+        }
+        _ => None,
+    }
+}
+
+fn inline(out: &mut String, atom: &Atom) {
+    match atom {
+        Atom::Identifier(s) | Atom::String(s) => out.push_str(&s.0),
+        Atom::Group(group) => inline_group(out, group),
+        Atom::Neoteric { lhs, rhs } => {
+            inline(out, lhs);
+            inline_group(out, rhs);
+        }
+        Atom::Raw { marker, body } => {
+            out.push_str(&marker.0);
+            inline_group(out, body);
+        }
+        // nothing sensible to write, but the text should still parse
+        Atom::Error(_) => out.push_str("()"),
+    }
+}
+
+fn inline_group(out: &mut String, group: &Group) {
+    let (open, close) = group.group_type.delimiters();
+    out.push_str(open);
+    inline_all(out, &group.children);
+    out.push_str(close);
+}
+
+fn inline_all(out: &mut String, atoms: &[Atom]) {
+    for (i, atom) in atoms.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        inline(out, atom);
+    }
+}
+
+/// Writes `atom` fully parenthesized with single spaces between elements,
+/// after lowering it with [`Atom::normalize`]: neoteric expressions are
+/// turned into lists like in SRFI 105, `f(x)` into `(f x)` and `f[x]` into
+/// `($bracket-apply$ f x)`, curly groups are curly-infix and brackets are
+/// plain lists. `#raw` groups keep their marker.
+///
+/// ```
+/// let atom = sweet_expr::parse_one("f{x + 1}").unwrap();
+/// let mut out = String::new();
+/// sweet_expr::printer::write_sexpr(&atom, &mut out).unwrap();
+/// assert_eq!(out, "(f (+ x 1))");
+/// ```
+pub fn write_sexpr(atom: &Atom, out: &mut impl fmt::Write) -> fmt::Result {
+    write_sexpr_with(atom, out, false)
+}
+
+/// [`write_sexpr`] without any spaces that aren't needed to separate two
+/// atoms, `(f (g x) y)` is written as `(f(g x)y)`
+pub fn write_sexpr_minified(atom: &Atom, out: &mut impl fmt::Write) -> fmt::Result {
+    write_sexpr_with(atom, out, true)
+}
+
+fn write_sexpr_with(atom: &Atom, out: &mut impl fmt::Write, compact: bool) -> fmt::Result {
+    SexprWriter::new(out, compact).atom(&atom.clone().normalize())
+}
+
+/// Where a part of the output of [`write_sexpr_document`] came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SexprMapping {
+    /// byte range in the written text
+    pub output: Range<usize>,
+    /// span of the atom in the sweet-expression source
+    pub source: Span,
+}
+
+/// Maps the text [`write_sexpr_document`] wrote back to the source, so errors
+/// a Scheme reports on the converted text can point at the sweet-expression
+/// they came from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SexprSourceMap {
+    /// one per written atom, in the order they were written
+    pub mappings: Vec<SexprMapping>,
+}
+
+impl SexprSourceMap {
+    /// The innermost atom written at `offset` of the output
+    pub fn lookup(&self, offset: usize) -> Option<&SexprMapping> {
+        self.mappings
+            .iter()
+            .filter(|mapping| mapping.output.contains(&offset))
+            .min_by_key(|mapping| mapping.output.len())
+    }
+}
+
+/// Writes every form with [`write_sexpr`] on a line of its own, and records
+/// where each written atom came from. Atoms made up by the normalization,
+/// like `$bracket-apply$`, point at the syntax they replace.
+///
+/// ```
+/// use sweet_expr::printer::write_sexpr_document;
+///
+/// let source = "define f(x)\n  g x[0]\n";
+/// let atoms = sweet_expr::parse(source).unwrap();
+/// let mut out = String::new();
+/// let map = write_sexpr_document(&atoms, &mut out).unwrap();
+/// assert_eq!(out, "(define (f x) (g ($bracket-apply$ x 0)))\n");
+///
+/// let zero = out.find('0').unwrap();
+/// assert_eq!(&source[map.lookup(zero).unwrap().source], "0");
+/// let apply = out.find('$').unwrap();
+/// assert_eq!(&source[map.lookup(apply).unwrap().source], "[");
+/// ```
+pub fn write_sexpr_document(
+    atoms: &[Atom],
+    out: &mut impl fmt::Write,
+) -> Result<SexprSourceMap, fmt::Error> {
+    let mut writer = SexprWriter::new(out, false);
+    writer.mappings = Some(vec![]);
+    for atom in atoms {
+        writer.atom(&atom.clone().normalize())?;
+        writer.write_str("\n")?;
+    }
+    Ok(SexprSourceMap {
+        mappings: writer.mappings.unwrap_or_default(),
+    })
+}
+
+struct SexprWriter<'a, W> {
+    out: &'a mut W,
+    compact: bool,
+    // bytes written so far
+    pos: usize,
+    mappings: Option<Vec<SexprMapping>>,
+}
+
+impl<'a, W: fmt::Write> SexprWriter<'a, W> {
+    fn new(out: &'a mut W, compact: bool) -> Self {
+        Self {
+            out,
+            compact,
+            pos: 0,
+            mappings: None,
+        }
+    }
+
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.pos += s.len();
+        self.out.write_str(s)
+    }
+
+    // the tree is normalized, there are only lists left
+    fn atom(&mut self, atom: &Atom) -> fmt::Result {
+        let start = self.pos;
+        match atom {
+            Atom::Identifier(s) | Atom::String(s) => self.write_str(&s.0)?,
+            Atom::Group(group) => self.list(&group.children)?,
+            Atom::Neoteric { lhs, rhs } => {
+                self.atom(lhs)?;
+                self.list(&rhs.children)?;
+            }
+            Atom::Raw { marker, body } => {
+                self.write_str(&marker.0)?;
+                self.list(&body.children)?;
+            }
+            Atom::Error(_) => self.write_str("()")?,
+        }
+        if let Some(mappings) = &mut self.mappings {
+            mappings.push(SexprMapping {
+                output: start..self.pos,
+                source: atom.span(),
+            });
+        }
+        Ok(())
+    }
+
+    fn list(&mut self, children: &[Atom]) -> fmt::Result {
+        self.write_str("(")?;
+        let mut prev: Option<&Atom> = None;
+        for atom in children {
+            if let Some(prev) = prev {
+                // a parenthesis on either side separates the two already
+                let delimited = !matches!(prev, Atom::Identifier(_) | Atom::String(_))
+                    || matches!(
+                        atom,
+                        Atom::Group(_) | Atom::Neoteric { .. } | Atom::Error(_)
+                    );
+                if !(self.compact && delimited) {
+                    self.write_str(" ")?;
+                }
+            }
+            prev = Some(atom);
+            self.atom(atom)?;
+        }
+        self.write_str(")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::same_shape;
+
+    fn reprint(source: &str, printer: &Printer) -> String {
+        let atoms = crate::parse(source).unwrap();
+        let text = printer.print_document(&atoms);
+        let reparsed = crate::parse(&text).unwrap();
+        assert!(
+            atoms.len() == reparsed.len()
+                && atoms.iter().zip(&reparsed).all(|(a, b)| same_shape(a, b)),
+            "{text:?} doesn't parse back to {source:?}"
+        );
+        text
+    }
+
+    #[test]
+    fn breaks_long_lists() {
+        let source = "define (fact n)\n  if {n <= 1} 1 {n * fact(- n 1)}\n";
+        assert_eq!(
+            reprint(source, &Printer::new()),
+            "define (fact n) (if {n <= 1} 1 {n * fact(- n 1)})\n"
+        );
+        assert_eq!(
+            reprint(source, &Printer::new().width(40)),
+            "define\n    fact n\n    if {n <= 1} 1 {n * fact(- n 1)}\n"
+        );
+        assert_eq!(
+            reprint(source, &Printer::new().width(10).indent(2)),
+            "define\n  fact n\n  if\n    {n <= 1}\n    1\n    {n * fact(- n 1)}\n"
+        );
+    }
+
+    #[test]
+    fn single_elements_keep_parentheses() {
+        assert_eq!(reprint("(a) b\n(c)\n", &Printer::new()), "(a) b\n(c)\n");
+        assert_eq!(
+            reprint("f (g) ()\n", &Printer::new().width(1)),
+            "f\n    (g)\n    ()\n"
+        );
+    }
+
+    #[test]
+    fn sexprs() {
+        let source = "define fact(n)\n  if {n <= 1} 1 {n * fact{n - 1}}\nv[i j] [a] {a + b * c} #raw(f(x))\n";
+        let atoms = crate::parse(source).unwrap();
+        let written: Vec<_> = atoms
+            .iter()
+            .map(|atom| {
+                let mut out = String::new();
+                write_sexpr(atom, &mut out).unwrap();
+                out
+            })
+            .collect();
+        assert_eq!(
+            written,
+            [
+                "(define (fact n) (if (<= n 1) 1 (* n (fact (- n 1)))))",
+                "(($bracket-apply$ v i j) (a) ($nfx$ a + b * c) #raw(f (x)))"
+            ]
+        );
+
+        let mut out = String::new();
+        write_sexpr_minified(&atoms[0], &mut out).unwrap();
+        assert_eq!(out, "(define(fact n)(if(<= n 1)1(* n(fact(- n 1)))))");
+        out.clear();
+        write_sexpr_minified(&atoms[1], &mut out).unwrap();
+        assert_eq!(
+            out,
+            "(($bracket-apply$ v i j)(a)($nfx$ a + b * c)#raw(f(x)))"
+        );
+    }
+
+    #[test]
+    fn sexpr_source_map() {
+        let source = "a {b + c}\nf(x)\n";
+        let atoms = crate::parse(source).unwrap();
+        let mut out = String::new();
+        let map = write_sexpr_document(&atoms, &mut out).unwrap();
+        assert_eq!(out, "(a (+ b c))\n(f x)\n");
+        let mapped: Vec<_> = out
+            .char_indices()
+            .filter(|(_, c)| !" ()\n".contains(*c))
+            .map(|(i, _)| &source[map.lookup(i).unwrap().source])
+            .collect();
+        assert_eq!(mapped, ["a", "+", "b", "c", "f", "x"]);
+        assert_eq!(&source[map.lookup(3).unwrap().source], "{b + c}");
+        assert_eq!(&source[map.lookup(0).unwrap().source], "a {b + c}");
+        assert!(map.lookup(11).is_none());
+    }
+
+    #[test]
+    fn minified() {
+        let source = "define fact(n)\n  if {n <= 1}\n    1\n    {n * fact{n - 1}}\n\nfact 5\n";
+        assert_eq!(
+            reprint(source, &Printer::minified()),
+            "define fact(n) (if {n <= 1} 1 {n * fact{n - 1}})\nfact 5\n"
+        );
+    }
+
+    #[test]
+    fn inline_forms_are_kept() {
+        let source = "let [x f(y)] #raw(a(b)) g{1 + 2}\n";
+        assert_eq!(
+            reprint(source, &Printer::new().width(8)),
+            "let\n    [x f(y)]\n    #raw(a (b))\n    g{1 + 2}\n"
+        );
+    }
+
+    #[test]
+    fn format_options() {
+        let atoms = crate::parse("(define (f x) (g x (h y)) {x + 1})").unwrap();
+        let indentation = Printer::new().short_forms(ShortForms::Indentation);
+        assert_eq!(
+            indentation.print(&atoms[0]),
+            "define\n    f x\n    g\n        x\n        h y\n    {x + 1}"
+        );
+        let aligned = Printer::with_options(FormatOptions {
+            width: 20,
+            indent: 2,
+            align_arguments: true,
+            ..FormatOptions::default()
+        });
+        assert_eq!(
+            aligned.print(&atoms[0]),
+            "define (f x)\n       g x (h y)\n       {x + 1}"
+        );
+        assert_eq!(aligned.options().indent, 2);
+        for printer in [indentation, aligned.width(0)] {
+            let printed = printer.print_document(&atoms);
+            crate::test_support::assert_same_shape(
+                &printed,
+                &crate::parse(&printed).unwrap(),
+                &atoms,
+            );
+        }
+    }
+
+    #[test]
+    fn tricky_roundtrips() {
+        let sources = [
+            "f (x)",
+            "f(x) f (x) f((x))",
+            "(f)(x) f(x)(y) a[b][c] f{x}",
+            "((a)) (a) () [] {} {a}",
+            "\"a \\\"b\\\"\" \"\" f(\"s\")",
+            "a $ b \\\\ c . d <* e *>",
+            "#raw(f (x)) '(a b) ,@c",
+            "define f(x)\n    let [y g(x)]\n        {y * 2}",
+        ];
+        for source in sources {
+            for atom in crate::parse(source).unwrap() {
+                crate::test_support::assert_roundtrip(&atom);
+            }
+        }
+
+        let unwritable = crate::test_support::paren([Atom::ident("a b")]);
+        let err = std::panic::catch_unwind(|| crate::test_support::assert_roundtrip(&unwritable));
+        assert!(err.is_err());
+    }
+}
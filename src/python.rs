@@ -0,0 +1,173 @@
+//! The `sweet_expr` Python module, for loading sweet-expression data in
+//! Python without going through a subprocess.
+//!
+//! Build it as an extension module with the `python` feature, e.g. with
+//! `cargo rustc --release --features python --crate-type cdylib`, and copy
+//! the library to `sweet_expr.so` (`sweet_expr.pyd` on Windows). Then
+//!
+//! ```python
+//! import sweet_expr
+//!
+//! sweet_expr.parse("define f(x)\n  {x + 1}\n")
+//! # [['define', ['f', 'x'], ['+', 'x', '1']]]
+//! sweet_expr.parse("a b", spans=True)
+//! # [([('a', Span(0, 1)), ('b', Span(2, 3))], Span(0, 3))]
+//! ```
+//!
+//! Every form is lowered the way [`DialectConfig::curly_infix`] does it, so
+//! groups of any kind are lists and everything else is a `str`. Strings keep
+//! their quotes, so they can be told apart from identifiers. With
+//! `spans=True`, every value is paired with its [`Span`].
+//!
+//! Source that doesn't parse raises `sweet_expr.ParseError`, a subclass of
+//! `ValueError` whose arguments are the message and the byte offsets of the
+//! start and end of the error.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyList, PyString, PyTuple};
+
+use crate::diagnostic::Diagnostic;
+use crate::normalize::DialectConfig;
+use crate::value::*;
+
+create_exception!(sweet_expr, ParseError, PyValueError);
+
+/// Byte offsets into the parsed source
+#[pyclass(frozen, eq, get_all, skip_from_py_object, module = "sweet_expr")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[pymethods]
+impl Span {
+    #[new]
+    fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Span({}, {})", self.start, self.end)
+    }
+}
+
+/// Parses `source` into nested lists of strings
+#[pyfunction]
+#[pyo3(signature = (source, *, spans = false))]
+fn parse<'py>(py: Python<'py>, source: &str, spans: bool) -> PyResult<Bound<'py, PyList>> {
+    let atoms = crate::parse(source).map_err(|err| {
+        let span = Diagnostic::from(&err).span().unwrap_or(0..0);
+        ParseError::new_err((err.to_string(), span.start, span.end))
+    })?;
+    let atoms = DialectConfig::curly_infix().normalize_all(atoms);
+    let values = atoms
+        .iter()
+        .map(|atom| to_python(py, atom, spans))
+        .collect::<PyResult<Vec<_>>>()?;
+    PyList::new(py, values)
+}
+
+fn to_python<'py>(py: Python<'py>, atom: &Atom, spans: bool) -> PyResult<Bound<'py, PyAny>> {
+    let list =
+        |children: &[Atom], head: Option<&Spanned<Cow<str>>>| -> PyResult<Bound<'py, PyAny>> {
+            let mut values = vec![];
+            if let Some(Spanned(head, span, _)) = head {
+                let head = PyString::new(py, head).into_any();
+                values.push(with_span(py, head, span.range(), spans)?);
+            }
+            for child in children {
+                values.push(to_python(py, child, spans)?);
+            }
+            Ok(PyList::new(py, values)?.into_any())
+        };
+    let value = match atom {
+        Atom::Identifier(Spanned(text, ..)) | Atom::String(Spanned(text, ..)) => {
+            PyString::new(py, text).into_any()
+        }
+        Atom::Group(group) => list(&group.children, None)?,
+        Atom::Raw { marker, body } => list(&body.children, Some(marker))?,
+        // `normalize_all` lowers these and the parse was strict, but a panic
+        // mustn't cross into Python if that ever changes
+        Atom::Neoteric { .. } | Atom::Error(_) => {
+            return Err(PyValueError::new_err(format!(
+                "`{atom}` can't be converted"
+            )));
+        }
+    };
+    with_span(py, value, atom.span().range(), spans)
+}
+
+fn with_span<'py>(
+    py: Python<'py>,
+    value: Bound<'py, PyAny>,
+    range: Range<usize>,
+    spans: bool,
+) -> PyResult<Bound<'py, PyAny>> {
+    if !spans {
+        return Ok(value);
+    }
+    let span = Bound::new(py, Span::new(range.start, range.end))?;
+    Ok(PyTuple::new(py, [value, span.into_any()])?.into_any())
+}
+
+#[pymodule]
+fn sweet_expr(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(parse, m)?)?;
+    m.add_class::<Span>()?;
+    m.add("ParseError", m.py().get_type::<ParseError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::IntoPyDict;
+
+    #[test]
+    fn nested_lists() {
+        Python::initialize();
+        Python::attach(|py| {
+            let m = PyModule::new(py, "sweet_expr").unwrap();
+            sweet_expr(&m).unwrap();
+            let parse = m.getattr("parse").unwrap();
+
+            let parsed = parse
+                .call1(("define f(x)\n  {x + \"1\"}\n#raw(a b)",))
+                .unwrap();
+            assert_eq!(
+                parsed.repr().unwrap().to_string(),
+                r#"[['define', ['f', 'x'], ['+', 'x', '"1"']], ['#raw', 'a', 'b']]"#
+            );
+
+            let kwargs = [("spans", true)].into_py_dict(py).unwrap();
+            let parsed = parse.call(("a [b]",), Some(&kwargs)).unwrap();
+            assert_eq!(
+                parsed.repr().unwrap().to_string(),
+                "[([('a', Span(0, 1)), ([('b', Span(3, 4))], Span(2, 5))], Span(0, 5))]"
+            );
+
+            let err = parse.call1(("a (",)).unwrap_err();
+            assert!(err.is_instance_of::<ParseError>(py));
+            assert!(err.is_instance_of::<PyValueError>(py));
+            let (_, start, end): (String, usize, usize) =
+                err.value(py).getattr("args").unwrap().extract().unwrap();
+            assert!(start <= end);
+        });
+    }
+
+    #[test]
+    fn unlowered_atoms_are_an_error() {
+        Python::initialize();
+        Python::attach(|py| {
+            let atom = crate::parse_one("f(x)").unwrap();
+            let err = to_python(py, &atom, false).unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+        });
+    }
+}
@@ -0,0 +1,272 @@
+//! Expanding quasiquoted templates, the building block of macros and code
+//! generators.
+//!
+//! Templates use the usual Scheme syntax, either the prefixes `` ` ``, `,`
+//! and `,@` or the spelled out `(quasiquote ...)`, `(unquote ...)` and
+//! `(unquote-splicing ...)`. Only names can be unquoted: [`expand`] replaces
+//! `,name` with the atom `name` is bound to, and `,@name` with the elements
+//! of the list it's bound to. Quasiquotes inside of the template are kept
+//! together with their unquotes, apart from the ones nested in an unquote of
+//! their own, like in Scheme.
+//!
+//! ```
+//! use std::collections::HashMap;
+//! use sweet_expr::quasiquote::expand;
+//!
+//! let template = sweet_expr::parse_one("`(define ,name (lambda ,args ,@body))").unwrap();
+//! let bindings = HashMap::from([
+//!     ("name", sweet_expr::parse_one("add").unwrap()),
+//!     ("args", sweet_expr::parse_one("(x y)").unwrap()),
+//!     ("body", sweet_expr::parse_one("((log x) {x + y})").unwrap()),
+//! ]);
+//! let code = expand(&template, &bindings).unwrap();
+//! assert_eq!(code.to_string(), "(define add (lambda (x y) (log x) {x + y}))");
+//! ```
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::source_map::Span;
+use crate::value::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuasiquoteError {
+    /// an unquoted name without a binding
+    Unbound(Spanned<String>),
+    /// an unquote of something else than a name, like `,(f x)`
+    NotAName(Spanned<()>),
+    /// a `,@` that isn't inside of a list, or whose binding isn't a list
+    CantSplice(Spanned<()>),
+}
+
+impl fmt::Display for QuasiquoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuasiquoteError::Unbound(name) => write!(f, "`{}` isn't bound", name.0),
+            QuasiquoteError::NotAName(_) => f.write_str("only names can be unquoted"),
+            QuasiquoteError::CantSplice(_) => {
+                f.write_str("`,@` splices the elements of a list into a list")
+            }
+        }
+    }
+}
+
+impl std::error::Error for QuasiquoteError {}
+
+/// A copy of `template` with the unquotes replaced by their bindings.
+/// `template` is a quasiquote, or what's inside of one.
+pub fn expand<'src>(
+    template: &Atom<'src>,
+    bindings: &HashMap<&str, Atom<'src>>,
+) -> Result<Atom<'src>, QuasiquoteError> {
+    let body = quasiquoted(template).unwrap_or(Cow::Borrowed(template));
+    if let Some((true, _)) = unquote(&body) {
+        return Err(QuasiquoteError::CantSplice(spanned(&body)));
+    }
+    let mut atoms = Expander { bindings }.expand(&body, 0)?;
+    Ok(atoms.remove(0))
+}
+
+struct Expander<'b, 'src> {
+    bindings: &'b HashMap<&'b str, Atom<'src>>,
+}
+
+impl<'src> Expander<'_, 'src> {
+    // what `atom` expands to, more than one atom for splices. `depth` counts
+    // the quasiquotes around it inside of the template.
+    fn expand(&self, atom: &Atom<'src>, depth: usize) -> Result<Vec<Atom<'src>>, QuasiquoteError> {
+        let depth = match unquote(atom) {
+            Some((splicing, name)) if depth == 0 => return self.substitute(atom, splicing, name),
+            Some(_) => depth - 1,
+            None if quasiquoted(atom).is_some() => depth + 1,
+            None => depth,
+        };
+        let atom = match atom {
+            Atom::Group(group) => Atom::Group(self.group(group, depth)?),
+            Atom::Neoteric { lhs, rhs } => Atom::Neoteric {
+                lhs: Box::new(self.single(lhs, depth)?),
+                rhs: self.group(rhs, depth)?,
+            },
+            atom => atom.clone(),
+        };
+        Ok(vec![atom])
+    }
+
+    fn substitute(
+        &self,
+        atom: &Atom<'src>,
+        splicing: bool,
+        name: Option<Spanned<&str>>,
+    ) -> Result<Vec<Atom<'src>>, QuasiquoteError> {
+        let name = name.ok_or_else(|| QuasiquoteError::NotAName(spanned(atom)))?;
+        let value = self
+            .bindings
+            .get(name.0)
+            .ok_or_else(|| QuasiquoteError::Unbound(name.map(str::to_owned)))?;
+        match value {
+            _ if !splicing => Ok(vec![value.clone()]),
+            Atom::Group(group)
+                if matches!(
+                    group.group_type,
+                    GroupType::Parenthesis | GroupType::Indentation
+                ) =>
+            {
+                Ok(group.children.clone())
+            }
+            _ => Err(QuasiquoteError::CantSplice(spanned(atom))),
+        }
+    }
+
+    fn group(&self, group: &Group<'src>, depth: usize) -> Result<Group<'src>, QuasiquoteError> {
+        let mut children = vec![];
+        for child in &group.children {
+            children.extend(self.expand(child, depth)?);
+        }
+        Ok(Group {
+            children,
+            ..group.clone()
+        })
+    }
+
+    // the head of a neoteric expression can't be spliced
+    fn single(&self, atom: &Atom<'src>, depth: usize) -> Result<Atom<'src>, QuasiquoteError> {
+        if let (0, Some((true, _))) = (depth, unquote(atom)) {
+            return Err(QuasiquoteError::CantSplice(spanned(atom)));
+        }
+        Ok(self.expand(atom, depth)?.remove(0))
+    }
+}
+
+fn spanned(atom: &Atom) -> Spanned<()> {
+    Spanned((), atom.span(), atom.file())
+}
+
+// what's inside of `` `x ``, `` `(...) `` or `(quasiquote x)`
+fn quasiquoted<'a, 'src>(atom: &'a Atom<'src>) -> Option<Cow<'a, Atom<'src>>> {
+    match atom {
+        Atom::Identifier(Spanned(ident, span, file)) if ident.len() > 1 => {
+            let name = ident.strip_prefix('`')?;
+            let span = Span {
+                start: span.start + 1,
+                end: span.end,
+            };
+            Some(Cow::Owned(Atom::Identifier(Spanned(
+                name.to_owned().into(),
+                span,
+                *file,
+            ))))
+        }
+        Atom::Neoteric { lhs, rhs }
+            if lhs.as_identifier() == Some("`") && rhs.group_type == GroupType::Parenthesis =>
+        {
+            Some(Cow::Owned(Atom::Group(rhs.clone())))
+        }
+        Atom::Group(group) => match &group.children[..] {
+            [head, body] if head.as_identifier() == Some("quasiquote") => Some(Cow::Borrowed(body)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// whether `atom` is an unquote, and if so whether it splices and the name
+// it unquotes, `None` if it's not a name
+fn unquote<'a>(atom: &'a Atom) -> Option<(bool, Option<Spanned<&'a str>>)> {
+    match atom {
+        Atom::Identifier(Spanned(ident, span, file)) => {
+            let (splicing, name) = match ident.strip_prefix(",@") {
+                Some(name) => (true, name),
+                None => (false, ident.strip_prefix(',')?),
+            };
+            // a lone `,` is the head of `,(...)`
+            if name.is_empty() {
+                return None;
+            }
+            let span = Span {
+                start: span.end - name.len() as u32,
+                end: span.end,
+            };
+            Some((splicing, Some(Spanned(name, span, *file))))
+        }
+        Atom::Neoteric { lhs, .. } => match lhs.as_identifier()? {
+            "," => Some((false, None)),
+            ",@" => Some((true, None)),
+            _ => None,
+        },
+        Atom::Group(group) => {
+            let [head, arg] = &group.children[..] else {
+                return None;
+            };
+            let splicing = match head.as_identifier()? {
+                "unquote" => false,
+                "unquote-splicing" => true,
+                _ => return None,
+            };
+            let name = match arg {
+                Atom::Identifier(Spanned(name, span, file)) => Some(Spanned(&**name, *span, *file)),
+                _ => None,
+            };
+            Some((splicing, name))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_one;
+
+    fn bindings() -> HashMap<&'static str, Atom<'static>> {
+        HashMap::from([("x", Atom::ident("1")), ("ys", parse_one("(2 3)").unwrap())])
+    }
+
+    fn expanded(template: &str) -> Result<String, QuasiquoteError> {
+        let template = parse_one(template).unwrap();
+        expand(&template, &bindings()).map(|atom| atom.to_string())
+    }
+
+    #[test]
+    fn expansion() {
+        assert_eq!(expanded("`(a ,x ,@ys b)").unwrap(), "(a 1 2 3 b)");
+        assert_eq!(
+            expanded("(quasiquote (a (unquote x) (unquote-splicing ys)))").unwrap(),
+            "(a 1 2 3)"
+        );
+        assert_eq!(expanded("`,x").unwrap(), "1");
+        assert_eq!(expanded("f(,x ,@ys)").unwrap(), "(f 1 2 3)");
+        assert_eq!(expanded("{,x + ,@ys}").unwrap(), "{1 + 2 3}");
+        assert_eq!(expanded("'(quote ,x)").unwrap(), "(' quote 1)");
+        // only the unquotes of the outermost quasiquote are replaced
+        assert_eq!(
+            expanded("`(a `(b ,(c ,x) ,y))").unwrap(),
+            "(a (` b (, c 1) ,y))"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let err = expanded("`(a ,z)").unwrap_err();
+        assert_eq!(err.to_string(), "`z` isn't bound");
+        assert!(
+            matches!(err, QuasiquoteError::Unbound(Spanned(_, span, _)) if span == Span::new(5, 6))
+        );
+        assert!(matches!(
+            expanded("`(a ,(f x))"),
+            Err(QuasiquoteError::NotAName(_))
+        ));
+        assert!(matches!(
+            expanded("`(a ,@x)"),
+            Err(QuasiquoteError::CantSplice(_))
+        ));
+        assert!(matches!(
+            expanded("`,@ys"),
+            Err(QuasiquoteError::CantSplice(_))
+        ));
+        assert!(matches!(
+            expanded(",@ys(a)"),
+            Err(QuasiquoteError::CantSplice(_))
+        ));
+    }
+}
@@ -0,0 +1,387 @@
+//! Finding atoms with selectors, like CSS selectors over s-expressions.
+//!
+//! A selector is a list of steps separated by whitespace, which matches
+//! descendants, or ` > `, which only matches direct children. A leading `>`
+//! only matches top-level forms. Each step is one of
+//!
+//! - `*`, any atom
+//! - `identifier`, `string`, `number`, `group`, `neoteric` or `raw`, any atom
+//!   of that kind
+//! - `=name`, the identifier `name`
+//! - any other name, forms headed by it: groups whose first element is that
+//!   identifier and neoteric expressions calling it. Quote names that look
+//!   like anything else, `"group"` is a form headed by `group`.
+//!
+//! followed by any of `:nth(i)` for the element at index `i` of its form and
+//! `:last` for the last one. Neoteric expressions count as lists here,
+//! `f(x)` has the elements `f` and `x` like `(f x)`. `#raw` bodies are never
+//! searched.
+//!
+//! ```
+//! let config = sweet_expr::parse("server\n  port 8080\n  route \"/\" index\nport 1\n").unwrap();
+//! let ports = sweet_expr::query::select(&config, "server > port > number").unwrap();
+//! assert_eq!(ports.len(), 1);
+//! assert_eq!(ports[0].as_identifier(), Some("8080"));
+//!
+//! let names = sweet_expr::query::select(&config, "route > *:nth(1)").unwrap();
+//! assert_eq!(names[0].as_string(), Some("\"/\""));
+//! ```
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::value::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    steps: Vec<(Combinator, Step)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    base: Base,
+    positions: Vec<Position>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Base {
+    Any,
+    Kind(Kind),
+    Identifier(String),
+    Head(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Identifier,
+    String,
+    Number,
+    Group,
+    Neoteric,
+    Raw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    Nth(usize),
+    Last,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectorError {
+    /// the selector is empty, or a `>` isn't followed by a step
+    ExpectedStep,
+    /// something after a `:` that isn't `nth(i)` or `last`
+    UnknownPosition(String),
+    UnterminatedQuote,
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectorError::ExpectedStep => f.write_str("expected a step"),
+            SelectorError::UnknownPosition(pos) => write!(f, "unknown position `:{pos}`"),
+            SelectorError::UnterminatedQuote => f.write_str("unterminated quote"),
+        }
+    }
+}
+
+impl std::error::Error for SelectorError {}
+
+impl FromStr for Selector {
+    type Err = SelectorError;
+
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        let mut steps = vec![];
+        let mut combinator = Combinator::Descendant;
+        let mut expecting_step = false;
+        let mut rest = selector;
+        while let Some((token, after)) = next_token(rest)? {
+            rest = after;
+            if token == ">" {
+                if expecting_step {
+                    return Err(SelectorError::ExpectedStep);
+                }
+                combinator = Combinator::Child;
+                expecting_step = true;
+                continue;
+            }
+            steps.push((combinator, parse_step(token)?));
+            combinator = Combinator::Descendant;
+            expecting_step = false;
+        }
+        if expecting_step || steps.is_empty() {
+            return Err(SelectorError::ExpectedStep);
+        }
+        Ok(Selector { steps })
+    }
+}
+
+// the next whitespace-separated token, whitespace inside quotes included
+fn next_token(text: &str) -> Result<Option<(&str, &str)>, SelectorError> {
+    let text = text.trim_start();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let mut quoted = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => return Ok(Some((&text[..i], &text[i..]))),
+            _ => {}
+        }
+    }
+    match quoted {
+        true => Err(SelectorError::UnterminatedQuote),
+        false => Ok(Some((text, ""))),
+    }
+}
+
+fn parse_step(token: &str) -> Result<Step, SelectorError> {
+    let (base, mut rest) = match token.strip_prefix('=') {
+        Some(ident) => {
+            let (name, rest) = name(ident)?;
+            (Base::Identifier(name.to_string()), rest)
+        }
+        None if token.starts_with('"') => {
+            let (name, rest) = name(token)?;
+            (Base::Head(name.to_string()), rest)
+        }
+        None => {
+            let (word, rest) = name(token)?;
+            let base = match word {
+                "*" => Base::Any,
+                "identifier" => Base::Kind(Kind::Identifier),
+                "string" => Base::Kind(Kind::String),
+                "number" => Base::Kind(Kind::Number),
+                "group" => Base::Kind(Kind::Group),
+                "neoteric" => Base::Kind(Kind::Neoteric),
+                "raw" => Base::Kind(Kind::Raw),
+                _ => Base::Head(word.to_string()),
+            };
+            (base, rest)
+        }
+    };
+
+    let mut positions = vec![];
+    while let Some(after) = rest.strip_prefix(':') {
+        let end = after.find(':').unwrap_or(after.len());
+        let (position, after) = after.split_at(end);
+        let nth = position
+            .strip_prefix("nth(")
+            .and_then(|n| n.strip_suffix(')'))
+            .and_then(|n| n.parse().ok());
+        positions.push(match (position, nth) {
+            (_, Some(n)) => Position::Nth(n),
+            ("last", None) => Position::Last,
+            _ => return Err(SelectorError::UnknownPosition(position.to_string())),
+        });
+        rest = after;
+    }
+    Ok(Step { base, positions })
+}
+
+// a name, quoted or up to the first `:`, and what follows it
+fn name(text: &str) -> Result<(&str, &str), SelectorError> {
+    if let Some(quoted) = text.strip_prefix('"') {
+        let end = quoted.find('"').ok_or(SelectorError::UnterminatedQuote)?;
+        return Ok((&quoted[..end], &quoted[end + 1..]));
+    }
+    Ok(text.split_at(text.find(':').unwrap_or(text.len())))
+}
+
+#[derive(Clone, Copy)]
+struct Node<'a, 'src> {
+    atom: &'a Atom<'src>,
+    index: usize,
+    siblings: usize,
+}
+
+fn elements<'a, 'src>(atom: &'a Atom<'src>) -> Vec<&'a Atom<'src>> {
+    match atom {
+        Atom::Group(group) => group.children.iter().collect(),
+        Atom::Neoteric { lhs, rhs } => std::iter::once(&**lhs).chain(&rhs.children).collect(),
+        Atom::Identifier(_) | Atom::String(_) | Atom::Raw { .. } | Atom::Error(_) => vec![],
+    }
+}
+
+fn for_each<'a, 'src>(
+    atoms: &[&'a Atom<'src>],
+    combinator: Combinator,
+    f: &mut impl FnMut(Node<'a, 'src>),
+) {
+    for (index, &atom) in atoms.iter().enumerate() {
+        let node = Node {
+            atom,
+            index,
+            siblings: atoms.len(),
+        };
+        f(node);
+        if combinator == Combinator::Descendant {
+            for_each(&elements(node.atom), combinator, f);
+        }
+    }
+}
+
+fn head<'a>(atom: &'a Atom) -> Option<&'a str> {
+    match atom {
+        Atom::Group(group) => group.children.first()?.as_identifier(),
+        Atom::Neoteric { lhs, .. } => lhs.as_identifier(),
+        _ => None,
+    }
+}
+
+impl Step {
+    fn matches(&self, node: Node) -> bool {
+        let atom = node.atom;
+        let base = match &self.base {
+            Base::Any => true,
+            Base::Kind(Kind::Identifier) => atom.is_identifier(),
+            Base::Kind(Kind::String) => atom.is_string(),
+            Base::Kind(Kind::Number) => atom.is_number(),
+            Base::Kind(Kind::Group) => atom.is_group(),
+            Base::Kind(Kind::Neoteric) => atom.is_neoteric(),
+            Base::Kind(Kind::Raw) => atom.is_raw(),
+            Base::Identifier(name) => atom.as_identifier() == Some(name),
+            Base::Head(name) => head(atom) == Some(name),
+        };
+        base && self.positions.iter().all(|position| match *position {
+            Position::Nth(n) => node.index == n,
+            Position::Last => node.index + 1 == node.siblings,
+        })
+    }
+}
+
+impl Selector {
+    /// All atoms in `document` the selector matches, in source order
+    pub fn select<'a, 'src>(&self, document: &'a [Atom<'src>]) -> Vec<&'a Atom<'src>> {
+        self.select_in(&document.iter().collect::<Vec<_>>())
+    }
+
+    fn select_in<'a, 'src>(&self, roots: &[&'a Atom<'src>]) -> Vec<&'a Atom<'src>> {
+        let mut matches: Vec<&Atom> = vec![];
+        for (i, (combinator, step)) in self.steps.iter().enumerate() {
+            let mut seen = HashSet::new();
+            let mut next = vec![];
+            let mut visit = |node: Node<'a, 'src>| {
+                if step.matches(node) && seen.insert(node.atom as *const Atom) {
+                    next.push(node.atom);
+                }
+            };
+            if i == 0 {
+                for_each(roots, *combinator, &mut visit);
+            } else {
+                for atom in &matches {
+                    for_each(&elements(atom), *combinator, &mut visit);
+                }
+            }
+            matches = next;
+        }
+        matches
+    }
+}
+
+/// Parses `selector` and runs it on `document`, see the [module docs](self)
+pub fn select<'a, 'src>(
+    document: &'a [Atom<'src>],
+    selector: &str,
+) -> Result<Vec<&'a Atom<'src>>, SelectorError> {
+    Ok(selector.parse::<Selector>()?.select(document))
+}
+
+impl<'src> Atom<'src> {
+    /// [`select`] on the elements of this atom as if they were a document,
+    /// the atom itself is never matched
+    pub fn select(&self, selector: &str) -> Result<Vec<&Atom<'src>>, SelectorError> {
+        Ok(selector.parse::<Selector>()?.select_in(&elements(self)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(atoms: Vec<&Atom>) -> Vec<String> {
+        atoms.iter().map(|atom| atom.to_string()).collect()
+    }
+
+    const SOURCE: &str = "\
+define (f x)
+  let ((y 1))
+    g(x y)
+define z 2
+import (a b) #raw(define q)
+";
+
+    #[test]
+    fn descendants_and_children() {
+        let document = crate::parse(SOURCE).unwrap();
+        assert_eq!(
+            texts(select(&document, "define > identifier").unwrap()),
+            ["define", "define", "z", "2"]
+        );
+        assert_eq!(
+            texts(select(&document, "define number").unwrap()),
+            ["1", "2"]
+        );
+        assert_eq!(texts(select(&document, "let g").unwrap()), ["(g x y)"]);
+        assert_eq!(texts(select(&document, "=y").unwrap()), ["y", "y"]);
+        assert_eq!(
+            texts(select(&document, "> group:nth(1)").unwrap()),
+            ["(define z 2)"]
+        );
+        assert_eq!(
+            texts(select(&document, "> import").unwrap()),
+            ["(import (a b) #raw(define q))"]
+        );
+    }
+
+    #[test]
+    fn positions() {
+        let document = crate::parse(SOURCE).unwrap();
+        assert_eq!(
+            texts(select(&document, "> define > *:nth(1)").unwrap()),
+            ["(f x)", "z"]
+        );
+        assert_eq!(texts(select(&document, "g > *:last").unwrap()), ["y"]);
+        assert_eq!(
+            texts(select(&document, "define > *:nth(1):last").unwrap()),
+            Vec::<String>::new()
+        );
+        assert_eq!(texts(document[1].select("*:last").unwrap()), ["2"]);
+    }
+
+    #[test]
+    fn quoted_names() {
+        let document = crate::parse("(group a) (\"x y\" b) (>= c)").unwrap();
+        assert_eq!(
+            texts(select(&document, "\"group\" > *:last").unwrap()),
+            ["a"]
+        );
+        assert_eq!(
+            texts(select(&document, ">= > identifier:last").unwrap()),
+            ["c"]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        for (selector, err) in [
+            ("", SelectorError::ExpectedStep),
+            ("a >", SelectorError::ExpectedStep),
+            ("a > > b", SelectorError::ExpectedStep),
+            ("a:first", SelectorError::UnknownPosition("first".into())),
+            ("a:nth(x)", SelectorError::UnknownPosition("nth(x)".into())),
+            ("\"a b", SelectorError::UnterminatedQuote),
+        ] {
+            assert_eq!(selector.parse::<Selector>(), Err(err), "{selector}");
+        }
+    }
+}
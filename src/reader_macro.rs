@@ -0,0 +1,282 @@
+//! Reader macros, for dialects that want literals of their own, like dates,
+//! UUIDs or regexes, without forking the lexer.
+//!
+//! A handler is registered for a prefix, like `#date` or `~`, and is called
+//! for every identifier that starts with it, the longest prefix wins. It gets
+//! a [`ReaderMacro`] that sits right after that identifier, can take the
+//! tokens and atoms that follow, and returns the atom that replaces all of
+//! it. `#raw` groups opt out of reader macros like of everything else.
+//!
+//! ```
+//! use sweet_expr::lexer::{tokenise, Token};
+//! use sweet_expr::parser::{Parser, ParserOptions};
+//! use sweet_expr::reader_macro::ReaderMacros;
+//! use sweet_expr::value::Atom;
+//!
+//! // `#date"2024-01-31"` is `(date "2024-01-31")`
+//! let macros = ReaderMacros::new().on("#date", |m| match m.peek_adjacent() {
+//!     Some((Token::String(_), _)) => {
+//!         let date = m.parse_atom()?;
+//!         let head = Atom::Identifier(m.spanned("date".into()));
+//!         Ok(m.list(vec![head, date]))
+//!     }
+//!     _ => Err(m.error("`#date` is followed by a string")),
+//! });
+//! let options = ParserOptions::new().reader_macros(macros);
+//! let atoms = Parser::with_options(tokenise("due #date\"2024-01-31\""), options.clone())
+//!     .parse_toplevel()
+//!     .unwrap();
+//! assert_eq!(atoms[0].to_string(), "(due (date \"2024-01-31\"))");
+//!
+//! let err = Parser::with_options(tokenise("due #date 5"), options)
+//!     .parse_toplevel()
+//!     .unwrap_err();
+//! assert_eq!(err.to_string(), "`#date` is followed by a string");
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::lexer::Token;
+use crate::parser::{ParseError, Parser};
+use crate::source_map::{FileId, Span};
+use crate::value::*;
+
+/// What a reader macro is expanded by
+pub type Handler = dyn for<'m, 'src> Fn(&mut ReaderMacro<'m, 'src>) -> Result<Atom<'src>, ParseError<'src>>
+    + Send
+    + Sync;
+
+/// The reader macros of a parser, see
+/// [`ParserOptions::reader_macros`](crate::parser::ParserOptions::reader_macros).
+/// Two tables are equal if they have the same handlers for the same
+/// prefixes, not just handlers that do the same.
+#[derive(Clone, Default)]
+pub struct ReaderMacros {
+    handlers: Vec<(String, Arc<Handler>)>,
+}
+
+impl ReaderMacros {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `handler` for identifiers starting with `prefix`, replacing a
+    /// handler registered for the same prefix before
+    pub fn on<F>(mut self, prefix: impl Into<String>, handler: F) -> Self
+    where
+        F: for<'m, 'src> Fn(&mut ReaderMacro<'m, 'src>) -> Result<Atom<'src>, ParseError<'src>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let prefix = prefix.into();
+        self.handlers.retain(|(other, _)| *other != prefix);
+        self.handlers.push((prefix, Arc::new(handler)));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    pub fn prefixes(&self) -> impl Iterator<Item = &str> {
+        self.handlers.iter().map(|(prefix, _)| prefix.as_str())
+    }
+
+    // the handler for the longest prefix of `ident`, with that prefix's length
+    pub(crate) fn find(&self, ident: &str) -> Option<(usize, Arc<Handler>)> {
+        self.handlers
+            .iter()
+            .filter(|(prefix, _)| ident.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(prefix, handler)| (prefix.len(), handler.clone()))
+    }
+}
+
+impl fmt::Debug for ReaderMacros {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.prefixes()).finish()
+    }
+}
+
+impl PartialEq for ReaderMacros {
+    fn eq(&self, other: &Self) -> bool {
+        self.handlers.len() == other.handlers.len()
+            && self.handlers.iter().all(|(prefix, handler)| {
+                other
+                    .handlers
+                    .iter()
+                    .any(|(p, h)| p == prefix && Arc::ptr_eq(h, handler))
+            })
+    }
+}
+
+impl Eq for ReaderMacros {}
+
+/// The parser while a reader macro is expanded. It starts right after the
+/// identifier with the prefix, the handler takes as much of what follows as
+/// it needs.
+pub struct ReaderMacro<'m, 'src> {
+    parser: &'m mut Parser<'src>,
+    ident: &'src str,
+    start: u32,
+    prefix_len: usize,
+}
+
+impl<'m, 'src> ReaderMacro<'m, 'src> {
+    pub(crate) fn new(
+        parser: &'m mut Parser<'src>,
+        ident: &'src str,
+        span: Span,
+        prefix_len: usize,
+    ) -> Self {
+        Self {
+            parser,
+            ident,
+            start: span.start,
+            prefix_len,
+        }
+    }
+
+    /// The whole identifier that starts the macro, prefix included
+    pub fn ident(&self) -> &'src str {
+        self.ident
+    }
+
+    /// The part of the identifier after the prefix, `/a+/` for `~/a+/`
+    pub fn rest(&self) -> &'src str {
+        &self.ident[self.prefix_len..]
+    }
+
+    /// From the start of the identifier to the end of what was taken so far
+    pub fn span(&self) -> Span {
+        let end = self
+            .parser
+            .last_tok_span()
+            .expect("the identifier was taken");
+        Span {
+            start: self.start,
+            end: end.end,
+        }
+    }
+
+    pub fn file(&self) -> Option<FileId> {
+        self.parser.options().file
+    }
+
+    /// `value` with the span of the macro, for building the atom it expands to
+    pub fn spanned<T>(&self, value: T) -> Spanned<T> {
+        Spanned(value, self.span(), self.file())
+    }
+
+    /// A list spanning the macro
+    pub fn list(&self, children: Vec<Atom<'src>>) -> Atom<'src> {
+        let span = self.span();
+        Atom::Group(Group {
+            group_type: GroupType::Parenthesis,
+            start_delim: Spanned((), Span::at(span.start as usize), self.file()),
+            children,
+            end_delim: Spanned((), Span::at(span.end as usize), self.file()),
+        })
+    }
+
+    /// The next token, including the `Indent`, `Dedent` and `Newline` tokens
+    /// of the layout
+    pub fn peek(&self) -> Option<(Token<'src>, Span)> {
+        self.parser.peek_tok(0).map(|(&tok, span)| (tok, span))
+    }
+
+    /// The next token if there's no space between it and what was taken so
+    /// far, like the string in `#date"2024-01-31"`
+    pub fn peek_adjacent(&self) -> Option<(Token<'src>, Span)> {
+        self.peek()
+            .filter(|(_, span)| span.start == self.span().end)
+    }
+
+    pub fn next_token(&mut self) -> Option<(Token<'src>, Span)> {
+        let next = self.peek()?;
+        self.parser.advance();
+        Some(next)
+    }
+
+    /// Parses the next atom, which can be a reader macro too
+    pub fn parse_atom(&mut self) -> Result<Atom<'src>, ParseError<'src>> {
+        self.parser.parse_atom()
+    }
+
+    /// An error pointing at the macro
+    pub fn error(&self, message: impl Into<String>) -> ParseError<'src> {
+        ParseError::ReaderMacro {
+            message: message.into(),
+            span: self.span(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenise;
+    use crate::parser::ParserOptions;
+
+    fn macros() -> ReaderMacros {
+        ReaderMacros::new()
+            .on("'", |m| {
+                let quote = Atom::Identifier(m.spanned("quote".into()));
+                let quoted = m.parse_atom()?;
+                Ok(m.list(vec![quote, quoted]))
+            })
+            .on("~", |m| {
+                let regex = m.rest().to_string();
+                Ok(Atom::String(m.spanned(format!("{regex:?}").into())))
+            })
+            .on("~~", |m| Ok(Atom::Identifier(m.spanned("two".into()))))
+    }
+
+    fn parse(source: &str) -> Result<Vec<String>, ParseError<'_>> {
+        let options = ParserOptions::new().reader_macros(macros());
+        let atoms = Parser::with_options(tokenise(source), options).parse_toplevel()?;
+        Ok(atoms.iter().map(ToString::to_string).collect())
+    }
+
+    #[test]
+    fn dispatch() {
+        assert_eq!(
+            parse("f '(a b) ~/a+/ ~~x\n").unwrap(),
+            ["(f (quote (a b)) \"/a+/\" two)"]
+        );
+        // `''x` is a single identifier, the handler sees `'x` as the rest
+        assert_eq!(parse("' ' x").unwrap(), ["(quote (quote x))"]);
+        assert_eq!(parse("#raw('(x))").unwrap(), ["#raw(' (x))"]);
+
+        let atoms = crate::parse("'x").unwrap();
+        assert_eq!(atoms[0].to_string(), "'x");
+    }
+
+    #[test]
+    fn spans_and_limits() {
+        let options = ParserOptions::new().reader_macros(macros());
+        let atoms = Parser::with_options(tokenise("g 'h(x)"), options.clone())
+            .parse_toplevel()
+            .unwrap();
+        let Atom::Group(group) = &atoms[0] else {
+            panic!("expected a group, got {atoms:?}");
+        };
+        assert_eq!(group.children[1].span(), Span::new(2, 7));
+
+        let deep = "' ".repeat(10) + "x";
+        let err = Parser::with_options(tokenise(&deep), options.max_depth(4))
+            .parse_toplevel()
+            .unwrap_err();
+        assert!(matches!(err, ParseError::TooDeep { limit: 4, .. }));
+
+        let table = macros();
+        assert_eq!(table, table.clone());
+        assert_ne!(macros(), macros());
+        assert_eq!(
+            format!("{:?}", ReaderMacros::new().on("#u", |m| m.parse_atom())),
+            "{\"#u\"}"
+        );
+    }
+}
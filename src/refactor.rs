@@ -0,0 +1,203 @@
+//! Source-to-source refactorings. They don't rewrite the text themselves but
+//! produce [`TextEdit`]s, so editors can apply them as a single undo step.
+
+use std::{fmt, ops::Range};
+
+use crate::value::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub span: Range<usize>,
+    pub replacement: String,
+}
+
+/// Applies non-overlapping edits to `source`
+pub fn apply_edits(source: &str, edits: &[TextEdit]) -> String {
+    let mut edits: Vec<_> = edits.iter().collect();
+    edits.sort_by_key(|edit| edit.span.start);
+
+    let mut out = String::with_capacity(source.len());
+    let mut pos = 0;
+    for edit in edits {
+        out.push_str(&source[pos..edit.span.start]);
+        out.push_str(&edit.replacement);
+        pos = edit.span.end;
+    }
+    out.push_str(&source[pos..]);
+    out
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscodeError {
+    /// indentation groups have no delimiters to change
+    NoDelimiters,
+    /// the contents of the group wouldn't be a curly-infix expression, e.g.
+    /// `(a b c d)` can't become `{a b c d}`
+    NotInfix { span: Range<usize> },
+}
+
+impl fmt::Display for TranscodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranscodeError::NoDelimiters => f.write_str("indentation groups don't have delimiters"),
+            TranscodeError::NotInfix { .. } => {
+                f.write_str("the group's contents are not a valid infix expression")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TranscodeError {}
+
+/// Changes the delimiters of every `from` group that lies completely inside
+/// `selection` to those of `to`, e.g. all `[]` to `()`.
+///
+/// Groups inside `#raw` bodies are left alone. Turning a group into `{}`
+/// requires its contents to make sense as curly-infix, that is at most two
+/// elements or an odd number of elements with the same operator between all
+/// operands.
+pub fn change_delimiters(
+    document: &[Atom],
+    selection: Range<usize>,
+    from: GroupType,
+    to: GroupType,
+) -> Result<Vec<TextEdit>, TranscodeError> {
+    if !(from.is_explicit() && to.is_explicit()) {
+        return Err(TranscodeError::NoDelimiters);
+    }
+    let (open, close) = to.delimiters();
+
+    let mut edits = vec![];
+    if from == to {
+        return Ok(edits);
+    }
+
+    let mut transcoder = Transcoder {
+        selection,
+        from,
+        to,
+        open,
+        close,
+        edits: &mut edits,
+    };
+    for atom in document {
+        transcoder.atom(atom)?;
+    }
+    Ok(edits)
+}
+
+struct Transcoder<'a> {
+    selection: Range<usize>,
+    from: GroupType,
+    to: GroupType,
+    open: &'static str,
+    close: &'static str,
+    edits: &'a mut Vec<TextEdit>,
+}
+
+impl Transcoder<'_> {
+    fn atom(&mut self, atom: &Atom) -> Result<(), TranscodeError> {
+        match atom {
+            Atom::Identifier(_) | Atom::String(_) | Atom::Raw { .. } | Atom::Error(_) => Ok(()),
+            Atom::Group(group) => self.group(group),
+            Atom::Neoteric { lhs, rhs } => {
+                self.atom(lhs)?;
+                self.group(rhs)
+            }
+        }
+    }
+
+    fn group(&mut self, group: &Group) -> Result<(), TranscodeError> {
+        let extent = group.span().range();
+        let selected = self.selection.start <= extent.start && extent.end <= self.selection.end;
+        if selected && group.group_type == self.from {
+            if matches!(self.to, GroupType::Curly) && !is_infix(&group.children) {
+                return Err(TranscodeError::NotInfix { span: extent });
+            }
+            self.edits.push(TextEdit {
+                span: group.start_delim.1.range(),
+                replacement: self.open.to_owned(),
+            });
+            self.edits.push(TextEdit {
+                span: group.end_delim.1.range(),
+                replacement: self.close.to_owned(),
+            });
+        }
+        for child in &group.children {
+            self.atom(child)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_infix(children: &[Atom]) -> bool {
+    if children.len() <= 2 {
+        return true;
+    }
+    if children.len().is_multiple_of(2) {
+        return false;
+    }
+    let Atom::Identifier(op) = &children[1] else {
+        return false;
+    };
+    children
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .all(|child| matches!(child, Atom::Identifier(other) if other.0 == op.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brackets_to_parens_and_back() {
+        let source = "let [x 1] [y 2]\n    f[x] #raw[a [b]]\n";
+        let document = crate::parse(source).unwrap();
+
+        let edits = change_delimiters(
+            &document,
+            0..source.len(),
+            GroupType::Bracket,
+            GroupType::Parenthesis,
+        )
+        .unwrap();
+        assert_eq!(
+            apply_edits(source, &edits),
+            "let (x 1) (y 2)\n    f(x) #raw[a [b]]\n"
+        );
+
+        // only groups entirely inside the selection change
+        let edits = change_delimiters(&document, 4..10, GroupType::Bracket, GroupType::Parenthesis)
+            .unwrap();
+        assert_eq!(
+            apply_edits(source, &edits),
+            "let (x 1) [y 2]\n    f[x] #raw[a [b]]\n"
+        );
+    }
+
+    #[test]
+    fn curly_needs_infix() {
+        let source = "(a + b + c) (- x) (a b c d)";
+        let document = crate::parse(source).unwrap();
+
+        let edits =
+            change_delimiters(&document, 0..17, GroupType::Parenthesis, GroupType::Curly).unwrap();
+        assert_eq!(apply_edits(source, &edits), "{a + b + c} {- x} (a b c d)");
+
+        let err = change_delimiters(
+            &document,
+            0..source.len(),
+            GroupType::Parenthesis,
+            GroupType::Curly,
+        )
+        .unwrap_err();
+        assert_eq!(err, TranscodeError::NotInfix { span: 18..27 });
+
+        assert_eq!(
+            change_delimiters(&document, 0..1, GroupType::Indentation, GroupType::Curly),
+            Err(TranscodeError::NoDelimiters)
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Support for interactive shells, which have to decide whether to evaluate
+//! what was typed so far or to ask for another line.
+//!
+//! Like the SRFI 110 reference REPL, a top-level expression only ends at a
+//! blank line, since until then the next line could still be an indented
+//! child of the last one.
+
+use crate::lexer::{tokenise, Token};
+use crate::parser::{Dialect, ParseError, Parser};
+
+#[derive(Debug)]
+pub enum InputStatus<'src> {
+    /// ready to be evaluated, also used for input that is only whitespace
+    /// and comments
+    Complete,
+    /// an explicit group or a string is still open, or the input doesn't end
+    /// with a blank line yet
+    Incomplete,
+    /// more input can't fix this
+    Invalid(ParseError<'src>),
+}
+
+pub fn classify(source: &str, dialect: Dialect) -> InputStatus<'_> {
+    let tokens: Vec<_> = tokenise(source).collect();
+
+    // the string regex only fails when there's no closing quote
+    let open_string = tokens
+        .iter()
+        .any(|(tok, span)| matches!(tok, Token::Error(_)) && source[*span].starts_with('"'));
+    if open_string {
+        return InputStatus::Incomplete;
+    }
+
+    let only_trivia = tokens
+        .iter()
+        .all(|(tok, _)| matches!(tok, Token::Newline | Token::Spaces(_) | Token::Comment));
+
+    match Parser::with_dialect(tokens.into_iter(), dialect).parse_toplevel() {
+        Ok(_) if only_trivia || ends_with_blank_line(source) => InputStatus::Complete,
+        Ok(_) => InputStatus::Incomplete,
+        // the end of the input came before a closing delimiter
+        Err(ParseError::UnclosedGroup { .. } | ParseError::ExpectedTokFoundEof { .. }) => {
+            InputStatus::Incomplete
+        }
+        Err(err) => InputStatus::Invalid(err),
+    }
+}
+
+fn ends_with_blank_line(source: &str) -> bool {
+    let mut lines = source.rsplit('\n');
+    // whatever follows the last newline, usually nothing
+    let last = lines.next().unwrap_or("");
+    let blank = lines.next().unwrap_or("");
+    last.trim().is_empty() && blank.trim().is_empty() && lines.next().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(source: &str) -> &'static str {
+        match classify(source, Dialect::Sweet) {
+            InputStatus::Complete => "complete",
+            InputStatus::Incomplete => "incomplete",
+            InputStatus::Invalid(_) => "invalid",
+        }
+    }
+
+    #[test]
+    fn classify_partial_input() {
+        assert_eq!(status(""), "complete");
+        assert_eq!(status("; just a comment\n"), "complete");
+        assert_eq!(status("f x\n"), "incomplete");
+        assert_eq!(status("f x\n\n"), "complete");
+        assert_eq!(status("define f(x)\n    g x\n"), "incomplete");
+        assert_eq!(status("define f(x)\n    g x\n  \n"), "complete");
+        assert_eq!(status("(a b\n\n"), "incomplete");
+        assert_eq!(status("{a + [b\n"), "incomplete");
+        assert_eq!(status("print \"hello\n\n"), "incomplete");
+        assert_eq!(status("a)\n\n"), "invalid");
+        assert_eq!(status("(a]\n"), "invalid");
+    }
+}
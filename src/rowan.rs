@@ -0,0 +1,241 @@
+//! The [`cst`](crate::cst) as a [`rowan`] tree, with the `rowan` feature, for
+//! tools built the way rust-analyzer is.
+//!
+//! Like the CST, the tree is lossless and is built for broken input too.
+//! Nodes and tokens have a [`SyntaxKind`], [`SweetExpr`] is the
+//! [`Language`] that ties the two together.
+//!
+//! ```
+//! use sweet_expr::rowan::{parse, SyntaxKind};
+//!
+//! let parse = parse("f(x) ; doc\n");
+//! let root = parse.syntax();
+//! assert_eq!(root.to_string(), "f(x) ; doc\n");
+//! let call = root.first_child().unwrap();
+//! assert_eq!(call.kind(), SyntaxKind::Neoteric);
+//! assert_eq!(call.text(), "f(x)");
+//! ```
+
+use ::rowan::{GreenNode, GreenNodeBuilder, Language};
+
+use crate::cst::{self, Element, Node, NodeKind};
+use crate::lexer::Token;
+use crate::parser::{ParseError, ParserOptions};
+use crate::value::GroupType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u16)]
+pub enum SyntaxKind {
+    // tokens
+    Identifier,
+    String,
+    Comment,
+    ParenOpen,
+    ParenClose,
+    CurlyOpen,
+    CurlyClose,
+    BracketOpen,
+    BracketClose,
+    /// a line break, `\n` or `\r\n`
+    Newline,
+    Whitespace,
+    /// text the lexer didn't understand
+    ErrorToken,
+
+    // nodes
+    Document,
+    ParenGroup,
+    CurlyGroup,
+    BracketGroup,
+    /// a group made by indentation, without delimiter tokens
+    IndentGroup,
+    /// `f(x)`, the head followed by the group it's called with
+    Neoteric,
+    /// `#raw(...)`, the marker token followed by the body
+    Raw,
+    /// tokens the recovering parser couldn't make sense of
+    Error,
+}
+
+impl SyntaxKind {
+    const ALL: [SyntaxKind; 20] = [
+        SyntaxKind::Identifier,
+        SyntaxKind::String,
+        SyntaxKind::Comment,
+        SyntaxKind::ParenOpen,
+        SyntaxKind::ParenClose,
+        SyntaxKind::CurlyOpen,
+        SyntaxKind::CurlyClose,
+        SyntaxKind::BracketOpen,
+        SyntaxKind::BracketClose,
+        SyntaxKind::Newline,
+        SyntaxKind::Whitespace,
+        SyntaxKind::ErrorToken,
+        SyntaxKind::Document,
+        SyntaxKind::ParenGroup,
+        SyntaxKind::CurlyGroup,
+        SyntaxKind::BracketGroup,
+        SyntaxKind::IndentGroup,
+        SyntaxKind::Neoteric,
+        SyntaxKind::Raw,
+        SyntaxKind::Error,
+    ];
+
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::Comment | SyntaxKind::Newline | SyntaxKind::Whitespace
+        )
+    }
+}
+
+impl From<SyntaxKind> for ::rowan::SyntaxKind {
+    fn from(kind: SyntaxKind) -> Self {
+        Self(kind as u16)
+    }
+}
+
+impl From<Token<'_>> for SyntaxKind {
+    fn from(token: Token<'_>) -> Self {
+        match token {
+            Token::Identifier(_) => SyntaxKind::Identifier,
+            Token::String(_) => SyntaxKind::String,
+            Token::Comment => SyntaxKind::Comment,
+            Token::ParenOpen => SyntaxKind::ParenOpen,
+            Token::ParenClose => SyntaxKind::ParenClose,
+            Token::CurlyOpen => SyntaxKind::CurlyOpen,
+            Token::CurlyClose => SyntaxKind::CurlyClose,
+            Token::BracketOpen => SyntaxKind::BracketOpen,
+            Token::BracketClose => SyntaxKind::BracketClose,
+            Token::Newline => SyntaxKind::Newline,
+            // the CST only has the tokens of the source, these never show up
+            Token::Spaces(_) | Token::Indent | Token::Dedent => SyntaxKind::Whitespace,
+            Token::Error(_) => SyntaxKind::ErrorToken,
+        }
+    }
+}
+
+impl From<NodeKind> for SyntaxKind {
+    fn from(kind: NodeKind) -> Self {
+        match kind {
+            NodeKind::Document => SyntaxKind::Document,
+            NodeKind::Group(GroupType::Parenthesis) => SyntaxKind::ParenGroup,
+            NodeKind::Group(GroupType::Curly) => SyntaxKind::CurlyGroup,
+            NodeKind::Group(GroupType::Bracket) => SyntaxKind::BracketGroup,
+            NodeKind::Group(GroupType::Indentation) => SyntaxKind::IndentGroup,
+            NodeKind::Neoteric => SyntaxKind::Neoteric,
+            NodeKind::Raw => SyntaxKind::Raw,
+            NodeKind::Error => SyntaxKind::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SweetExpr {}
+
+impl Language for SweetExpr {
+    type Kind = SyntaxKind;
+
+    fn kind_from_raw(raw: ::rowan::SyntaxKind) -> SyntaxKind {
+        // rowan only hands back kinds that came from `kind_to_raw`
+        SyntaxKind::ALL[raw.0 as usize]
+    }
+
+    fn kind_to_raw(kind: SyntaxKind) -> ::rowan::SyntaxKind {
+        kind.into()
+    }
+}
+
+pub type SyntaxNode = ::rowan::SyntaxNode<SweetExpr>;
+pub type SyntaxToken = ::rowan::SyntaxToken<SweetExpr>;
+pub type SyntaxElement = ::rowan::SyntaxElement<SweetExpr>;
+
+#[derive(Debug)]
+pub struct Parse<'src> {
+    pub green: GreenNode,
+    /// see [`cst::Cst::errors`]
+    pub errors: Vec<ParseError<'src>>,
+}
+
+impl Parse<'_> {
+    pub fn syntax(&self) -> SyntaxNode {
+        SyntaxNode::new_root(self.green.clone())
+    }
+}
+
+pub fn parse(source: &str) -> Parse<'_> {
+    parse_with_options(source, ParserOptions::default())
+}
+
+pub fn parse_with_options(source: &str, options: ParserOptions) -> Parse<'_> {
+    let cst = cst::parse_with_options(source, options);
+    Parse {
+        green: green(&cst.root),
+        errors: cst.errors,
+    }
+}
+
+/// The green tree for a CST node
+pub fn green(node: &Node) -> GreenNode {
+    let mut builder = GreenNodeBuilder::new();
+    build(node, &mut builder);
+    builder.finish()
+}
+
+fn build(node: &Node, builder: &mut GreenNodeBuilder) {
+    builder.start_node(SyntaxKind::from(node.kind).into());
+    for child in &node.children {
+        match child {
+            Element::Node(node) => build(node, builder),
+            Element::Token(token) => {
+                builder.token(SyntaxKind::from(token.token).into(), token.text)
+            }
+        }
+    }
+    builder.finish_node();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_round_trip() {
+        for kind in SyntaxKind::ALL {
+            assert_eq!(SweetExpr::kind_from_raw(SweetExpr::kind_to_raw(kind)), kind);
+        }
+    }
+
+    #[test]
+    fn lossless_tree() {
+        let source = "define f(x) ; doc\n  #raw(a b) [c]\n  ) {d + e}\n";
+        let parse = parse(source);
+        let root = parse.syntax();
+        assert_eq!(root.to_string(), source);
+        assert!(!parse.errors.is_empty());
+
+        let kinds: Vec<_> = root.descendants().map(|node| node.kind()).collect();
+        use SyntaxKind::*;
+        assert_eq!(
+            kinds,
+            [
+                Document,
+                IndentGroup,
+                Neoteric,
+                ParenGroup,
+                IndentGroup,
+                Raw,
+                ParenGroup,
+                BracketGroup,
+                Error,
+                CurlyGroup
+            ]
+        );
+        let comment = root
+            .descendants_with_tokens()
+            .find(|element| element.kind() == Comment)
+            .unwrap();
+        assert_eq!(comment.as_token().unwrap().text(), "; doc");
+        assert!(comment.kind().is_trivia());
+    }
+}
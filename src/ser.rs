@@ -0,0 +1,590 @@
+//! Serializing Rust data as sweet-expressions with serde, the counterpart of
+//! [`crate::de`]: whatever is written here reads back the same.
+//!
+//! Structs and maps become one `key value...` line per entry, with sequences,
+//! structs and maps after a key spread over the rest of the line, or over
+//! indented lines when they don't fit. `None` is a key on its own, written
+//! as `(key)`, unit variants are identifiers and other variants are
+//! `(variant value...)`.
+//! The text is laid out by the default [`Printer`].
+//!
+//! Strings can't contain backslashes, sweet-expressions have no way of
+//! writing them.
+//!
+//! ```
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize)]
+//! struct Config {
+//!     name: String,
+//!     port: u16,
+//!     tags: Vec<&'static str>,
+//!     server: Server,
+//! }
+//!
+//! #[derive(Serialize)]
+//! struct Server {
+//!     host: &'static str,
+//!     backup: Option<&'static str>,
+//! }
+//!
+//! let config = Config {
+//!     name: "app".into(),
+//!     port: 8080,
+//!     tags: vec!["web", "api"],
+//!     server: Server { host: "localhost", backup: None },
+//! };
+//! assert_eq!(
+//!     sweet_expr::to_string(&config).unwrap(),
+//!     "name \"app\"\nport 8080\ntags \"web\" \"api\"\nserver (host \"localhost\") (backup)\n"
+//! );
+//! ```
+
+use std::{fmt, io};
+
+use serde::ser::{self, Serialize};
+
+use crate::number::Number;
+use crate::printer::Printer;
+use crate::value::*;
+
+#[derive(Debug)]
+pub enum Error {
+    Custom(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Custom(message) => f.write_str(message),
+            Error::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Custom(_) => None,
+            Error::Io(err) => Some(err),
+        }
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Custom(msg.to_string())
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// Writes `value` as an indented sweet-expression document, see the
+/// [module docs](self)
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    Ok(Printer::new().print_document(&to_atoms(value)?))
+}
+
+pub fn to_writer<W: io::Write, T: Serialize + ?Sized>(
+    mut writer: W,
+    value: &T,
+) -> Result<(), Error> {
+    writer.write_all(to_string(value)?.as_bytes())?;
+    Ok(())
+}
+
+/// The forms [`to_string`] would write, for printing them some other way
+pub fn to_atoms<T: Serialize + ?Sized>(value: &T) -> Result<Vec<OwnedAtom>, Error> {
+    Ok(match value.serialize(Serializer)? {
+        Value::Atom(atom) => vec![atom],
+        Value::List(items) => items,
+    })
+}
+
+// sequences, structs and maps are kept apart from other groups so they can be
+// spread after a key
+enum Value {
+    Atom(OwnedAtom),
+    List(Vec<OwnedAtom>),
+}
+
+impl Value {
+    fn into_atom(self) -> OwnedAtom {
+        match self {
+            Value::Atom(atom) => atom,
+            Value::List(items) => Group::paren(items).into(),
+        }
+    }
+
+    fn after(self, head: OwnedAtom) -> OwnedAtom {
+        let mut items = vec![head];
+        match self {
+            Value::Atom(atom) => items.push(atom),
+            Value::List(rest) => items.extend(rest),
+        }
+        Group::paren(items).into()
+    }
+}
+
+// whether `text` reads back as the identifier `text`
+fn is_plain_identifier(text: &str) -> bool {
+    !text.is_empty()
+        && !text.contains(|c: char| c.is_whitespace() || "(){}[]\";".contains(c))
+        && !matches!(text, "." | "$" | "\\\\")
+        && text != crate::parser::RAW_MARKER
+}
+
+fn identifier(name: &str) -> Result<OwnedAtom, Error> {
+    match is_plain_identifier(name) {
+        true => Ok(Atom::ident(name.to_string())),
+        false => Err(Error::Custom(format!(
+            "`{name}` can't be written as an identifier"
+        ))),
+    }
+}
+
+fn string(text: &str) -> Result<OwnedAtom, Error> {
+    Atom::try_string(text)
+        .map(Atom::into_owned)
+        .ok_or_else(|| Error::Custom(format!("{text:?} contains a backslash")))
+}
+
+fn number(number: Number) -> Result<Value, Error> {
+    Ok(Value::Atom(Atom::ident(number.to_string())))
+}
+
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = Seq;
+    type SerializeTuple = Seq;
+    type SerializeTupleStruct = Seq;
+    type SerializeTupleVariant = Seq;
+    type SerializeMap = Pairs;
+    type SerializeStruct = Pairs;
+    type SerializeStructVariant = Pairs;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Atom(Atom::ident(if v { "true" } else { "false" })))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        number(Number::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        match i64::try_from(v) {
+            Ok(v) => self.serialize_i64(v),
+            Err(_) => Err(Error::Custom(format!("{v} is too large, numbers are i64"))),
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        number(Number::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        string(v).map(Value::Atom)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        v.iter()
+            .map(|&byte| number(Number::Integer(byte.into())).map(Value::into_atom))
+            .collect::<Result<_, _>>()
+            .map(Value::List)
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::List(vec![]))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Atom(Group::paren([]).into()))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        identifier(variant).map(Value::Atom)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        let value = value.serialize(Serializer)?;
+        Ok(Value::Atom(value.after(identifier(variant)?)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Seq, Error> {
+        Ok(Seq {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            variant: false,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Seq, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Seq, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Seq, Error> {
+        let mut items = Vec::with_capacity(len + 1);
+        items.push(identifier(variant)?);
+        Ok(Seq {
+            items,
+            variant: true,
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Pairs, Error> {
+        Ok(Pairs {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Pairs, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Pairs, Error> {
+        Ok(Pairs {
+            items: Vec::with_capacity(len),
+            key: None,
+            variant: Some(identifier(variant)?),
+        })
+    }
+}
+
+struct Seq {
+    items: Vec<OwnedAtom>,
+    // tuple variants have their name as the first item and are a group
+    variant: bool,
+}
+
+impl Seq {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(value.serialize(Serializer)?.into_atom());
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        Ok(match self.variant {
+            true => Value::Atom(Group::paren(self.items).into()),
+            false => Value::List(self.items),
+        })
+    }
+}
+
+impl ser::SerializeSeq for Seq {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for Seq {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for Seq {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for Seq {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+struct Pairs {
+    items: Vec<OwnedAtom>,
+    key: Option<OwnedAtom>,
+    // struct variants are `(variant (field value)...)`
+    variant: Option<OwnedAtom>,
+}
+
+impl Pairs {
+    fn push<T: Serialize + ?Sized>(&mut self, key: OwnedAtom, value: &T) -> Result<(), Error> {
+        self.items.push(value.serialize(Serializer)?.after(key));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        Ok(match self.variant {
+            Some(variant) => Value::Atom(Value::List(self.items).after(variant)),
+            None => Value::List(self.items),
+        })
+    }
+}
+
+impl ser::SerializeMap for Pairs {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        // string keys are written as identifiers where they read back the same
+        let key = match key.serialize(Serializer)? {
+            Value::Atom(atom) => match atom.unescaped() {
+                Some(text) if is_plain_identifier(&text) => Atom::ident(text.into_owned()),
+                _ => atom,
+            },
+            Value::List(_) => return Err(Error::Custom("map keys must be single atoms".into())),
+        };
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().expect("serialize_key is called first");
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for Pairs {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let key = identifier(key)?;
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for Pairs {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let key = identifier(key)?;
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "kebab-case")]
+    enum Shape {
+        Point,
+        Circle(f64),
+        Rect(f64, f64),
+        Poly { sides: u8, name: Option<String> },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct State {
+        title: String,
+        count: u64,
+        ratio: f32,
+        enabled: bool,
+        limit: Option<u32>,
+        shapes: Vec<Shape>,
+        grid: Vec<Vec<i8>>,
+        labels: BTreeMap<String, (char, ())>,
+    }
+
+    fn state() -> State {
+        State {
+            title: "say \"hi\"".into(),
+            count: 3,
+            ratio: 0.5,
+            enabled: false,
+            limit: None,
+            shapes: vec![
+                Shape::Point,
+                Shape::Circle(1.0),
+                Shape::Rect(2.0, 3.5),
+                Shape::Poly {
+                    sides: 5,
+                    name: Some("star".into()),
+                },
+            ],
+            grid: vec![vec![1], vec![], vec![2, 3]],
+            labels: BTreeMap::from([("a b".into(), ('x', ())), ("c".into(), ('y', ()))]),
+        }
+    }
+
+    #[test]
+    fn indented_output() {
+        assert_eq!(
+            to_string(&state()).unwrap(),
+            r#"title "say \"hi\""
+count 3
+ratio 0.5
+enabled false
+(limit)
+shapes point (circle 1.0) (rect 2.0 3.5) (poly (sides 5) (name "star"))
+grid (1) () (2 3)
+labels ("a b" "x" ()) (c "y" ())
+"#
+        );
+
+        let long = BTreeMap::from([("items", ["a".repeat(40), "b".repeat(40)])]);
+        assert_eq!(
+            to_string(&long).unwrap(),
+            format!(
+                "items\n    \"{}\"\n    \"{}\"\n",
+                "a".repeat(40),
+                "b".repeat(40)
+            )
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let text = to_string(&state()).unwrap();
+        assert_eq!(crate::from_str::<State>(&text).unwrap(), state());
+
+        for shape in state().shapes {
+            let text = to_string(&shape).unwrap();
+            assert_eq!(crate::from_str::<Shape>(&text).unwrap(), shape, "{text}");
+        }
+
+        let mut out = vec![];
+        to_writer(&mut out, &[1, 2]).unwrap();
+        assert_eq!(out, b"1\n2\n");
+    }
+
+    #[test]
+    fn unrepresentable() {
+        assert!(to_string("a\\b").is_err());
+        assert!(to_string(&u64::MAX).is_err());
+        assert!(to_string(&BTreeMap::from([(vec![1], 2)])).is_err());
+    }
+}
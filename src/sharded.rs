@@ -0,0 +1,49 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+pub(crate) const SHARD_BITS: u32 = 4;
+pub(crate) const SHARDS: usize = 1 << SHARD_BITS;
+
+/// A fixed number of independently locked `T`s, picked by the hash of a key,
+/// so threads working on different keys rarely contend on the same lock
+#[derive(Debug)]
+pub(crate) struct Sharded<T> {
+    shards: Box<[RwLock<T>]>,
+    hasher: RandomState,
+}
+
+impl<T: Default> Default for Sharded<T> {
+    fn default() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| RwLock::default()).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl<T> Sharded<T> {
+    pub(crate) fn index_for<K: Hash + ?Sized>(&self, key: &K) -> usize {
+        self.hasher.hash_one(key) as usize % SHARDS
+    }
+
+    pub(crate) fn read(&self, index: usize) -> RwLockReadGuard<'_, T> {
+        // a panic while holding the lock can't leave the maps in an
+        // inconsistent state, so poisoning is ignored
+        self.shards[index]
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub(crate) fn write(&self, index: usize) -> RwLockWriteGuard<'_, T> {
+        self.shards[index]
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = RwLockReadGuard<'_, T>> {
+        (0..SHARDS).map(|i| self.read(i))
+    }
+}
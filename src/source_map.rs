@@ -0,0 +1,254 @@
+use std::{
+    fmt,
+    ops::{Index, Range},
+    sync::{Arc, RwLock},
+};
+
+/// Byte range in a source, like `Range<usize>` but `Copy` and half the size.
+/// Sources are limited to 4 GiB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        let offset = |pos: usize| u32::try_from(pos).expect("sources are limited to 4 GiB");
+        Self {
+            start: offset(start),
+            end: offset(end),
+        }
+    }
+
+    /// The empty span at `pos`
+    pub fn at(pos: usize) -> Self {
+        Self::new(pos, pos)
+    }
+
+    pub fn range(self) -> Range<usize> {
+        self.start as usize..self.end as usize
+    }
+
+    pub fn len(self) -> usize {
+        (self.end - self.start) as usize
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.start == self.end
+    }
+
+    /// The smallest span covering both
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
+impl From<Range<usize>> for Span {
+    fn from(range: Range<usize>) -> Self {
+        Self::new(range.start, range.end)
+    }
+}
+
+impl From<Span> for Range<usize> {
+    fn from(span: Span) -> Self {
+        span.range()
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+impl Index<Span> for str {
+    type Output = str;
+
+    fn index(&self, span: Span) -> &str {
+        &self[span.range()]
+    }
+}
+
+/// Maps byte offsets in a source string to line and column numbers
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    // byte offset at which every line starts, the first entry is always 0
+    line_starts: Vec<usize>,
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            line_starts,
+            len: source.len(),
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The 0-based line that contains `offset`
+    pub fn line(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        }
+    }
+
+    /// Byte range of the line, including the line terminator
+    pub fn line_span(&self, line: usize) -> Range<usize> {
+        let start = self.line_starts[line];
+        let end = self.line_starts.get(line + 1).copied().unwrap_or(self.len);
+        start..end
+    }
+
+    /// The 0-based line and the 0-based column (in chars) of `offset`
+    pub fn line_col(&self, source: &str, offset: usize) -> (usize, usize) {
+        let line = self.line(offset);
+        let start = self.line_starts[line];
+        (line, source[start..offset].chars().count())
+    }
+
+    /// The 0-based range of lines touched by `span`
+    pub fn lines(&self, span: &Range<usize>) -> Range<usize> {
+        let first = self.line(span.start);
+        let last = if span.end > span.start {
+            self.line(span.end - 1)
+        } else {
+            first
+        };
+        first..last + 1
+    }
+}
+
+/// Identifies a file in a [`SourceMap`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileId(pub u32);
+
+#[derive(Debug)]
+pub struct SourceFile {
+    pub name: String,
+    pub source: Arc<str>,
+    pub lines: LineIndex,
+}
+
+/// All files of an application, shared between threads.
+///
+/// Files are only ever added, so lookups just take a read lock long enough
+/// to clone an `Arc`.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: RwLock<Vec<Arc<SourceFile>>>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, name: impl Into<String>, source: impl Into<Arc<str>>) -> FileId {
+        let source = source.into();
+        let file = Arc::new(SourceFile {
+            name: name.into(),
+            lines: LineIndex::new(&source),
+            source,
+        });
+        let mut files = self.files.write().unwrap_or_else(|err| err.into_inner());
+        files.push(file);
+        FileId(files.len() as u32 - 1)
+    }
+
+    pub fn get(&self, id: FileId) -> Option<Arc<SourceFile>> {
+        let files = self.files.read().unwrap_or_else(|err| err.into_inner());
+        files.get(id.0 as usize).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.files
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ParseCache;
+    use crate::interner::Interner;
+    use crate::lexer::tokenise;
+    use crate::parser::{Parser, ParserOptions};
+    use crate::value::Atom;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn shared_types_are_sync() {
+        assert_send_sync::<SourceMap>();
+        assert_send_sync::<Interner>();
+        assert_send_sync::<ParseCache<Vec<String>>>();
+    }
+
+    #[test]
+    fn files_added_from_many_threads() {
+        let map = SourceMap::new();
+        std::thread::scope(|scope| {
+            for i in 0..4 {
+                let map = &map;
+                scope.spawn(move || {
+                    let id = map.add(format!("file{i}.sweet"), format!("a\nb{i}\n"));
+                    let file = map.get(id).unwrap();
+                    assert_eq!(file.name, format!("file{i}.sweet"));
+                    assert_eq!(file.lines.line(3), 1);
+                });
+            }
+        });
+        assert_eq!(map.len(), 4);
+    }
+
+    #[test]
+    fn compact_spans() {
+        assert_eq!(std::mem::size_of::<Span>(), 8);
+        let span = Span::from(2..5);
+        assert_eq!(&"f(xyz)"[span], "xyz");
+        assert_eq!(Range::from(span), 2..5);
+        assert_eq!(span.to(Span::at(7)), Span::new(2, 7));
+    }
+
+    #[test]
+    fn spans_carry_the_file() {
+        let map = SourceMap::new();
+        map.add("a.sweet", "x");
+        let id = map.add("b.sweet", "f(y)");
+        let file = map.get(id).unwrap();
+
+        let options = ParserOptions::new().file(id);
+        let document = Parser::with_options(tokenise(&file.source), options)
+            .parse_toplevel()
+            .unwrap();
+        let Atom::Neoteric { lhs, rhs } = &document[0] else {
+            panic!("expected a neoteric expression, got {document:?}");
+        };
+        let Atom::Identifier(f) = &**lhs else {
+            panic!("expected an identifier, got {lhs:?}");
+        };
+        assert_eq!(f.file(), Some(id));
+        assert_eq!(rhs.end_delim.file(), Some(id));
+        assert_eq!(map.get(f.file().unwrap()).unwrap().name, "b.sweet");
+    }
+}
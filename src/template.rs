@@ -0,0 +1,285 @@
+//! Filling in templates with atoms, instead of pasting strings together
+//! before parsing.
+//!
+//! In a template `$name` is a placeholder for a single atom and `$name...`
+//! for any number of them, spliced into the group around it. Replacements
+//! are atoms, so a string stays one string whatever quotes are in it. A `$`
+//! on its own is the sublist marker as usual, and placeholders inside of
+//! string literals are left alone.
+//!
+//! ```
+//! use sweet_expr::template::{Replacements, Template};
+//! use sweet_expr::value::Atom;
+//!
+//! let template: Template = "server $name\n  host $host\n  ports $ports...\n".parse().unwrap();
+//! let replacements = Replacements::new()
+//!     .with("name", Atom::ident("web"))
+//!     .with("host", Atom::string("say \"hi\""))
+//!     .with_rest("ports", [Atom::ident("80"), Atom::ident("443")]);
+//! let atoms = template.instantiate(&replacements).unwrap();
+//! assert_eq!(
+//!     atoms[0].to_string(),
+//!     "(server web (host \"say \\\"hi\\\"\") (ports 80 443))"
+//! );
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::diagnostic::Diagnostic;
+use crate::value::*;
+
+/// The forms of a document with placeholders in them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    atoms: Vec<OwnedAtom>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateError {
+    /// the template doesn't parse
+    Syntax(Diagnostic),
+    /// a placeholder without a replacement, `rest` for `$name...`
+    Unbound {
+        name: String,
+        rest: bool,
+        span: Range<usize>,
+    },
+    /// a `$name...` as the head of a neoteric expression, where only a
+    /// single atom fits
+    RestAsHead { span: Range<usize> },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Syntax(err) => err.fmt(f),
+            TemplateError::Unbound { name, rest, .. } => {
+                let dots = if *rest { "..." } else { "" };
+                write!(f, "no replacement for `${name}{dots}`")
+            }
+            TemplateError::RestAsHead { .. } => {
+                f.write_str("`$name...` can't be the head of a neoteric expression")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+impl FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let atoms =
+            crate::parse(template).map_err(|err| TemplateError::Syntax(Diagnostic::from(&err)))?;
+        Ok(Template {
+            atoms: atoms.into_iter().map(Atom::into_owned).collect(),
+        })
+    }
+}
+
+/// What the placeholders of a template are replaced with
+#[derive(Debug, Clone, Default)]
+pub struct Replacements<'src> {
+    single: HashMap<String, Atom<'src>>,
+    rests: HashMap<String, Vec<Atom<'src>>>,
+}
+
+impl<'src> Replacements<'src> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces `$name` with `atom`
+    pub fn with(mut self, name: impl Into<String>, atom: Atom<'src>) -> Self {
+        self.single.insert(name.into(), atom);
+        self
+    }
+
+    /// Replaces `$name...` with `atoms`
+    pub fn with_rest(
+        mut self,
+        name: impl Into<String>,
+        atoms: impl IntoIterator<Item = Atom<'src>>,
+    ) -> Self {
+        self.rests.insert(name.into(), atoms.into_iter().collect());
+        self
+    }
+}
+
+impl Template {
+    pub fn atoms(&self) -> &[OwnedAtom] {
+        &self.atoms
+    }
+
+    /// The names of all placeholders, in order, with the ones for rests
+    /// ending in `...`
+    pub fn placeholders(&self) -> Vec<&str> {
+        fn collect<'a>(atom: &'a Atom, out: &mut Vec<&'a str>) {
+            if let Some(name) = atom.as_identifier().filter(|_| placeholder(atom).is_some()) {
+                out.push(&name[1..]);
+            }
+            match atom {
+                Atom::Group(group) => group.children.iter().for_each(|c| collect(c, out)),
+                Atom::Neoteric { lhs, rhs } => {
+                    collect(lhs, out);
+                    rhs.children.iter().for_each(|c| collect(c, out));
+                }
+                _ => {}
+            }
+        }
+        let mut out = vec![];
+        self.atoms.iter().for_each(|atom| collect(atom, &mut out));
+        out
+    }
+
+    /// The forms of the template with every placeholder replaced. The
+    /// replacements keep their spans, everything else has the span it has in
+    /// the template.
+    pub fn instantiate<'src>(
+        &self,
+        replacements: &Replacements<'src>,
+    ) -> Result<Vec<Atom<'src>>, TemplateError> {
+        instantiate_all(&self.atoms, replacements)
+    }
+}
+
+// `Some((name, rest))` for `$name` and `$name...`
+fn placeholder<'a>(atom: &'a Atom) -> Option<(&'a str, bool)> {
+    let name = atom.as_identifier()?.strip_prefix('$')?;
+    let (name, rest) = match name.strip_suffix("...") {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    (!name.is_empty()).then_some((name, rest))
+}
+
+fn instantiate_all<'src>(
+    atoms: &[OwnedAtom],
+    replacements: &Replacements<'src>,
+) -> Result<Vec<Atom<'src>>, TemplateError> {
+    let mut out = Vec::with_capacity(atoms.len());
+    for atom in atoms {
+        match placeholder(atom) {
+            Some((name, true)) => match replacements.rests.get(name) {
+                Some(atoms) => out.extend(atoms.iter().cloned()),
+                None => return Err(unbound(atom, name, true)),
+            },
+            _ => out.push(instantiate(atom, replacements)?),
+        }
+    }
+    Ok(out)
+}
+
+fn instantiate<'src>(
+    atom: &OwnedAtom,
+    replacements: &Replacements<'src>,
+) -> Result<Atom<'src>, TemplateError> {
+    if let Some((name, rest)) = placeholder(atom) {
+        if rest {
+            return Err(TemplateError::RestAsHead {
+                span: atom.span().range(),
+            });
+        }
+        return match replacements.single.get(name) {
+            Some(replacement) => Ok(replacement.clone()),
+            None => Err(unbound(atom, name, false)),
+        };
+    }
+    Ok(match atom {
+        Atom::Group(group) => Atom::Group(Group {
+            children: instantiate_all(&group.children, replacements)?,
+            ..group.clone()
+        }),
+        Atom::Neoteric { lhs, rhs } => Atom::Neoteric {
+            lhs: Box::new(instantiate(lhs, replacements)?),
+            rhs: Group {
+                children: instantiate_all(&rhs.children, replacements)?,
+                ..rhs.clone()
+            },
+        },
+        atom => atom.clone(),
+    })
+}
+
+fn unbound(atom: &Atom, name: &str, rest: bool) -> TemplateError {
+    TemplateError::Unbound {
+        name: name.to_string(),
+        rest,
+        span: atom.span().range(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(text: &str) -> Template {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn instantiation() {
+        let stanza =
+            template("$kind $name\n  path \"$name\" $dirs...\n  $extra...\n$f(x) f($args...)\n");
+        assert_eq!(
+            stanza.placeholders(),
+            ["kind", "name", "dirs...", "extra...", "f", "args..."]
+        );
+        let replacements = Replacements::new()
+            .with("kind", Atom::ident("mount"))
+            .with("name", "(a b)".parse().unwrap())
+            .with_rest("dirs", [Atom::string("/a"), Atom::string("/b")])
+            .with_rest("extra", [])
+            .with("f", Atom::ident("g"))
+            .with_rest("args", [Atom::ident("1")]);
+        let atoms = stanza.instantiate(&replacements).unwrap();
+        let text: Vec<_> = atoms.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            text,
+            [
+                "(mount (a b) (path \"$name\" \"/a\" \"/b\"))",
+                "((g x) (f 1))"
+            ]
+        );
+
+        // the sublist marker isn't a placeholder
+        let sublist = template("a $ b c\n");
+        assert!(sublist.placeholders().is_empty());
+        assert_eq!(
+            sublist.instantiate(&Replacements::new()).unwrap()[0].to_string(),
+            "(a $ b c)"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let err = template("a $b")
+            .instantiate(&Replacements::new())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "no replacement for `$b`");
+        assert_eq!(
+            err,
+            TemplateError::Unbound {
+                name: "b".into(),
+                rest: false,
+                span: 2..4
+            }
+        );
+        let only_single = Replacements::new().with("b", Atom::ident("x"));
+        let err = template("a $b...").instantiate(&only_single).unwrap_err();
+        assert_eq!(err.to_string(), "no replacement for `$b...`");
+        let rest = Replacements::new().with_rest("f", []);
+        assert!(matches!(
+            template("$f...(x)").instantiate(&rest),
+            Err(TemplateError::RestAsHead { span }) if span == (0..5)
+        ));
+        assert!(matches!(
+            "(a".parse::<Template>(),
+            Err(TemplateError::Syntax(_))
+        ));
+    }
+}
@@ -0,0 +1,241 @@
+//! Helpers for testing code that consumes parsed trees, most importantly
+//! [`assert_parses_to!`](crate::assert_parses_to).
+//!
+//! Expected trees can be written as sweet-expression text or with the terse
+//! constructors in this module. They are compared by shape only: spans are
+//! ignored and indentation groups match parenthesized groups.
+
+use crate::lexer::{tokenise, Token};
+use crate::printer::{Printer, ShortForms};
+use crate::value::*;
+
+/// Asserts that `source` parses to the expected top-level forms, which are
+/// either given as source text or built with the constructors in
+/// [`test_support`](crate::test_support).
+///
+/// ```
+/// use sweet_expr::assert_parses_to;
+/// use sweet_expr::test_support::*;
+///
+/// assert_parses_to!("define (f x)\n    g(x)\n", "(define (f x) g(x))");
+/// assert_parses_to!("f[1]", neoteric(ident("f"), bracket([ident("1")])));
+/// ```
+#[macro_export]
+macro_rules! assert_parses_to {
+    ($source:expr, $expected:expr $(,)?) => {{
+        let source: &str = $source;
+        let actual =
+            $crate::parse(source).unwrap_or_else(|err| panic!("failed to parse {source:?}: {err}"));
+        let expected = $crate::test_support::ExpectedTree::expected_tree($expected);
+        $crate::test_support::assert_same_shape(source, &actual, &expected);
+    }};
+}
+
+/// Something that describes the expected top-level forms of a document
+pub trait ExpectedTree<'a> {
+    fn expected_tree(self) -> Vec<Atom<'a>>;
+}
+
+impl<'a> ExpectedTree<'a> for &'a str {
+    fn expected_tree(self) -> Vec<Atom<'a>> {
+        crate::parse(self)
+            .unwrap_or_else(|err| panic!("failed to parse expected tree {self:?}: {err}"))
+    }
+}
+
+impl<'a> ExpectedTree<'a> for Atom<'a> {
+    fn expected_tree(self) -> Vec<Atom<'a>> {
+        vec![self]
+    }
+}
+
+impl<'a> ExpectedTree<'a> for Vec<Atom<'a>> {
+    fn expected_tree(self) -> Vec<Atom<'a>> {
+        self
+    }
+}
+
+impl<'a, const N: usize> ExpectedTree<'a> for [Atom<'a>; N] {
+    fn expected_tree(self) -> Vec<Atom<'a>> {
+        self.into()
+    }
+}
+
+#[track_caller]
+pub fn assert_same_shape(source: &str, actual: &[Atom], expected: &[Atom]) {
+    let same = actual.len() == expected.len()
+        && actual.iter().zip(expected).all(|(a, b)| same_shape(a, b));
+    if !same {
+        panic!(
+            "{source:?} did not parse to the expected tree\n  actual: {}\nexpected: {}",
+            render_all(actual),
+            render_all(expected)
+        );
+    }
+}
+
+/// Asserts that `atom` prints to text that parses back to the same tree,
+/// both on one line and broken up over as many lines as possible. That holds
+/// for every tree the parser produces, built trees can break it with
+/// identifiers and strings that can't be written, like an identifier with a
+/// space in it, which this reports as such.
+///
+/// ```
+/// use sweet_expr::test_support::*;
+///
+/// assert_roundtrip(&paren([ident("f"), paren([ident("x")])]));
+/// assert_roundtrip(&neoteric(ident("f"), paren([ident("x")])));
+/// ```
+#[track_caller]
+pub fn assert_roundtrip(atom: &Atom) {
+    check_writable(atom);
+    let printers = [
+        Printer::minified(),
+        Printer::new().width(0),
+        Printer::new().width(0).align_arguments(true),
+        Printer::new().short_forms(ShortForms::Indentation),
+    ];
+    for printer in printers {
+        let printed = printer.print(atom);
+        let reparsed = crate::parse(&printed).unwrap_or_else(|err| {
+            panic!(
+                "{} was printed as {printed:?}, which doesn't parse: {err}",
+                render_all(std::slice::from_ref(atom))
+            )
+        });
+        assert_same_shape(&printed, &reparsed, std::slice::from_ref(atom));
+    }
+}
+
+#[track_caller]
+fn check_writable(atom: &Atom) {
+    let single_token = |text: &str, string: bool| {
+        let mut tokens = tokenise(text);
+        match (tokens.next(), tokens.next()) {
+            (Some((Token::Identifier(_), span)), None) if !string => span.len() == text.len(),
+            (Some((Token::String(_), span)), None) if string => span.len() == text.len(),
+            _ => false,
+        }
+    };
+    match atom {
+        Atom::Identifier(Spanned(name, ..)) if !single_token(name, false) => {
+            panic!("the identifier {name:?} can't be written as one")
+        }
+        Atom::String(Spanned(literal, ..)) if !single_token(literal, true) => {
+            panic!("{literal:?} isn't a valid string literal")
+        }
+        Atom::Group(group) | Atom::Raw { body: group, .. } => {
+            group.children.iter().for_each(check_writable)
+        }
+        Atom::Neoteric { lhs, rhs } => {
+            check_writable(lhs);
+            rhs.children.iter().for_each(check_writable);
+        }
+        _ => {}
+    }
+}
+
+/// Structural equality that ignores spans and doesn't distinguish
+/// indentation groups from parenthesized ones
+pub fn same_shape(a: &Atom, b: &Atom) -> bool {
+    match (a, b) {
+        (Atom::Identifier(a), Atom::Identifier(b)) | (Atom::String(a), Atom::String(b)) => {
+            a.0 == b.0
+        }
+        (Atom::Group(a), Atom::Group(b)) => same_group_shape(a, b),
+        (Atom::Neoteric { lhs: la, rhs: ra }, Atom::Neoteric { lhs: lb, rhs: rb }) => {
+            same_shape(la, lb) && same_group_shape(ra, rb)
+        }
+        (
+            Atom::Raw {
+                marker: ma,
+                body: ba,
+            },
+            Atom::Raw {
+                marker: mb,
+                body: bb,
+            },
+        ) => ma.0 == mb.0 && same_group_shape(ba, bb),
+        (Atom::Error(_), Atom::Error(_)) => true,
+        _ => false,
+    }
+}
+
+fn same_group_shape(a: &Group, b: &Group) -> bool {
+    fn normalized(ty: GroupType) -> GroupType {
+        match ty {
+            GroupType::Indentation => GroupType::Parenthesis,
+            ty => ty,
+        }
+    }
+    normalized(a.group_type) == normalized(b.group_type)
+        && a.children.len() == b.children.len()
+        && a.children
+            .iter()
+            .zip(&b.children)
+            .all(|(a, b)| same_shape(a, b))
+}
+
+/// Renders atoms as s-expressions for assertion messages, keeping the
+/// delimiters of explicit groups
+pub fn render_all(atoms: &[Atom]) -> String {
+    let mut out = String::new();
+    for (i, atom) in atoms.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        render(&mut out, atom);
+    }
+    out
+}
+
+fn render(out: &mut String, atom: &Atom) {
+    match atom {
+        Atom::Identifier(s) | Atom::String(s) => out.push_str(&s.0),
+        Atom::Group(group) => render_group(out, group),
+        Atom::Neoteric { lhs, rhs } => {
+            render(out, lhs);
+            render_group(out, rhs);
+        }
+        Atom::Raw { marker, body } => {
+            out.push_str(&marker.0);
+            render_group(out, body);
+        }
+        Atom::Error(_) => out.push_str("<error>"),
+    }
+}
+
+fn render_group(out: &mut String, group: &Group) {
+    out.push(group.group_type.open_char());
+    out.push_str(&render_all(&group.children));
+    out.push(group.group_type.close_char());
+}
+
+pub fn ident(name: &str) -> Atom<'_> {
+    Atom::ident(name)
+}
+
+/// A string atom, `literal` includes the quotes just like in the source
+pub fn string(literal: &str) -> Atom<'_> {
+    Atom::String(Spanned::synthetic(literal.into()))
+}
+
+pub fn paren<'a>(children: impl IntoIterator<Item = Atom<'a>>) -> Atom<'a> {
+    Group::paren(children).into()
+}
+
+pub fn bracket<'a>(children: impl IntoIterator<Item = Atom<'a>>) -> Atom<'a> {
+    Group::bracket(children).into()
+}
+
+pub fn curly<'a>(children: impl IntoIterator<Item = Atom<'a>>) -> Atom<'a> {
+    Group::curly(children).into()
+}
+
+/// `lhs` directly followed by `rhs`, which has to be a group
+pub fn neoteric<'a>(lhs: Atom<'a>, rhs: Atom<'a>) -> Atom<'a> {
+    let Atom::Group(rhs) = rhs else {
+        panic!("the right-hand side of a neoteric expression has to be a group");
+    };
+    Atom::neoteric(lhs, rhs)
+}
@@ -0,0 +1,345 @@
+//! Layout information that isn't part of the tree but that formatters need to
+//! keep, like the blank lines that separate groups of related top-level forms
+//! and comments.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::lexer::{tokenise, Token};
+use crate::source_map::LineIndex;
+use crate::value::Atom;
+
+/// A run of consecutive blank lines between top-level forms
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlankLines {
+    /// index of the top-level form that follows the run, equal to the number
+    /// of forms for blank lines at the end of the document
+    pub before_form: usize,
+    pub count: usize,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TopLevelTrivia {
+    /// in source order, a comment line between two runs separates them
+    pub blank_lines: Vec<BlankLines>,
+}
+
+impl TopLevelTrivia {
+    pub fn new(source: &str, document: &[Atom]) -> Self {
+        let index = LineIndex::new(source);
+        // a trailing newline doesn't start another line
+        let line_count = if source.ends_with('\n') {
+            index.line_count() - 1
+        } else {
+            index.line_count()
+        };
+
+        let mut trivia = Self::default();
+        let mut next_line = 0;
+        for (i, form) in document.iter().enumerate() {
+            let lines = index.lines(&form.span().range());
+            trivia.collect_runs(source, &index, next_line..lines.start, i);
+            next_line = lines.end;
+        }
+        trivia.collect_runs(source, &index, next_line..line_count, document.len());
+        trivia
+    }
+
+    fn collect_runs(&mut self, source: &str, index: &LineIndex, lines: Range<usize>, form: usize) {
+        let mut run: Option<BlankLines> = None;
+        for line in lines {
+            let span = index.line_span(line);
+            if source[span.clone()].trim().is_empty() {
+                match &mut run {
+                    Some(run) => {
+                        run.count += 1;
+                        run.span.end = span.end;
+                    }
+                    None => {
+                        run = Some(BlankLines {
+                            before_form: form,
+                            count: 1,
+                            span,
+                        })
+                    }
+                }
+            } else {
+                self.blank_lines.extend(run.take());
+            }
+        }
+        self.blank_lines.extend(run);
+    }
+
+    /// Number of blank lines directly in front of the form, without a
+    /// comment in between
+    pub fn blank_lines_before(&self, form: usize, source: &str, document: &[Atom]) -> usize {
+        let Some(start) = document.get(form).map(|atom| atom.span().start as usize) else {
+            return 0;
+        };
+        self.blank_lines
+            .iter()
+            .find(|run| run.before_form == form && source[run.span.end..start].trim().is_empty())
+            .map_or(0, |run| run.count)
+    }
+
+    /// Like [`TopLevelTrivia::blank_lines_before`], but capped at `max`, which
+    /// is what a formatter normalizing blank lines would emit
+    pub fn normalized_blank_lines_before(
+        &self,
+        form: usize,
+        source: &str,
+        document: &[Atom],
+        max: usize,
+    ) -> usize {
+        self.blank_lines_before(form, source, document).min(max)
+    }
+}
+
+/// A `;` comment, `text` includes the semicolons
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment<'src> {
+    pub text: &'src str,
+    pub span: Range<usize>,
+}
+
+impl<'src> Comment<'src> {
+    /// The text without the leading semicolons and the space after them
+    pub fn content(&self) -> &'src str {
+        let text = self.text.trim_start_matches(';');
+        text.strip_prefix(' ').unwrap_or(text).trim_end()
+    }
+}
+
+/// Which atom a comment belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommentRules {
+    /// comment lines directly above an atom lead it. With this set they
+    /// also do when there are blank lines in between.
+    pub across_blank_lines: bool,
+    /// a comment after code belongs to the atom on the same line that ends
+    /// right before it
+    pub trailing: bool,
+}
+
+impl Default for CommentRules {
+    fn default() -> Self {
+        Self {
+            across_blank_lines: false,
+            trailing: true,
+        }
+    }
+}
+
+/// Comments attached to the atoms of a document. Of several atoms starting
+/// (or ending) at the same place, the outermost one gets the comment, so the
+/// comment above `define f(x)` belongs to the whole `define` form. Only atoms
+/// that fit on the line can have trailing comments.
+#[derive(Debug, Clone, Default)]
+pub struct Comments<'src> {
+    leading: HashMap<Range<usize>, Vec<Comment<'src>>>,
+    trailing: HashMap<Range<usize>, Comment<'src>>,
+    /// comments that aren't attached to any atom, e.g. at the end of a
+    /// block or separated by a blank line
+    pub detached: Vec<Comment<'src>>,
+}
+
+impl<'src> Comments<'src> {
+    pub fn new(source: &'src str, document: &[Atom], rules: CommentRules) -> Self {
+        let index = LineIndex::new(source);
+        let mut extents = vec![];
+        for atom in document {
+            collect_extents(atom, 0, &mut extents);
+        }
+        // outermost first among atoms with the same start
+        extents.sort_by_key(|(extent, depth)| (extent.start, *depth));
+
+        let mut comments = Self::default();
+        let mut block: Vec<Comment<'src>> = vec![];
+        for (tok, span) in tokenise(source) {
+            if tok != Token::Comment {
+                continue;
+            }
+            let span = span.range();
+            let comment = Comment {
+                text: &source[span.clone()],
+                span: span.clone(),
+            };
+            let line_start = index.line_span(index.line(span.start)).start;
+            if !source[line_start..span.start].trim().is_empty() {
+                comments.attach_trailing(comment, &index, &extents, rules);
+                continue;
+            }
+
+            // a comment line continues the block if no atom starts in between
+            if let Some(last) = block.last() {
+                let apart = index.line(span.start) > index.line(last.span.start) + 1;
+                let atom_between = extents
+                    .iter()
+                    .any(|(extent, _)| (last.span.end..span.start).contains(&extent.start));
+                if atom_between || (apart && !rules.across_blank_lines) {
+                    comments.attach_leading(std::mem::take(&mut block), &index, &extents, rules);
+                }
+            }
+            block.push(comment);
+        }
+        if !block.is_empty() {
+            comments.attach_leading(block, &index, &extents, rules);
+        }
+        comments
+    }
+
+    pub fn leading(&self, atom: &Atom) -> &[Comment<'src>] {
+        self.leading
+            .get(&atom.span().range())
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn trailing(&self, atom: &Atom) -> Option<&Comment<'src>> {
+        self.trailing.get(&atom.span().range())
+    }
+
+    fn attach_leading(
+        &mut self,
+        block: Vec<Comment<'src>>,
+        index: &LineIndex,
+        extents: &[(Range<usize>, usize)],
+        rules: CommentRules,
+    ) {
+        let end = block.last().expect("blocks aren't empty").span.end;
+        let next = extents.iter().find(|(extent, _)| extent.start >= end);
+        match next {
+            Some((extent, _))
+                if rules.across_blank_lines || index.line(extent.start) == index.line(end) + 1 =>
+            {
+                self.leading
+                    .entry(extent.clone())
+                    .or_default()
+                    .extend(block);
+            }
+            _ => self.detached.extend(block),
+        }
+    }
+
+    fn attach_trailing(
+        &mut self,
+        comment: Comment<'src>,
+        index: &LineIndex,
+        extents: &[(Range<usize>, usize)],
+        rules: CommentRules,
+    ) {
+        let line = index.line(comment.span.start);
+        let owner = extents
+            .iter()
+            .filter(|(extent, _)| {
+                extent.end <= comment.span.start && index.line(extent.start) == line
+            })
+            .min_by_key(|(extent, depth)| (std::cmp::Reverse(extent.end), *depth));
+        match owner {
+            Some((extent, _)) if rules.trailing => {
+                self.trailing.insert(extent.clone(), comment);
+            }
+            _ => self.detached.push(comment),
+        }
+    }
+}
+
+fn collect_extents(atom: &Atom, depth: usize, out: &mut Vec<(Range<usize>, usize)>) {
+    out.push((atom.span().range(), depth));
+    match atom {
+        Atom::Identifier(_) | Atom::String(_) | Atom::Error(_) => {}
+        Atom::Group(group) => {
+            for child in &group.children {
+                collect_extents(child, depth + 1, out);
+            }
+        }
+        Atom::Neoteric { lhs, rhs } => {
+            collect_extents(lhs, depth + 1, out);
+            for child in &rhs.children {
+                collect_extents(child, depth + 1, out);
+            }
+        }
+        Atom::Raw { body, .. } => {
+            for child in &body.children {
+                collect_extents(child, depth + 1, out);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blank_line_runs() {
+        let source = "a\n\n\nb\n  c\n\n; comment\n\nd\n\n";
+        let document = crate::parse(source).unwrap();
+        let trivia = TopLevelTrivia::new(source, &document);
+
+        let runs: Vec<_> = trivia
+            .blank_lines
+            .iter()
+            .map(|run| (run.before_form, run.count))
+            .collect();
+        assert_eq!(runs, [(1, 2), (2, 1), (2, 1), (3, 1)]);
+        assert_eq!(&source[trivia.blank_lines[0].span.clone()], "\n\n");
+
+        assert_eq!(trivia.blank_lines_before(1, source, &document), 2);
+        assert_eq!(trivia.blank_lines_before(2, source, &document), 1);
+        assert_eq!(
+            trivia.normalized_blank_lines_before(1, source, &document, 1),
+            1
+        );
+    }
+
+    #[test]
+    fn attached_comments() {
+        let source = "\
+;; Doubles x.
+;; Pure.
+define double(x)
+    {x * 2} ; the result
+    ; dangling
+
+; separate
+
+f 1 ; call
+";
+        let document = crate::parse(source).unwrap();
+        let comments = Comments::new(source, &document, CommentRules::default());
+
+        let docs: Vec<_> = comments
+            .leading(&document[0])
+            .iter()
+            .map(Comment::content)
+            .collect();
+        assert_eq!(docs, ["Doubles x.", "Pure."]);
+        let Atom::Group(define) = &document[0] else {
+            panic!("expected a group, got {:?}", document[0]);
+        };
+        assert_eq!(
+            comments.trailing(&define.children[2]).map(|c| c.text),
+            Some("; the result")
+        );
+        assert_eq!(
+            comments.trailing(&document[1]).map(|c| c.text),
+            Some("; call")
+        );
+        let detached: Vec<_> = comments.detached.iter().map(|c| c.text).collect();
+        assert_eq!(detached, ["; dangling", "; separate"]);
+
+        let rules = CommentRules {
+            across_blank_lines: true,
+            trailing: false,
+        };
+        let comments = Comments::new(source, &document, rules);
+        let docs: Vec<_> = comments
+            .leading(&document[1])
+            .iter()
+            .map(|c| c.text)
+            .collect();
+        assert_eq!(docs, ["; dangling", "; separate"]);
+        assert_eq!(comments.detached.len(), 2);
+    }
+}
@@ -0,0 +1,128 @@
+//! Checks for documents from untrusted sources, e.g. configuration files
+//! uploaded by users.
+//!
+//! [`validate_data`] only accepts plain data: identifiers (which includes
+//! numbers), strings and groups. Anything that would make an interpreter do
+//! more than build lists is reported, so the input can be rejected before it
+//! reaches one.
+
+use std::{fmt, ops::Range};
+
+use crate::diagnostic::{Diagnostic, Label, Severity};
+use crate::value::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// `f(x)`, which is a call
+    Neoteric,
+    /// `{a + b}`, which is a call of the operator
+    CurlyInfix,
+    /// a `#raw(...)` group
+    Raw,
+    /// an identifier starting with `#`, like `#include` or `#!fold-case`
+    Directive,
+    /// `'x`, `` `x ``, `,x` and `,@x`
+    Quote,
+    /// something that didn't parse
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    pub span: Range<usize>,
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ViolationKind::Neoteric => "function calls are not allowed in data",
+            ViolationKind::CurlyInfix => "infix expressions are not allowed in data",
+            ViolationKind::Raw => "`#raw` groups are not allowed in data",
+            ViolationKind::Directive => "directives are not allowed in data",
+            ViolationKind::Quote => "quotation is not allowed in data",
+            ViolationKind::Error => "invalid syntax",
+        })
+    }
+}
+
+impl From<&Violation> for Diagnostic {
+    fn from(violation: &Violation) -> Self {
+        Diagnostic::new(
+            Severity::Error,
+            "sweet_expr::data_only",
+            violation.kind.to_string(),
+        )
+        .with_label(Label::primary(violation.span.clone(), "not plain data"))
+    }
+}
+
+/// Every construct in `document` that isn't plain data, in source order
+pub fn validate_data(document: &[Atom]) -> Vec<Violation> {
+    let mut violations = vec![];
+    for atom in document {
+        visit(atom, &mut violations);
+    }
+    violations
+}
+
+fn visit(atom: &Atom, violations: &mut Vec<Violation>) {
+    let mut report = |kind| {
+        violations.push(Violation {
+            kind,
+            span: atom.span().range(),
+        })
+    };
+    match atom {
+        Atom::Identifier(Spanned(ident, ..)) => {
+            if ident.starts_with('#') {
+                report(ViolationKind::Directive);
+            } else if ident.starts_with(['\'', '`', ',']) {
+                report(ViolationKind::Quote);
+            }
+        }
+        Atom::String(_) => {}
+        Atom::Group(group) => {
+            if matches!(group.group_type, GroupType::Curly) {
+                report(ViolationKind::CurlyInfix);
+            }
+            for child in &group.children {
+                visit(child, violations);
+            }
+        }
+        // nothing inside those is looked at any further, one violation is
+        // enough to reject the input
+        Atom::Neoteric { .. } => report(ViolationKind::Neoteric),
+        Atom::Raw { .. } => report(ViolationKind::Raw),
+        Atom::Error(_) => report(ViolationKind::Error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_plain_data_passes() {
+        let source = "server\n    host \"example.com\"\n    ports [80 443]\n";
+        assert!(validate_data(&crate::parse(source).unwrap()).is_empty());
+
+        let source = "a f(x) {1 + 2} #raw(b) #include 'q (c ,d)\n";
+        let document = crate::parse(source).unwrap();
+        let found: Vec<_> = validate_data(&document)
+            .iter()
+            .map(|v| (v.kind, &source[v.span.clone()]))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (ViolationKind::Neoteric, "f(x)"),
+                (ViolationKind::CurlyInfix, "{1 + 2}"),
+                (ViolationKind::Raw, "#raw(b)"),
+                (ViolationKind::Directive, "#include"),
+                (ViolationKind::Quote, "'q"),
+                (ViolationKind::Quote, ",d"),
+            ]
+        );
+    }
+}
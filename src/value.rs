@@ -1,7 +1,40 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
 
-#[derive(Debug)]
-pub struct Spanned<T>(pub T, pub std::ops::Range<usize>);
+use crate::diagnostic::Diagnostic;
+use crate::number::Number;
+use crate::source_map::{FileId, Span};
+
+/// A value with its byte range in the source and, when the parser was given
+/// one with [`ParserOptions::file`](crate::parser::ParserOptions::file), the
+/// file the source came from
+///
+/// Equality and hashing include span and file, see [`IgnoreSpans`] for
+/// comparing only the values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Spanned<T>(pub T, pub Span, pub Option<FileId>);
+
+impl<T> Spanned<T> {
+    pub fn file(&self) -> Option<FileId> {
+        self.2
+    }
+
+    /// Replaces the value, keeping span and file
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Spanned<U> {
+        Spanned(f(self.0), self.1, self.2)
+    }
+}
+
+impl<T> Spanned<T> {
+    /// A value that doesn't come from any source, with an empty span at 0
+    pub fn synthetic(value: T) -> Self {
+        Spanned(value, Span::default(), None)
+    }
+}
 
 impl<T> Deref for Spanned<T> {
     type Target = T;
@@ -17,20 +50,33 @@ impl<T> DerefMut for Spanned<T> {
     }
 }
 
-#[derive(Debug)]
+/// Text is borrowed from the source where possible, atoms that were created
+/// or rewritten after parsing can own theirs. With the `serde` feature,
+/// deserialized text is always owned.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Atom<'src> {
     /// Any non-string,-bracket or -whitespace sequence of characters
-    Identifier(Spanned<&'src str>),
+    Identifier(Spanned<Cow<'src, str>>),
     /// A sequence of characters between two " chars, only checks for \" escapes
-    String(Spanned<&'src str>),
+    String(Spanned<Cow<'src, str>>),
     Group(Group<'src>),
     Neoteric {
         lhs: Box<Atom<'src>>,
         rhs: Group<'src>,
     },
+    /// A group prefixed with `#raw`, kept exactly as written: nothing inside
+    /// it is desugared, so `f(x)` stays two separate atoms
+    Raw {
+        marker: Spanned<Cow<'src, str>>,
+        body: Group<'src>,
+    },
+    /// Inserted by the recovering parser in place of tokens it couldn't parse
+    Error(Spanned<()>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GroupType {
     Indentation, // practically the same as Parenthesis
     Parenthesis,
@@ -38,10 +84,675 @@ pub enum GroupType {
     Bracket,
 }
 
-#[derive(Debug)]
+impl GroupType {
+    /// Opening and closing delimiter, indentation groups are written as
+    /// parentheses
+    pub fn delimiters(self) -> (&'static str, &'static str) {
+        match self {
+            GroupType::Indentation | GroupType::Parenthesis => ("(", ")"),
+            GroupType::Curly => ("{", "}"),
+            GroupType::Bracket => ("[", "]"),
+        }
+    }
+
+    /// `(`, `{` or `[`, indentation groups count as parentheses
+    pub fn open_char(self) -> char {
+        match self {
+            GroupType::Indentation | GroupType::Parenthesis => '(',
+            GroupType::Curly => '{',
+            GroupType::Bracket => '[',
+        }
+    }
+
+    /// `)`, `}` or `]`, indentation groups count as parentheses
+    pub fn close_char(self) -> char {
+        match self {
+            GroupType::Indentation | GroupType::Parenthesis => ')',
+            GroupType::Curly => '}',
+            GroupType::Bracket => ']',
+        }
+    }
+
+    /// The group type a delimiter opens, or `None` for anything else
+    pub fn from_open_char(c: char) -> Option<Self> {
+        match c {
+            '(' => Some(GroupType::Parenthesis),
+            '{' => Some(GroupType::Curly),
+            '[' => Some(GroupType::Bracket),
+            _ => None,
+        }
+    }
+
+    /// The group type a delimiter closes, or `None` for anything else
+    pub fn from_close_char(c: char) -> Option<Self> {
+        match c {
+            ')' => Some(GroupType::Parenthesis),
+            '}' => Some(GroupType::Curly),
+            ']' => Some(GroupType::Bracket),
+            _ => None,
+        }
+    }
+
+    /// Written with delimiters, i.e. anything but an indentation group
+    pub fn is_explicit(self) -> bool {
+        self != GroupType::Indentation
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Group<'src> {
     pub group_type: GroupType,
     pub start_delim: Spanned<()>,
+    /// A plain `Vec`, on purpose. Storing the first few children inline in a
+    /// `SmallVec<[Atom; N]>` puts atoms inside of atoms, which has no finite
+    /// size. `SmallVec<[Box<Atom>; N]>` compiles, but allocates every child
+    /// on its own where the `Vec` allocates once per group, and makes every
+    /// atom bigger.
     pub children: Vec<Atom<'src>>,
     pub end_delim: Spanned<()>,
 }
+
+impl Atom<'_> {
+    /// Byte range of the whole atom, from the start of its first token to
+    /// the end of its last one. Indentation groups end where their last child
+    /// ends, their end delimiter might already point into the next line.
+    ///
+    /// ```
+    /// let atom = sweet_expr::parse_one("f(x y)").unwrap();
+    /// assert_eq!(atom.span().range(), 0..6);
+    /// ```
+    pub fn span(&self) -> Span {
+        match self {
+            Atom::Identifier(s) | Atom::String(s) => s.1,
+            Atom::Error(e) => e.1,
+            Atom::Group(group) => group.span(),
+            Atom::Neoteric { lhs, rhs } => lhs.span().to(rhs.span()),
+            Atom::Raw { marker, body } => marker.1.to(body.span()),
+        }
+    }
+
+    /// The file the atom was parsed from, see
+    /// [`ParserOptions::file`](crate::parser::ParserOptions::file)
+    pub fn file(&self) -> Option<FileId> {
+        match self {
+            Atom::Identifier(s) | Atom::String(s) => s.2,
+            Atom::Error(e) => e.2,
+            Atom::Group(group) => group.start_delim.2,
+            Atom::Neoteric { lhs, .. } => lhs.file(),
+            Atom::Raw { marker, .. } => marker.2,
+        }
+    }
+}
+
+impl Group<'_> {
+    /// Byte range from the opening to the closing delimiter, see
+    /// [`Atom::span`]
+    pub fn span(&self) -> Span {
+        let start = self.start_delim.1.start;
+        match self.group_type {
+            GroupType::Indentation => {
+                let end = self
+                    .children
+                    .last()
+                    .map(|child| child.span().end)
+                    .unwrap_or(self.start_delim.1.end);
+                Span { start, end }
+            }
+            _ => Span {
+                start,
+                end: self.end_delim.1.end,
+            },
+        }
+    }
+}
+
+impl<'src> Atom<'src> {
+    /// ```
+    /// let atom = sweet_expr::parse_one("print(name \"hi\")").unwrap();
+    /// let (lhs, args) = atom.as_neoteric().unwrap();
+    /// assert_eq!(lhs.as_identifier(), Some("print"));
+    /// assert_eq!(args.children[1].as_string(), Some("\"hi\""));
+    /// assert!(args.children[0].is_identifier());
+    /// ```
+    pub fn as_identifier(&self) -> Option<&str> {
+        match self {
+            Atom::Identifier(ident) => Some(&ident.0),
+            _ => None,
+        }
+    }
+
+    /// The literal as written, including the quotes, see
+    /// [`Atom::unescaped`] for its contents
+    pub fn as_string(&self) -> Option<&str> {
+        match self {
+            Atom::String(string) => Some(&string.0),
+            _ => None,
+        }
+    }
+
+    /// What a string literal stands for: the text between the quotes with
+    /// `\"` turned into `"`, the only escape there is. Only allocates if the
+    /// literal contains escapes.
+    ///
+    /// ```
+    /// let atom = sweet_expr::parse_one(r#""say \"hi\"""#).unwrap();
+    /// assert_eq!(atom.as_string(), Some(r#""say \"hi\"""#));
+    /// assert_eq!(atom.unescaped().unwrap(), r#"say "hi""#);
+    /// ```
+    pub fn unescaped(&self) -> Option<Cow<'_, str>> {
+        let literal = self.as_string()?;
+        let inner = literal
+            .strip_prefix('"')
+            .and_then(|inner| inner.strip_suffix('"'))
+            .unwrap_or(literal);
+        Some(match inner.contains("\\\"") {
+            true => Cow::Owned(inner.replace("\\\"", "\"")),
+            false => Cow::Borrowed(inner),
+        })
+    }
+
+    pub fn as_group(&self) -> Option<&Group<'src>> {
+        match self {
+            Atom::Group(group) => Some(group),
+            _ => None,
+        }
+    }
+
+    pub fn as_neoteric(&self) -> Option<(&Atom<'src>, &Group<'src>)> {
+        match self {
+            Atom::Neoteric { lhs, rhs } => Some((lhs, rhs)),
+            _ => None,
+        }
+    }
+
+    /// The marker, e.g. `#raw`, and the body
+    pub fn as_raw(&self) -> Option<(&str, &Group<'src>)> {
+        match self {
+            Atom::Raw { marker, body } => Some((&marker.0, body)),
+            _ => None,
+        }
+    }
+
+    pub fn is_identifier(&self) -> bool {
+        matches!(self, Atom::Identifier(_))
+    }
+
+    pub fn is_string(&self) -> bool {
+        matches!(self, Atom::String(_))
+    }
+
+    pub fn is_group(&self) -> bool {
+        matches!(self, Atom::Group(_))
+    }
+
+    pub fn is_neoteric(&self) -> bool {
+        matches!(self, Atom::Neoteric { .. })
+    }
+
+    pub fn is_raw(&self) -> bool {
+        matches!(self, Atom::Raw { .. })
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, Atom::Error(_))
+    }
+
+    // for error messages, "expected a string, found {}"
+    pub(crate) fn describe(&self) -> &'static str {
+        match self {
+            Atom::Identifier(_) if self.is_number() => "a number",
+            Atom::Identifier(_) => "an identifier",
+            Atom::String(_) => "a string",
+            Atom::Group(_) => "a group",
+            Atom::Neoteric { .. } => "a neoteric expression",
+            Atom::Raw { .. } => "a `#raw` group",
+            Atom::Error(_) => "invalid syntax",
+        }
+    }
+}
+
+/// Constructors for building trees in code. Spans are empty unless set with
+/// [`Atom::with_span`].
+///
+/// ```
+/// use sweet_expr::value::{Atom, Group};
+///
+/// let atom = Atom::call("print", [Atom::string("hi"), Group::curly([
+///     Atom::ident("a"),
+///     Atom::ident("+"),
+///     Atom::ident("b"),
+/// ]).into()]);
+/// assert_eq!(atom.to_string(), "(print \"hi\" {a + b})");
+/// ```
+impl<'src> Atom<'src> {
+    pub fn ident(name: impl Into<Cow<'src, str>>) -> Self {
+        Atom::Identifier(Spanned::synthetic(name.into()))
+    }
+
+    /// A string literal containing `text`. Quotes in it are escaped, there
+    /// are no other escapes, so `text` can't contain backslashes: the
+    /// literal wouldn't parse. See [`Atom::try_string`] for text that isn't
+    /// known to be free of them.
+    pub fn string(text: &str) -> Self {
+        debug_assert!(
+            !text.contains('\\'),
+            "string literals can't contain backslashes: {text:?}"
+        );
+        let literal = format!("\"{}\"", text.replace('"', "\\\""));
+        Atom::String(Spanned::synthetic(literal.into()))
+    }
+
+    /// [`Atom::string`], or `None` if `text` contains a backslash
+    ///
+    /// ```
+    /// use sweet_expr::value::Atom;
+    ///
+    /// let atom = Atom::try_string("a\"b").unwrap();
+    /// assert_eq!(atom.to_string(), "\"a\\\"b\"");
+    /// assert_eq!(Atom::try_string("C:\\dir"), None);
+    /// ```
+    pub fn try_string(text: &str) -> Option<Self> {
+        (!text.contains('\\')).then(|| Self::string(text))
+    }
+
+    /// `lhs(args...)`
+    pub fn neoteric(lhs: Atom<'src>, rhs: Group<'src>) -> Self {
+        Atom::Neoteric {
+            lhs: Box::new(lhs),
+            rhs,
+        }
+    }
+
+    /// `name(args...)`
+    pub fn call(
+        name: impl Into<Cow<'src, str>>,
+        args: impl IntoIterator<Item = Atom<'src>>,
+    ) -> Self {
+        Atom::neoteric(Atom::ident(name), Group::paren(args))
+    }
+
+    /// Sets the span of an identifier, string or error. Groups get their
+    /// delimiters put at the start and end of `span`, so [`Atom::span`]
+    /// returns it. Neoteric expressions and `#raw` groups have no span of
+    /// their own, set those of their parts instead.
+    pub fn with_span(mut self, span: Span) -> Self {
+        match &mut self {
+            Atom::Identifier(s) | Atom::String(s) => s.1 = span,
+            Atom::Error(e) => e.1 = span,
+            Atom::Group(group) => {
+                group.start_delim.1 = Span::at(span.start as usize);
+                group.end_delim.1 = Span::at(span.end as usize);
+            }
+            Atom::Neoteric { .. } | Atom::Raw { .. } => {}
+        }
+        self
+    }
+}
+
+/// Builds an [`Atom`] from s-expression syntax at compile time. Several
+/// elements make a list, like a line of sweet-expressions does; `()`, `[]`
+/// and `{}` are the respective groups. Rust string literals become string
+/// atoms and every other token an identifier. `#{expr}` splices in an atom or
+/// group computed at runtime.
+///
+/// Everything has to be made of Rust tokens: there are no neoteric
+/// expressions, `f(x)` is the same as `f (x)`, and `a-b` is three atoms. Splice
+/// in [`Atom::ident`] for names like that.
+///
+/// ```
+/// use sweet_expr::sweet;
+/// use sweet_expr::value::Atom;
+///
+/// let name = Atom::ident("x");
+/// let atom = sweet!(define #{name} {1 + 2} "doc" [a]);
+/// assert_eq!(atom.to_string(), "(define x {1 + 2} \"doc\" [a])");
+/// ```
+#[macro_export]
+macro_rules! sweet {
+    (@items [$($out:expr),*]) => {
+        ::std::vec![$($out),*]
+    };
+    (@items [$($out:expr),*] # { $e:expr } $($rest:tt)*) => {
+        $crate::sweet!(@items [$($out,)* $crate::value::Atom::from($e)] $($rest)*)
+    };
+    (@items [$($out:expr),*] $t:tt $($rest:tt)*) => {
+        $crate::sweet!(@items [$($out,)* $crate::sweet!($t)] $($rest)*)
+    };
+    (( $($inner:tt)* )) => {
+        $crate::value::Atom::Group($crate::value::Group::paren($crate::sweet!(@items [] $($inner)*)))
+    };
+    ([ $($inner:tt)* ]) => {
+        $crate::value::Atom::Group($crate::value::Group::bracket($crate::sweet!(@items [] $($inner)*)))
+    };
+    ({ $($inner:tt)* }) => {
+        $crate::value::Atom::Group($crate::value::Group::curly($crate::sweet!(@items [] $($inner)*)))
+    };
+    (# { $e:expr }) => {
+        $crate::value::Atom::from($e)
+    };
+    ($lit:literal) => {
+        $crate::value::Atom::from_literal_token(::std::stringify!($lit))
+    };
+    ($t:tt) => {
+        $crate::value::Atom::ident(::std::stringify!($t))
+    };
+    ($($t:tt)+) => {
+        $crate::value::Atom::Group($crate::value::Group::paren($crate::sweet!(@items [] $($t)+)))
+    };
+}
+
+impl Atom<'static> {
+    // used by `sweet!`, string literals are the only tokens that aren't
+    // identifiers
+    #[doc(hidden)]
+    pub fn from_literal_token(token: &'static str) -> Self {
+        if token.starts_with('"') {
+            Atom::String(Spanned::synthetic(token.into()))
+        } else {
+            Atom::ident(token)
+        }
+    }
+}
+
+impl<'src> Group<'src> {
+    pub fn new(group_type: GroupType, children: impl IntoIterator<Item = Atom<'src>>) -> Self {
+        Group {
+            group_type,
+            start_delim: Spanned::synthetic(()),
+            children: children.into_iter().collect(),
+            end_delim: Spanned::synthetic(()),
+        }
+    }
+
+    pub fn paren(children: impl IntoIterator<Item = Atom<'src>>) -> Self {
+        Group::new(GroupType::Parenthesis, children)
+    }
+
+    pub fn bracket(children: impl IntoIterator<Item = Atom<'src>>) -> Self {
+        Group::new(GroupType::Bracket, children)
+    }
+
+    pub fn curly(children: impl IntoIterator<Item = Atom<'src>>) -> Self {
+        Group::new(GroupType::Curly, children)
+    }
+}
+
+impl<'src> From<Group<'src>> for Atom<'src> {
+    fn from(group: Group<'src>) -> Self {
+        Atom::Group(group)
+    }
+}
+
+/// Renders a plain s-expression: neoteric expressions become lists like in
+/// SRFI 105, `f(x)` as `(f x)` and `f[x]` as `($bracket-apply$ f x)`, and
+/// indentation groups are parenthesized. Curly and bracket groups keep their
+/// delimiters.
+impl fmt::Display for Atom<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Atom::Identifier(s) | Atom::String(s) => f.write_str(&s.0),
+            Atom::Group(group) => group.fmt(f),
+            Atom::Neoteric { lhs, rhs } => {
+                match rhs.group_type {
+                    GroupType::Indentation | GroupType::Parenthesis => write!(f, "({lhs}")?,
+                    GroupType::Bracket => write!(f, "($bracket-apply$ {lhs}")?,
+                    // `f{x}` is `(f {x})`, the curly group is the only argument
+                    GroupType::Curly => return write!(f, "({lhs} {rhs})"),
+                }
+                for child in &rhs.children {
+                    write!(f, " {child}")?;
+                }
+                f.write_str(")")
+            }
+            Atom::Raw { marker, body } => write!(f, "{}{body}", marker.0),
+            Atom::Error(_) => f.write_str("<error>"),
+        }
+    }
+}
+
+impl fmt::Display for Group<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (open, close) = self.group_type.delimiters();
+        f.write_str(open)?;
+        for (i, child) in self.children.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{child}")?;
+        }
+        f.write_str(close)
+    }
+}
+
+impl<'src> Atom<'src> {
+    /// Copies all borrowed text, so the tree can be kept after the source is
+    /// gone
+    pub fn into_owned(self) -> Atom<'static> {
+        let owned = |text: Spanned<Cow<'src, str>>| text.map(|text| Cow::Owned(text.into_owned()));
+        match self {
+            Atom::Identifier(ident) => Atom::Identifier(owned(ident)),
+            Atom::String(s) => Atom::String(owned(s)),
+            Atom::Group(group) => Atom::Group(group.into_owned()),
+            Atom::Neoteric { lhs, rhs } => Atom::Neoteric {
+                lhs: Box::new(lhs.into_owned()),
+                rhs: rhs.into_owned(),
+            },
+            Atom::Raw { marker, body } => Atom::Raw {
+                marker: owned(marker),
+                body: body.into_owned(),
+            },
+            Atom::Error(e) => Atom::Error(e),
+        }
+    }
+}
+
+/// An atom that doesn't borrow from its source
+pub type OwnedAtom = Atom<'static>;
+
+/// Parses a single expression like [`parse_one`](crate::parse_one). The
+/// error is a [`Diagnostic`], since a [`ParseError`](crate::parser::ParseError)
+/// would borrow from the string.
+///
+/// ```
+/// use sweet_expr::value::OwnedAtom;
+///
+/// let atom: OwnedAtom = "foo(1 2)".parse()?;
+/// assert_eq!(atom.to_string(), "(foo 1 2)");
+/// assert!("(a".parse::<OwnedAtom>().is_err());
+/// # Ok::<(), sweet_expr::diagnostic::Diagnostic>(())
+/// ```
+impl FromStr for Atom<'static> {
+    type Err = Diagnostic;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        crate::parse_one(s)
+            .map(Atom::into_owned)
+            .map_err(|err| Diagnostic::from(&err))
+    }
+}
+
+impl Group<'_> {
+    pub fn into_owned(self) -> Group<'static> {
+        Group {
+            group_type: self.group_type,
+            start_delim: self.start_delim,
+            children: self.children.into_iter().map(Atom::into_owned).collect(),
+            end_delim: self.end_delim,
+        }
+    }
+}
+
+impl Atom<'_> {
+    /// Structural equality, spans and files aren't compared
+    pub fn eq_ignoring_spans(&self, other: &Atom) -> bool {
+        match (self, other) {
+            (Atom::Identifier(a), Atom::Identifier(b)) | (Atom::String(a), Atom::String(b)) => {
+                a.0 == b.0
+            }
+            (Atom::Group(a), Atom::Group(b)) => a.eq_ignoring_spans(b),
+            (Atom::Neoteric { lhs: la, rhs: ra }, Atom::Neoteric { lhs: lb, rhs: rb }) => {
+                la.eq_ignoring_spans(lb) && ra.eq_ignoring_spans(rb)
+            }
+            (
+                Atom::Raw {
+                    marker: ma,
+                    body: ba,
+                },
+                Atom::Raw {
+                    marker: mb,
+                    body: bb,
+                },
+            ) => ma.0 == mb.0 && ba.eq_ignoring_spans(bb),
+            (Atom::Error(_), Atom::Error(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn hash_ignoring_spans<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Atom::Identifier(s) | Atom::String(s) => s.0.hash(state),
+            Atom::Group(group) => group.hash_ignoring_spans(state),
+            Atom::Neoteric { lhs, rhs } => {
+                lhs.hash_ignoring_spans(state);
+                rhs.hash_ignoring_spans(state);
+            }
+            Atom::Raw { marker, body } => {
+                marker.0.hash(state);
+                body.hash_ignoring_spans(state);
+            }
+            Atom::Error(_) => {}
+        }
+    }
+}
+
+impl Group<'_> {
+    pub fn eq_ignoring_spans(&self, other: &Group) -> bool {
+        self.group_type == other.group_type
+            && self.children.len() == other.children.len()
+            && self
+                .children
+                .iter()
+                .zip(&other.children)
+                .all(|(a, b)| a.eq_ignoring_spans(b))
+    }
+
+    fn hash_ignoring_spans<H: Hasher>(&self, state: &mut H) {
+        self.group_type.hash(state);
+        self.children.len().hash(state);
+        for child in &self.children {
+            child.hash_ignoring_spans(state);
+        }
+    }
+}
+
+/// Compares and hashes the atom without its spans, e.g. to deduplicate forms
+/// parsed from different places in a `HashSet`
+#[derive(Debug, Clone, Copy)]
+pub struct IgnoreSpans<'a, 'src>(pub &'a Atom<'src>);
+
+impl PartialEq for IgnoreSpans<'_, '_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.eq_ignoring_spans(other.0)
+    }
+}
+
+impl Eq for IgnoreSpans<'_, '_> {}
+
+impl Hash for IgnoreSpans<'_, '_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash_ignoring_spans(state);
+    }
+}
+
+/// Plain data, for applications that want the values written in a file
+/// rather than the syntax they were written in. See [`Atom::to_value`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Value {
+    Symbol(String),
+    /// the text of a string literal, with the escapes resolved
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    List(Vec<Value>),
+}
+
+impl Atom<'_> {
+    /// The data the atom stands for, after lowering it with
+    /// [`Atom::normalize`]. Identifiers that are numbers are numbers, see
+    /// [`Number::parse`], `true`, `false`, `#t` and `#f` are booleans and
+    /// every group is a list. `#raw` groups are strings of their text, and
+    /// invalid syntax is an empty list, like the printer writes it.
+    ///
+    /// ```
+    /// use sweet_expr::value::Value;
+    ///
+    /// let atom = sweet_expr::parse_one("limits(512 1.5 #t \"a\\\"b\") {x + 1}").unwrap();
+    /// let Value::List(values) = atom.to_value() else { unreachable!() };
+    /// assert_eq!(
+    ///     values[0],
+    ///     Value::List(vec![
+    ///         Value::Symbol("limits".into()),
+    ///         Value::Int(512),
+    ///         Value::Float(1.5),
+    ///         Value::Bool(true),
+    ///         Value::String("a\"b".into()),
+    ///     ])
+    /// );
+    /// assert_eq!(values[1].to_string(), "(+ x 1)");
+    /// ```
+    pub fn to_value(&self) -> Value {
+        lowered_value(&self.clone().normalize())
+    }
+}
+
+fn lowered_value(atom: &Atom) -> Value {
+    match atom {
+        Atom::Identifier(ident) => match (&*ident.0, Number::parse(&ident.0)) {
+            (_, Some(Number::Integer(int))) => Value::Int(int),
+            (_, Some(Number::Float(float))) => Value::Float(float),
+            ("true" | "#t", _) => Value::Bool(true),
+            ("false" | "#f", _) => Value::Bool(false),
+            (symbol, _) => Value::Symbol(symbol.to_string()),
+        },
+        Atom::String(_) => Value::String(atom.unescaped().unwrap_or_default().into_owned()),
+        Atom::Group(group) => Value::List(group.children.iter().map(lowered_value).collect()),
+        // lowering turns all of those into lists, but trees built by hand
+        // can have the odd one left
+        Atom::Neoteric { lhs, rhs } => Value::List(
+            std::iter::once(lhs.as_ref())
+                .chain(&rhs.children)
+                .map(lowered_value)
+                .collect(),
+        ),
+        Atom::Raw { .. } => Value::String(atom.to_string()),
+        Atom::Error(_) => Value::List(vec![]),
+    }
+}
+
+/// Written as a plain s-expression, strings and floats so that they parse
+/// back to the same value
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Symbol(symbol) => f.write_str(symbol),
+            Value::String(string) => write!(f, "\"{}\"", string.replace('"', "\\\"")),
+            Value::Int(int) => Number::Integer(*int).fmt(f),
+            Value::Float(float) => Number::Float(*float).fmt(f),
+            Value::Bool(bool) => f.write_str(if *bool { "#t" } else { "#f" }),
+            Value::List(values) => {
+                f.write_str("(")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    value.fmt(f)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Traversals over trees of atoms. [`Visit`] and [`VisitMut`] walk the tree
+//! calling a method for every kind of node, override the ones of interest and
+//! call the matching `walk_*` function to continue below a node.
+//! [`Atom::transform`] rebuilds a tree bottom-up from a closure.
+//!
+//! `#raw` bodies are kept as written, so none of these descend into them by
+//! default. Override [`Visit::visit_raw`] to look inside anyway.
+//!
+//! ```
+//! use std::borrow::Cow;
+//! use sweet_expr::value::Spanned;
+//! use sweet_expr::visit::VisitMut;
+//!
+//! struct Rename;
+//!
+//! impl<'src> VisitMut<'src> for Rename {
+//!     fn visit_identifier_mut(&mut self, ident: &mut Spanned<Cow<'src, str>>) {
+//!         if ident.0 == "fn" {
+//!             ident.0 = "lambda".into();
+//!         }
+//!     }
+//! }
+//!
+//! let mut atom = sweet_expr::parse_one("fn(x)").unwrap();
+//! Rename.visit_atom_mut(&mut atom);
+//! assert_eq!(atom.to_string(), "(lambda x)");
+//! ```
+
+use std::borrow::Cow;
+
+use crate::value::*;
+
+pub trait Visit<'src> {
+    fn visit_atom(&mut self, atom: &Atom<'src>) {
+        walk_atom(self, atom);
+    }
+
+    fn visit_group(&mut self, group: &Group<'src>) {
+        walk_group(self, group);
+    }
+
+    fn visit_identifier(&mut self, _ident: &Spanned<Cow<'src, str>>) {}
+
+    fn visit_string(&mut self, _string: &Spanned<Cow<'src, str>>) {}
+
+    /// Doesn't visit the body by default
+    fn visit_raw(&mut self, _marker: &Spanned<Cow<'src, str>>, _body: &Group<'src>) {}
+
+    fn visit_error(&mut self, _error: &Spanned<()>) {}
+}
+
+pub fn walk_atom<'src, V: Visit<'src> + ?Sized>(visitor: &mut V, atom: &Atom<'src>) {
+    match atom {
+        Atom::Identifier(ident) => visitor.visit_identifier(ident),
+        Atom::String(string) => visitor.visit_string(string),
+        Atom::Group(group) => visitor.visit_group(group),
+        Atom::Neoteric { lhs, rhs } => {
+            visitor.visit_atom(lhs);
+            visitor.visit_group(rhs);
+        }
+        Atom::Raw { marker, body } => visitor.visit_raw(marker, body),
+        Atom::Error(error) => visitor.visit_error(error),
+    }
+}
+
+pub fn walk_group<'src, V: Visit<'src> + ?Sized>(visitor: &mut V, group: &Group<'src>) {
+    for child in &group.children {
+        visitor.visit_atom(child);
+    }
+}
+
+/// [`Visit`] with mutable access, for rewriting a tree in place
+pub trait VisitMut<'src> {
+    fn visit_atom_mut(&mut self, atom: &mut Atom<'src>) {
+        walk_atom_mut(self, atom);
+    }
+
+    fn visit_group_mut(&mut self, group: &mut Group<'src>) {
+        walk_group_mut(self, group);
+    }
+
+    fn visit_identifier_mut(&mut self, _ident: &mut Spanned<Cow<'src, str>>) {}
+
+    fn visit_string_mut(&mut self, _string: &mut Spanned<Cow<'src, str>>) {}
+
+    /// Doesn't visit the body by default
+    fn visit_raw_mut(&mut self, _marker: &mut Spanned<Cow<'src, str>>, _body: &mut Group<'src>) {}
+
+    fn visit_error_mut(&mut self, _error: &mut Spanned<()>) {}
+}
+
+pub fn walk_atom_mut<'src, V: VisitMut<'src> + ?Sized>(visitor: &mut V, atom: &mut Atom<'src>) {
+    match atom {
+        Atom::Identifier(ident) => visitor.visit_identifier_mut(ident),
+        Atom::String(string) => visitor.visit_string_mut(string),
+        Atom::Group(group) => visitor.visit_group_mut(group),
+        Atom::Neoteric { lhs, rhs } => {
+            visitor.visit_atom_mut(lhs);
+            visitor.visit_group_mut(rhs);
+        }
+        Atom::Raw { marker, body } => visitor.visit_raw_mut(marker, body),
+        Atom::Error(error) => visitor.visit_error_mut(error),
+    }
+}
+
+pub fn walk_group_mut<'src, V: VisitMut<'src> + ?Sized>(visitor: &mut V, group: &mut Group<'src>) {
+    for child in &mut group.children {
+        visitor.visit_atom_mut(child);
+    }
+}
+
+impl<'src> Atom<'src> {
+    /// Rebuilds the tree bottom-up: `f` gets every atom after its children
+    /// were transformed and returns its replacement. Spans are whatever `f`
+    /// leaves them at, atoms it returns unchanged keep theirs.
+    ///
+    /// The left-hand side of a neoteric expression is transformed, its
+    /// argument group only element-wise, since it has to stay a group.
+    pub fn transform(self, f: &mut impl FnMut(Atom<'src>) -> Atom<'src>) -> Atom<'src> {
+        let atom = match self {
+            Atom::Group(group) => Atom::Group(group.transform_children(f)),
+            Atom::Neoteric { lhs, rhs } => Atom::Neoteric {
+                lhs: Box::new(lhs.transform(f)),
+                rhs: rhs.transform_children(f),
+            },
+            atom @ (Atom::Identifier(_) | Atom::String(_) | Atom::Raw { .. } | Atom::Error(_)) => {
+                atom
+            }
+        };
+        f(atom)
+    }
+}
+
+impl<'src> Group<'src> {
+    fn transform_children(mut self, f: &mut impl FnMut(Atom<'src>) -> Atom<'src>) -> Group<'src> {
+        self.children = self
+            .children
+            .into_iter()
+            .map(|child| child.transform(f))
+            .collect();
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Identifiers(Vec<String>);
+
+    impl<'src> Visit<'src> for Identifiers {
+        fn visit_identifier(&mut self, ident: &Spanned<Cow<'src, str>>) {
+            self.0.push(ident.0.to_string());
+        }
+    }
+
+    #[test]
+    fn visits_in_source_order() {
+        let atom = crate::parse_one("f(a [b] #raw(c) {d + e})").unwrap();
+        let mut idents = Identifiers::default();
+        idents.visit_atom(&atom);
+        assert_eq!(idents.0, ["f", "a", "b", "d", "+", "e"]);
+    }
+
+    fn is_form(group: &Group, head: &str) -> bool {
+        matches!(group.children.first(), Some(Atom::Identifier(ident)) if ident.0 == head)
+    }
+
+    #[test]
+    fn transform_expands_forms() {
+        // `unless c body` → `if c () body`
+        let atom = crate::parse_one("(f (unless x (g (unless y z))))").unwrap();
+        let expanded = atom.transform(&mut |atom| match atom {
+            Atom::Group(mut group) if is_form(&group, "unless") => {
+                let delim = group.children[0].span();
+                group.children[0] = Atom::Identifier(Spanned("if".into(), delim, None));
+                let empty = Group {
+                    group_type: GroupType::Parenthesis,
+                    start_delim: Spanned((), delim, None),
+                    children: vec![],
+                    end_delim: Spanned((), delim, None),
+                };
+                group.children.insert(2, Atom::Group(empty));
+                Atom::Group(group)
+            }
+            atom => atom,
+        });
+        assert_eq!(expanded.to_string(), "(f (if x () (g (if y () z))))");
+    }
+}
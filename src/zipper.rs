@@ -0,0 +1,223 @@
+//! A cursor for moving around a tree and editing it in place, for structured
+//! editors and other interactive tools.
+//!
+//! The [`Zipper`] owns the tree while it's being edited. The focused atom is
+//! held on its own and everything around it is kept as a path of frames, so
+//! moving and replacing don't clone anything and no parent pointers are
+//! needed. [`Zipper::into_atom`] puts the tree back together.
+//!
+//! The elements of a neoteric expression are its left-hand side followed by
+//! its arguments, like for `(f x)`. `#raw` bodies can't be entered.
+//!
+//! ```
+//! use sweet_expr::zipper::Zipper;
+//!
+//! let atom = sweet_expr::parse_one("define f(x)\n  g x\n").unwrap();
+//! let mut zipper = Zipper::new(atom);
+//! assert!(zipper.down() && zipper.right() && zipper.down());
+//! assert_eq!(zipper.focus().to_string(), "f");
+//! zipper.replace(sweet_expr::value::Atom::ident("h"));
+//! assert_eq!(zipper.into_atom().to_string(), "(define (h x) (g x))");
+//! ```
+
+use crate::value::*;
+
+pub struct Zipper<'src> {
+    focus: Atom<'src>,
+    path: Vec<Frame<'src>>,
+}
+
+struct Frame<'src> {
+    parent: Parent<'src>,
+    left: Vec<Atom<'src>>,
+    // in reverse, the next sibling is last
+    right: Vec<Atom<'src>>,
+}
+
+// the parent of the focus with its children taken out
+enum Parent<'src> {
+    Group(Group<'src>),
+    // the argument group, the left-hand side is the first element
+    Neoteric(Group<'src>),
+}
+
+impl<'src> Zipper<'src> {
+    /// A zipper focused on `root`
+    pub fn new(root: Atom<'src>) -> Self {
+        Self {
+            focus: root,
+            path: vec![],
+        }
+    }
+
+    pub fn focus(&self) -> &Atom<'src> {
+        &self.focus
+    }
+
+    pub fn focus_mut(&mut self) -> &mut Atom<'src> {
+        &mut self.focus
+    }
+
+    /// Replaces the focused atom, returning the old one
+    pub fn replace(&mut self, atom: Atom<'src>) -> Atom<'src> {
+        std::mem::replace(&mut self.focus, atom)
+    }
+
+    /// How many levels below the root the focus is
+    pub fn depth(&self) -> usize {
+        self.path.len()
+    }
+
+    /// Index of the focus among its siblings, 0 at the root
+    pub fn index(&self) -> usize {
+        self.path.last().map_or(0, |frame| frame.left.len())
+    }
+
+    /// Moves to the first element of the focused atom. Returns `false` and
+    /// stays where it is for atoms without elements.
+    pub fn down(&mut self) -> bool {
+        let (parent, mut elements) = match &mut self.focus {
+            Atom::Group(group) if !group.children.is_empty() => {
+                let children = std::mem::take(&mut group.children);
+                (Parent::Group(group.clone()), children)
+            }
+            Atom::Neoteric { lhs, rhs } => {
+                let lhs = std::mem::replace(&mut **lhs, placeholder());
+                let mut elements = vec![lhs];
+                elements.append(&mut rhs.children);
+                (Parent::Neoteric(rhs.clone()), elements)
+            }
+            _ => return false,
+        };
+        elements.reverse();
+        self.focus = elements.pop().unwrap();
+        self.path.push(Frame {
+            parent,
+            left: vec![],
+            right: elements,
+        });
+        true
+    }
+
+    /// Moves to the atom containing the focus, `false` at the root
+    pub fn up(&mut self) -> bool {
+        let Some(frame) = self.path.pop() else {
+            return false;
+        };
+        let focus = std::mem::replace(&mut self.focus, placeholder());
+        let mut elements = frame.left;
+        elements.push(focus);
+        elements.extend(frame.right.into_iter().rev());
+        self.focus = match frame.parent {
+            Parent::Group(mut group) => {
+                group.children = elements;
+                Atom::Group(group)
+            }
+            Parent::Neoteric(mut rhs) => {
+                let mut elements = elements.into_iter();
+                let lhs = elements.next().unwrap();
+                rhs.children = elements.collect();
+                Atom::neoteric(lhs, rhs)
+            }
+        };
+        true
+    }
+
+    /// Moves to the previous sibling, `false` if there is none
+    pub fn left(&mut self) -> bool {
+        let Some(frame) = self.path.last_mut() else {
+            return false;
+        };
+        let Some(prev) = frame.left.pop() else {
+            return false;
+        };
+        frame.right.push(std::mem::replace(&mut self.focus, prev));
+        true
+    }
+
+    /// Moves to the next sibling, `false` if there is none
+    pub fn right(&mut self) -> bool {
+        let Some(frame) = self.path.last_mut() else {
+            return false;
+        };
+        let Some(next) = frame.right.pop() else {
+            return false;
+        };
+        frame.left.push(std::mem::replace(&mut self.focus, next));
+        true
+    }
+
+    /// Inserts a sibling before the focus, `false` at the root
+    pub fn insert_left(&mut self, atom: Atom<'src>) -> bool {
+        match self.path.last_mut() {
+            Some(frame) => {
+                frame.left.push(atom);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Inserts a sibling after the focus, `false` at the root
+    pub fn insert_right(&mut self, atom: Atom<'src>) -> bool {
+        match self.path.last_mut() {
+            Some(frame) => {
+                frame.right.push(atom);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Goes all the way up and returns the edited tree
+    pub fn into_atom(mut self) -> Atom<'src> {
+        while self.up() {}
+        self.focus
+    }
+}
+
+// stands in for atoms that were moved out for a moment
+fn placeholder<'src>() -> Atom<'src> {
+    Atom::Error(Spanned::synthetic(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_around() {
+        let atom = crate::parse_one("(a (b c) f(x y) #raw(d))").unwrap();
+        let mut zipper = Zipper::new(atom.clone());
+        assert!(!zipper.up() && !zipper.left() && !zipper.right());
+        assert!(zipper.down());
+        assert!(!zipper.left());
+        assert!(zipper.right() && zipper.down() && zipper.right());
+        assert_eq!(zipper.focus().to_string(), "c");
+        assert_eq!((zipper.depth(), zipper.index()), (2, 1));
+        assert!(!zipper.right() && !zipper.down());
+
+        assert!(zipper.up() && zipper.right() && zipper.down());
+        assert_eq!(zipper.focus().to_string(), "f");
+        assert!(zipper.right() && zipper.right() && !zipper.right());
+        assert_eq!(zipper.focus().to_string(), "y");
+
+        assert!(zipper.up() && zipper.right() && !zipper.down());
+        assert_eq!(zipper.into_atom(), atom);
+    }
+
+    #[test]
+    fn edits() {
+        let atom = crate::parse_one("f(x) [a]").unwrap();
+        let mut zipper = Zipper::new(atom);
+        zipper.down();
+        zipper.down();
+        assert!(zipper.insert_left(Atom::ident("g")));
+        zipper.replace(Atom::ident("h"));
+        zipper.up();
+        zipper.right();
+        zipper.down();
+        assert!(zipper.insert_right(Atom::ident("b")));
+        assert_eq!(zipper.into_atom().to_string(), "((g h x) [a b])");
+    }
+}
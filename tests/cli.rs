@@ -0,0 +1,105 @@
+//! Runs the command line tools on small inputs
+
+#![cfg(feature = "cli")]
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run(bin: &str, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(bin)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn sweet2sexp() {
+    let bin = env!("CARGO_BIN_EXE_sweet2sexp");
+    let out = run(bin, &[], "define f(x)\n  g {x + 1}\nf 2\n");
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "(define (f x) (g (+ x 1)))\n(f 2)\n"
+    );
+
+    let out = run(bin, &["--minify", "-"], "a (b c) d\n");
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "(a(b c)d)\n");
+
+    let out = run(bin, &[], "a (\n");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(out.stdout.is_empty());
+    assert!(String::from_utf8(out.stderr)
+        .unwrap()
+        .starts_with("in -:\n"));
+
+    let out = run(bin, &["--frobnicate"], "");
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn sweet_fmt() {
+    let bin = env!("CARGO_BIN_EXE_sweet-fmt");
+    let out = run(bin, &[], "(f\n  x)   ; why\n\n\n(g)\n");
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "f x ; why\n\n(g)\n");
+
+    let out = run(bin, &["--check"], "f x\n");
+    assert!(out.status.success() && out.stdout.is_empty());
+    let out = run(bin, &["--check", "--width", "4"], "f x y\n");
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "-\n");
+
+    let out = run(bin, &["--style", "neoteric"], "(f (g x))\n");
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "f g(x)\n");
+    let out = run(bin, &["--blank-lines", "2"], "a\n\n\n\nb\n");
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "a\n\n\nb\n");
+    let out = run(bin, &["--join-spaced-groups"], "f (x)\n");
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "f(x)\n");
+    let out = run(bin, &["--style", "curly"], "");
+    assert_eq!(out.status.code(), Some(2));
+
+    let dir = std::env::temp_dir().join(format!("sweet-fmt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("a.sweet");
+    std::fs::write(&file, "(a (b c))\n").unwrap();
+    let out = run(bin, &["--indent", "2", file.to_str().unwrap()], "");
+    assert!(out.status.success());
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "a (b c)\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sweet_check() {
+    let bin = env!("CARGO_BIN_EXE_sweet");
+    let out = run(bin, &["check"], "define f(x)\n  g x\n");
+    assert!(out.status.success() && out.stderr.is_empty());
+
+    let out = run(bin, &["check", "-"], "a (\n");
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.starts_with("in -:\n") && stderr.contains("error"));
+
+    // warnings are shown but only fail the check when asked to
+    let out = run(bin, &["check", "--lint"], "f x \n");
+    assert!(out.status.success());
+    assert!(String::from_utf8(out.stderr)
+        .unwrap()
+        .contains("trailing whitespace"));
+    let out = run(bin, &["check", "--lint", "--deny-warnings"], "f x \n");
+    assert_eq!(out.status.code(), Some(1));
+    let out = run(bin, &["check", "--lint", "--quiet"], "f x \n");
+    assert!(out.status.success() && out.stderr.is_empty());
+
+    assert_eq!(run(bin, &["frobnicate"], "").status.code(), Some(2));
+    assert_eq!(run(bin, &[], "").status.code(), Some(2));
+}
@@ -0,0 +1,46 @@
+//! Checks the rendered errors for every input in `tests/snapshots/errors`
+//! against the expected output stored next to it. Run with
+//! `SWEET_EXPR_BLESS=1` to update the expected output after an intentional
+//! change to the messages.
+
+use std::{fs, path::Path};
+
+use sweet_expr::{diagnostic::RenderOptions, lexer::tokenise, parser::Parser};
+
+#[test]
+fn rendered_errors_match_snapshots() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots/errors");
+    let bless = std::env::var_os("SWEET_EXPR_BLESS").is_some();
+
+    let mut inputs: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sweet"))
+        .collect();
+    inputs.sort();
+    assert!(!inputs.is_empty());
+
+    let mut mismatches = vec![];
+    for input in inputs {
+        let source = fs::read_to_string(&input).unwrap();
+        let err = Parser::new(tokenise(&source))
+            .parse_toplevel()
+            .expect_err("snapshot inputs must fail to parse");
+        let rendered = err.render_with(&source, &RenderOptions::stable());
+
+        let expected_path = input.with_extension("txt");
+        if bless {
+            fs::write(&expected_path, &rendered).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+        if rendered != expected {
+            mismatches.push(format!(
+                "{}:\n--- expected\n{expected}--- actual\n{rendered}",
+                input.display()
+            ));
+        }
+    }
+
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}